@@ -2,8 +2,12 @@
 
 mod mem;
 mod null;
+mod overlay;
+mod qcow2;
 mod raw;
 
 pub use mem::Mem;
 pub use null::Null;
+pub use overlay::{Overlay, OverlayCtl};
+pub use qcow2::Qcow2;
 pub use raw::Raw;
@@ -0,0 +1,518 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use byteorder::{ByteOrder, LE};
+use futures::io::{AsyncRead, AsyncSeek, AsyncWrite};
+
+use crate::block::BlockDev;
+
+const MAGIC: &[u8; 8] = b"CLKYCOW1";
+/// Granularity of copy-on-write tracking (one IDE sector).
+const BLOCK_SIZE: u64 = 512;
+const HEADER_LEN: u64 = 512;
+/// Block data is aligned to typical host filesystem block boundaries, to
+/// ensure unwritten blocks remain sparse.
+const DATA_ALIGN: u64 = 4096;
+
+/// Copy-on-write overlay block device.
+///
+/// Reads are serviced from a read-only base image, while writes are redirected
+/// to a sparse delta file. The delta file persists across runs, and can later
+/// be committed back into the base image or discarded (via an [OverlayCtl]).
+///
+/// Delta file layout:
+///
+/// ```text
+/// 0x000: magic (b"CLKYCOW1")
+/// 0x008: base image length (u64 LE)
+/// 0x010: block size (u32 LE)
+/// 0x200: dirty block bitmap (1 bit per block)
+/// ...  : block data, at a 4K aligned offset, laid out 1:1 with the base image
+/// ```
+pub struct Overlay {
+    pos: u64,
+    state: Arc<Mutex<OverlayState>>,
+}
+
+/// Handle to commit or discard the changes made to an [Overlay] block device.
+/// Can be freely cloned, and used while the block device is in use.
+#[derive(Clone)]
+pub struct OverlayCtl {
+    state: Arc<Mutex<OverlayState>>,
+}
+
+struct OverlayState {
+    len: u64,
+    base_path: PathBuf,
+    base: File,
+    delta: File,
+    dirty: Vec<u8>,
+    data_start: u64,
+}
+
+impl std::fmt::Debug for Overlay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("Overlay")
+            .field("pos", &self.pos)
+            .field("len", &state.len)
+            .field("base_path", &state.base_path)
+            .field("dirty_blocks", &state.dirty_blocks())
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for OverlayCtl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OverlayCtl").finish()
+    }
+}
+
+fn invalid_delta(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Overlay {
+    /// Open a base image (read-only) with the given delta file, creating the
+    /// delta file if it doesn't exist.
+    ///
+    /// Existing delta files must have been created against a base image of the
+    /// same size.
+    pub fn new(base_path: impl AsRef<Path>, delta_path: impl AsRef<Path>) -> io::Result<Overlay> {
+        let base_path = base_path.as_ref().to_owned();
+        let base = File::open(&base_path)?;
+        let len = base.metadata()?.len();
+
+        let mut delta = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(delta_path)?;
+
+        let nblocks = len.div_ceil(BLOCK_SIZE);
+        let bitmap_len = nblocks.div_ceil(8);
+        let data_start = (HEADER_LEN + bitmap_len).div_ceil(DATA_ALIGN) * DATA_ALIGN;
+
+        let mut dirty = vec![0; bitmap_len as usize];
+
+        if delta.metadata()?.len() == 0 {
+            let mut header = [0; HEADER_LEN as usize];
+            header[0..8].copy_from_slice(MAGIC);
+            LE::write_u64(&mut header[8..16], len);
+            LE::write_u32(&mut header[16..20], BLOCK_SIZE as u32);
+            delta.write_all(&header)?;
+            delta.write_all(&dirty)?;
+            delta.set_len(data_start + len)?;
+        } else {
+            let mut header = [0; HEADER_LEN as usize];
+            delta.read_exact(&mut header)?;
+            if &header[0..8] != MAGIC {
+                return Err(invalid_delta("not a clicky overlay delta file"));
+            }
+            if LE::read_u64(&header[8..16]) != len {
+                return Err(invalid_delta("delta file doesn't match base image size"));
+            }
+            if LE::read_u32(&header[16..20]) as u64 != BLOCK_SIZE {
+                return Err(invalid_delta("unsupported delta file block size"));
+            }
+            delta.read_exact(&mut dirty)?;
+        }
+
+        Ok(Overlay {
+            pos: 0,
+            state: Arc::new(Mutex::new(OverlayState {
+                len,
+                base_path,
+                base,
+                delta,
+                dirty,
+                data_start,
+            })),
+        })
+    }
+
+    /// Returns a handle to commit or discard the overlay's changes.
+    pub fn ctl(&self) -> OverlayCtl {
+        OverlayCtl {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl OverlayCtl {
+    /// Write all modified blocks back into the base image, and reset the delta.
+    /// Returns the number of blocks written.
+    pub fn commit(&self) -> io::Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let mut base = OpenOptions::new().write(true).open(&state.base_path)?;
+
+        let mut buf = [0; BLOCK_SIZE as usize];
+        let mut count = 0;
+        for block in 0..state.nblocks() {
+            if !state.is_dirty(block) {
+                continue;
+            }
+            let len = state.block_len(block);
+            let offset = state.data_start + block * BLOCK_SIZE;
+            state.delta.seek(SeekFrom::Start(offset))?;
+            state.delta.read_exact(&mut buf[..len])?;
+            base.seek(SeekFrom::Start(block * BLOCK_SIZE))?;
+            base.write_all(&buf[..len])?;
+            count += 1;
+        }
+        base.sync_all()?;
+
+        state.reset()?;
+        Ok(count)
+    }
+
+    /// Throw away all modified blocks. Returns the number of blocks discarded.
+    pub fn discard(&self) -> io::Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let count = state.dirty_blocks();
+        state.reset()?;
+        Ok(count)
+    }
+
+    /// Returns the number of blocks which differ from the base image.
+    pub fn dirty_blocks(&self) -> u64 {
+        self.state.lock().unwrap().dirty_blocks()
+    }
+}
+
+impl OverlayState {
+    fn nblocks(&self) -> u64 {
+        self.len.div_ceil(BLOCK_SIZE)
+    }
+
+    /// The final block may be shorter than BLOCK_SIZE.
+    fn block_len(&self, block: u64) -> usize {
+        (self.len - block * BLOCK_SIZE).min(BLOCK_SIZE) as usize
+    }
+
+    fn is_dirty(&self, block: u64) -> bool {
+        self.dirty[(block / 8) as usize] & (1 << (block % 8)) != 0
+    }
+
+    fn dirty_blocks(&self) -> u64 {
+        self.dirty.iter().map(|b| b.count_ones() as u64).sum()
+    }
+
+    fn mark_dirty(&mut self, block: u64) -> io::Result<()> {
+        let idx = (block / 8) as usize;
+        self.dirty[idx] |= 1 << (block % 8);
+        self.delta.seek(SeekFrom::Start(HEADER_LEN + idx as u64))?;
+        self.delta.write_all(&self.dirty[idx..=idx])
+    }
+
+    /// Clear the dirty bitmap, and punch out any existing block data.
+    fn reset(&mut self) -> io::Result<()> {
+        self.dirty.iter_mut().for_each(|b| *b = 0);
+        self.delta.seek(SeekFrom::Start(HEADER_LEN))?;
+        self.delta.write_all(&self.dirty)?;
+        self.delta.set_len(self.data_start)?;
+        self.delta.set_len(self.data_start + self.len)?;
+        self.delta.sync_all()
+    }
+
+    /// Read up to the end of the block containing `pos`.
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if pos >= self.len {
+            return Ok(0);
+        }
+
+        let block = pos / BLOCK_SIZE;
+        let offset = pos % BLOCK_SIZE;
+        let n = (buf.len() as u64).min(self.block_len(block) as u64 - offset) as usize;
+
+        if self.is_dirty(block) {
+            self.delta.seek(SeekFrom::Start(self.data_start + pos))?;
+            self.delta.read_exact(&mut buf[..n])?;
+        } else {
+            self.base.seek(SeekFrom::Start(pos))?;
+            self.base.read_exact(&mut buf[..n])?;
+        }
+
+        Ok(n)
+    }
+
+    /// Write up to the end of the block containing `pos`.
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> io::Result<usize> {
+        if pos >= self.len {
+            return Ok(0);
+        }
+
+        let block = pos / BLOCK_SIZE;
+        let offset = pos % BLOCK_SIZE;
+        let block_len = self.block_len(block);
+        let n = (buf.len() as u64).min(block_len as u64 - offset) as usize;
+
+        if self.is_dirty(block) || n == block_len {
+            self.delta.seek(SeekFrom::Start(self.data_start + pos))?;
+            self.delta.write_all(&buf[..n])?;
+        } else {
+            // partial write to a clean block: copy the rest of the block from
+            // the base image
+            let mut data = [0; BLOCK_SIZE as usize];
+            self.base.seek(SeekFrom::Start(block * BLOCK_SIZE))?;
+            self.base.read_exact(&mut data[..block_len])?;
+            data[offset as usize..][..n].copy_from_slice(&buf[..n]);
+            self.delta
+                .seek(SeekFrom::Start(self.data_start + block * BLOCK_SIZE))?;
+            self.delta.write_all(&data[..block_len])?;
+        }
+
+        if !self.is_dirty(block) {
+            self.mark_dirty(block)?;
+        }
+
+        Ok(n)
+    }
+}
+
+impl BlockDev for Overlay {
+    fn len(&self) -> u64 {
+        self.state.lock().unwrap().len
+    }
+}
+
+impl Read for Overlay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        let mut total = 0;
+        while total < buf.len() {
+            let n = state.read_at(self.pos, &mut buf[total..])?;
+            if n == 0 {
+                break;
+            }
+            self.pos += n as u64;
+            total += n;
+        }
+        Ok(total)
+    }
+}
+
+impl Write for Overlay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        let mut total = 0;
+        while total < buf.len() {
+            let n = state.write_at(self.pos, &buf[total..])?;
+            if n == 0 {
+                break;
+            }
+            self.pos += n as u64;
+            total += n;
+        }
+        Ok(total)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.lock().unwrap().delta.flush()
+    }
+}
+
+impl Seek for Overlay {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let len = self.state.lock().unwrap().len;
+        let new_pos = match pos {
+            SeekFrom::Start(off) => Some(off),
+            SeekFrom::End(off) => (len as i64).checked_add(off).map(|p| p as u64),
+            SeekFrom::Current(off) => (self.pos as i64).checked_add(off).map(|p| p as u64),
+        };
+
+        match new_pos {
+            Some(p) if (p as i64) >= 0 => {
+                self.pos = p;
+                Ok(p)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+impl AsyncRead for Overlay {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(io::Read::read(&mut *self, buf))
+    }
+}
+
+impl AsyncWrite for Overlay {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(io::Write::write(&mut *self, buf))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(io::Write::flush(&mut *self))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for Overlay {
+    fn poll_seek(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        pos: io::SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        Poll::Ready(io::Seek::seek(&mut *self, pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Not a multiple of BLOCK_SIZE, so the final block is a short one.
+    const LEN: usize = 3 * BLOCK_SIZE as usize - 100;
+
+    /// Scratch directory containing a base image, removed when dropped.
+    struct Scratch {
+        dir: PathBuf,
+    }
+
+    impl Scratch {
+        fn new(name: &str) -> Scratch {
+            let dir = std::env::temp_dir().join(format!(
+                "clicky-overlay-{}-{}",
+                std::process::id(),
+                name
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("base.img"), base_data()).unwrap();
+            Scratch { dir }
+        }
+
+        fn base(&self) -> Vec<u8> {
+            std::fs::read(self.dir.join("base.img")).unwrap()
+        }
+
+        fn open(&self) -> Overlay {
+            Overlay::new(self.dir.join("base.img"), self.dir.join("delta.cow")).unwrap()
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn base_data() -> Vec<u8> {
+        (0..LEN).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn write_at(overlay: &mut Overlay, pos: u64, data: &[u8]) {
+        overlay.seek(SeekFrom::Start(pos)).unwrap();
+        overlay.write_all(data).unwrap();
+    }
+
+    fn read_all(overlay: &mut Overlay) -> Vec<u8> {
+        let mut data = Vec::new();
+        overlay.seek(SeekFrom::Start(0)).unwrap();
+        overlay.read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn partial_sector_writes() {
+        let scratch = Scratch::new("partial");
+        let mut overlay = scratch.open();
+
+        // straddles the boundary between the first two blocks
+        write_at(&mut overlay, 510, &[0xaa; 4]);
+        // within the (short) final block
+        write_at(&mut overlay, LEN as u64 - 1, &[0xbb]);
+
+        let mut expected = base_data();
+        expected[510..514].copy_from_slice(&[0xaa; 4]);
+        expected[LEN - 1] = 0xbb;
+
+        assert_eq!(read_all(&mut overlay), expected);
+        assert_eq!(overlay.ctl().dirty_blocks(), 3);
+        assert_eq!(scratch.base(), base_data());
+    }
+
+    #[test]
+    fn reads_mix_base_and_delta() {
+        let scratch = Scratch::new("mixed");
+        let mut overlay = scratch.open();
+        write_at(&mut overlay, BLOCK_SIZE, &[0xcc; BLOCK_SIZE as usize]);
+        assert_eq!(overlay.ctl().dirty_blocks(), 1);
+
+        let mut expected = base_data();
+        expected[BLOCK_SIZE as usize..][..BLOCK_SIZE as usize].fill(0xcc);
+
+        // clean -> dirty -> clean
+        let mut buf = vec![0; LEN - 256];
+        overlay.seek(SeekFrom::Start(256)).unwrap();
+        overlay.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, expected[256..]);
+
+        // the delta persists across runs
+        drop(overlay);
+        let mut overlay = scratch.open();
+        assert_eq!(overlay.ctl().dirty_blocks(), 1);
+        assert_eq!(read_all(&mut overlay), expected);
+    }
+
+    #[test]
+    fn commit() {
+        let scratch = Scratch::new("commit");
+        let mut overlay = scratch.open();
+        write_at(&mut overlay, 10, b"hello");
+        write_at(&mut overlay, LEN as u64 - 3, b"end");
+
+        let mut expected = base_data();
+        expected[10..15].copy_from_slice(b"hello");
+        expected[LEN - 3..].copy_from_slice(b"end");
+
+        let ctl = overlay.ctl();
+        assert_eq!(ctl.commit().unwrap(), 2);
+        assert_eq!(ctl.dirty_blocks(), 0);
+        assert_eq!(scratch.base(), expected);
+        assert_eq!(read_all(&mut overlay), expected);
+    }
+
+    #[test]
+    fn discard() {
+        let scratch = Scratch::new("discard");
+        let mut overlay = scratch.open();
+        write_at(&mut overlay, 400, &[0xdd; 800]);
+
+        let ctl = overlay.ctl();
+        assert_eq!(ctl.discard().unwrap(), 3);
+        assert_eq!(ctl.dirty_blocks(), 0);
+        assert_eq!(scratch.base(), base_data());
+        assert_eq!(read_all(&mut overlay), base_data());
+    }
+
+    #[test]
+    fn rejects_mismatched_base() {
+        let scratch = Scratch::new("mismatch");
+        drop(scratch.open());
+
+        std::fs::write(scratch.dir.join("base.img"), [0; 512]).unwrap();
+        let err =
+            Overlay::new(scratch.dir.join("base.img"), scratch.dir.join("delta.cow")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,655 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt, BE};
+use futures::io::{AsyncRead, AsyncSeek, AsyncWrite};
+
+use crate::block::BlockDev;
+
+const MAGIC: u32 = 0x5146_49fb; // "QFI\xfb"
+
+/// Host offset bits of L1 / L2 table entries
+const OFFSET_MASK: u64 = 0x00ff_ffff_ffff_fe00;
+/// Set when the cluster's refcount is exactly one
+const FLAG_COPIED: u64 = 1 << 63;
+const FLAG_COMPRESSED: u64 = 1 << 62;
+/// (v3 only) Cluster reads as all zeros
+const FLAG_ZERO: u64 = 1;
+
+/// Cluster size used for newly created images (64K, same as qemu-img)
+const DEFAULT_CLUSTER_BITS: u32 = 16;
+/// Only 16-bit refcounts are supported (the qemu-img default)
+const REFCOUNT_ORDER: u32 = 4;
+
+fn unsupported(msg: &str) -> io::Error {
+    io::Error::other(format!("unsupported qcow2 image: {}", msg))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid qcow2 image: {}", msg),
+    )
+}
+
+/// qcow2 image backed block device.
+///
+/// Supports version 2 and 3 images, with a few limitations: compressed
+/// clusters, encryption, backing files, internal snapshots, external data
+/// files, and non-16-bit refcounts are not supported.
+///
+/// Newly written clusters are appended to the end of the image file, and
+/// clusters are never freed.
+pub struct Qcow2 {
+    file: File,
+    pos: u64,
+
+    len: u64,
+    version: u32,
+    cluster_bits: u32,
+    l1_table_offset: u64,
+    l1: Vec<u64>,
+    refcount_table_offset: u64,
+    refcount_table: Vec<u64>,
+    /// Offset of the next cluster to allocate
+    next_free: u64,
+}
+
+impl std::fmt::Debug for Qcow2 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Qcow2")
+            .field("pos", &self.pos)
+            .field("len", &self.len)
+            .field("version", &self.version)
+            .field("cluster_bits", &self.cluster_bits)
+            .field("next_free", &self.next_free)
+            .finish()
+    }
+}
+
+impl Qcow2 {
+    /// Open an existing qcow2 image.
+    pub fn new(mut file: File) -> io::Result<Qcow2> {
+        file.seek(SeekFrom::Start(0))?;
+        let mut header = [0; 104];
+        file.read_exact(&mut header[..72])?;
+
+        if BE::read_u32(&header[0..4]) != MAGIC {
+            return Err(invalid("bad magic"));
+        }
+
+        let version = BE::read_u32(&header[4..8]);
+        let backing_file_offset = BE::read_u64(&header[8..16]);
+        let cluster_bits = BE::read_u32(&header[20..24]);
+        let len = BE::read_u64(&header[24..32]);
+        let crypt_method = BE::read_u32(&header[32..36]);
+        let l1_size = BE::read_u32(&header[36..40]);
+        let l1_table_offset = BE::read_u64(&header[40..48]);
+        let refcount_table_offset = BE::read_u64(&header[48..56]);
+        let refcount_table_clusters = BE::read_u32(&header[56..60]);
+        let nb_snapshots = BE::read_u32(&header[60..64]);
+
+        match version {
+            2 => {}
+            3 => {
+                file.read_exact(&mut header[72..104])?;
+                let incompatible_features = BE::read_u64(&header[72..80]);
+                let refcount_order = BE::read_u32(&header[96..100]);
+                if incompatible_features != 0 {
+                    return Err(unsupported(&format!(
+                        "incompatible features {:#x} (if the image is marked dirty, \
+                         try running `qemu-img check -r all`)",
+                        incompatible_features
+                    )));
+                }
+                if refcount_order != REFCOUNT_ORDER {
+                    return Err(unsupported("only 16-bit refcounts are supported"));
+                }
+            }
+            _ => return Err(unsupported(&format!("version {}", version))),
+        }
+
+        if !(9..=21).contains(&cluster_bits) {
+            return Err(invalid("cluster size out of range"));
+        }
+        if backing_file_offset != 0 {
+            return Err(unsupported("backing files"));
+        }
+        if crypt_method != 0 {
+            return Err(unsupported("encryption"));
+        }
+        if nb_snapshots != 0 {
+            return Err(unsupported("internal snapshots"));
+        }
+
+        let cluster_size = 1u64 << cluster_bits;
+        let l2_entries = cluster_size / 8;
+        if (l1_size as u64) < len.div_ceil(cluster_size * l2_entries) {
+            return Err(invalid("L1 table too small"));
+        }
+
+        let read_table = |file: &mut File, offset: u64, n: usize| -> io::Result<Vec<u64>> {
+            file.seek(SeekFrom::Start(offset))?;
+            let mut table = vec![0; n];
+            file.read_u64_into::<BE>(&mut table)?;
+            Ok(table)
+        };
+
+        let l1 = read_table(&mut file, l1_table_offset, l1_size as usize)?;
+        let refcount_table = read_table(
+            &mut file,
+            refcount_table_offset,
+            (refcount_table_clusters as u64 * cluster_size / 8) as usize,
+        )?;
+
+        let file_len = file.metadata()?.len();
+
+        Ok(Qcow2 {
+            file,
+            pos: 0,
+
+            len,
+            version,
+            cluster_bits,
+            l1_table_offset,
+            l1,
+            refcount_table_offset,
+            refcount_table,
+            next_free: file_len.div_ceil(cluster_size) * cluster_size,
+        })
+    }
+
+    /// Initialize a new (empty) qcow2 v3 image with the specified virtual size,
+    /// overwriting any existing contents of `file`.
+    pub fn create(mut file: File, len: u64) -> io::Result<Qcow2> {
+        let cluster_bits = DEFAULT_CLUSTER_BITS;
+        let cluster_size = 1u64 << cluster_bits;
+        let l2_entries = cluster_size / 8;
+
+        let l1_size = len.div_ceil(cluster_size * l2_entries);
+        let l1_clusters = (l1_size * 8).div_ceil(cluster_size).max(1);
+
+        // layout: header, refcount table, refcount block, L1 table
+        let refcount_table_offset = cluster_size;
+        let refcount_block_offset = 2 * cluster_size;
+        let l1_table_offset = 3 * cluster_size;
+        let total_clusters = 3 + l1_clusters;
+
+        file.set_len(0)?;
+        file.set_len(total_clusters * cluster_size)?;
+
+        let mut header = vec![0; 104];
+        BE::write_u32(&mut header[0..4], MAGIC);
+        BE::write_u32(&mut header[4..8], 3);
+        BE::write_u32(&mut header[20..24], cluster_bits);
+        BE::write_u64(&mut header[24..32], len);
+        BE::write_u32(&mut header[36..40], l1_size as u32);
+        BE::write_u64(&mut header[40..48], l1_table_offset);
+        BE::write_u64(&mut header[48..56], refcount_table_offset);
+        BE::write_u32(&mut header[56..60], 1);
+        BE::write_u32(&mut header[96..100], REFCOUNT_ORDER);
+        BE::write_u32(&mut header[100..104], 104);
+        // end of header extensions
+        header.extend_from_slice(&[0; 8]);
+
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header)?;
+
+        file.seek(SeekFrom::Start(refcount_table_offset))?;
+        file.write_u64::<BE>(refcount_block_offset)?;
+
+        file.seek(SeekFrom::Start(refcount_block_offset))?;
+        for _ in 0..total_clusters {
+            file.write_u16::<BE>(1)?;
+        }
+
+        file.sync_all()?;
+        Qcow2::new(file)
+    }
+
+    fn cluster_size(&self) -> u64 {
+        1 << self.cluster_bits
+    }
+
+    /// Split a guest offset into (L1 index, L2 index, offset in cluster)
+    fn split_offset(&self, offset: u64) -> (usize, u64, u64) {
+        let l2_bits = self.cluster_bits - 3;
+        let l1_idx = offset >> (self.cluster_bits + l2_bits);
+        let l2_idx = (offset >> self.cluster_bits) & ((1 << l2_bits) - 1);
+        let in_cluster = offset & (self.cluster_size() - 1);
+        (l1_idx as usize, l2_idx, in_cluster)
+    }
+
+    fn read_u64_at(&mut self, offset: u64) -> io::Result<u64> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_u64::<BE>()
+    }
+
+    fn write_u64_at(&mut self, offset: u64, val: u64) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_u64::<BE>(val)
+    }
+
+    /// Returns the L2 table entry for a guest offset (or 0 if the L2 table
+    /// isn't allocated)
+    fn l2_entry(&mut self, offset: u64) -> io::Result<u64> {
+        let (l1_idx, l2_idx, _) = self.split_offset(offset);
+        let l2_offset = self.l1[l1_idx] & OFFSET_MASK;
+        if l2_offset == 0 {
+            return Ok(0);
+        }
+        self.read_u64_at(l2_offset + l2_idx * 8)
+    }
+
+    /// Append a zeroed cluster to the end of the image, returning its offset.
+    fn alloc_cluster(&mut self) -> io::Result<u64> {
+        let offset = self.next_free;
+        self.next_free += self.cluster_size();
+        self.file.set_len(self.next_free)?;
+        self.set_refcount(offset, 1)?;
+        Ok(offset)
+    }
+
+    fn set_refcount(&mut self, offset: u64, refcount: u16) -> io::Result<()> {
+        let cluster_idx = offset >> self.cluster_bits;
+        // 16-bit refcounts
+        let entries_per_block = self.cluster_size() / 2;
+        let table_idx = (cluster_idx / entries_per_block) as usize;
+        let block_idx = cluster_idx % entries_per_block;
+
+        if table_idx >= self.refcount_table.len() {
+            return Err(unsupported("growing the refcount table"));
+        }
+
+        let mut block_offset = self.refcount_table[table_idx] & OFFSET_MASK;
+        if block_offset == 0 {
+            block_offset = self.next_free;
+            self.next_free += self.cluster_size();
+            self.file.set_len(self.next_free)?;
+
+            self.refcount_table[table_idx] = block_offset;
+            let entry_offset = self.refcount_table_offset + table_idx as u64 * 8;
+            self.write_u64_at(entry_offset, block_offset)?;
+
+            // the refcount block needs a refcount too
+            self.set_refcount(block_offset, 1)?;
+        }
+
+        self.file
+            .seek(SeekFrom::Start(block_offset + block_idx * 2))?;
+        self.file.write_u16::<BE>(refcount)
+    }
+
+    /// Returns the host offset of the cluster containing the given guest
+    /// offset, allocating it if required.
+    fn host_cluster_for_write(&mut self, offset: u64) -> io::Result<u64> {
+        let (l1_idx, l2_idx, _) = self.split_offset(offset);
+
+        let mut l2_offset = self.l1[l1_idx] & OFFSET_MASK;
+        if l2_offset == 0 {
+            l2_offset = self.alloc_cluster()?;
+            self.l1[l1_idx] = l2_offset | FLAG_COPIED;
+            let entry_offset = self.l1_table_offset + l1_idx as u64 * 8;
+            self.write_u64_at(entry_offset, self.l1[l1_idx])?;
+        }
+
+        let l2_entry_offset = l2_offset + l2_idx * 8;
+        let entry = self.read_u64_at(l2_entry_offset)?;
+        if entry & FLAG_COMPRESSED != 0 {
+            return Err(unsupported("writing to compressed clusters"));
+        }
+
+        let mut cluster_offset = entry & OFFSET_MASK;
+        let zeroed = self.version >= 3 && entry & FLAG_ZERO != 0;
+
+        if cluster_offset != 0 && !zeroed {
+            return Ok(cluster_offset);
+        }
+
+        if cluster_offset == 0 {
+            cluster_offset = self.alloc_cluster()?;
+        } else {
+            // preallocated zero cluster
+            self.file.seek(SeekFrom::Start(cluster_offset))?;
+            self.file
+                .write_all(&vec![0; self.cluster_size() as usize])?;
+        }
+
+        self.write_u64_at(l2_entry_offset, cluster_offset | FLAG_COPIED)?;
+        Ok(cluster_offset)
+    }
+
+    /// Read up to the end of the cluster containing `pos`.
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let (_, _, in_cluster) = self.split_offset(pos);
+        let n = (buf.len() as u64)
+            .min(self.cluster_size() - in_cluster)
+            .min(self.len - pos) as usize;
+        let buf = &mut buf[..n];
+
+        let entry = self.l2_entry(pos)?;
+        if entry & FLAG_COMPRESSED != 0 {
+            return Err(unsupported("compressed clusters"));
+        }
+
+        let cluster_offset = entry & OFFSET_MASK;
+        let zeroed = self.version >= 3 && entry & FLAG_ZERO != 0;
+        if cluster_offset == 0 || zeroed {
+            buf.iter_mut().for_each(|b| *b = 0);
+        } else {
+            self.file
+                .seek(SeekFrom::Start(cluster_offset + in_cluster))?;
+            self.file.read_exact(buf)?;
+        }
+
+        Ok(n)
+    }
+
+    /// Write up to the end of the cluster containing `pos`.
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> io::Result<usize> {
+        let (_, _, in_cluster) = self.split_offset(pos);
+        let n = (buf.len() as u64)
+            .min(self.cluster_size() - in_cluster)
+            .min(self.len - pos) as usize;
+
+        // avoid allocating clusters for zero writes to unallocated clusters
+        if buf[..n].iter().all(|b| *b == 0) {
+            let entry = self.l2_entry(pos)?;
+            if entry & OFFSET_MASK == 0 && entry & FLAG_COMPRESSED == 0 {
+                return Ok(n);
+            }
+        }
+
+        let cluster_offset = self.host_cluster_for_write(pos)?;
+        self.file
+            .seek(SeekFrom::Start(cluster_offset + in_cluster))?;
+        self.file.write_all(&buf[..n])?;
+
+        Ok(n)
+    }
+}
+
+impl BlockDev for Qcow2 {
+    fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl Read for Qcow2 {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut total = 0;
+        while total < buf.len() && self.pos < self.len {
+            let n = self.read_at(self.pos, &mut buf[total..])?;
+            self.pos += n as u64;
+            total += n;
+        }
+        Ok(total)
+    }
+}
+
+impl Write for Qcow2 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut total = 0;
+        while total < buf.len() && self.pos < self.len {
+            let n = self.write_at(self.pos, &buf[total..])?;
+            self.pos += n as u64;
+            total += n;
+        }
+        Ok(total)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for Qcow2 {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(off) => Some(off as i64),
+            SeekFrom::End(off) => (self.len as i64).checked_add(off),
+            SeekFrom::Current(off) => (self.pos as i64).checked_add(off),
+        };
+
+        match new_pos {
+            Some(p) if p >= 0 => {
+                self.pos = p as u64;
+                Ok(self.pos)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+impl AsyncRead for Qcow2 {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(io::Read::read(&mut *self, buf))
+    }
+}
+
+impl AsyncWrite for Qcow2 {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(io::Write::write(&mut *self, buf))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(io::Write::flush(&mut *self))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for Qcow2 {
+    fn poll_seek(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        pos: io::SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        Poll::Ready(io::Seek::seek(&mut *self, pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::OpenOptions;
+    use std::path::PathBuf;
+
+    /// Scratch file holding an image, removed when dropped.
+    struct Scratch {
+        path: PathBuf,
+    }
+
+    impl Scratch {
+        fn new(name: &str, data: &[u8]) -> Scratch {
+            let path = std::env::temp_dir().join(format!(
+                "clicky-qcow2-{}-{}.qcow2",
+                std::process::id(),
+                name
+            ));
+            std::fs::write(&path, data).unwrap();
+            Scratch { path }
+        }
+
+        fn file(&self) -> File {
+            (OpenOptions::new().read(true).write(true))
+                .open(&self.path)
+                .unwrap()
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    /// Build an empty image, laid out as: header, refcount table, refcount
+    /// block, L1 table (one cluster each).
+    fn image(version: u32, cluster_bits: u32, len: u64) -> Vec<u8> {
+        let cluster_size = 1usize << cluster_bits;
+        let l1_size = len.div_ceil((cluster_size as u64) * (cluster_size as u64 / 8));
+        assert!(l1_size as usize * 8 <= cluster_size);
+
+        let mut data = vec![0; 4 * cluster_size];
+        let header = &mut data[..104];
+        BE::write_u32(&mut header[0..4], MAGIC);
+        BE::write_u32(&mut header[4..8], version);
+        BE::write_u32(&mut header[20..24], cluster_bits);
+        BE::write_u64(&mut header[24..32], len);
+        BE::write_u32(&mut header[36..40], l1_size as u32);
+        BE::write_u64(&mut header[40..48], 3 * cluster_size as u64);
+        BE::write_u64(&mut header[48..56], cluster_size as u64);
+        BE::write_u32(&mut header[56..60], 1);
+        if version >= 3 {
+            BE::write_u32(&mut header[96..100], REFCOUNT_ORDER);
+            BE::write_u32(&mut header[100..104], 104);
+        }
+
+        BE::write_u64(&mut data[cluster_size..], 2 * cluster_size as u64);
+        for i in 0..4 {
+            BE::write_u16(&mut data[2 * cluster_size + i * 2..], 1);
+        }
+        data
+    }
+
+    fn write_at(qcow2: &mut Qcow2, pos: u64, data: &[u8]) {
+        qcow2.seek(SeekFrom::Start(pos)).unwrap();
+        qcow2.write_all(data).unwrap();
+    }
+
+    fn read_at(qcow2: &mut Qcow2, pos: u64, len: usize) -> Vec<u8> {
+        let mut buf = vec![0xff; len];
+        qcow2.seek(SeekFrom::Start(pos)).unwrap();
+        qcow2.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn unallocated_reads_are_zero() {
+        let scratch = Scratch::new("unallocated", &image(3, 9, 1 << 20));
+        let mut qcow2 = Qcow2::new(scratch.file()).unwrap();
+        assert_eq!(qcow2.len(), 1 << 20);
+
+        assert_eq!(read_at(&mut qcow2, 0, 4096), vec![0; 4096]);
+        assert_eq!(read_at(&mut qcow2, (1 << 20) - 1000, 1000), vec![0; 1000]);
+
+        // writing zeros to an unallocated cluster doesn't allocate anything
+        let file_len = scratch.file().metadata().unwrap().len();
+        write_at(&mut qcow2, 1000, &[0; 1000]);
+        assert_eq!(scratch.file().metadata().unwrap().len(), file_len);
+    }
+
+    #[test]
+    fn allocating_writes_read_back() {
+        let scratch = Scratch::new("alloc", &image(2, 9, 1 << 20));
+        let mut qcow2 = Qcow2::new(scratch.file()).unwrap();
+
+        // straddles a cluster boundary, and partially fills both clusters
+        let data = (0..100).collect::<Vec<u8>>();
+        write_at(&mut qcow2, 512 - 50, &data);
+
+        assert_eq!(read_at(&mut qcow2, 512 - 50, 100), data);
+        assert_eq!(read_at(&mut qcow2, 0, 512 - 50), vec![0; 512 - 50]);
+        assert_eq!(read_at(&mut qcow2, 512 + 50, 512 - 50), vec![0; 512 - 50]);
+
+        // overwriting an allocated cluster
+        write_at(&mut qcow2, 512, &[0; 10]);
+        let mut expected = data.clone();
+        expected[50..60].fill(0);
+        assert_eq!(read_at(&mut qcow2, 512 - 50, 100), expected);
+
+        // everything made it to disk
+        let mut qcow2 = Qcow2::new(scratch.file()).unwrap();
+        assert_eq!(read_at(&mut qcow2, 512 - 50, 100), expected);
+    }
+
+    #[test]
+    fn writes_spanning_l2_tables() {
+        // with 512 byte clusters, each L2 table maps 64 clusters (32K)
+        let scratch = Scratch::new("l2", &image(3, 9, 1 << 20));
+        let mut qcow2 = Qcow2::new(scratch.file()).unwrap();
+
+        let boundary = 64 * 512;
+        let data = (0..2000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        write_at(&mut qcow2, boundary - 1000, &data);
+        assert_eq!(read_at(&mut qcow2, boundary - 1000, 2000), data);
+
+        assert_ne!(qcow2.l1[0] & OFFSET_MASK, 0);
+        assert_ne!(qcow2.l1[1] & OFFSET_MASK, 0);
+        assert_ne!(qcow2.l1[0] & OFFSET_MASK, qcow2.l1[1] & OFFSET_MASK);
+
+        let mut qcow2 = Qcow2::new(scratch.file()).unwrap();
+        assert_eq!(read_at(&mut qcow2, boundary - 1000, 2000), data);
+    }
+
+    #[test]
+    fn create() {
+        let scratch = Scratch::new("create", &[]);
+        let mut qcow2 = Qcow2::create(scratch.file(), 3 << 20).unwrap();
+        assert_eq!(qcow2.len(), 3 << 20);
+        assert_eq!(read_at(&mut qcow2, 0, 512), vec![0; 512]);
+
+        write_at(&mut qcow2, 2 << 20, b"hello");
+        let mut qcow2 = Qcow2::new(scratch.file()).unwrap();
+        assert_eq!(read_at(&mut qcow2, 2 << 20, 5), b"hello");
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let open = |name: &str, data: Vec<u8>| {
+            let scratch = Scratch::new(name, &data);
+            Qcow2::new(scratch.file()).map(drop)
+        };
+
+        for version in [1, 4] {
+            let err = open("version", image(version, 9, 1 << 20)).unwrap_err();
+            assert!(err.to_string().contains("version"), "{}", err);
+        }
+
+        let mut data = image(3, 9, 1 << 20);
+        data[0] = 0;
+        assert_eq!(
+            open("magic", data).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        let mut data = image(3, 9, 1 << 20);
+        BE::write_u64(&mut data[72..80], 1); // dirty
+        let err = open("features", data).unwrap_err();
+        assert!(err.to_string().contains("incompatible features"), "{}", err);
+
+        let mut data = image(3, 9, 1 << 20);
+        BE::write_u32(&mut data[96..100], 5);
+        let err = open("refcount", data).unwrap_err();
+        assert!(err.to_string().contains("refcounts"), "{}", err);
+
+        let mut data = image(2, 9, 1 << 20);
+        BE::write_u64(&mut data[8..16], 0x1000);
+        let err = open("backing", data).unwrap_err();
+        assert!(err.to_string().contains("backing files"), "{}", err);
+
+        let mut data = image(2, 9, 1 << 20);
+        BE::write_u32(&mut data[36..40], 0);
+        assert_eq!(
+            open("l1", data).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Emulated time.
+//!
+//! Devices which need to know the current time (e.g: timers) should query a
+//! shared [Clock] instead of reaching for `Instant::now()` directly. This makes
+//! it possible to swap out wall-clock time for deterministic "virtual" time,
+//! which advances by a fixed amount per executed instruction.
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use relativity::Instant;
+
+use crate::snapshot::Snapshot;
+
+/// Determines how emulated time advances.
+#[derive(Debug, Default, Copy, Clone)]
+pub enum ClockMode {
+    /// Emulated time tracks wall-clock time. Timers are driven by async tasks
+    /// running on the system's executor.
+    #[default]
+    Wall,
+    /// Emulated time advances by a fixed amount each time the system is
+    /// stepped. Timers are driven synchronously from the system's `step`
+    /// method, resulting in fully reproducible execution. Time skips ahead to
+    /// the next device deadline whenever both cores are asleep.
+    Virtual { nanos_per_step: u32 },
+}
+
+#[derive(Debug)]
+enum ClockKind {
+    Wall { epoch: Instant },
+    Virtual,
+}
+
+#[derive(Debug)]
+struct ClockInner {
+    kind: ClockKind,
+    /// Nanoseconds added to the clock's base time.
+    offset: AtomicI64,
+}
+
+/// A shared source of emulated time. Clones of a clock share the same
+/// underlying time.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    inner: Arc<ClockInner>,
+}
+
+impl Clock {
+    /// Create a new clock, starting at time zero.
+    pub fn new(mode: ClockMode) -> Clock {
+        let kind = match mode {
+            ClockMode::Wall => ClockKind::Wall {
+                epoch: Instant::now(),
+            },
+            ClockMode::Virtual { .. } => ClockKind::Virtual,
+        };
+
+        Clock {
+            inner: Arc::new(ClockInner {
+                kind,
+                offset: AtomicI64::new(0),
+            }),
+        }
+    }
+
+    /// Check if the clock is a virtual clock.
+    pub fn is_virtual(&self) -> bool {
+        matches!(self.inner.kind, ClockKind::Virtual)
+    }
+
+    /// Returns the time elapsed since the clock was created.
+    pub fn now(&self) -> Duration {
+        let offset = self.inner.offset.load(Ordering::SeqCst);
+        let nanos = match self.inner.kind {
+            ClockKind::Wall { epoch } => epoch.elapsed().as_nanos() as i64 + offset,
+            ClockKind::Virtual => offset,
+        };
+        Duration::from_nanos(nanos.max(0) as u64)
+    }
+
+    /// Advance a virtual clock by the specified duration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called on a wall clock.
+    pub fn advance(&self, duration: Duration) {
+        assert!(self.is_virtual(), "cannot advance a wall clock");
+        (self.inner.offset).fetch_add(duration.as_nanos() as i64, Ordering::SeqCst);
+    }
+
+    /// Set the clock's current time.
+    fn set(&self, now: Duration) {
+        let offset = match self.inner.kind {
+            ClockKind::Wall { epoch } => now.as_nanos() as i64 - epoch.elapsed().as_nanos() as i64,
+            ClockKind::Virtual => now.as_nanos() as i64,
+        };
+        self.inner.offset.store(offset, Ordering::SeqCst);
+    }
+}
+
+impl Snapshot for Clock {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.now().save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let mut now = Duration::default();
+        now.load(r)?;
+        self.set(now);
+        Ok(())
+    }
+}
@@ -0,0 +1,500 @@
+//! Basic-block code coverage.
+//!
+//! The [Coverage] collector is fed every executed instruction, and groups runs
+//! of sequentially executed instructions into basic blocks. A block ends
+//! whenever execution doesn't continue on to the next instruction (i.e: on
+//! taken branches, exceptions, etc...).
+//!
+//! Coverage can be exported in `drcov` format (for use with tools such as
+//! Lighthouse / Cartographer / bncov), or as `lcov` tracefiles (for use with
+//! `genhtml` and friends) when DWARF line info is available.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use crate::devices::platform::pp::common::CpuId;
+use crate::symbols::LineTable;
+
+/// Coverage output formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverageFormat {
+    /// DynamoRIO `drcov` (version 2)
+    Drcov,
+    /// `lcov` tracefile (requires DWARF line info)
+    Lcov,
+}
+
+impl std::str::FromStr for CoverageFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<CoverageFormat, &'static str> {
+        match s {
+            "drcov" => Ok(CoverageFormat::Drcov),
+            "lcov" => Ok(CoverageFormat::Lcov),
+            _ => Err("expected `drcov` or `lcov`"),
+        }
+    }
+}
+
+/// Options which control how coverage is exported.
+#[derive(Debug, Clone)]
+pub struct CoverageOpts {
+    pub format: CoverageFormat,
+    /// Only export blocks executed by the specified core (default: all cores)
+    pub core: Option<CpuId>,
+    /// `drcov` module name (i.e: the name of the binary the coverage should be
+    /// matched against)
+    pub module: String,
+    /// `drcov` module base address. Blocks below the base are not exported.
+    pub base: u32,
+}
+
+impl Default for CoverageOpts {
+    fn default() -> CoverageOpts {
+        CoverageOpts {
+            format: CoverageFormat::Drcov,
+            core: None,
+            module: "firmware".into(),
+            base: 0,
+        }
+    }
+}
+
+impl CoverageOpts {
+    /// Parse a single `key=value` option (i.e: `format=<drcov|lcov>`,
+    /// `core=<cpu|cop>`, `module=<name>`, or `base=<addr>`).
+    pub fn parse_opt(&mut self, opt: &str) -> Result<(), &'static str> {
+        let mut s = opt.splitn(2, '=');
+        let kind = s.next().unwrap();
+        let val = s.next().ok_or("missing argument for coverage option")?;
+        match kind {
+            "format" => self.format = val.parse()?,
+            "core" => {
+                self.core = Some(match val {
+                    "cpu" => CpuId::Cpu,
+                    "cop" => CpuId::Cop,
+                    _ => return Err("expected `cpu` or `cop`"),
+                })
+            }
+            "module" => self.module = val.into(),
+            "base" => {
+                let base = match val.strip_prefix("0x") {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => val.parse(),
+                };
+                self.base = base.map_err(|_| "could not parse `base`")?;
+            }
+            _ => return Err("unknown coverage option"),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    /// Size of the block (in bytes)
+    size: u32,
+    /// Number of times the block was executed
+    hits: u64,
+}
+
+/// Collects the basic blocks executed by each core.
+#[derive(Debug, Default)]
+pub struct Coverage {
+    /// (start, end) of the block each core is currently executing
+    current: [Option<(u32, u32)>; 2],
+    /// Executed blocks, keyed by start address
+    blocks: [HashMap<u32, Block>; 2],
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    /// Discard all collected coverage.
+    pub fn clear(&mut self) {
+        *self = Coverage::default();
+    }
+
+    /// Number of unique blocks executed by the specified core (or by any core).
+    pub fn num_blocks(&self, core: Option<CpuId>) -> usize {
+        self.merged(core).len()
+    }
+
+    /// Called after a core executes an instruction.
+    ///
+    /// `pc` and `thumb` describe the executed instruction, while `next_pc` is
+    /// the core's PC after executing it.
+    pub fn on_step(&mut self, core: CpuId, pc: u32, thumb: bool, next_pc: u32) {
+        let core = core as usize;
+
+        let start = match self.current[core].take() {
+            Some((start, end)) if end == pc => start,
+            // execution was redirected without being observed by `on_step`
+            // (e.g: via GDB / a loaded save state)
+            Some((start, end)) => {
+                self.end_block(core, start, end);
+                pc
+            }
+            None => pc,
+        };
+
+        let end = pc.wrapping_add(if thumb { 2 } else { 4 });
+        if next_pc == end {
+            self.current[core] = Some((start, end));
+        } else {
+            self.end_block(core, start, end);
+        }
+    }
+
+    fn end_block(&mut self, core: usize, start: u32, end: u32) {
+        let block = self.blocks[core]
+            .entry(start)
+            .or_insert(Block { size: 0, hits: 0 });
+        block.size = block.size.max(end.wrapping_sub(start));
+        block.hits += 1;
+    }
+
+    /// Return all blocks executed by the specified core (or by any core),
+    /// sorted by start address.
+    fn merged(&self, core: Option<CpuId>) -> BTreeMap<u32, Block> {
+        let cores = match core {
+            Some(core) => core as usize..core as usize + 1,
+            None => 0..2,
+        };
+
+        let mut merged = BTreeMap::new();
+        for core in cores {
+            // include the blocks which are currently being executed
+            let current = self.current[core].map(|(start, end)| {
+                let block = Block {
+                    size: end.wrapping_sub(start),
+                    hits: 1,
+                };
+                (start, block)
+            });
+
+            for (start, block) in self.blocks[core]
+                .iter()
+                .map(|(&s, &b)| (s, b))
+                .chain(current)
+            {
+                let merged = merged.entry(start).or_insert(Block { size: 0, hits: 0 });
+                merged.size = merged.size.max(block.size);
+                merged.hits += block.hits;
+            }
+        }
+        merged
+    }
+
+    /// Write coverage in the specified format. `lines` is only required when
+    /// exporting `lcov` tracefiles.
+    pub fn write(&self, w: impl Write, opts: &CoverageOpts, lines: &LineTable) -> io::Result<()> {
+        match opts.format {
+            CoverageFormat::Drcov => self.write_drcov(w, opts.core, &opts.module, opts.base),
+            CoverageFormat::Lcov => self.write_lcov(w, opts.core, lines),
+        }
+    }
+
+    /// Write coverage as a `drcov` (version 2) file, with all blocks
+    /// attributed to a single module named `module`, based at `base`.
+    pub fn write_drcov(
+        &self,
+        mut w: impl Write,
+        core: Option<CpuId>,
+        module: &str,
+        base: u32,
+    ) -> io::Result<()> {
+        // drcov block sizes are 16 bits, so larger blocks must be split up
+        let mut bbs = Vec::new();
+        for (start, block) in self.merged(core).range(base..) {
+            let mut offset = start - base;
+            let mut remaining = block.size;
+            while remaining != 0 {
+                let size = remaining.min(u16::MAX as u32 & !3);
+                bbs.push((offset, size as u16));
+                offset = offset.wrapping_add(size);
+                remaining -= size;
+            }
+        }
+
+        writeln!(w, "DRCOV VERSION: 2")?;
+        writeln!(w, "DRCOV FLAVOR: drcov")?;
+        writeln!(w, "Module Table: version 2, count 1")?;
+        writeln!(
+            w,
+            "Columns: id, base, end, entry, checksum, timestamp, path"
+        )?;
+        writeln!(
+            w,
+            "  0, {:#018x}, {:#018x}, {:#018x}, {:#010x}, {:#010x}, {}",
+            base,
+            u32::MAX,
+            0,
+            0,
+            0,
+            module
+        )?;
+        writeln!(w, "BB Table: {} bbs", bbs.len())?;
+        for (offset, size) in bbs {
+            // struct { u32 start; u16 size; u16 mod_id; }
+            w.write_all(&offset.to_le_bytes())?;
+            w.write_all(&size.to_le_bytes())?;
+            w.write_all(&0u16.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Write coverage as an `lcov` tracefile, using DWARF line info to map
+    /// executed blocks back to source lines.
+    ///
+    /// A line's hit count is the number of times the most frequently executed
+    /// block overlapping any of the line's instructions was executed.
+    pub fn write_lcov(
+        &self,
+        mut w: impl Write,
+        core: Option<CpuId>,
+        lines: &LineTable,
+    ) -> io::Result<()> {
+        if lines.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no DWARF line info loaded (see `--elf`)",
+            ));
+        }
+
+        let blocks = self.merged(core);
+        let max_block_size = blocks.values().map(|b| b.size).max().unwrap_or(0);
+
+        // file -> line -> hits
+        let mut files: BTreeMap<&str, BTreeMap<u32, u64>> = BTreeMap::new();
+        for (file, line, addrs) in lines.iter() {
+            // only blocks starting within `max_block_size` of the range could
+            // possibly overlap it
+            let hits = blocks
+                .range(addrs.start.saturating_sub(max_block_size)..addrs.end)
+                .filter(|(&start, block)| start.wrapping_add(block.size) > addrs.start)
+                .map(|(_, block)| block.hits)
+                .max()
+                .unwrap_or(0);
+
+            let line_hits = files.entry(file).or_default().entry(line).or_default();
+            *line_hits = (*line_hits).max(hits);
+        }
+
+        writeln!(w, "TN:")?;
+        for (file, lines) in files {
+            writeln!(w, "SF:{}", file)?;
+            for (line, hits) in &lines {
+                writeln!(w, "DA:{},{}", line, hits)?;
+            }
+            writeln!(w, "LF:{}", lines.len())?;
+            writeln!(
+                w,
+                "LH:{}",
+                lines.values().filter(|&&hits| hits != 0).count()
+            )?;
+            writeln!(w, "end_of_record")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `cov` a core's execution trace (i.e: the PC of each executed
+    /// instruction, followed by the core's final PC).
+    fn run(cov: &mut Coverage, core: CpuId, thumb: bool, trace: &[u32]) {
+        for pcs in trace.windows(2) {
+            cov.on_step(core, pcs[0], thumb, pcs[1]);
+        }
+    }
+
+    /// The CPU runs a (twice executed) ARM block at 0x1000, which branches to
+    /// 0x2000 and 0x3000 (the latter of which is still being executed), while
+    /// the COP runs some Thumb code at 0x800, before branching to 0x4000.
+    fn coverage() -> Coverage {
+        let mut cov = Coverage::new();
+        #[rustfmt::skip]
+        run(&mut cov, CpuId::Cpu, false, &[
+            0x1000, 0x1004, 0x1008,
+            0x2000,
+            0x1000, 0x1004, 0x1008,
+            0x3000, 0x3004,
+        ]);
+        run(&mut cov, CpuId::Cop, true, &[0x800, 0x802, 0x4000, 0x4002]);
+        cov
+    }
+
+    fn drcov(cov: &Coverage, opts: &CoverageOpts) -> Vec<u8> {
+        let mut out = Vec::new();
+        cov.write(&mut out, opts, &LineTable::default()).unwrap();
+        out
+    }
+
+    fn drcov_expected(module: &str, base: u32, bbs: &[(u32, u16)]) -> Vec<u8> {
+        let mut expected = format!(
+            "DRCOV VERSION: 2\n\
+             DRCOV FLAVOR: drcov\n\
+             Module Table: version 2, count 1\n\
+             Columns: id, base, end, entry, checksum, timestamp, path\n  \
+             0, {:#018x}, 0x00000000ffffffff, 0x0000000000000000, 0x00000000, 0x00000000, {}\n\
+             BB Table: {} bbs\n",
+            base,
+            module,
+            bbs.len()
+        )
+        .into_bytes();
+        for (offset, size) in bbs {
+            expected.extend(offset.to_le_bytes());
+            expected.extend(size.to_le_bytes());
+            expected.extend(0u16.to_le_bytes());
+        }
+        expected
+    }
+
+    #[test]
+    fn blocks() {
+        let mut cov = coverage();
+        assert_eq!(cov.num_blocks(Some(CpuId::Cpu)), 3);
+        assert_eq!(cov.num_blocks(Some(CpuId::Cop)), 2);
+        assert_eq!(cov.num_blocks(None), 5);
+
+        // both cores executing the same block are counted once
+        run(
+            &mut cov,
+            CpuId::Cop,
+            false,
+            &[0x1000, 0x1004, 0x1008, 0x5000],
+        );
+        assert_eq!(cov.num_blocks(Some(CpuId::Cop)), 3);
+        assert_eq!(cov.num_blocks(None), 5);
+
+        cov.clear();
+        assert_eq!(cov.num_blocks(None), 0);
+    }
+
+    #[test]
+    fn drcov_output() {
+        let cov = coverage();
+
+        let opts = CoverageOpts {
+            module: "fw.elf".into(),
+            base: 0x1000,
+            ..CoverageOpts::default()
+        };
+        let expected = drcov_expected(
+            "fw.elf",
+            0x1000,
+            &[(0x0000, 12), (0x1000, 4), (0x2000, 4), (0x3000, 2)],
+        );
+        assert_eq!(drcov(&cov, &opts), expected);
+
+        let opts = CoverageOpts {
+            core: Some(CpuId::Cop),
+            ..CoverageOpts::default()
+        };
+        let expected = drcov_expected("firmware", 0, &[(0x800, 4), (0x4000, 2)]);
+        assert_eq!(drcov(&cov, &opts), expected);
+    }
+
+    #[test]
+    fn drcov_splits_large_blocks() {
+        let mut cov = Coverage::new();
+        let trace = (0..=0x4002).map(|i| 0x10000 + i * 4).collect::<Vec<_>>();
+        run(&mut cov, CpuId::Cpu, false, &trace);
+
+        let expected = drcov_expected("firmware", 0, &[(0x10000, 0xfffc), (0x1fffc, 0xc)]);
+        assert_eq!(drcov(&cov, &CoverageOpts::default()), expected);
+    }
+
+    #[test]
+    fn lcov_output() {
+        let cov = coverage();
+        let opts = CoverageOpts {
+            format: CoverageFormat::Lcov,
+            ..CoverageOpts::default()
+        };
+
+        let mut out = Vec::new();
+        let err = cov
+            .write(&mut out, &opts, &LineTable::default())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let lines = LineTable::from_lines(&[
+            ("src/a.c", 10, 0x1000..0x1008),
+            ("src/a.c", 11, 0x1008..0x100c),
+            ("src/a.c", 12, 0x100c..0x1010),
+            ("src/b.c", 3, 0x2000..0x2004),
+            // only the line's second instruction is executed
+            ("src/b.c", 5, 0x2ffc..0x3004),
+            // a line which is split across multiple ranges
+            ("src/a.c", 10, 0x4000..0x4002),
+        ]);
+        cov.write(&mut out, &opts, &lines).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "TN:\n\
+             SF:src/a.c\n\
+             DA:10,2\n\
+             DA:11,2\n\
+             DA:12,0\n\
+             LF:3\n\
+             LH:2\n\
+             end_of_record\n\
+             SF:src/b.c\n\
+             DA:3,1\n\
+             DA:5,1\n\
+             LF:2\n\
+             LH:2\n\
+             end_of_record\n"
+        );
+    }
+
+    #[test]
+    fn parse_opt() {
+        let mut opts = CoverageOpts::default();
+        for opt in [
+            "format=lcov",
+            "core=cop",
+            "module=rockbox.elf",
+            "base=0x40000000",
+        ] {
+            opts.parse_opt(opt).unwrap();
+        }
+        assert_eq!(opts.format, CoverageFormat::Lcov);
+        assert_eq!(opts.core, Some(CpuId::Cop));
+        assert_eq!(opts.module, "rockbox.elf");
+        assert_eq!(opts.base, 0x4000_0000);
+
+        for opt in ["format=drcov", "core=cpu", "module=a=b", "base=4096"] {
+            opts.parse_opt(opt).unwrap();
+        }
+        assert_eq!(opts.format, CoverageFormat::Drcov);
+        assert_eq!(opts.core, Some(CpuId::Cpu));
+        assert_eq!(opts.module, "a=b");
+        assert_eq!(opts.base, 4096);
+
+        let cases = [
+            "format",
+            "format=gcov",
+            "core=both",
+            "base=",
+            "base=0x",
+            "base=0x100000000",
+            "base=-1",
+            "base=0xzz",
+            "size=4",
+            "",
+        ];
+        for opt in cases.iter() {
+            assert!(opts.parse_opt(opt).is_err(), "{:?}", opt);
+        }
+    }
+}
@@ -0,0 +1,342 @@
+use crate::devices::prelude::*;
+
+use std::io::{self, Read, Write};
+use std::sync::{Arc, RwLock};
+
+use super::rgb565_to_argb;
+use crate::gui::RenderCallback;
+use crate::snapshot::{load_bytes, save_bytes, Snapshot};
+
+const LCD_WIDTH: usize = 320;
+const LCD_HEIGHT: usize = 240;
+
+/// Amount of BCM memory which is backed by the emulator. The real chip has
+/// much more, but the host interface only ever pokes at the low 2MB.
+const MEM_LEN: usize = 2 * 1024 * 1024;
+
+#[allow(dead_code)]
+mod flags {
+    /* CONTROL register (read) */
+    pub const CONTROL_WRITE_READY: usize = 1;
+    pub const CONTROL_READ_READY: usize = 4;
+
+    /* CONTROL register (write) */
+    pub const CONTROL_EXECUTE: u32 = 0x31;
+
+    /* RD_ADDR register (read) */
+    pub const RD_ADDR_READY: usize = 0;
+}
+
+/// Well-known locations in BCM memory.
+mod addr {
+    pub const COMMAND: usize = 0x1f8;
+    pub const CMDPARAM: usize = 0xe0000;
+    pub const RECT_DATA: usize = CMDPARAM + 0x20;
+}
+
+/// Commands written to `addr::COMMAND`. Encoded as `(!cmd << 16) | cmd`.
+mod cmd {
+    pub const LCD_UPDATE: u32 = 0xffff_0000;
+    pub const LCD_UPDATERECT: u32 = 0xfffa_0005;
+}
+
+/// Broadcom BCM2722 VideoCore "Host Interface".
+///
+/// Only emulates the small subset of the BCM2722 which is used to drive the
+/// 320x240 RGB565 LCD on the iPod 5g. The host writes command parameters and
+/// pixel data into BCM memory through an auto-incrementing address window,
+/// and then kicks off a command by writing to the CONTROL register.
+///
+/// The VideoCore itself is not emulated: commands complete instantaneously,
+/// and any firmware uploaded to the chip is simply stored in memory.
+///
+/// Supported commands: `LCD_UPDATE` (full-screen blit from `CMDPARAM`), and
+/// `LCD_UPDATERECT` (partial blit of a packed rect).
+pub struct Bcm2722 {
+    /// Latched low half of a 16-bit write to WR_ADDR / RD_ADDR
+    addr_latch: Option<u16>,
+    /// Latched low half of a 16-bit write to DATA
+    write_latch: Option<u16>,
+    /// Latched high half of a 16-bit read from DATA
+    read_latch: Option<u16>,
+
+    wr_addr: u32,
+    rd_addr: u32,
+
+    mem: Box<[u8]>,
+    lcd: Arc<RwLock<Vec<u16>>>,
+}
+
+impl std::fmt::Debug for Bcm2722 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Bcm2722")
+            .field("addr_latch", &self.addr_latch)
+            .field("write_latch", &self.write_latch)
+            .field("read_latch", &self.read_latch)
+            .field("wr_addr", &self.wr_addr)
+            .field("rd_addr", &self.rd_addr)
+            .field("mem", &"[...]")
+            .field("lcd", &"[...]")
+            .finish()
+    }
+}
+
+impl Snapshot for Bcm2722 {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.addr_latch.save(w)?;
+        self.write_latch.save(w)?;
+        self.read_latch.save(w)?;
+        self.wr_addr.save(w)?;
+        self.rd_addr.save(w)?;
+        save_bytes(w, &self.mem)?;
+        for px in self.lcd.write().unwrap().iter_mut() {
+            px.save(w)?;
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        self.addr_latch.load(r)?;
+        self.write_latch.load(r)?;
+        self.read_latch.load(r)?;
+        self.wr_addr.load(r)?;
+        self.rd_addr.load(r)?;
+        load_bytes(r, &mut self.mem)?;
+        for px in self.lcd.write().unwrap().iter_mut() {
+            px.load(r)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Bcm2722 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bcm2722 {
+    pub fn new() -> Bcm2722 {
+        Bcm2722 {
+            addr_latch: None,
+            write_latch: None,
+            read_latch: None,
+
+            wr_addr: 0,
+            rd_addr: 0,
+
+            mem: vec![0; MEM_LEN].into_boxed_slice(),
+            lcd: Arc::new(RwLock::new(vec![0; LCD_WIDTH * LCD_HEIGHT])),
+        }
+    }
+
+    /// Returns a callback to update the framebuffer.
+    ///
+    /// The callback accepts a minifb framebuffer, and returns the rendered
+    /// dimensions.
+    pub fn render_callback(&self) -> RenderCallback {
+        let lcd = Arc::clone(&self.lcd);
+
+        Box::new(move |buf: &mut Vec<u32>| -> (usize, usize) {
+            let lcd = lcd.read().unwrap();
+
+            let new_buf = lcd.iter().map(|&px| rgb565_to_argb(px));
+
+            // replace in-place
+            buf.splice(.., new_buf);
+
+            (LCD_WIDTH, LCD_HEIGHT)
+        })
+    }
+
+    fn mem_idx(addr: u32) -> MemResult<usize> {
+        let addr = addr as usize;
+        if addr + 4 > MEM_LEN {
+            return Err(ContractViolation {
+                msg: format!("access to unemulated BCM memory address {:#x?}", addr),
+                severity: Warn,
+                stub_val: Some(0),
+            });
+        }
+        Ok(addr)
+    }
+
+    fn mem_r32(&self, addr: usize) -> u32 {
+        let mut val = [0; 4];
+        val.copy_from_slice(&self.mem[addr..addr + 4]);
+        u32::from_le_bytes(val)
+    }
+
+    fn mem_w32(&mut self, addr: usize, val: u32) {
+        self.mem[addr..addr + 4].copy_from_slice(&val.to_le_bytes())
+    }
+
+    fn write_data(&mut self, val: u32) -> MemResult<()> {
+        let addr = Self::mem_idx(self.wr_addr)?;
+        self.mem_w32(addr, val);
+        self.wr_addr = self.wr_addr.wrapping_add(4);
+        Ok(())
+    }
+
+    fn read_data(&mut self) -> MemResult<u32> {
+        let addr = Self::mem_idx(self.rd_addr)?;
+        self.rd_addr = self.rd_addr.wrapping_add(4);
+        Ok(self.mem_r32(addr))
+    }
+
+    fn control_status(&self) -> u32 {
+        // the emulated BCM is always ready
+        let mut val = 0u32;
+        val.set_bit(flags::CONTROL_WRITE_READY, true);
+        val.set_bit(flags::CONTROL_READ_READY, true);
+        val
+    }
+
+    /// Blits a `width x height` block of packed RGB565 pixels from `src` to the
+    /// LCD at (`x`, `y`). Pixels which fall outside the LCD are skipped.
+    fn blit(&mut self, src: usize, x: usize, y: usize, width: usize, height: usize) {
+        let mut lcd = self.lcd.write().unwrap();
+        let src = self.mem[src..].chunks_exact(2);
+        // the rect comes straight from the guest, so make sure it can't overflow
+        for (i, px) in src.take(width.saturating_mul(height)).enumerate() {
+            let (dx, dy) = (x.saturating_add(i % width), y.saturating_add(i / width));
+            if dx < LCD_WIDTH && dy < LCD_HEIGHT {
+                lcd[dy * LCD_WIDTH + dx] = u16::from_le_bytes([px[0], px[1]]);
+            }
+        }
+    }
+
+    fn execute(&mut self) -> MemResult<()> {
+        let res = match self.mem_r32(addr::COMMAND) {
+            cmd::LCD_UPDATE => {
+                self.blit(addr::CMDPARAM, 0, 0, LCD_WIDTH, LCD_HEIGHT);
+                Ok(())
+            }
+            cmd::LCD_UPDATERECT => {
+                let param = |i: usize| self.mem_r32(addr::CMDPARAM + i * 4) as usize;
+                let (x0, y0, x1, y1) = (param(1), param(2), param(3), param(4));
+                if x1 < x0 || y1 < y0 {
+                    Err(ContractViolation {
+                        msg: format!("invalid LCD_UPDATERECT ({},{})-({},{})", x0, y0, x1, y1),
+                        severity: Error,
+                        stub_val: None,
+                    })
+                } else {
+                    let (width, height) =
+                        ((x1 - x0).saturating_add(1), (y1 - y0).saturating_add(1));
+                    self.blit(addr::RECT_DATA, x0, y0, width, height);
+                    Ok(())
+                }
+            }
+            other => Err(ContractViolation {
+                msg: format!("unimplemented BCM command {:#010x?}", other),
+                severity: Error,
+                stub_val: None,
+            }),
+        };
+
+        // signal command completion
+        self.mem_w32(addr::COMMAND, 0);
+        res
+    }
+
+    fn write_addr(&mut self, rd: bool, val: u32) {
+        if rd {
+            self.rd_addr = val;
+            self.read_latch = None;
+        } else {
+            self.wr_addr = val;
+            self.write_latch = None;
+        }
+    }
+}
+
+impl Device for Bcm2722 {
+    fn kind(&self) -> &'static str {
+        "BCM2722"
+    }
+
+    fn probe(&self, offset: u32) -> Probe {
+        let reg = match offset {
+            0x00000 => "DATA",
+            0x10000 => "WR_ADDR",
+            0x20000 => "RD_ADDR",
+            0x30000 => "CONTROL",
+            0x40000 => "ALT_DATA",
+            0x50000 => "ALT_WR_ADDR",
+            0x60000 => "ALT_RD_ADDR",
+            0x70000 => "ALT_CONTROL",
+            _ => return Probe::Unmapped,
+        };
+
+        Probe::Register(reg)
+    }
+}
+
+impl Memory for Bcm2722 {
+    fn r16(&mut self, offset: u32) -> MemResult<u16> {
+        match offset & !0x40000 {
+            0x00000 => match self.read_latch.take() {
+                Some(hi) => Ok(hi),
+                None => {
+                    let val = self.read_data()?;
+                    self.read_latch = Some((val >> 16) as u16);
+                    Ok(val as u16)
+                }
+            },
+            _ => Ok(self.r32(offset)? as u16),
+        }
+    }
+
+    fn w16(&mut self, offset: u32, val: u16) -> MemResult<()> {
+        match offset & !0x40000 {
+            0x00000 => match self.write_latch.take() {
+                Some(lo) => self.write_data(lo as u32 | (val as u32) << 16),
+                None => {
+                    self.write_latch = Some(val);
+                    Ok(())
+                }
+            },
+            0x10000 | 0x20000 => {
+                let rd = offset & !0x40000 == 0x20000;
+                match self.addr_latch.take() {
+                    Some(lo) => self.write_addr(rd, lo as u32 | (val as u32) << 16),
+                    None => self.addr_latch = Some(val),
+                }
+                Ok(())
+            }
+            _ => self.w32(offset, val as u32),
+        }
+    }
+
+    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+        match offset & !0x40000 {
+            0x00000 => self.read_data(),
+            0x10000 => Err(InvalidAccess),
+            0x20000 => Ok(1 << flags::RD_ADDR_READY),
+            0x30000 => Ok(self.control_status()),
+            _ => Err(Unexpected),
+        }
+    }
+
+    fn w32(&mut self, offset: u32, val: u32) -> MemResult<()> {
+        match offset & !0x40000 {
+            0x00000 => self.write_data(val),
+            0x10000 => {
+                self.addr_latch = None;
+                self.write_addr(false, val);
+                Ok(())
+            }
+            0x20000 => {
+                self.addr_latch = None;
+                self.write_addr(true, val);
+                Ok(())
+            }
+            0x30000 => match val {
+                flags::CONTROL_EXECUTE => self.execute(),
+                _ => Err(StubWrite(Warn, ())),
+            },
+            _ => Err(Unexpected),
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use crate::devices::prelude::*;
 
+use std::io::{self, Read, Write};
 use std::sync::{Arc, RwLock};
 
 use crate::gui::RenderCallback;
+use crate::snapshot::Snapshot;
 
 const CGRAM_WIDTH: usize = 168;
 const CGRAM_HEIGHT: usize = 132;
@@ -35,6 +37,9 @@ const EMU_CGRAM_WIDTH: usize = 256;
 const EMU_CGRAM_BYTES: usize = (EMU_CGRAM_WIDTH * CGRAM_HEIGHT) * 2 / 8;
 const EMU_CGRAM_LEN: usize = EMU_CGRAM_BYTES / 2; // addressed as 16-bit words
 
+/// LCD1 interface "busy" bit
+const LCD1_BUSY: u32 = 0x8000;
+
 // TODO: migrate to bit_field crate + mod reg { const X: usize = Y; ... }
 #[derive(Debug, Default, Copy, Clone)]
 struct InternalRegs {
@@ -85,8 +90,55 @@ struct InternalRegs {
     se2: u8,
 }
 
+impl_snapshot!(InternalRegs {
+    cms,
+    sgs,
+    nl,
+    nw,
+    eor,
+    b_c,
+    stb,
+    slp,
+    ap,
+    dc,
+    ps,
+    bt,
+    bs,
+    vr,
+    ct,
+    i_d,
+    am,
+    lg,
+    rt,
+    spt,
+    gsh,
+    gsl,
+    rev,
+    d,
+    c,
+    cm,
+    wm,
+    hs,
+    he,
+    vs,
+    ve,
+    ss1,
+    se1,
+    ss2,
+    se2
+});
+
 /// Hitachi HD66753 168x132 monochrome LCD Controller.
+///
+/// Connected to the PP502x's LCD1 interface. Most iPods drive the controller
+/// using 8-bit transfers (latching the high byte first), though the iPod mini
+/// 2g uses the interface's "bridge" mode, where full 16-bit commands / data are
+/// written to the command register in a single transfer (tagged with either
+/// `0x74` or `0x76` in bits 16..=23).
 pub struct Hd66753 {
+    /// LCD1 interface control register
+    control: u32,
+
     // FIXME: not sure if there are separate latches for the command and data registers...
     write_byte_latch: Option<u8>,
     read_byte_latch: Option<u8>,
@@ -101,9 +153,32 @@ pub struct Hd66753 {
     ireg: Arc<RwLock<InternalRegs>>,
 }
 
+impl Snapshot for Hd66753 {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.control.save(w)?;
+        self.write_byte_latch.save(w)?;
+        self.read_byte_latch.save(w)?;
+        self.ir.save(w)?;
+        self.ac.save(w)?;
+        self.cgram.write().unwrap().save(w)?;
+        self.ireg.write().unwrap().save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        self.control.load(r)?;
+        self.write_byte_latch.load(r)?;
+        self.read_byte_latch.load(r)?;
+        self.ir.load(r)?;
+        self.ac.load(r)?;
+        self.cgram.write().unwrap().load(r)?;
+        self.ireg.write().unwrap().load(r)
+    }
+}
+
 impl std::fmt::Debug for Hd66753 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Hd66753")
+            .field("control", &self.control)
             .field("write_byte_latch", &self.write_byte_latch)
             .field("read_byte_latch", &self.read_byte_latch)
             .field("ir", &self.ir)
@@ -123,6 +198,7 @@ impl Hd66753 {
         }));
 
         Hd66753 {
+            control: 0,
             ir: 0,
             ac: 0,
             cgram,
@@ -356,6 +432,20 @@ impl Hd66753 {
         Ok(())
     }
 
+    fn set_ir(&mut self, val: u16) -> MemResult<()> {
+        self.ir = val;
+
+        if self.ir > 0x12 {
+            return Err(ContractViolation {
+                msg: format!("set invalid LCD Command: {:#04x?}", val),
+                severity: Error,
+                stub_val: None,
+            });
+        }
+
+        Ok(())
+    }
+
     fn handle_data_read(&mut self) -> MemResult<u16> {
         match self.ir {
             // device code read
@@ -389,7 +479,7 @@ impl Memory for Hd66753 {
     fn r32(&mut self, offset: u32) -> MemResult<u32> {
         if offset == 0x0 {
             // bypass the latch
-            return Ok(0); // HACK: Emulated LCD is never busy
+            return Ok(self.control & !LCD1_BUSY); // HACK: Emulated LCD is never busy
         }
 
         if let Some(val) = self.read_byte_latch.take() {
@@ -410,7 +500,21 @@ impl Memory for Hd66753 {
     fn w32(&mut self, offset: u32, val: u32) -> MemResult<()> {
         if offset == 0x0 {
             // bypass the latch
-            return Err(StubWrite(Error, ()));
+            self.control = val;
+            return Err(StubWrite(Debug, ()));
+        }
+
+        // bridge mode: full 16-bit command / data writes
+        if offset == 0x8 && val > 0xff {
+            return match val >> 16 {
+                0x74 => self.set_ir(val as u16),
+                0x76 => self.handle_data_write(val as u16),
+                _ => Err(ContractViolation {
+                    msg: format!("invalid LCD1 bridge write: {:#010x?}", val),
+                    severity: Error,
+                    stub_val: None,
+                }),
+            };
         }
 
         // the iPod uses the controller via an 8-bit interface
@@ -424,19 +528,7 @@ impl Memory for Hd66753 {
         };
 
         match offset {
-            0x8 => {
-                self.ir = val;
-
-                if self.ir > 0x12 {
-                    return Err(ContractViolation {
-                        msg: format!("set invalid LCD Command: {:#04x?}", val),
-                        severity: Error,
-                        stub_val: None,
-                    });
-                }
-
-                Ok(())
-            }
+            0x8 => self.set_ir(val),
             0x10 => Ok(self.handle_data_write(val)?),
             _ => Err(Unexpected),
         }
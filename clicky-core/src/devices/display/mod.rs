@@ -1,3 +1,13 @@
 //! Display-related devices.
 
+pub mod bcm2722;
 pub mod hd66753;
+pub mod photo_lcd;
+
+/// Convert a RGB565 pixel into a ARGB8888 pixel.
+fn rgb565_to_argb(px: u16) -> u32 {
+    let r = (px >> 11) as u32 & 0x1f;
+    let g = (px >> 5) as u32 & 0x3f;
+    let b = px as u32 & 0x1f;
+    0xff00_0000 | ((r << 3 | r >> 2) << 16) | ((g << 2 | g >> 4) << 8) | (b << 3 | b >> 2)
+}
@@ -0,0 +1,295 @@
+use crate::devices::prelude::*;
+
+use std::io::{self, Read, Write};
+use std::sync::{Arc, RwLock};
+
+use super::rgb565_to_argb;
+use crate::gui::RenderCallback;
+use crate::snapshot::Snapshot;
+
+const LCD_WIDTH: usize = 220;
+const LCD_HEIGHT: usize = 176;
+
+#[allow(dead_code)]
+mod flags {
+    type Range = std::ops::RangeInclusive<usize>;
+
+    /* LCD2_PORT */
+    pub const PORT_BUSY: usize = 31;
+    pub const PORT_KIND: Range = 24..=31;
+    pub const PORT_KIND_CMD: u32 = 0x80;
+    pub const PORT_KIND_DATA: u32 = 0x81;
+
+    /* LCD2_BLOCK_CTRL */
+    pub const BLOCK_CTRL_INIT: u32 = 0x1000_0080;
+    pub const BLOCK_CTRL_START: u32 = 0x3400_0000;
+    pub const BLOCK_CTRL_TXOK: usize = 24;
+    pub const BLOCK_CTRL_READY: usize = 26;
+
+    /* LCD2_BLOCK_CONFIG */
+    pub const BLOCK_CONFIG_LEN: Range = 0..=15;
+}
+
+/// Panel registers
+mod reg {
+    pub const START_VERT: usize = 0x12;
+    pub const START_HORIZ: usize = 0x13;
+    pub const END_VERT: usize = 0x15;
+    pub const END_HORIZ: usize = 0x16;
+}
+
+/// State of an in-progress block transfer
+#[derive(Debug, Default)]
+struct BlockXfer {
+    /// Bytes remaining in the transfer
+    remaining: usize,
+    /// Current panel position
+    horiz: usize,
+    vert: usize,
+}
+
+impl_snapshot!(BlockXfer {
+    remaining,
+    horiz,
+    vert
+});
+
+/// iPod photo 220x176 colour LCD, driven through the PP502x's LCD2 interface.
+///
+/// Emulates both the LCD2 interface (a command port + a DMA-like block transfer
+/// engine) and the "type 0" LCD panel hanging off of it. Panel commands are
+/// sent as an index / value pair through the command port, and pixel data is
+/// streamed through the block engine.
+///
+/// The panel is mounted sideways: "vertical" lines correspond to screen rows,
+/// and "horizontal" positions count right-to-left across the screen. Pixels
+/// are sent big-endian, two per word.
+///
+/// The "type 1" panel (found in later iPod photo revisions) is not supported.
+pub struct PhotoLcd {
+    /// Register index waiting for a value
+    index: Option<u16>,
+    regs: [u16; 0x100],
+
+    block_ctrl: u32,
+    block_config: u32,
+    xfer: BlockXfer,
+
+    lcd: Arc<RwLock<Vec<u16>>>,
+}
+
+impl std::fmt::Debug for PhotoLcd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PhotoLcd")
+            .field("index", &self.index)
+            .field("regs", &self.regs)
+            .field("block_ctrl", &self.block_ctrl)
+            .field("block_config", &self.block_config)
+            .field("xfer", &self.xfer)
+            .field("lcd", &"[...]")
+            .finish()
+    }
+}
+
+impl Snapshot for PhotoLcd {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.index.save(w)?;
+        self.regs.save(w)?;
+        self.block_ctrl.save(w)?;
+        self.block_config.save(w)?;
+        self.xfer.save(w)?;
+        for px in self.lcd.write().unwrap().iter_mut() {
+            px.save(w)?;
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        self.index.load(r)?;
+        self.regs.load(r)?;
+        self.block_ctrl.load(r)?;
+        self.block_config.load(r)?;
+        self.xfer.load(r)?;
+        for px in self.lcd.write().unwrap().iter_mut() {
+            px.load(r)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for PhotoLcd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PhotoLcd {
+    pub fn new() -> PhotoLcd {
+        PhotoLcd {
+            index: None,
+            regs: [0; 0x100],
+
+            block_ctrl: 0,
+            block_config: 0,
+            xfer: BlockXfer::default(),
+
+            lcd: Arc::new(RwLock::new(vec![0; LCD_WIDTH * LCD_HEIGHT])),
+        }
+    }
+
+    /// Returns a callback to update the framebuffer.
+    ///
+    /// The callback accepts a minifb framebuffer, and returns the rendered
+    /// dimensions.
+    pub fn render_callback(&self) -> RenderCallback {
+        let lcd = Arc::clone(&self.lcd);
+
+        Box::new(move |buf: &mut Vec<u32>| -> (usize, usize) {
+            let lcd = lcd.read().unwrap();
+
+            let new_buf = lcd.iter().map(|&px| rgb565_to_argb(px));
+
+            // replace in-place
+            buf.splice(.., new_buf);
+
+            (LCD_WIDTH, LCD_HEIGHT)
+        })
+    }
+
+    fn write_port(&mut self, val: u32) -> MemResult<()> {
+        let kind = val.get_bits(flags::PORT_KIND);
+        if kind != flags::PORT_KIND_CMD && kind != flags::PORT_KIND_DATA {
+            return Err(ContractViolation {
+                msg: format!("invalid LCD2 port write: {:#010x?}", val),
+                severity: Error,
+                stub_val: None,
+            });
+        }
+
+        let val = val as u16;
+        match self.index.take() {
+            None => {
+                self.index = Some(val);
+                Ok(())
+            }
+            Some(idx) => match self.regs.get_mut(idx as usize) {
+                Some(reg) => {
+                    *reg = val;
+                    Ok(())
+                }
+                None => Err(ContractViolation {
+                    msg: format!("write to invalid LCD register {:#04x?}", idx),
+                    severity: Error,
+                    stub_val: None,
+                }),
+            },
+        }
+    }
+
+    fn start_xfer(&mut self) {
+        self.xfer = BlockXfer {
+            remaining: self.block_config.get_bits(flags::BLOCK_CONFIG_LEN) as usize + 1,
+            horiz: self.regs[reg::START_HORIZ] as usize,
+            vert: self.regs[reg::START_VERT] as usize,
+        };
+    }
+
+    fn put_pixel(&mut self, px: u16) {
+        let (start_h, end_h) = (
+            self.regs[reg::START_HORIZ] as usize,
+            self.regs[reg::END_HORIZ] as usize,
+        );
+        let (start_v, end_v) = (
+            self.regs[reg::START_VERT] as usize,
+            self.regs[reg::END_VERT] as usize,
+        );
+
+        let BlockXfer { horiz, vert, .. } = self.xfer;
+        if horiz < LCD_WIDTH && vert < LCD_HEIGHT {
+            let x = (LCD_WIDTH - 1) - horiz;
+            self.lcd.write().unwrap()[vert * LCD_WIDTH + x] = px.swap_bytes();
+        }
+
+        // advance horizontally, wrapping around to the next vertical line
+        if horiz == end_h {
+            self.xfer.horiz = start_h;
+            self.xfer.vert = match start_v <= end_v {
+                true => vert.wrapping_add(1),
+                false => vert.wrapping_sub(1),
+            };
+        } else {
+            self.xfer.horiz = match start_h <= end_h {
+                true => horiz.wrapping_add(1),
+                false => horiz.wrapping_sub(1),
+            };
+        }
+    }
+
+    fn write_block_data(&mut self, val: u32) -> MemResult<()> {
+        if self.xfer.remaining == 0 {
+            return Err(ContractViolation {
+                msg: "LCD2 block data written without an active transfer".into(),
+                severity: Error,
+                stub_val: None,
+            });
+        }
+
+        self.put_pixel(val as u16);
+        self.put_pixel((val >> 16) as u16);
+        self.xfer.remaining = self.xfer.remaining.saturating_sub(4);
+        Ok(())
+    }
+}
+
+impl Device for PhotoLcd {
+    fn kind(&self) -> &'static str {
+        "Photo LCD"
+    }
+
+    fn probe(&self, offset: u32) -> Probe {
+        let reg = match offset {
+            0x0c => "LCD2 Port",
+            0x20 => "LCD2 Block Ctrl",
+            0x24 => "LCD2 Block Config",
+            0x100..=0x1ff => "LCD2 Block Data",
+            _ => return Probe::Unmapped,
+        };
+
+        Probe::Register(reg)
+    }
+}
+
+impl Memory for PhotoLcd {
+    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+        match offset {
+            0x0c => Ok(0), // HACK: Emulated LCD is never busy
+            0x20 => {
+                let mut val = self.block_ctrl;
+                val.set_bit(flags::BLOCK_CTRL_TXOK, true);
+                val.set_bit(flags::BLOCK_CTRL_READY, self.xfer.remaining == 0);
+                Ok(val)
+            }
+            0x24 => Ok(self.block_config),
+            _ => Err(Unexpected),
+        }
+    }
+
+    fn w32(&mut self, offset: u32, val: u32) -> MemResult<()> {
+        match offset {
+            0x0c => self.write_port(val)?,
+            0x20 => {
+                self.block_ctrl = val;
+                match val {
+                    flags::BLOCK_CTRL_INIT => self.xfer = BlockXfer::default(),
+                    flags::BLOCK_CTRL_START => self.start_xfer(),
+                    _ => return Err(StubWrite(Warn, ())),
+                }
+            }
+            0x24 => self.block_config = val,
+            0x100..=0x1ff => self.write_block_data(val)?,
+            _ => return Err(Unexpected),
+        }
+
+        Ok(())
+    }
+}
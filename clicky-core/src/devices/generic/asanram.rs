@@ -1,9 +1,12 @@
 use crate::devices::prelude::*;
 
+use std::io::{self, Read, Write};
 use std::vec::Vec;
 
 use byteorder::{ByteOrder, LittleEndian};
 
+use crate::snapshot::{load_bitmap, load_bytes, save_bitmap, save_bytes, Snapshot};
+
 /// RAM device which raises ContractViolation warnings when reading from
 /// uninitialized memory.
 pub struct AsanRam {
@@ -12,6 +15,18 @@ pub struct AsanRam {
     only_warn_once: bool,
 }
 
+impl Snapshot for AsanRam {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        save_bytes(w, &self.mem)?;
+        save_bitmap(w, &self.initialized)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        load_bytes(r, &mut self.mem)?;
+        load_bitmap(r, &mut self.initialized)
+    }
+}
+
 impl std::fmt::Debug for AsanRam {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AsanRam")
@@ -153,4 +168,38 @@ impl Memory for AsanRam {
         LittleEndian::write_u32(&mut self.mem[offset..offset + 4], val);
         Ok(())
     }
+
+    // peeking at uninitialized memory isn't reported (nor does it mark the
+    // memory as initialized)
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        let offset = offset as usize;
+        let bytes = self.mem.get(offset..offset.checked_add(4)?)?;
+        Some(LittleEndian::read_u32(bytes))
+    }
+
+    fn peek8(&self, offset: u32) -> Option<u8> {
+        self.mem.get(offset as usize).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peek_bounds() {
+        let mut ram = AsanRam::new(8, false);
+        ram.bulk_write(0, &[0, 1, 2, 3]);
+
+        assert_eq!(ram.peek32(0), Some(0x0302_0100));
+        // uninitialized memory can be peeked at
+        assert_eq!(ram.peek32(4), Some(0x2d2d_2d2d));
+        assert_eq!(ram.peek32(5), None);
+        assert_eq!(ram.peek32(8), None);
+        assert_eq!(ram.peek32(u32::MAX), None);
+
+        assert_eq!(ram.peek8(7), Some(b'-'));
+        assert_eq!(ram.peek8(8), None);
+        assert_eq!(ram.peek8(u32::MAX), None);
+    }
 }
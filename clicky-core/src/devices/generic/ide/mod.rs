@@ -1,12 +1,13 @@
 use crate::devices::prelude::*;
 
 use std::convert::TryFrom;
-use std::io;
+use std::io::{self, Read, Write};
 
 use futures::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use num_enum::TryFromPrimitive;
 
 use crate::block::BlockDev;
+use crate::snapshot::{invalid_data, Snapshot};
 
 // TODO?: make num heads / num sectors configurable?
 const NUM_HEADS: usize = 16;
@@ -31,6 +32,19 @@ impl From<bool> for IdeIdx {
     }
 }
 
+impl Snapshot for IdeIdx {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        matches!(self, IdeIdx::IDE1).save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let mut is_ide1 = false;
+        is_ide1.load(r)?;
+        *self = IdeIdx::from(is_ide1);
+        Ok(())
+    }
+}
+
 impl std::fmt::Display for IdeIdx {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -95,12 +109,26 @@ enum IdeCmd {
 }
 
 mod iobuf {
+    use crate::snapshot::{load_bytes, save_bytes, Snapshot};
+
     // TODO: provide a zero-copy constructor which uses the `Read` trait
     pub struct IdeIoBuf {
         buf: [u8; 512],
         idx: usize,
     }
 
+    impl Snapshot for IdeIoBuf {
+        fn save(&mut self, w: &mut dyn std::io::Write) -> std::io::Result<()> {
+            save_bytes(w, &self.buf)?;
+            self.idx.save(w)
+        }
+
+        fn load(&mut self, r: &mut dyn std::io::Read) -> std::io::Result<()> {
+            load_bytes(r, &mut self.buf)?;
+            self.idx.load(r)
+        }
+    }
+
     impl std::fmt::Debug for IdeIoBuf {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
             f.debug_struct("IdeIoBuf")
@@ -156,6 +184,35 @@ enum IdeDriveState {
     WriteAsyncFlush,
 }
 
+impl Snapshot for IdeDriveState {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        use self::IdeDriveState::*;
+        let mut tag: u8 = match self {
+            Idle => 0,
+            ReadReady => 1,
+            ReadAsyncLoad => 2,
+            WriteReady => 3,
+            WriteAsyncFlush => 4,
+        };
+        tag.save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        use self::IdeDriveState::*;
+        let mut tag = 0u8;
+        tag.load(r)?;
+        *self = match tag {
+            0 => Idle,
+            1 => ReadReady,
+            2 => ReadAsyncLoad,
+            3 => WriteReady,
+            4 => WriteAsyncFlush,
+            _ => return Err(invalid_data("invalid IdeDriveState")),
+        };
+        Ok(())
+    }
+}
+
 /// Transfer Mode set by the "Set Transfer Mode" (0x03) subcommand of the "Set
 /// Features" command.
 #[derive(Debug)]
@@ -193,12 +250,41 @@ impl IdeTransferMode {
         }
     }
 
+    /// Inverse of `from_u8`. `Reserved` and `Invalid` map to an arbitrary
+    /// value in their respective ranges.
+    fn to_u8(&self) -> u8 {
+        use self::IdeTransferMode::*;
+
+        match *self {
+            Pio => 0x00,
+            PioNoIORDY => 0x01,
+            Invalid => 0x02,
+            PioFlowControl(mode) => 0x08 | mode,
+            DMASingleWord(mode) => 0x10 | mode,
+            DMAMultiWord(mode) => 0x20 | mode,
+            Reserved => 0x40,
+        }
+    }
+
     fn is_dma(&self) -> bool {
         use self::IdeTransferMode::*;
         matches!(self, DMASingleWord(..) | DMAMultiWord(..))
     }
 }
 
+impl Snapshot for IdeTransferMode {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.to_u8().save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let mut val = 0u8;
+        val.load(r)?;
+        *self = IdeTransferMode::from_u8(val);
+        Ok(())
+    }
+}
+
 #[derive(Debug, Default)]
 struct IdeRegs {
     error: u8,
@@ -217,6 +303,19 @@ struct IdeRegs {
     nein: bool,
 }
 
+impl_snapshot!(IdeRegs {
+    error,
+    feature,
+    sector_count,
+    lba0_sector_no,
+    lba1_cyl_lo,
+    lba2_cyl_hi,
+    lba3_dev_head,
+    status,
+    srst,
+    nein
+});
+
 /// Various IDE toggles and features.
 #[derive(Debug)]
 struct IdeDriveConfig {
@@ -227,6 +326,12 @@ struct IdeDriveConfig {
     transfer_mode: IdeTransferMode,
 }
 
+impl_snapshot!(IdeDriveConfig {
+    eightbit,
+    multi_sect,
+    transfer_mode
+});
+
 #[derive(Debug)]
 struct IdeDrive {
     blockdev: Box<dyn BlockDev>,
@@ -239,6 +344,58 @@ struct IdeDrive {
     iobuf: IdeIoBuf,
     reg: IdeRegs,
     cfg: IdeDriveConfig,
+
+    journal: Option<WriteJournal>,
+}
+
+/// Record of the sectors overwritten on a drive's blockdev, making it possible
+/// to undo writes (e.g: when rewinding the system to an earlier checkpoint).
+#[derive(Debug, Default)]
+struct WriteJournal {
+    /// Number of entries which have been pruned from the start of the journal
+    pruned: usize,
+    /// (offset, previous contents)
+    entries: Vec<(u64, Box<[u8]>)>,
+}
+
+impl WriteJournal {
+    fn pos(&self) -> usize {
+        self.pruned + self.entries.len()
+    }
+}
+
+/// Position in each drive's write journal (indexed by `IdeIdx`).
+pub type IdeJournalPos = [usize; 2];
+
+/// Only the drive's emulated state and current seek position are saved. The
+/// _contents_ of the underlying blockdev are not included.
+impl Snapshot for IdeDrive {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        let mut pos = futures_executor::block_on(self.blockdev.seek(io::SeekFrom::Current(0)))?;
+        pos.save(w)?;
+
+        self.irq.save(w)?;
+        self.dmarq.save(w)?;
+        self.state.save(w)?;
+        self.remaining_sectors.save(w)?;
+        self.iobuf.save(w)?;
+        self.reg.save(w)?;
+        self.cfg.save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let mut pos = 0u64;
+        pos.load(r)?;
+        futures_executor::block_on(self.blockdev.seek(io::SeekFrom::Start(pos)))?;
+
+        self.irq.load(r)?;
+        self.dmarq.load(r)?;
+        self.state.load(r)?;
+        self.remaining_sectors.load(r)?;
+        self.iobuf.load(r)?;
+        self.reg.load(r)?;
+        self.cfg.load(r)
+    }
 }
 
 impl IdeDrive {
@@ -261,9 +418,38 @@ impl IdeDrive {
                 multi_sect: 0,
                 transfer_mode: IdeTransferMode::Pio,
             },
+
+            journal: None,
         }
     }
 
+    /// Undo all journaled writes made after `pos`, restoring the blockdev's
+    /// current seek position afterwards.
+    fn rollback(&mut self, pos: usize) -> io::Result<()> {
+        let journal = match &mut self.journal {
+            Some(journal) => journal,
+            None => return Ok(()),
+        };
+
+        if pos < journal.pruned {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "write journal has been pruned past the requested position",
+            ));
+        }
+
+        let blockdev = &mut self.blockdev;
+        futures_executor::block_on(async {
+            let current = blockdev.seek(io::SeekFrom::Current(0)).await?;
+            for (offset, data) in journal.entries.drain(pos - journal.pruned..).rev() {
+                blockdev.seek(io::SeekFrom::Start(offset)).await?;
+                blockdev.write_all(&data).await?;
+            }
+            blockdev.seek(io::SeekFrom::Start(current)).await?;
+            Ok(())
+        })
+    }
+
     /// Handles LBA/CHS offset translation, returning the offset into the
     /// blockdev (in blocks, _not bytes_).
     ///
@@ -383,6 +569,15 @@ impl IdeDrive {
 
             // TODO: async this!
             futures_executor::block_on(async {
+                if let Some(journal) = &mut self.journal {
+                    // stash the sector's previous contents, so the write can be undone
+                    let offset = self.blockdev.seek(io::SeekFrom::Current(0)).await?;
+                    let mut data = Box::new([0; 512]);
+                    self.blockdev.read_exact(&mut data[..]).await?;
+                    self.blockdev.seek(io::SeekFrom::Start(offset)).await?;
+                    journal.entries.push((offset, data));
+                }
+
                 if let Err(e) = self.blockdev.write_all(self.iobuf.as_raw()).await {
                     // XXX: actually set error bits
                     return Err(e);
@@ -703,6 +898,37 @@ pub struct IdeController {
     ide1: Option<IdeDrive>,
 }
 
+impl Snapshot for IdeController {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.common_irq_line.save(w)?;
+        self.dmarq.save(w)?;
+        self.selected_device.save(w)?;
+        for ide in [&mut self.ide0, &mut self.ide1].iter_mut() {
+            ide.is_some().save(w)?;
+            if let Some(ide) = ide {
+                ide.save(w)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        self.common_irq_line.load(r)?;
+        self.dmarq.load(r)?;
+        self.selected_device.load(r)?;
+        for ide in [&mut self.ide0, &mut self.ide1].iter_mut() {
+            let mut attached = false;
+            attached.load(r)?;
+            match (attached, ide) {
+                (false, None) => {}
+                (true, Some(ide)) => ide.load(r)?,
+                _ => return Err(invalid_data("mismatch between attached IDE drives")),
+            }
+        }
+        Ok(())
+    }
+}
+
 /// It'd be nice if this was a method, but it makes borrowing other fields of
 /// (&mut self) as pain, since the borrow checker doesn't work across function
 /// boundaries.
@@ -770,6 +996,56 @@ impl IdeController {
         ide.take().map(|ide| ide.blockdev)
     }
 
+    fn drives_mut(&mut self) -> [(IdeIdx, Option<&mut IdeDrive>); 2] {
+        [
+            (IdeIdx::IDE0, self.ide0.as_mut()),
+            (IdeIdx::IDE1, self.ide1.as_mut()),
+        ]
+    }
+
+    /// Start (or stop) journaling writes to attached drives, which can then be
+    /// undone via `rollback`. Stopping discards the existing journal.
+    pub fn set_journaling(&mut self, enabled: bool) {
+        for (_, ide) in self.drives_mut() {
+            if let Some(ide) = ide {
+                ide.journal = enabled.then(WriteJournal::default);
+            }
+        }
+    }
+
+    /// Return the current position in each drive's write journal.
+    pub fn journal_pos(&self) -> IdeJournalPos {
+        let pos = |ide: &Option<IdeDrive>| {
+            ide.as_ref()
+                .and_then(|ide| ide.journal.as_ref())
+                .map_or(0, |journal| journal.pos())
+        };
+        [pos(&self.ide0), pos(&self.ide1)]
+    }
+
+    /// Undo all writes made to attached drives since the journal was at `pos`.
+    pub fn rollback(&mut self, pos: IdeJournalPos) -> io::Result<()> {
+        for (idx, ide) in self.drives_mut() {
+            if let Some(ide) = ide {
+                ide.rollback(pos[idx as usize])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Discard journal entries from before `pos`, as they'll never be rolled
+    /// back.
+    pub fn prune_journal(&mut self, pos: IdeJournalPos) {
+        for (idx, ide) in self.drives_mut() {
+            if let Some(journal) = ide.and_then(|ide| ide.journal.as_mut()) {
+                let n = pos[idx as usize].saturating_sub(journal.pruned);
+                let n = n.min(journal.entries.len());
+                journal.entries.drain(..n);
+                journal.pruned += n;
+            }
+        }
+    }
+
     /// Check if an IDE drive is currently asserting an IRQ.
     pub fn irq_state(&self, idx: IdeIdx) -> bool {
         let ide = match idx {
@@ -1,14 +1,27 @@
 use crate::devices::prelude::*;
 
+use std::io::{self, Read, Write};
 use std::vec::Vec;
 
 use byteorder::{ByteOrder, LittleEndian};
 
+use crate::snapshot::{load_bytes, save_bytes, Snapshot};
+
 /// Basic RAM device.
 pub struct Ram {
     mem: Vec<u8>,
 }
 
+impl Snapshot for Ram {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        save_bytes(w, &self.mem)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        load_bytes(r, &mut self.mem)
+    }
+}
+
 impl std::fmt::Debug for Ram {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Ram").field("mem", &"[..]").finish()
@@ -90,4 +103,35 @@ impl Memory for Ram {
         LittleEndian::write_u32(&mut self.mem[offset..], val);
         Ok(())
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        let offset = offset as usize;
+        let bytes = self.mem.get(offset..offset.checked_add(4)?)?;
+        Some(LittleEndian::read_u32(bytes))
+    }
+
+    fn peek8(&self, offset: u32) -> Option<u8> {
+        self.mem.get(offset as usize).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peek_bounds() {
+        let mut ram = Ram::new(8);
+        ram.bulk_write(0, &[0, 1, 2, 3, 4, 5, 6, 7]);
+
+        assert_eq!(ram.peek32(0), Some(0x0302_0100));
+        assert_eq!(ram.peek32(4), Some(0x0706_0504));
+        assert_eq!(ram.peek32(5), None);
+        assert_eq!(ram.peek32(8), None);
+        assert_eq!(ram.peek32(u32::MAX), None);
+
+        assert_eq!(ram.peek8(7), Some(7));
+        assert_eq!(ram.peek8(8), None);
+        assert_eq!(ram.peek8(u32::MAX), None);
+    }
 }
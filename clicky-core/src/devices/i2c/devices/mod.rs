@@ -1,3 +1,5 @@
 mod pcf5060x;
+mod wmcodec;
 
 pub use pcf5060x::*;
+pub use wmcodec::*;
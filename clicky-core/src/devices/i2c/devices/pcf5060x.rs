@@ -2,9 +2,15 @@ use crate::devices::i2c::prelude::*;
 
 use std::convert::TryFrom;
 
-use chrono::{Datelike, Local, Timelike};
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, Timelike};
 use num_enum::TryFromPrimitive;
 
+use crate::clock::Clock;
+use crate::signal;
+
+/// OOCC1 - Go to standby (i.e: power-off)
+const OOCC1_GOSTDBY: u8 = 1 << 0;
+
 /// PCF5060x - Controller for Power Supply and Battery Management + RTC
 #[derive(Debug)]
 pub struct Pcf5060x {
@@ -13,12 +19,22 @@ pub struct Pcf5060x {
     inner: Pcf5060xImpl,
 }
 
+impl_snapshot!(Pcf5060x {
+    last_op_was_write,
+    register,
+    inner
+});
+
 impl Pcf5060x {
-    pub fn new() -> Pcf5060x {
+    /// When using a virtual clock, the RTC counts up from midnight on
+    /// 2000-01-01 instead of reporting the host's local time.
+    ///
+    /// `power_off` is set when the guest requests to enter standby mode.
+    pub fn new(clock: Clock, power_off: signal::Trigger) -> Pcf5060x {
         Pcf5060x {
             last_op_was_write: false,
             register: None,
-            inner: Pcf5060xImpl::new(),
+            inner: Pcf5060xImpl::new(clock, power_off),
         }
     }
 }
@@ -210,6 +226,8 @@ enum Reg {
 
 #[derive(Debug)]
 struct Pcf5060xImpl {
+    clock: Clock,
+    power_off: signal::Trigger,
     int_mask: [u8; 3],
     oocc1: u8,
     oocc2: u8,
@@ -221,9 +239,23 @@ struct Pcf5060xImpl {
     bvmc: u8,
 }
 
+impl_snapshot!(Pcf5060xImpl {
+    int_mask,
+    oocc1,
+    oocc2,
+    lpregc1,
+    dxregc1,
+    dcdcx,
+    mbcc2,
+    rtc_alarm,
+    bvmc
+});
+
 impl Pcf5060xImpl {
-    fn new() -> Pcf5060xImpl {
+    fn new(clock: Clock, power_off: signal::Trigger) -> Pcf5060xImpl {
         Pcf5060xImpl {
+            clock,
+            power_off,
             int_mask: [0; 3],
             oocc1: 0,
             oocc2: 0,
@@ -241,7 +273,17 @@ impl Pcf5060xImpl {
             ((x / 10) << 4) | (x % 10)
         }
 
-        let now = Local::now();
+        let now: NaiveDateTime = if self.clock.is_virtual() {
+            let elapsed = chrono::Duration::from_std(self.clock.now())
+                .map_err(|_| Fatal("virtual clock out of range".into()))?;
+            // the virtual clock starts at midnight on 2000-01-01
+            let epoch = NaiveDate::from_ymd_opt(2000, 1, 1)
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .expect("virtual clock epoch is a valid date");
+            epoch + elapsed
+        } else {
+            Local::now().naive_local()
+        };
 
         use Reg::*;
         let val = match reg {
@@ -326,7 +368,17 @@ impl Pcf5060xImpl {
         match reg {
             ID_____ => Err(InvalidAccess),
             // On/Off control (OOC)
-            OOCC1__ => Err(StubWrite(Info, self.oocc1 = data)),
+            OOCC1__ => {
+                self.oocc1 = data;
+                if data & OOCC1_GOSTDBY != 0 {
+                    // the actual wake-up sources (OOCC1 bits 4..=6) don't matter,
+                    // as nothing can "press the power button" on an emulated iPod
+                    info!("PMU entering standby mode (OOCC1 = {:#04x?})", data);
+                    self.power_off.set();
+                    return Ok(());
+                }
+                Err(StubWrite(Info, ()))
+            }
             OOCC2__ => Err(StubWrite(Info, self.oocc2 = data)),
             // low drop-out linear regulators
             LPREGC1 => Ok(self.lpregc1 = data),
@@ -0,0 +1,339 @@
+use crate::devices::i2c::prelude::*;
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::snapshot::Snapshot;
+
+/// Supported Wolfson codec models.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WmCodecKind {
+    /// Used in the iPod mini / iPod 3g
+    Wm8731,
+    /// Used in the iPod 4g / iPod photo
+    Wm8975,
+    /// Used in the iPod 5g
+    Wm8758,
+}
+
+const SAMPLE_RATE_REG: usize = 0x08;
+const RESET_REG: usize = 0x0f;
+
+#[allow(dead_code)]
+mod wm8758 {
+    type Range = std::ops::RangeInclusive<usize>;
+
+    pub const RESET_REG: usize = 0x00;
+    pub const CLOCKING_REG: usize = 0x06;
+    pub const ADDITIONAL_CTRL_REG: usize = 0x07;
+    pub const PLLN_REG: usize = 0x24;
+    pub const PLLK1_REG: usize = 0x25;
+    pub const PLLK2_REG: usize = 0x26;
+    pub const PLLK3_REG: usize = 0x27;
+
+    /* Clock Gen Control (R6) */
+    pub const CLKSEL: usize = 8;
+    pub const MCLKDIV: Range = 5..=7;
+
+    /* PLL N (R36) */
+    pub const PLLPRESCALE: usize = 4;
+    pub const PLLN: Range = 0..=3;
+
+    /// Standard sample rates the computed rate gets snapped to.
+    pub const STANDARD_RATES: [u32; 9] =
+        [8000, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000];
+}
+
+/// Frequency of the MCLK fed to the codec on PP502x based iPods.
+const MCLK: f64 = 12_000_000.0;
+
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+/// Wolfson WM8731 / WM8975 / WM8758 audio codec.
+///
+/// The codecs are write-only, and use a 7-bit register address + 9-bit data
+/// format, sent as two bytes: `[(reg << 1) | (data >> 8), data & 0xff]`.
+///
+/// At the moment, only the sample rate is taken into account when emulating
+/// audio output. Volume, mute, and de-emphasis settings are tracked, but not
+/// applied to the output samples.
+#[derive(Debug)]
+pub struct WmCodec {
+    kind: WmCodecKind,
+    sample_rate: Arc<AtomicU32>,
+
+    data: [u8; 2],
+    data_len: usize,
+    regs: [u16; 64],
+}
+
+impl Snapshot for WmCodec {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.data.save(w)?;
+        self.data_len.save(w)?;
+        self.regs.save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        self.data.load(r)?;
+        self.data_len.load(r)?;
+        self.regs.load(r)?;
+        self.update_sample_rate();
+        Ok(())
+    }
+}
+
+impl WmCodec {
+    pub fn new(kind: WmCodecKind) -> WmCodec {
+        WmCodec {
+            kind,
+            sample_rate: Arc::new(DEFAULT_SAMPLE_RATE.into()),
+
+            data: [0; 2],
+            data_len: 0,
+            regs: [0; 64],
+        }
+    }
+
+    /// Returns a handle to the codec's current sample rate (in Hz).
+    pub fn sample_rate(&self) -> Arc<AtomicU32> {
+        Arc::clone(&self.sample_rate)
+    }
+
+    fn update_sample_rate(&mut self) {
+        if self.kind == WmCodecKind::Wm8758 {
+            return self.update_sample_rate_wm8758();
+        }
+
+        let val = self.regs[SAMPLE_RATE_REG];
+
+        // On both the WM8731 and the WM8975, bit 0 selects "USB mode", and bits
+        // 1..=5 select the sample rate (on the WM8731, bit 1 is BOSR, and
+        // bits 2..=5 are SR[3:0], which ends up being equivalent).
+        //
+        // XXX: this assumes the codec is clocked off a 12MHz MCLK in USB mode,
+        // as is the case on all PP502x based iPods. Normal mode is treated
+        // identically.
+        let rate = match val.get_bits(1..=5) {
+            0b00000 => 48000,
+            0b00010 => 8000,
+            0b01100 => 32000,
+            0b01110 => 96000,
+            0b10001 => 44100,
+            0b10011 => 8018,
+            0b10111 => 22050,
+            0b11001 => 11025,
+            0b11111 => 88200,
+            sr => {
+                warn!(
+                    "{:?}: unsupported sample rate config {:#07b}",
+                    self.kind, sr
+                );
+                return;
+            }
+        };
+
+        self.sample_rate.store(rate, Ordering::Relaxed);
+    }
+
+    /// Unlike the other codecs, the WM8758 derives its sample rate from MCLK
+    /// (optionally fed through a PLL) and a clock divider, so the rate has to
+    /// be worked out from first principles.
+    fn update_sample_rate_wm8758(&mut self) {
+        let clocking = self.regs[wm8758::CLOCKING_REG];
+
+        let mclk = if clocking.get_bit(wm8758::CLKSEL) {
+            let plln = self.regs[wm8758::PLLN_REG];
+            let k = (self.regs[wm8758::PLLK1_REG].get_bits(0..=5) as u32) << 18
+                | (self.regs[wm8758::PLLK2_REG] as u32) << 9
+                | self.regs[wm8758::PLLK3_REG] as u32;
+            let n = plln.get_bits(wm8758::PLLN) as f64 + k as f64 / (1 << 24) as f64;
+
+            let mut pll_in = MCLK;
+            if plln.get_bit(wm8758::PLLPRESCALE) {
+                pll_in /= 2.0;
+            }
+            // the PLL output is fed through a fixed divide-by-4
+            pll_in * n / 4.0
+        } else {
+            MCLK
+        };
+
+        let mclkdiv = match clocking.get_bits(wm8758::MCLKDIV) {
+            0 => 1.0,
+            1 => 1.5,
+            2 => 2.0,
+            3 => 3.0,
+            4 => 4.0,
+            5 => 6.0,
+            6 => 8.0,
+            7 => 12.0,
+            _ => unreachable!(),
+        };
+
+        let rate = mclk / mclkdiv / 256.0;
+        if rate < 1.0 {
+            // PLL hasn't been configured yet
+            return;
+        }
+
+        // the PLL can't hit standard rates exactly, so snap to the nearest one
+        let rate = *wm8758::STANDARD_RATES
+            .iter()
+            .min_by_key(|&&r| (r as f64 - rate).abs() as u32)
+            .unwrap();
+
+        self.sample_rate.store(rate, Ordering::Relaxed);
+    }
+
+    fn write_reg(&mut self, reg: usize, val: u16) -> MemResult<()> {
+        let max_reg = match self.kind {
+            WmCodecKind::Wm8731 => 0x0f,
+            WmCodecKind::Wm8975 => 0x2a,
+            WmCodecKind::Wm8758 => 0x3d,
+        };
+
+        if reg > max_reg {
+            return Err(ContractViolation {
+                msg: format!("write to invalid register {:#04x}", reg),
+                severity: Error,
+                stub_val: None,
+            });
+        }
+
+        match (self.kind, reg) {
+            (WmCodecKind::Wm8758, wm8758::RESET_REG) => {
+                self.regs = [0; 64];
+                self.sample_rate
+                    .store(DEFAULT_SAMPLE_RATE, Ordering::Relaxed);
+            }
+            (
+                WmCodecKind::Wm8758,
+                wm8758::CLOCKING_REG
+                | wm8758::ADDITIONAL_CTRL_REG
+                | wm8758::PLLN_REG..=wm8758::PLLK3_REG,
+            ) => {
+                self.regs[reg] = val;
+                self.update_sample_rate();
+            }
+            (WmCodecKind::Wm8758, _) => self.regs[reg] = val,
+            (_, RESET_REG) => {
+                self.regs = [0; 64];
+                self.sample_rate
+                    .store(DEFAULT_SAMPLE_RATE, Ordering::Relaxed);
+            }
+            (_, SAMPLE_RATE_REG) => {
+                self.regs[reg] = val;
+                self.update_sample_rate();
+            }
+            _ => self.regs[reg] = val,
+        }
+
+        Ok(())
+    }
+}
+
+impl Device for WmCodec {
+    fn kind(&self) -> &'static str {
+        match self.kind {
+            WmCodecKind::Wm8731 => "WM8731",
+            WmCodecKind::Wm8975 => "WM8975",
+            WmCodecKind::Wm8758 => "WM8758",
+        }
+    }
+
+    fn probe(&self, _offset: u32) -> Probe {
+        let reg = match self.data_len {
+            0 => return Probe::Register("<no register selected>"),
+            _ => self.data[0] >> 1,
+        };
+
+        let reg = match (self.kind, reg) {
+            (WmCodecKind::Wm8758, 0x00) => "Software Reset",
+            (WmCodecKind::Wm8758, 0x01) => "Power Management 1",
+            (WmCodecKind::Wm8758, 0x02) => "Power Management 2",
+            (WmCodecKind::Wm8758, 0x03) => "Power Management 3",
+            (WmCodecKind::Wm8758, 0x04) => "Audio Interface",
+            (WmCodecKind::Wm8758, 0x06) => "Clock Gen Control",
+            (WmCodecKind::Wm8758, 0x07) => "Additional Control",
+            (WmCodecKind::Wm8758, 0x0a) => "DAC Control",
+            (WmCodecKind::Wm8758, 0x0b) => "Left DAC Digital Vol",
+            (WmCodecKind::Wm8758, 0x0c) => "Right DAC Digital Vol",
+            (WmCodecKind::Wm8758, 0x24) => "PLL N",
+            (WmCodecKind::Wm8758, 0x25) => "PLL K 1",
+            (WmCodecKind::Wm8758, 0x26) => "PLL K 2",
+            (WmCodecKind::Wm8758, 0x27) => "PLL K 3",
+            (WmCodecKind::Wm8758, 0x34) => "LOUT1 Volume",
+            (WmCodecKind::Wm8758, 0x35) => "ROUT1 Volume",
+            (WmCodecKind::Wm8758, 0x36) => "LOUT2 Volume",
+            (WmCodecKind::Wm8758, 0x37) => "ROUT2 Volume",
+            (WmCodecKind::Wm8758, _) => "<unknown>",
+            (_, 0x00) => "Left Input Volume",
+            (_, 0x01) => "Right Input Volume",
+            (WmCodecKind::Wm8731, 0x02) => "Left Headphone Out",
+            (WmCodecKind::Wm8731, 0x03) => "Right Headphone Out",
+            (WmCodecKind::Wm8731, 0x04) => "Analogue Audio Path Control",
+            (WmCodecKind::Wm8731, 0x05) => "Digital Audio Path Control",
+            (WmCodecKind::Wm8731, 0x06) => "Power Down Control",
+            (WmCodecKind::Wm8731, 0x09) => "Active Control",
+            (WmCodecKind::Wm8975, 0x02) => "LOUT1 Volume",
+            (WmCodecKind::Wm8975, 0x03) => "ROUT1 Volume",
+            (WmCodecKind::Wm8975, 0x05) => "ADC and DAC Control",
+            (WmCodecKind::Wm8975, 0x0a) => "Left DAC Volume",
+            (WmCodecKind::Wm8975, 0x0b) => "Right DAC Volume",
+            (WmCodecKind::Wm8975, 0x0c) => "Bass Control",
+            (WmCodecKind::Wm8975, 0x0d) => "Treble Control",
+            (WmCodecKind::Wm8975, 0x17) => "Additional Control 1",
+            (WmCodecKind::Wm8975, 0x18) => "Additional Control 2",
+            (WmCodecKind::Wm8975, 0x19) => "Power Management 1",
+            (WmCodecKind::Wm8975, 0x1a) => "Power Management 2",
+            (WmCodecKind::Wm8975, 0x22) => "Left Out Mix 1",
+            (WmCodecKind::Wm8975, 0x25) => "Right Out Mix 2",
+            (WmCodecKind::Wm8975, 0x28) => "LOUT2 Volume",
+            (WmCodecKind::Wm8975, 0x29) => "ROUT2 Volume",
+            (_, 0x07) => "Digital Audio Interface Format",
+            (_, 0x08) => "Sampling Control",
+            (_, 0x0f) => "Reset",
+            _ => "<unknown>",
+        };
+
+        Probe::Register(reg)
+    }
+}
+
+impl I2CDevice for WmCodec {
+    fn read(&mut self) -> MemResult<u8> {
+        // the codec's control interface is write-only
+        Err(InvalidAccess)
+    }
+
+    fn write(&mut self, data: u8) -> MemResult<()> {
+        if self.data_len == 2 {
+            return Err(ContractViolation {
+                msg: "wrote more than 2 bytes in a single transaction".into(),
+                severity: Error,
+                stub_val: None,
+            });
+        }
+
+        self.data[self.data_len] = data;
+        self.data_len += 1;
+        Ok(())
+    }
+
+    fn write_done(&mut self) -> MemResult<()> {
+        let data_len = std::mem::replace(&mut self.data_len, 0);
+        if data_len != 2 {
+            return Err(ContractViolation {
+                msg: format!("expected a 2 byte transaction, got {} bytes", data_len),
+                severity: Error,
+                stub_val: None,
+            });
+        }
+
+        let reg = (self.data[0] >> 1) as usize;
+        let val = ((self.data[0] as u16 & 1) << 8) | self.data[1] as u16;
+        self.write_reg(reg, val)
+    }
+}
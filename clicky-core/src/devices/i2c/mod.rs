@@ -1,5 +1,7 @@
 use crate::devices::prelude::*;
 
+use crate::snapshot::Snapshot;
+
 pub mod devices;
 pub mod prelude;
 
@@ -9,7 +11,10 @@ pub mod prelude;
 /// different `probe` behavior. Instead of using the provided `offset`, they
 /// should instead return the name of whatever internal register was previously
 /// selected.
-pub trait I2CDevice: Device {
+///
+/// i2c devices must also implement `Snapshot`, as they are saved / restored
+/// alongside their parent controller.
+pub trait I2CDevice: Device + Snapshot {
     /// Read an 8-bit value from the device.
     fn read(&mut self) -> MemResult<u8>;
     /// Write an 8-bit value from the device.
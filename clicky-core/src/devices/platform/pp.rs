@@ -40,10 +40,29 @@ pub use ppcon::*;
 pub use serial::*;
 pub use usec_timer::*;
 
+pub mod pp5002;
+
 pub mod common {
-    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    use std::io::{self, Read, Write};
+
+    use crate::snapshot::{invalid_data, Snapshot};
+
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
     pub enum CpuId {
         Cpu,
         Cop,
     }
+
+    impl Snapshot for CpuId {
+        fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+            (*self == CpuId::Cop).save(w)
+        }
+
+        fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+            let mut is_cop = false;
+            is_cop.load(r).map_err(|_| invalid_data("invalid CpuId"))?;
+            *self = if is_cop { CpuId::Cop } else { CpuId::Cpu };
+            Ok(())
+        }
+    }
 }
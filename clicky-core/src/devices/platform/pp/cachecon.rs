@@ -9,6 +9,11 @@ pub struct CacheCon {
     cache_ctrl_enable: bool,
 }
 
+impl_snapshot!(CacheCon {
+    local_evt,
+    cache_ctrl_enable
+});
+
 impl CacheCon {
     pub fn new() -> CacheCon {
         CacheCon {
@@ -35,8 +40,8 @@ impl Device for CacheCon {
     }
 }
 
-impl Memory for CacheCon {
-    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+impl CacheCon {
+    fn read32(&self, offset: u32) -> MemResult<u32> {
         match offset {
             0x00 => {
                 let val = *0u32
@@ -49,6 +54,12 @@ impl Memory for CacheCon {
             _ => Err(Unexpected),
         }
     }
+}
+
+impl Memory for CacheCon {
+    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+        self.read32(offset)
+    }
 
     fn w32(&mut self, offset: u32, val: u32) -> MemResult<()> {
         match offset {
@@ -65,4 +76,8 @@ impl Memory for CacheCon {
             _ => Err(Unexpected),
         }
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        peek_result(self.read32(offset))
+    }
 }
@@ -1,23 +1,80 @@
 use crate::devices::prelude::*;
 
+use std::io::{self, Read, Write};
 use std::time::Duration;
 
 use futures::future::{self, Either};
 use pin_utils::pin_mut;
-use relativity::{Instant, Timeout};
+use relativity::Timeout;
 
+use crate::clock::Clock;
 use crate::signal::irq;
+use crate::snapshot::{invalid_data, Snapshot};
 
-#[derive(Debug, Copy, Clone)]
+/// Interrupter deadlines are expressed in terms of the emulated [Clock].
+#[derive(Debug, Default, Copy, Clone)]
 enum InterrupterState {
-    Oneshot { next: Instant },
-    Repeating { next: Instant, period: Duration },
+    Oneshot {
+        next: Duration,
+    },
+    Repeating {
+        next: Duration,
+        period: Duration,
+    },
+    #[default]
     Disabled,
 }
 
+impl InterrupterState {
+    /// Returns the state with any already-elapsed deadlines advanced past
+    /// `now`, mirroring what the interrupter task would have done.
+    fn catch_up(self, now: Duration) -> InterrupterState {
+        match self {
+            InterrupterState::Oneshot { next } if next < now => InterrupterState::Disabled,
+            InterrupterState::Repeating { next, period } if next < now => {
+                let period_ns = period.as_nanos().max(1);
+                let late_ns = (now - next).as_nanos() % period_ns;
+                InterrupterState::Repeating {
+                    next: now + Duration::from_nanos((period_ns - late_ns) as u64),
+                    period,
+                }
+            }
+            state => state,
+        }
+    }
+}
+
+impl Snapshot for InterrupterState {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        let (mut tag, mut next, mut period) = match *self {
+            InterrupterState::Disabled => (0u8, Duration::default(), Duration::default()),
+            InterrupterState::Oneshot { next } => (1, next, Duration::default()),
+            InterrupterState::Repeating { next, period } => (2, next, period),
+        };
+        tag.save(w)?;
+        next.save(w)?;
+        period.save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let (mut tag, mut next, mut period) = (0u8, Duration::default(), Duration::default());
+        tag.load(r)?;
+        next.load(r)?;
+        period.load(r)?;
+        *self = match tag {
+            0 => InterrupterState::Disabled,
+            1 => InterrupterState::Oneshot { next },
+            2 => InterrupterState::Repeating { next, period },
+            _ => return Err(invalid_data("invalid InterrupterState")),
+        };
+        Ok(())
+    }
+}
+
 async fn interrupter_task(
     label: &'static str,
     mut irq: irq::Sender,
+    clock: Clock,
     msg_rx: async_channel::Receiver<InterrupterState>,
 ) {
     let mut state = InterrupterState::Disabled;
@@ -28,11 +85,11 @@ async fn interrupter_task(
 
             // If the period is set low enough, and there's a lag spike which causes the interrupter
             // task to not be polled for a while, it's possible for `next` to have already passed,
-            // resulting in a panic when doing a naive `next - Instant::now()`. In that case, we
+            // resulting in a panic when doing a naive `next - clock.now()`. In that case, we
             // simply play "catch up" and immediately fire the interrupt.
             InterrupterState::Oneshot { next } | InterrupterState::Repeating { next, .. } => {
                 Some({
-                    let now = Instant::now();
+                    let now = clock.now();
                     if next < now {
                         warn!("Timer{} can't keep up!", label);
                         Duration::from_secs(0)
@@ -77,11 +134,16 @@ async fn interrupter_task(
 }
 
 /// Configurable microsecond timer used on the PP5020.
+///
+/// When using a wall clock, IRQs are fired from an async task running on the
+/// system executor. When using a virtual clock, the system must periodically
+/// call `tick` instead.
 // XXX: check hardware if the timer is incrementing or decrementing
 #[derive(Debug)]
 pub struct CfgTimer {
     label: &'static str,
     irq: irq::Sender,
+    clock: Clock,
 
     counter: u32,
     unknown_cfg: bool,
@@ -90,22 +152,70 @@ pub struct CfgTimer {
 
     val: u32,
 
-    last: Instant,
+    last: Duration,
     last_interrupter_state: Option<InterrupterState>,
-    interrupter_tx: async_channel::Sender<InterrupterState>,
+    interrupter_tx: Option<async_channel::Sender<InterrupterState>>,
+}
+
+impl Snapshot for CfgTimer {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        // the interrupter task doesn't report back when it advances a repeating
+        // timer, so bring the deadline up to date before saving it.
+        let now = self.clock.now();
+        let mut state = self.last_interrupter_state.map(|s| s.catch_up(now));
+
+        self.irq.save(w)?;
+        self.counter.save(w)?;
+        self.unknown_cfg.save(w)?;
+        self.repeat.save(w)?;
+        self.enable.save(w)?;
+        self.val.save(w)?;
+        self.last.save(w)?;
+        state.save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        self.irq.load(r)?;
+        self.counter.load(r)?;
+        self.unknown_cfg.load(r)?;
+        self.repeat.load(r)?;
+        self.enable.load(r)?;
+        self.val.load(r)?;
+        self.last.load(r)?;
+        self.last_interrupter_state.load(r)?;
+
+        if let Some(interrupter_tx) = &self.interrupter_tx {
+            let state = self.last_interrupter_state.unwrap_or_default();
+            interrupter_tx
+                .try_send(state)
+                .map_err(|e| invalid_data(format!("couldn't restore timer state: {}", e)))?;
+        }
+        Ok(())
+    }
 }
 
 impl CfgTimer {
-    pub fn new(label: &'static str, irq: irq::Sender, task_spawner: Spawner) -> CfgTimer {
-        // TODO: this should probably be bounded, right?
-        let (interrupter_tx, interrupter_rx) = async_channel::unbounded();
+    pub fn new(
+        label: &'static str,
+        irq: irq::Sender,
+        clock: Clock,
+        task_spawner: Spawner,
+    ) -> CfgTimer {
+        let interrupter_tx = if clock.is_virtual() {
+            None
+        } else {
+            // TODO: this should probably be bounded, right?
+            let (interrupter_tx, interrupter_rx) = async_channel::unbounded();
+
+            task_spawner
+                .spawn({
+                    let irq_clone = irq.clone();
+                    interrupter_task(label, irq_clone, clock.clone(), interrupter_rx)
+                })
+                .expect("failed to spawn timer task");
 
-        task_spawner
-            .spawn({
-                let irq_clone = irq.clone();
-                interrupter_task(label, irq_clone, interrupter_rx)
-            })
-            .expect("failed to spawn timer task");
+            Some(interrupter_tx)
+        };
 
         CfgTimer {
             label,
@@ -118,24 +228,81 @@ impl CfgTimer {
 
             val: 0,
 
-            last: Instant::now(),
+            last: clock.now(),
             last_interrupter_state: None,
             interrupter_tx,
+
+            clock,
         }
     }
 
+    /// Fire the timer's IRQ if its deadline has passed. Only has an effect
+    /// when using a virtual clock.
+    pub fn tick(&mut self) {
+        if self.interrupter_tx.is_some() {
+            return;
+        }
+
+        let now = self.clock.now();
+        let state = match self.last_interrupter_state {
+            Some(ref mut state) => state,
+            None => return,
+        };
+
+        match *state {
+            InterrupterState::Disabled => {}
+            InterrupterState::Oneshot { next } => {
+                if next <= now {
+                    self.irq.assert();
+                    *state = InterrupterState::Disabled;
+                }
+            }
+            InterrupterState::Repeating { next, period } => {
+                if next <= now {
+                    self.irq.assert();
+                    *state = InterrupterState::Repeating {
+                        next: next + period,
+                        period,
+                    }
+                    .catch_up(now);
+                }
+            }
+        }
+    }
+
+    /// Returns the time at which the timer will next fire its IRQ (if it's
+    /// armed).
+    pub fn next_deadline(&self) -> Option<Duration> {
+        match self.last_interrupter_state?.catch_up(self.clock.now()) {
+            InterrupterState::Disabled => None,
+            InterrupterState::Oneshot { next } | InterrupterState::Repeating { next, .. } => {
+                Some(next)
+            }
+        }
+    }
+
+    fn set_interrupter_state(&mut self, new_state: InterrupterState) -> MemResult<()> {
+        self.last_interrupter_state = Some(new_state);
+        if let Some(interrupter_tx) = &self.interrupter_tx {
+            interrupter_tx
+                .try_send(new_state)
+                .map_err(|e| Fatal(format!("couldn't set new timer state: {}", e)))?
+        }
+        Ok(())
+    }
+
     fn update_regs(&mut self) -> MemResult<()> {
         // XXX: this update code doesn't reference the counter _at all_.
         // As it stands, this code is identical to the regular, non-IRQ usec_timer.
         // Need to experiment with the actual hardware to determine proper behavior...
 
-        let now = Instant::now();
-        let elapsed = now.duration_since(self.last);
+        let elapsed = self.clock.now().saturating_sub(self.last);
         let elapsed_as_micros = elapsed.as_micros() as u32;
         // Reading the timer value in a tight loop could result in a delta time of 0
         if elapsed_as_micros != 0 {
             self.val = self.val.wrapping_sub(elapsed_as_micros);
-            self.last = now;
+            // don't discard any sub-microsecond remainder
+            self.last += Duration::from_micros(elapsed_as_micros as u64);
         }
 
         Ok(())
@@ -195,15 +362,11 @@ impl Memory for CfgTimer {
                 let new_state = {
                     if self.enable && !prev_enable {
                         let period = Duration::from_micros(self.counter as _);
+                        let next = self.clock.now() + period;
                         Some(if self.repeat {
-                            InterrupterState::Repeating {
-                                next: Instant::now() + period,
-                                period,
-                            }
+                            InterrupterState::Repeating { next, period }
                         } else {
-                            InterrupterState::Oneshot {
-                                next: Instant::now() + period,
-                            }
+                            InterrupterState::Oneshot { next }
                         })
                     } else if !self.enable {
                         Some(InterrupterState::Disabled)
@@ -213,10 +376,7 @@ impl Memory for CfgTimer {
                 };
 
                 if let Some(new_state) = new_state {
-                    self.last_interrupter_state = Some(new_state);
-                    self.interrupter_tx
-                        .try_send(new_state)
-                        .map_err(|e| Fatal(format!("couldn't set new timer state: {}", e)))?
+                    self.set_interrupter_state(new_state)?
                 }
             }),
             0x4 => Err(InvalidAccess),
@@ -1,9 +1,13 @@
 use crate::devices::prelude::*;
 
+use std::io::{self, Read, Write};
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
+use crate::clock::Clock;
+use crate::snapshot::{invalid_data, Snapshot};
+
 pub use super::common::CpuId;
 
 #[allow(dead_code)]
@@ -56,24 +60,99 @@ impl CounterSource {
 }
 
 /// PP5020 CPU controller
+///
+/// When using a wall clock, `PROC_WAIT_CNT` countdowns are run as async tasks
+/// on the system executor. When using a virtual clock, the system must
+/// periodically call `tick` instead.
 #[derive(Debug)]
 pub struct CpuCon {
     task_spawner: Spawner,
+    clock: Clock,
 
     cpuctl: Arc<AtomicU32>,
     copctl: Arc<AtomicU32>,
+    /// Pending `PROC_WAIT_CNT` wake-up times (only acted upon by `tick` when
+    /// using a virtual clock)
+    wake_deadline: [Option<Duration>; 2],
+}
+
+/// When using a wall clock, any in-flight `PROC_WAIT_CNT` countdowns are
+/// restarted from scratch when the snapshot is loaded.
+impl Snapshot for CpuCon {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.cpuctl.load(Ordering::SeqCst).save(w)?;
+        self.copctl.load(Ordering::SeqCst).save(w)?;
+        self.wake_deadline.save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let mut vals = [0u32; 2];
+        vals.load(r)?;
+        self.wake_deadline.load(r)?;
+
+        for (&cpu, &val) in [CpuId::Cpu, CpuId::Cop].iter().zip(vals.iter()) {
+            let cpuctl = match cpu {
+                CpuId::Cpu => &self.cpuctl,
+                CpuId::Cop => &self.copctl,
+            };
+            cpuctl.store(val, Ordering::SeqCst);
+
+            if !self.clock.is_virtual()
+                && val.get_bits(flags::FLOW_MASK) != 0
+                && val.get_bit(flags::PROC_WAIT_CNT)
+            {
+                self.on_update_cpuctl(cpu, val)
+                    .map_err(|e| invalid_data(format!("couldn't re-arm countdown: {:?}", e)))?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl CpuCon {
-    pub fn new(task_spawner: Spawner) -> CpuCon {
+    pub fn new(clock: Clock, task_spawner: Spawner) -> CpuCon {
         CpuCon {
             task_spawner,
+            clock,
 
             cpuctl: Arc::new(0x0000_0000.into()),
             copctl: Arc::new(0x0000_0000.into()),
+            wake_deadline: [None; 2],
         }
     }
 
+    /// Wake up any CPUs whose `PROC_WAIT_CNT` countdown has elapsed. Only has
+    /// an effect when using a virtual clock.
+    pub fn tick(&mut self) {
+        let now = self.clock.now();
+        for (cpuctl, deadline) in [&self.cpuctl, &self.copctl]
+            .iter()
+            .zip(self.wake_deadline.iter_mut())
+        {
+            if matches!(deadline, Some(d) if *d <= now) {
+                *deadline = None;
+                // TODO: check if flags::PROC_WAKE_INT is set, and fire an interrupt
+                cpuctl
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |mut reg| {
+                        Some(*reg.set_bits(flags::FLOW_MASK, 0))
+                    })
+                    .unwrap();
+            }
+        }
+    }
+
+    /// Returns the time at which the next `PROC_WAIT_CNT` countdown will wake a
+    /// sleeping CPU (if any).
+    pub fn next_deadline(&self) -> Option<Duration> {
+        let now = self.clock.now();
+        self.wake_deadline
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|d| *d > now)
+            .min()
+    }
+
     pub fn is_cpu_running(&mut self, cpu: CpuId) -> bool {
         let cpuctl = match cpu {
             CpuId::Cpu => &self.cpuctl,
@@ -84,6 +163,12 @@ impl CpuCon {
         cpuctl.load(Ordering::SeqCst).get_bits(flags::FLOW_MASK) == 0
     }
 
+    /// Check if the CPU is asleep without a pending `PROC_WAIT_CNT` countdown,
+    /// i.e: it can only be woken up by an interrupt.
+    pub fn is_cpu_halted(&mut self, cpu: CpuId) -> bool {
+        !self.is_cpu_running(cpu) && self.wake_deadline[cpu as usize].is_none()
+    }
+
     pub fn wake_on_interrupt(&mut self, cpu: CpuId) {
         let cpuctl = match cpu {
             CpuId::Cpu => &self.cpuctl,
@@ -99,7 +184,9 @@ impl CpuCon {
         });
     }
 
-    fn on_update_cpuctl(&self, cpu: CpuId, val: u32) -> MemResult<()> {
+    fn on_update_cpuctl(&mut self, cpu: CpuId, val: u32) -> MemResult<()> {
+        self.wake_deadline[cpu as usize] = None;
+
         if val.get_bit(flags::PROC_WAIT_CNT) {
             match val.get_bits(flags::PROC_CNT_MASK).count_ones() {
                 0 => return Ok(()), // TODO: double check if this is a synonym for sleep?
@@ -128,25 +215,31 @@ impl CpuCon {
 
             let duration = source.into_duration(val.get_bits(flags::COUNTER) as u8);
 
-            self.task_spawner
-                .spawn({
-                    let reg = Arc::clone(match cpu {
-                        CpuId::Cpu => &self.cpuctl,
-                        CpuId::Cop => &self.copctl,
-                    });
-                    // create timer outside of the task for slightly improved accuracy
-                    let timer = relativity::Timeout::new(duration);
-                    async move {
-                        timer.await;
-                        // TODO: check if flags::PROC_WAKE_INT is set, and fire an interrupt
-                        reg.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |mut reg| {
-                            let reg = *reg.set_bits(flags::FLOW_MASK, 0);
-                            Some(reg)
-                        })
-                        .unwrap();
-                    }
-                })
-                .expect("failed to spawn cpucon wakeup task");
+            // also tracked when using a wall clock, so that the system knows how long
+            // it can idle for.
+            self.wake_deadline[cpu as usize] = Some(self.clock.now() + duration);
+
+            if !self.clock.is_virtual() {
+                self.task_spawner
+                    .spawn({
+                        let reg = Arc::clone(match cpu {
+                            CpuId::Cpu => &self.cpuctl,
+                            CpuId::Cop => &self.copctl,
+                        });
+                        // create timer outside of the task for slightly improved accuracy
+                        let timer = relativity::Timeout::new(duration);
+                        async move {
+                            timer.await;
+                            // TODO: check if flags::PROC_WAKE_INT is set, and fire an interrupt
+                            reg.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |mut reg| {
+                                let reg = *reg.set_bits(flags::FLOW_MASK, 0);
+                                Some(reg)
+                            })
+                            .unwrap();
+                        }
+                    })
+                    .expect("failed to spawn cpucon wakeup task");
+            }
         }
 
         if val.get_bit(flags::PROC_WAKE_INT) {
@@ -177,14 +270,20 @@ impl Device for CpuCon {
     }
 }
 
-impl Memory for CpuCon {
-    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+impl CpuCon {
+    fn read32(&self, offset: u32) -> MemResult<u32> {
         match offset {
             0x0 => Ok(self.cpuctl.load(Ordering::SeqCst)),
             0x4 => Ok(self.copctl.load(Ordering::SeqCst)),
             _ => Err(Unexpected),
         }
     }
+}
+
+impl Memory for CpuCon {
+    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+        self.read32(offset)
+    }
 
     fn w32(&mut self, offset: u32, val: u32) -> MemResult<()> {
         match offset {
@@ -199,4 +298,8 @@ impl Memory for CpuCon {
             _ => Err(Unexpected),
         }
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        peek_result(self.read32(offset))
+    }
 }
@@ -8,6 +8,8 @@ pub struct CpuIdReg {
     cpuid: CpuId,
 }
 
+impl_snapshot!(CpuIdReg { cpuid });
+
 impl CpuIdReg {
     pub fn new() -> CpuIdReg {
         CpuIdReg { cpuid: CpuId::Cpu }
@@ -33,8 +35,8 @@ impl Device for CpuIdReg {
     }
 }
 
-impl Memory for CpuIdReg {
-    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+impl CpuIdReg {
+    fn read32(&self, offset: u32) -> MemResult<u32> {
         match offset {
             0x0 => match self.cpuid {
                 CpuId::Cpu => Ok(0x55555555),
@@ -43,6 +45,12 @@ impl Memory for CpuIdReg {
             _ => Err(Unexpected),
         }
     }
+}
+
+impl Memory for CpuIdReg {
+    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+        self.read32(offset)
+    }
 
     fn w32(&mut self, offset: u32, _val: u32) -> MemResult<()> {
         match offset {
@@ -50,4 +58,8 @@ impl Memory for CpuIdReg {
             _ => Err(Unexpected),
         }
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        peek_result(self.read32(offset))
+    }
 }
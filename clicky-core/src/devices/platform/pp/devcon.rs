@@ -13,6 +13,17 @@ pub struct DevCon {
     mystery: [u32; 1],
 }
 
+impl_snapshot!(DevCon {
+    reset,
+    enable,
+    clock_source,
+    pll_control,
+    pll_status,
+    cache_priority,
+    mystery_i2c,
+    mystery
+});
+
 impl DevCon {
     pub fn new() -> DevCon {
         DevCon {
@@ -53,8 +64,8 @@ impl Device for DevCon {
     }
 }
 
-impl Memory for DevCon {
-    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+impl DevCon {
+    fn read32(&self, offset: u32) -> MemResult<u32> {
         match offset {
             0x04 => Err(StubRead(Error, self.reset[0])),
             0x08 => Err(StubRead(Error, self.reset[1])),
@@ -70,6 +81,12 @@ impl Memory for DevCon {
             _ => Err(Unexpected),
         }
     }
+}
+
+impl Memory for DevCon {
+    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+        self.read32(offset)
+    }
 
     fn w32(&mut self, offset: u32, val: u32) -> MemResult<()> {
         match offset {
@@ -90,4 +107,8 @@ impl Memory for DevCon {
             _ => Err(Unexpected),
         }
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        peek_result(self.read32(offset))
+    }
 }
@@ -1,5 +1,30 @@
 use crate::devices::prelude::*;
 
+#[allow(dead_code)]
+mod flags {
+    type Range = std::ops::RangeInclusive<usize>;
+
+    /* Cmd */
+    pub const CMD_START: usize = 31;
+    pub const CMD_INTR: usize = 30;
+    pub const CMD_RAM_TO_PER: usize = 27;
+    pub const CMD_SINGLE: usize = 26;
+    pub const CMD_WAIT_REQ: usize = 24;
+    pub const CMD_REQ_ID: Range = 16..=19;
+    /// Transfer size (in bytes), minus 4
+    pub const CMD_SIZE: Range = 0..=15;
+
+    /* Status */
+    pub const STATUS_BUSY: usize = 31;
+    pub const STATUS_INTR: usize = 30;
+}
+
+/// Peripherals which can drive DMA transfers.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DmaReq {
+    I2S = 2,
+}
+
 #[derive(Debug, Default)]
 struct Dma {
     label: Option<&'static str>,
@@ -10,6 +35,50 @@ struct Dma {
     flags: u32,
     per_addr: u32,
     incr: u32,
+
+    /// Remaining bytes in the current transfer
+    remaining: u32,
+}
+
+impl_snapshot!(Dma {
+    cmd,
+    status,
+    ram_addr,
+    flags,
+    per_addr,
+    incr,
+    remaining
+});
+
+impl Dma {
+    fn is_active(&self, req: DmaReq) -> bool {
+        self.status.get_bit(flags::STATUS_BUSY)
+            && self.cmd.get_bits(flags::CMD_REQ_ID) == req as u32
+    }
+
+    fn on_update_cmd(&mut self) -> MemResult<()> {
+        if !self.cmd.get_bit(flags::CMD_START) {
+            self.status.set_bit(flags::STATUS_BUSY, false);
+            return Ok(());
+        }
+
+        let req = self.cmd.get_bits(flags::CMD_REQ_ID);
+        if req != DmaReq::I2S as u32 {
+            warn!(
+                "DMA{}: unimplemented request id {}",
+                self.label.unwrap_or("?"),
+                req
+            );
+            // if the error policy lets execution continue, the transfer simply
+            // never starts
+            self.status.set_bit(flags::STATUS_BUSY, false);
+            return Err(Unimplemented);
+        }
+
+        self.remaining = self.cmd.get_bits(flags::CMD_SIZE) + 4;
+        self.status.set_bit(flags::STATUS_BUSY, true);
+        Ok(())
+    }
 }
 
 impl Device for Dma {
@@ -51,10 +120,20 @@ pub struct DmaCon {
     // As per the pp5020 spec sheet: "A dedicated, high-performance ATA-66IDE controller with its
     // own DMA engine frees the processors from mundane management tasks."
     ide_dmarq: irq::Reciever,
+
+    irq: irq::Sender,
 }
 
+impl_snapshot!(DmaCon {
+    dma,
+    master_control,
+    master_status,
+    req_status,
+    irq
+});
+
 impl DmaCon {
-    pub fn new(ide_dmarq: irq::Reciever) -> DmaCon {
+    pub fn new(irq: irq::Sender, ide_dmarq: irq::Reciever) -> DmaCon {
         let mut dma = DmaCon {
             dma: Default::default(),
             master_control: 0,
@@ -62,6 +141,8 @@ impl DmaCon {
             req_status: 0,
 
             ide_dmarq,
+
+            irq,
         };
 
         dma.dma[0].label = Some("0");
@@ -80,6 +161,58 @@ impl DmaCon {
     pub fn do_ide_dma(&self) -> bool {
         self.ide_dmarq.asserted()
     }
+
+    /// Check if any channel is waiting on the specified peripheral.
+    pub fn is_active(&self, req: DmaReq) -> bool {
+        self.dma.iter().any(|dma| dma.is_active(req))
+    }
+
+    /// Advance the transfer on the channel waiting on the specified
+    /// peripheral by a single word, returning the `(src, dst)` addresses of the
+    /// word to copy. The caller is responsible for performing the actual copy.
+    ///
+    /// Fires the DMA interrupt (if requested) once the transfer completes.
+    pub fn next_word(&mut self, req: DmaReq) -> Option<(u32, u32)> {
+        let dma = self.dma.iter_mut().find(|dma| dma.is_active(req))?;
+
+        let ram_addr = dma.ram_addr;
+        dma.ram_addr = dma.ram_addr.wrapping_add(4);
+        dma.remaining = dma.remaining.saturating_sub(4);
+
+        if dma.remaining == 0 {
+            dma.status.set_bit(flags::STATUS_BUSY, false);
+            if dma.cmd.get_bit(flags::CMD_INTR) {
+                dma.status.set_bit(flags::STATUS_INTR, true);
+                self.irq.assert();
+            }
+        }
+
+        // XXX: DMA_INCR is ignored, and the peripheral address is always fixed
+        Some(if dma.cmd.get_bit(flags::CMD_RAM_TO_PER) {
+            (ram_addr, dma.per_addr)
+        } else {
+            (dma.per_addr, ram_addr)
+        })
+    }
+
+    /// Abort any transfer on the channel waiting on the specified peripheral.
+    pub fn abort(&mut self, req: DmaReq) {
+        for dma in self.dma.iter_mut().filter(|dma| dma.is_active(req)) {
+            dma.status.set_bit(flags::STATUS_BUSY, false);
+        }
+    }
+
+    fn update_irq(&mut self) {
+        if self
+            .dma
+            .iter()
+            .any(|dma| dma.status.get_bit(flags::STATUS_INTR))
+        {
+            self.irq.assert()
+        } else {
+            self.irq.clear()
+        }
+    }
 }
 
 impl Device for DmaCon {
@@ -113,11 +246,17 @@ impl Memory for DmaCon {
                 let id = (offset - 0x1000) / 0x20;
                 let dma = &mut self.dma[id as usize];
                 match offset % 0x20 {
-                    0x00 => Err(StubRead(Error, dma.cmd)),
-                    0x04 => Err(StubRead(Error, dma.status)),
-                    0x10 => Err(StubRead(Error, dma.ram_addr)),
+                    0x00 => Ok(dma.cmd),
+                    0x04 => {
+                        // reading the status register acknowledges the interrupt
+                        let status = dma.status;
+                        dma.status.set_bit(flags::STATUS_INTR, false);
+                        self.update_irq();
+                        Ok(status)
+                    }
+                    0x10 => Ok(dma.ram_addr),
                     0x14 => Err(StubRead(Error, dma.flags)),
-                    0x18 => Err(StubRead(Error, dma.per_addr)),
+                    0x18 => Ok(dma.per_addr),
                     0x1c => Err(StubRead(Error, dma.incr)),
                     _ => Err(Unexpected),
                 }
@@ -135,11 +274,14 @@ impl Memory for DmaCon {
                 let id = (offset - 0x1000) / 0x20;
                 let dma = &mut self.dma[id as usize];
                 match offset % 0x20 {
-                    0x00 => Err(StubWrite(Error, dma.cmd = val)),
+                    0x00 => {
+                        dma.cmd = val;
+                        dma.on_update_cmd()
+                    }
                     0x04 => Err(StubWrite(Error, dma.status = val)),
-                    0x10 => Err(StubWrite(Error, dma.ram_addr = val)),
+                    0x10 => Ok(dma.ram_addr = val),
                     0x14 => Err(StubWrite(Error, dma.flags = val)),
-                    0x18 => Err(StubWrite(Error, dma.per_addr = val)),
+                    0x18 => Ok(dma.per_addr = val),
                     0x1c => Err(StubWrite(Error, dma.incr = val)),
                     _ => Err(Unexpected),
                 }
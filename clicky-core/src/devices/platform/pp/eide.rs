@@ -15,6 +15,11 @@ struct IdeDriveCfg {
     _config: u32,
 }
 
+impl_snapshot!(IdeDriveCfg {
+    primary_timing,
+    secondary_timing
+});
+
 /// PP5020 EIDE Controller
 #[derive(Debug)]
 pub struct EIDECon {
@@ -35,6 +40,16 @@ pub struct EIDECon {
     unknown: u32,
 }
 
+impl_snapshot!(EIDECon {
+    ide0_cfg,
+    ide1_cfg,
+    ide,
+    dma_control,
+    dma_length,
+    dma_addr,
+    unknown
+});
+
 pub struct DmaErr;
 
 impl EIDECon {
@@ -120,4 +120,15 @@ impl Memory for Flash {
     fn w32(&mut self, _offset: u32, _val: u32) -> MemResult<()> {
         Err(StubWrite(Warn, ()))
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        if offset > 0xFFFFF {
+            return None;
+        }
+
+        match self.dump.as_ref() {
+            Some(dump) => Some(LittleEndian::read_u32(&dump[offset as usize..][..4])),
+            None => peek_result(Self::hle_vals(offset)),
+        }
+    }
 }
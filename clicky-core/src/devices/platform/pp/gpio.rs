@@ -1,7 +1,10 @@
 use crate::devices::prelude::*;
 
+use std::io::{self, Read, Write};
+
 use crate::devices::util::ArcMutexDevice;
 use crate::signal::{gpio, irq};
+use crate::snapshot::Snapshot;
 
 /// 8-bit GPIO Port
 #[derive(Debug)]
@@ -21,6 +24,38 @@ struct GpioPort {
     interrupt_level: u8,
 }
 
+impl Snapshot for GpioPort {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.irq.save(w)?;
+        for output in self.outputs.iter_mut().flatten() {
+            output.save(w)?;
+        }
+
+        self.enable.save(w)?;
+        self.output_enable.save(w)?;
+        self.output_val.save(w)?;
+        self.input_val.save(w)?;
+        self.interrupt_status.save(w)?;
+        self.interrupt_enable.save(w)?;
+        self.interrupt_level.save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        self.irq.load(r)?;
+        for output in self.outputs.iter_mut().flatten() {
+            output.load(r)?;
+        }
+
+        self.enable.load(r)?;
+        self.output_enable.load(r)?;
+        self.output_val.load(r)?;
+        self.input_val.load(r)?;
+        self.interrupt_status.load(r)?;
+        self.interrupt_enable.load(r)?;
+        self.interrupt_level.load(r)
+    }
+}
+
 impl GpioPort {
     fn new(irq: irq::Sender, label: &'static str) -> GpioPort {
         GpioPort {
@@ -136,8 +171,8 @@ impl Device for GpioPort {
     }
 }
 
-impl Memory for GpioPort {
-    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+impl GpioPort {
+    fn read32(&self, offset: u32) -> MemResult<u32> {
         match offset {
             0x00 => Ok(self.enable as u32),
             0x10 => Ok(self.output_enable as u32),
@@ -150,6 +185,12 @@ impl Memory for GpioPort {
             _ => Err(Unexpected),
         }
     }
+}
+
+impl Memory for GpioPort {
+    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+        self.read32(offset)
+    }
 
     fn w32(&mut self, offset: u32, val: u32) -> MemResult<()> {
         // it's an 8-bit interface
@@ -171,6 +212,10 @@ impl Memory for GpioPort {
 
         Ok(())
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        peek_result(self.read32(offset))
+    }
 }
 
 /// Block of 4 GPIO ports on the PP5020.
@@ -179,6 +224,8 @@ pub struct GpioBlock {
     port: [GpioPort; 4],
 }
 
+impl_snapshot!(GpioBlock { port });
+
 impl GpioBlock {
     pub fn new(irq: irq::Sender, labels: [&'static str; 4]) -> GpioBlock {
         GpioBlock {
@@ -243,6 +290,11 @@ impl Memory for GpioBlock {
         let port = (offset / 4) % 4;
         self.port[port as usize].w32(offset - 4 * port, val)
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        let port = (offset / 4) % 4;
+        self.port[port as usize].peek32(offset - 4 * port)
+    }
 }
 
 /// Standard GPIO addresses + 0x800 allow atomic port manipulation on PP502x.
@@ -1,6 +1,9 @@
 use crate::devices::prelude::*;
 
+use std::io::{self, Read, Write};
+
 use crate::devices::i2c::I2CDevice;
+use crate::snapshot::Snapshot;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 enum I2COp {
@@ -42,6 +45,28 @@ struct I2CTransactionCfg {
     len: Option<u8>,
 }
 
+impl Snapshot for I2CTransactionCfg {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.addr_op.map(I2COp::into_bit).save(w)?;
+        self.addr.save(w)?;
+        self.op.map(I2COp::into_bit).save(w)?;
+        self.len.save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let mut addr_op: Option<bool> = None;
+        let mut op: Option<bool> = None;
+        addr_op.load(r)?;
+        self.addr.load(r)?;
+        op.load(r)?;
+        self.len.load(r)?;
+
+        self.addr_op = addr_op.map(I2COp::from_bit);
+        self.op = op.map(I2COp::from_bit);
+        Ok(())
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 struct I2CTransaction {
     op: I2COp,
@@ -145,6 +170,32 @@ pub struct I2CCon {
     data: [u8; 4],
 }
 
+impl Snapshot for I2CCon {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        for addr in 0..128 {
+            if let Some(device) = &mut self.devices[addr] {
+                device.save(w)?;
+            }
+        }
+
+        self.busy.save(w)?;
+        self.txn.save(w)?;
+        self.data.save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        for addr in 0..128 {
+            if let Some(device) = &mut self.devices[addr] {
+                device.load(r)?;
+            }
+        }
+
+        self.busy.load(r)?;
+        self.txn.load(r)?;
+        self.data.load(r)
+    }
+}
+
 impl std::fmt::Debug for I2CCon {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("I2CCon")
@@ -1,21 +1,227 @@
 use crate::devices::prelude::*;
 
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::gui::AudioCallback;
+use crate::snapshot::{invalid_data, Snapshot};
+
+#[allow(dead_code)]
+mod flags {
+    type Range = std::ops::RangeInclusive<usize>;
+
+    /* Config */
+    pub const RESET: usize = 31;
+    pub const TX_FIFO_EN: usize = 29;
+    pub const RX_FIFO_EN: usize = 28;
+
+    /* FIFO Config */
+    pub const RX_FULL_COUNT: Range = 23..=28;
+    pub const TX_FREE_COUNT: Range = 16..=21;
+    pub const RX_CLEAR: usize = 12;
+    pub const TX_CLEAR: usize = 8;
+    pub const FIFO_FORMAT: Range = 4..=6;
+    pub const TX_IRQ_EN: usize = 1;
+    pub const RX_IRQ_EN: usize = 0;
+
+    // XXX: these are the only FIFO formats known to be used in the wild
+    /// One sample per write, stored in the upper halfword
+    pub const FIFO_FORMAT_LE32: u32 = 0;
+    /// Two samples per write (lower halfword first)
+    pub const FIFO_FORMAT_LE16_2: u32 = 3;
+}
+
+/// Number of 16-bit samples which fit in the TX FIFO
+const TX_FIFO_LEN: usize = 16;
+/// Fire the TX IRQ when the TX FIFO has at least this many free slots
+const TX_IRQ_THRESHOLD: usize = TX_FIFO_LEN / 2;
+/// Upper bound on the number of samples buffered for the audio callback. Older
+/// samples are dropped if the frontend doesn't keep up.
+const MAX_BUFFERED_SAMPLES: usize = 2 * 96000;
+
 /// PP5020 I2S controller.
+///
+/// Only audio output is emulated. Samples written to the TX FIFO are drained
+/// at the codec's sample rate, and forwarded to the frontend via an
+/// [AudioCallback]. The system must periodically call `tick` to drain the
+/// FIFO.
 #[derive(Debug)]
 pub struct I2SCon {
+    irq: irq::Sender,
+    clock: Clock,
+    sample_rate: Arc<AtomicU32>,
+    out: Arc<Mutex<VecDeque<i16>>>,
+
     config: u32,
-    clock: u32,
+    clk: u32,
     fifo_cfg: u32,
+
+    tx_fifo: VecDeque<i16>,
+    last_drain: Duration,
+}
+
+impl Snapshot for I2SCon {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.irq.save(w)?;
+        self.config.save(w)?;
+        self.clk.save(w)?;
+        self.fifo_cfg.save(w)?;
+        self.tx_fifo.len().save(w)?;
+        for sample in self.tx_fifo.iter() {
+            (*sample as u16).save(w)?;
+        }
+        self.last_drain.save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        self.irq.load(r)?;
+        self.config.load(r)?;
+        self.clk.load(r)?;
+        self.fifo_cfg.load(r)?;
+        let mut len = 0usize;
+        len.load(r)?;
+        if len > TX_FIFO_LEN {
+            return Err(invalid_data("invalid I2S TX FIFO length"));
+        }
+        self.tx_fifo.clear();
+        for _ in 0..len {
+            let mut sample = 0u16;
+            sample.load(r)?;
+            self.tx_fifo.push_back(sample as i16);
+        }
+        self.last_drain.load(r)
+    }
 }
 
 impl I2SCon {
-    pub fn new() -> I2SCon {
+    /// `sample_rate` should be provided by the attached codec.
+    pub fn new(irq: irq::Sender, clock: Clock, sample_rate: Arc<AtomicU32>) -> I2SCon {
         I2SCon {
+            irq,
+            sample_rate,
+            out: Arc::new(Mutex::new(VecDeque::new())),
+
             config: 0,
-            clock: 0,
+            clk: 0,
             fifo_cfg: 0,
+
+            tx_fifo: VecDeque::with_capacity(TX_FIFO_LEN),
+            last_drain: clock.now(),
+
+            clock,
         }
     }
+
+    /// Returns a callback to retrieve the samples output by the controller.
+    pub fn audio_callback(&self) -> AudioCallback {
+        let out = Arc::clone(&self.out);
+        let sample_rate = Arc::clone(&self.sample_rate);
+
+        Box::new(move |buf: &mut Vec<i16>| -> u32 {
+            buf.clear();
+            buf.extend(out.lock().unwrap().drain(..));
+            sample_rate.load(Ordering::Relaxed)
+        })
+    }
+
+    /// Number of free (16-bit) slots in the TX FIFO.
+    pub fn tx_free(&self) -> usize {
+        TX_FIFO_LEN - self.tx_fifo.len()
+    }
+
+    /// Drain the TX FIFO, and update the IRQ line.
+    pub fn tick(&mut self) {
+        self.drain();
+        self.update_irq();
+    }
+
+    /// Returns the time at which the next sample will be drained from the TX
+    /// FIFO (if there's anything to drain).
+    pub fn next_deadline(&self) -> Option<Duration> {
+        if self.tx_fifo.is_empty() || !self.tx_enabled() {
+            return None;
+        }
+
+        let rate = self.sample_rate.load(Ordering::Relaxed) as u64 * 2;
+        let period = Duration::from_nanos(1_000_000_000 / rate.max(1));
+        Some(self.last_drain + period)
+    }
+
+    fn tx_enabled(&self) -> bool {
+        self.config.get_bit(flags::TX_FIFO_EN)
+    }
+
+    fn drain(&mut self) {
+        if self.tx_fifo.is_empty() || !self.tx_enabled() {
+            return;
+        }
+
+        // two samples per frame (L, R)
+        let rate = self.sample_rate.load(Ordering::Relaxed) as u128 * 2;
+        let elapsed = self.clock.now().saturating_sub(self.last_drain);
+        let n = (elapsed.as_nanos() * rate / 1_000_000_000) as usize;
+        if n == 0 {
+            return;
+        }
+
+        let n = n.min(self.tx_fifo.len());
+        // don't discard the remainder
+        self.last_drain += Duration::from_nanos((n as u128 * 1_000_000_000 / rate) as u64);
+
+        let mut out = self.out.lock().unwrap();
+        out.extend(self.tx_fifo.drain(..n));
+        if out.len() > MAX_BUFFERED_SAMPLES {
+            let excess = out.len() - MAX_BUFFERED_SAMPLES;
+            out.drain(..excess);
+        }
+    }
+
+    fn update_irq(&mut self) {
+        if self.fifo_cfg.get_bit(flags::TX_IRQ_EN)
+            && self.tx_enabled()
+            && self.tx_free() >= TX_IRQ_THRESHOLD
+        {
+            self.irq.assert()
+        } else {
+            self.irq.clear()
+        }
+    }
+
+    fn push_sample(&mut self, sample: i16) -> MemResult<()> {
+        if self.tx_fifo.len() == TX_FIFO_LEN {
+            return Err(ContractViolation {
+                msg: "TX FIFO overflow".into(),
+                severity: Warn,
+                stub_val: None,
+            });
+        }
+
+        if self.tx_fifo.is_empty() {
+            // playback is (re)starting
+            self.last_drain = self.clock.now();
+        }
+        self.tx_fifo.push_back(sample);
+        Ok(())
+    }
+
+    fn fifo_write(&mut self, val: u32) -> MemResult<()> {
+        self.drain();
+
+        let res = match self.fifo_cfg.get_bits(flags::FIFO_FORMAT) {
+            flags::FIFO_FORMAT_LE32 => self.push_sample((val >> 16) as i16),
+            flags::FIFO_FORMAT_LE16_2 => self
+                .push_sample(val as i16)
+                .and_then(|_| self.push_sample((val >> 16) as i16)),
+            format => Err(Fatal(format!("unimplemented FIFO format {}", format))),
+        };
+
+        self.update_irq();
+        res
+    }
 }
 
 impl Device for I2SCon {
@@ -40,23 +246,68 @@ impl Device for I2SCon {
 impl Memory for I2SCon {
     fn r32(&mut self, offset: u32) -> MemResult<u32> {
         match offset {
-            0x00 => Err(StubRead(Error, self.config)),
-            0x08 => Err(StubRead(Error, self.clock)),
-            0x0c => Err(StubRead(Error, self.fifo_cfg)),
-            0x40 => Err(Unimplemented),
-            0x80 => Err(Unimplemented),
+            0x00 => Ok(self.config),
+            0x08 => Err(StubRead(Info, self.clk)),
+            0x0c => {
+                self.drain();
+                let mut val = self.fifo_cfg;
+                val.set_bits(flags::TX_FREE_COUNT, self.tx_free() as u32);
+                // recording isn't supported, so the RX FIFO is always empty
+                val.set_bits(flags::RX_FULL_COUNT, 0);
+                Ok(val)
+            }
+            0x40 => Err(InvalidAccess),
+            0x80 => Err(StubRead(Warn, 0)),
             _ => Err(Unexpected),
         }
     }
 
     fn w32(&mut self, offset: u32, val: u32) -> MemResult<()> {
         match offset {
-            0x00 => Err(StubWrite(Error, self.config = val)),
-            0x08 => Err(StubWrite(Error, self.clock = val)),
-            0x0c => Err(StubWrite(Error, self.fifo_cfg = val)),
-            0x40 => Err(Unimplemented),
-            0x80 => Err(Unimplemented),
+            0x00 => {
+                if val.get_bit(flags::RESET) {
+                    self.tx_fifo.clear();
+                }
+                if val.get_bit(flags::TX_FIFO_EN) && !self.tx_enabled() {
+                    self.last_drain = self.clock.now();
+                }
+                self.config = val;
+                self.update_irq();
+                if val.get_bit(flags::RX_FIFO_EN) {
+                    return Err(StubWrite(Warn, ()));
+                }
+                Ok(())
+            }
+            0x08 => Err(StubWrite(Info, self.clk = val)),
+            0x0c => {
+                if val.get_bit(flags::TX_CLEAR) {
+                    self.tx_fifo.clear();
+                }
+                let mut val = val;
+                self.fifo_cfg = *val
+                    .set_bits(flags::TX_FREE_COUNT, 0)
+                    .set_bits(flags::RX_FULL_COUNT, 0)
+                    .set_bit(flags::TX_CLEAR, false)
+                    .set_bit(flags::RX_CLEAR, false);
+                self.update_irq();
+                Ok(())
+            }
+            0x40 => self.fifo_write(val),
+            0x80 => Err(InvalidAccess),
             _ => Err(Unexpected),
         }
     }
+
+    fn w16(&mut self, offset: u32, val: u16) -> MemResult<()> {
+        match offset {
+            // 16-bit FIFO writes always push a single sample
+            0x40 => {
+                self.drain();
+                let res = self.push_sample(val as i16);
+                self.update_irq();
+                res
+            }
+            _ => self.w32(offset, val as u32),
+        }
+    }
 }
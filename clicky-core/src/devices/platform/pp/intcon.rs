@@ -32,6 +32,20 @@ macro_rules! impl_and_or {
 impl_and_or!(&, BitAnd, bitand);
 impl_and_or!(|, BitOr, bitor);
 
+/// The state of a single (registered) IRQ line.
+#[derive(Debug)]
+pub struct IrqLine {
+    pub idx: usize,
+    pub label: &'static str,
+    /// Whether the device is asserting the IRQ (on the cpu, cop)
+    pub asserted: (bool, bool),
+    /// Whether the IRQ is enabled (on the cpu, cop). Takes the "master toggle"
+    /// for hi IRQs into account.
+    pub enabled: (bool, bool),
+    /// Whether the IRQ is routed to the FIQ line (on the cpu, cop)
+    pub fiq: (bool, bool),
+}
+
 #[derive(Debug, Default)]
 struct IntConCpuRegs {
     irq_stat: u32,
@@ -40,6 +54,13 @@ struct IntConCpuRegs {
     priority: u32,
 }
 
+impl_snapshot!(IntConCpuRegs {
+    irq_stat,
+    fiq_stat,
+    enabled,
+    priority
+});
+
 #[derive(Debug)]
 enum IrqKind {
     Unregistered,
@@ -57,9 +78,12 @@ impl Default for IrqKind {
     }
 }
 
-/// Half of the PP5020 Interrupt Controller.
+/// A single bank of 32 IRQ lines.
+///
+/// The PP5020 Interrupt Controller is made up of two of these (see [IntCon]),
+/// whereas the PP5002's only has the one.
 #[derive(Debug, Default)]
-struct IntCon32 {
+pub struct IntCon32 {
     label: &'static str,
     irqs: [IrqKind; 32],
 
@@ -71,6 +95,8 @@ struct IntCon32 {
     _int_forced_clr: u32,
 }
 
+impl_snapshot!(IntCon32 { cpu, cop });
+
 impl IntCon32 {
     pub fn new(label: &'static str) -> IntCon32 {
         IntCon32 {
@@ -138,6 +164,41 @@ impl IntCon32 {
         // TODO: look into the "forced interrupt" functionality
     }
 
+    /// Iterate over all registered IRQ lines, numbering them from `base`.
+    /// `gate` is applied to the lines' enable bits (for the cpu, cop).
+    fn iter_lines(&self, base: usize, gate: (bool, bool)) -> impl Iterator<Item = IrqLine> + '_ {
+        let irqs = self.irqs.iter().enumerate();
+        irqs.filter_map(move |(i, irq)| {
+            let (cpu_irq, cop_irq) = match irq {
+                IrqKind::Unregistered => return None,
+                IrqKind::Shared(irq) => (irq, irq),
+                IrqKind::CoreSpecific { cpu_irq, cop_irq } => (cpu_irq, cop_irq),
+            };
+
+            Some(IrqLine {
+                idx: base + i,
+                label: cpu_irq.label(),
+                asserted: (cpu_irq.asserted(), cop_irq.asserted()),
+                enabled: (
+                    gate.0 && self.cpu.enabled.get_bit(i),
+                    gate.1 && self.cop.enabled.get_bit(i),
+                ),
+                fiq: (self.cpu.priority.get_bit(i), self.cop.priority.get_bit(i)),
+            })
+        })
+    }
+
+    /// Return the state of all registered IRQ lines.
+    pub fn lines(&self) -> Vec<IrqLine> {
+        self.iter_lines(0, (true, true)).collect()
+    }
+
+    /// Check if any interrupts are enabled on either core.
+    pub fn any_enabled(&self) -> bool {
+        self.cpu.enabled != 0 || self.cop.enabled != 0
+    }
+
+    /// Check if an IRQ/FIQ is being requested on the (cpu, cop)
     pub fn interrupt_status(&mut self) -> (IntStatus, IntStatus) {
         self.update_regs();
         (
@@ -195,8 +256,8 @@ impl Device for IntCon32 {
     }
 }
 
-impl Memory for IntCon32 {
-    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+impl IntCon32 {
+    fn read32(&self, offset: u32) -> MemResult<u32> {
         match offset {
             0x00 => Ok(self.cpu.irq_stat),
             0x04 => Ok(self.cop.irq_stat),
@@ -223,6 +284,12 @@ impl Memory for IntCon32 {
             _ => Err(Unexpected),
         }
     }
+}
+
+impl Memory for IntCon32 {
+    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+        self.read32(offset)
+    }
 
     fn w32(&mut self, offset: u32, val: u32) -> MemResult<()> {
         match offset {
@@ -251,6 +318,10 @@ impl Memory for IntCon32 {
             _ => Err(Unexpected),
         }
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        peek_result(self.read32(offset))
+    }
 }
 
 /// PP5020 Interrupt Controller
@@ -260,6 +331,8 @@ pub struct IntCon {
     hi: IntCon32,
 }
 
+impl_snapshot!(IntCon { lo, hi });
+
 impl IntCon {
     pub fn new() -> IntCon {
         IntCon {
@@ -320,6 +393,19 @@ impl IntCon {
         )
     }
 
+    /// Return the state of all registered IRQ lines.
+    pub fn lines(&self) -> Vec<IrqLine> {
+        (self.lo.iter_lines(0, (true, true)))
+            .chain(self.hi.iter_lines(32, self.hi_enabled()))
+            .collect()
+    }
+
+    /// Check if any interrupts are enabled on either core.
+    pub fn any_enabled(&self) -> bool {
+        // hi IRQs are gated by IRQ 30, so only the lo enables need to be checked
+        self.lo.any_enabled()
+    }
+
     /// Check if an IRQ/FIQ is being requested on the (cpu, cop)
     pub fn interrupt_status(&mut self) -> (IntStatus, IntStatus) {
         let (lo_cpu, lo_cop) = self.lo.interrupt_status();
@@ -363,4 +449,12 @@ impl Memory for IntCon {
             _ => Err(Unexpected),
         }
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        match offset {
+            0x000..=0x0ff => self.lo.peek32(offset),
+            0x100..=0x1ff => self.hi.peek32(offset - 0x100),
+            _ => None,
+        }
+    }
 }
@@ -12,6 +12,13 @@ pub struct Mailbox {
     shared_bits: u32,
 }
 
+impl_snapshot!(Mailbox {
+    selected_core,
+    cpu_irq,
+    cop_irq,
+    shared_bits
+});
+
 impl Mailbox {
     pub fn new(cpu_irq: irq::Sender, cop_irq: irq::Sender) -> Mailbox {
         Mailbox {
@@ -96,4 +103,12 @@ impl Memory for Mailbox {
             _ => Err(Unexpected),
         }
     }
+
+    // reading the shared bits normally acknowledges the mailbox IRQ
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        match offset {
+            0x00 => Some(self.shared_bits),
+            _ => None,
+        }
+    }
 }
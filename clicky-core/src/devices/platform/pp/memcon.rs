@@ -18,6 +18,8 @@ struct Mmap {
     physical: u32,
 }
 
+impl_snapshot!(Mmap { logical, physical });
+
 /// PP5020 Memory Controller. Content varies based on which CPU/COP is
 /// performing the access.
 #[derive(Debug)]
@@ -27,6 +29,12 @@ pub struct MemCon {
     copcon: MemConImpl,
 }
 
+impl_snapshot!(MemCon {
+    selected,
+    cpucon,
+    copcon
+});
+
 impl MemCon {
     pub fn new() -> MemCon {
         MemCon {
@@ -75,6 +83,13 @@ impl Memory for MemCon {
             CpuId::Cop => self.copcon.w32(offset, val),
         }
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        match self.selected {
+            CpuId::Cpu => self.cpucon.peek32(offset),
+            CpuId::Cop => self.copcon.peek32(offset),
+        }
+    }
 }
 
 /// PP5020 Memory Controller.
@@ -99,6 +114,15 @@ struct MemConImpl {
     cache_flush_mask: u32,
 }
 
+impl_snapshot!(MemConImpl {
+    cache_data,
+    cache_status,
+    mmap,
+    cache_mask,
+    cache_control,
+    cache_flush_mask
+});
+
 impl std::fmt::Debug for MemConImpl {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         f.debug_struct("MemConImpl")
@@ -244,8 +268,8 @@ impl Device for MemConImpl {
     }
 }
 
-impl Memory for MemConImpl {
-    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+impl MemConImpl {
+    fn read32(&self, offset: u32) -> MemResult<u32> {
         match offset {
             0x0000..=0x1fff => Err(StubRead(Error, self.cache_data[offset as usize])),
             0x2000..=0x3fff => Err(Unimplemented),
@@ -271,6 +295,12 @@ impl Memory for MemConImpl {
             _ => Err(Unexpected),
         }
     }
+}
+
+impl Memory for MemConImpl {
+    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+        self.read32(offset)
+    }
 
     fn w32(&mut self, offset: u32, val: u32) -> MemResult<()> {
         match offset {
@@ -314,4 +344,8 @@ impl Memory for MemConImpl {
             _ => Err(Unexpected),
         }
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        peek_result(self.read32(offset))
+    }
 }
@@ -58,6 +58,11 @@ pub struct OptoWheel {
     controls_status: u32,
 }
 
+impl_snapshot!(OptoWheel {
+    irq,
+    controls_status
+});
+
 impl OptoWheel {
     pub fn new(irq: irq::Sender) -> OptoWheel {
         OptoWheel {
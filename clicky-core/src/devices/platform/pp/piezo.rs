@@ -6,6 +6,8 @@ pub struct Piezo {
     control: u32,
 }
 
+impl_snapshot!(Piezo { control });
+
 impl Piezo {
     fn on_update_piezo(&self) {
         // TODO: actually output some sound
@@ -0,0 +1,93 @@
+use crate::devices::prelude::*;
+
+use super::super::common::CpuId;
+
+/// Put the core to sleep until it's woken by an interrupt (or the other core).
+const PROC_SLEEP: u8 = 0xca;
+/// Wake the core up.
+const PROC_WAKE: u8 = 0xce;
+
+/// PP5002 CPU controller.
+///
+/// Unlike the PP5020's controller, there's no support for sleeping on a
+/// countdown: cores are simply put to sleep / woken up by writing a magic value
+/// to their (byte-wide) control register.
+#[derive(Debug)]
+pub struct CpuCon {
+    ctl: [u8; 2],
+}
+
+impl_snapshot!(CpuCon { ctl });
+
+impl CpuCon {
+    pub fn new() -> CpuCon {
+        CpuCon {
+            ctl: [PROC_WAKE; 2],
+        }
+    }
+
+    pub fn is_cpu_running(&self, cpu: CpuId) -> bool {
+        self.ctl[cpu as usize] != PROC_SLEEP
+    }
+
+    pub fn wake_on_interrupt(&mut self, cpu: CpuId) {
+        self.ctl[cpu as usize] = PROC_WAKE;
+    }
+}
+
+impl Device for CpuCon {
+    fn kind(&self) -> &'static str {
+        "System Controller Block"
+    }
+
+    fn probe(&self, offset: u32) -> Probe {
+        let reg = match offset {
+            0x0 => "CPU Control",
+            0x4 => "COP Control",
+            _ => return Probe::Unmapped,
+        };
+
+        Probe::Register(reg)
+    }
+}
+
+impl CpuCon {
+    fn read32(&self, offset: u32) -> MemResult<u32> {
+        match offset {
+            0x0 => Ok(self.ctl[CpuId::Cpu as usize] as u32),
+            0x4 => Ok(self.ctl[CpuId::Cop as usize] as u32),
+            _ => Err(Unexpected),
+        }
+    }
+
+    fn write_ctl(&mut self, cpu: CpuId, val: u32) -> MemResult<()> {
+        let val = val.trunc_to_u8()?;
+        self.ctl[cpu as usize] = val;
+        match val {
+            PROC_SLEEP | PROC_WAKE => Ok(()),
+            _ => Err(ContractViolation {
+                msg: format!("unknown {:?} control value {:#04x}", cpu, val),
+                severity: Warn,
+                stub_val: None,
+            }),
+        }
+    }
+}
+
+impl Memory for CpuCon {
+    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+        self.read32(offset)
+    }
+
+    fn w32(&mut self, offset: u32, val: u32) -> MemResult<()> {
+        match offset {
+            0x0 => self.write_ctl(CpuId::Cpu, val),
+            0x4 => self.write_ctl(CpuId::Cop, val),
+            _ => Err(Unexpected),
+        }
+    }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        peek_result(self.read32(offset))
+    }
+}
@@ -0,0 +1,6 @@
+//! Devices specific to the PP5002 (i.e: devices whose registers differ from
+//! their PP502x counterparts).
+
+mod cpucon;
+
+pub use cpucon::*;
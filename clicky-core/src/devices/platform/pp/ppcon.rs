@@ -1,8 +1,13 @@
 use crate::devices::prelude::*;
 
+use std::convert::TryInto;
+
 /// Poorly documented PP50XX controller
 #[derive(Debug)]
 pub struct PPCon {
+    /// e.g: `b"PP5020D "`
+    chip_id: [u8; 8],
+
     dev_init: [u32; 8],
     dev_timing: [u32; 3],
     bootstrap_maybe: [u32; 2],
@@ -12,9 +17,20 @@ pub struct PPCon {
     gpo_input_enable: u32,
 }
 
+impl_snapshot!(PPCon {
+    dev_init,
+    dev_timing,
+    bootstrap_maybe,
+    gpo_val,
+    gpo_enable,
+    gpo_input_enable
+});
+
 impl PPCon {
-    pub fn new() -> PPCon {
+    pub fn new(chip_id: [u8; 8]) -> PPCon {
         PPCon {
+            chip_id,
+
             dev_init: [0; 8],
             dev_timing: [0; 3],
             bootstrap_maybe: [0; 2],
@@ -59,11 +75,11 @@ impl Device for PPCon {
     }
 }
 
-impl Memory for PPCon {
-    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+impl PPCon {
+    fn read32(&self, offset: u32) -> MemResult<u32> {
         match offset {
-            0x00 => Ok(u32::from_le_bytes(*b"PP50")),
-            0x04 => Ok(u32::from_le_bytes(*b"20D ")),
+            0x00 => Ok(u32::from_le_bytes(self.chip_id[0..4].try_into().unwrap())),
+            0x04 => Ok(u32::from_le_bytes(self.chip_id[4..8].try_into().unwrap())),
             0x08 => Err(StubRead(Info, self.bootstrap_maybe[0])),
             0x0c => Err(StubRead(Info, self.bootstrap_maybe[1])),
             0x10 => Err(StubRead(Info, self.dev_init[0])),
@@ -85,6 +101,12 @@ impl Memory for PPCon {
             _ => Err(Unexpected),
         }
     }
+}
+
+impl Memory for PPCon {
+    fn r32(&mut self, offset: u32) -> MemResult<u32> {
+        self.read32(offset)
+    }
 
     fn w32(&mut self, offset: u32, val: u32) -> MemResult<()> {
         match offset {
@@ -113,4 +135,8 @@ impl Memory for PPCon {
             _ => Err(Unexpected),
         }
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        peek_result(self.read32(offset))
+    }
 }
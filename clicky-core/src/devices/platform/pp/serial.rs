@@ -1,27 +1,279 @@
 use crate::devices::prelude::*;
 
-/// PP5020 serial controller
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+use crate::serial::SerialBackend;
+use crate::snapshot::{invalid_data, Snapshot};
+
+#[allow(dead_code)]
+mod flags {
+    type Range = std::ops::RangeInclusive<usize>;
+
+    /* Interrupt Enable Register */
+    pub const IER_RDA: usize = 0;
+    pub const IER_THRE: usize = 1;
+    pub const IER_RLS: usize = 2;
+    pub const IER_MS: usize = 3;
+    pub const IER_MASK: u8 = 0x0f;
+
+    /* Interrupt Identification Register */
+    pub const IIR_NONE: u8 = 0x01;
+    pub const IIR_RLS: u8 = 0x06;
+    pub const IIR_RDA: u8 = 0x04;
+    pub const IIR_TIMEOUT: u8 = 0x0c;
+    pub const IIR_THRE: u8 = 0x02;
+    pub const IIR_FIFO_EN: u8 = 0xc0;
+
+    /* FIFO Control Register */
+    pub const FCR_EN: usize = 0;
+    pub const FCR_RX_CLEAR: usize = 1;
+    pub const FCR_TX_CLEAR: usize = 2;
+    pub const FCR_RX_TRIGGER: Range = 6..=7;
+
+    /* Line Control Register */
+    pub const LCR_DLAB: usize = 7;
+
+    /* Modem Control Register */
+    pub const MCR_DTR: usize = 0;
+    pub const MCR_RTS: usize = 1;
+    pub const MCR_OUT1: usize = 2;
+    pub const MCR_OUT2: usize = 3;
+    pub const MCR_LOOPBACK: usize = 4;
+
+    /* Line Status Register */
+    pub const LSR_DR: usize = 0;
+    pub const LSR_OE: usize = 1;
+    pub const LSR_THRE: usize = 5;
+    pub const LSR_TEMT: usize = 6;
+
+    /* Modem Status Register */
+    pub const MSR_CTS: usize = 4;
+    pub const MSR_DSR: usize = 5;
+    pub const MSR_RI: usize = 6;
+    pub const MSR_DCD: usize = 7;
+}
+
+/// Size of the RX FIFO when FIFOs are enabled
+const RX_FIFO_LEN: usize = 16;
+
+/// PP5020 serial controller.
+///
+/// Emulates a 16550-compatible UART, connected to a host-side
+/// [SerialBackend]. The system must periodically call `tick` to poll the
+/// backend for incoming data.
+///
+/// Data is transmitted instantaneously, so the TX FIFO is always empty. Baud
+/// rate and line settings are tracked, but have no effect on the emulation.
 #[derive(Debug)]
 pub struct Serial {
     label: &'static str,
+    irq: irq::Sender,
+    backend: Box<dyn SerialBackend>,
 
     ier: u8,
     fcr: u8,
     lcr: u8,
     mcr: u8,
+    scr: u8,
+    dll: u8,
+    dlm: u8,
+
+    rx_fifo: VecDeque<u8>,
+    /// Set when the backend had no new data on the last poll
+    rx_idle: bool,
+    overrun: bool,
+    thre_pending: bool,
+}
+
+impl Snapshot for Serial {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.irq.save(w)?;
+        self.ier.save(w)?;
+        self.fcr.save(w)?;
+        self.lcr.save(w)?;
+        self.mcr.save(w)?;
+        self.scr.save(w)?;
+        self.dll.save(w)?;
+        self.dlm.save(w)?;
+        self.rx_fifo.len().save(w)?;
+        for b in self.rx_fifo.iter_mut() {
+            b.save(w)?;
+        }
+        self.rx_idle.save(w)?;
+        self.overrun.save(w)?;
+        self.thre_pending.save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        self.irq.load(r)?;
+        self.ier.load(r)?;
+        self.fcr.load(r)?;
+        self.lcr.load(r)?;
+        self.mcr.load(r)?;
+        self.scr.load(r)?;
+        self.dll.load(r)?;
+        self.dlm.load(r)?;
+        let mut len = 0usize;
+        len.load(r)?;
+        if len > RX_FIFO_LEN {
+            return Err(invalid_data("invalid UART RX FIFO length"));
+        }
+        self.rx_fifo.clear();
+        for _ in 0..len {
+            let mut b = 0u8;
+            b.load(r)?;
+            self.rx_fifo.push_back(b);
+        }
+        self.rx_idle.load(r)?;
+        self.overrun.load(r)?;
+        self.thre_pending.load(r)
+    }
 }
 
 impl Serial {
-    pub fn new(label: &'static str) -> Serial {
+    pub fn new(label: &'static str, irq: irq::Sender, backend: Box<dyn SerialBackend>) -> Serial {
         Serial {
             label,
+            irq,
+            backend,
 
             ier: 0,
             fcr: 0,
             lcr: 0,
             mcr: 0,
+            scr: 0,
+            dll: 0,
+            dlm: 0,
+
+            rx_fifo: VecDeque::with_capacity(RX_FIFO_LEN),
+            rx_idle: true,
+            overrun: false,
+            thre_pending: false,
+        }
+    }
+
+    /// Poll the backend for incoming data, and update the IRQ line.
+    pub fn tick(&mut self) {
+        if !self.loopback() && self.rx_fifo.len() < self.rx_fifo_len() {
+            match self.backend.read() {
+                Some(b) => {
+                    self.rx_fifo.push_back(b);
+                    self.rx_idle = false;
+                }
+                None => self.rx_idle = true,
+            }
+        } else {
+            self.rx_idle = true;
+        }
+
+        self.update_irq();
+    }
+
+    fn fifo_enabled(&self) -> bool {
+        self.fcr.get_bit(flags::FCR_EN)
+    }
+
+    fn loopback(&self) -> bool {
+        self.mcr.get_bit(flags::MCR_LOOPBACK)
+    }
+
+    fn dlab(&self) -> bool {
+        self.lcr.get_bit(flags::LCR_DLAB)
+    }
+
+    fn rx_fifo_len(&self) -> usize {
+        if self.fifo_enabled() {
+            RX_FIFO_LEN
+        } else {
+            1
         }
     }
+
+    fn rx_trigger_level(&self) -> usize {
+        if !self.fifo_enabled() {
+            return 1;
+        }
+
+        match self.fcr.get_bits(flags::FCR_RX_TRIGGER) {
+            0 => 1,
+            1 => 4,
+            2 => 8,
+            3 => 14,
+            _ => unreachable!(),
+        }
+    }
+
+    fn push_rx(&mut self, val: u8) {
+        if self.rx_fifo.len() == self.rx_fifo_len() {
+            self.overrun = true;
+        } else {
+            self.rx_fifo.push_back(val);
+        }
+    }
+
+    /// Returns the highest priority pending interrupt.
+    fn iir(&self) -> u8 {
+        if self.ier.get_bit(flags::IER_RLS) && self.overrun {
+            flags::IIR_RLS
+        } else if self.ier.get_bit(flags::IER_RDA) && self.rx_fifo.len() >= self.rx_trigger_level()
+        {
+            flags::IIR_RDA
+        } else if self.ier.get_bit(flags::IER_RDA)
+            && self.fifo_enabled()
+            && !self.rx_fifo.is_empty()
+            && self.rx_idle
+        {
+            flags::IIR_TIMEOUT
+        } else if self.ier.get_bit(flags::IER_THRE) && self.thre_pending {
+            flags::IIR_THRE
+        } else {
+            flags::IIR_NONE
+        }
+    }
+
+    fn update_irq(&mut self) {
+        if self.iir() != flags::IIR_NONE {
+            self.irq.assert()
+        } else {
+            self.irq.clear()
+        }
+    }
+
+    fn lsr(&mut self) -> u8 {
+        let mut val = 0u8;
+        val.set_bit(flags::LSR_DR, !self.rx_fifo.is_empty());
+        val.set_bit(flags::LSR_OE, std::mem::replace(&mut self.overrun, false));
+        // data is transmitted instantaneously
+        val.set_bit(flags::LSR_THRE, true);
+        val.set_bit(flags::LSR_TEMT, true);
+        val
+    }
+
+    fn msr(&self) -> u8 {
+        let mut val = 0u8;
+        if self.loopback() {
+            val.set_bit(flags::MSR_CTS, self.mcr.get_bit(flags::MCR_RTS));
+            val.set_bit(flags::MSR_DSR, self.mcr.get_bit(flags::MCR_DTR));
+            val.set_bit(flags::MSR_RI, self.mcr.get_bit(flags::MCR_OUT1));
+            val.set_bit(flags::MSR_DCD, self.mcr.get_bit(flags::MCR_OUT2));
+        } else {
+            // the host is always connected and ready
+            val.set_bit(flags::MSR_CTS, true);
+            val.set_bit(flags::MSR_DSR, true);
+            val.set_bit(flags::MSR_DCD, true);
+        }
+        val
+    }
+
+    fn transmit(&mut self, val: u8) {
+        if self.loopback() {
+            self.push_rx(val);
+        } else if let Err(e) = self.backend.write(val) {
+            warn!("Serial {}: failed to write to backend: {}", self.label, e);
+        }
+        self.thre_pending = true;
+    }
 }
 
 impl Device for Serial {
@@ -35,6 +287,8 @@ impl Device for Serial {
 
     fn probe(&self, offset: u32) -> Probe {
         let reg = match offset {
+            0x00 if self.dlab() => "DLL",
+            0x04 if self.dlab() => "DLM",
             0x00 => "RBR/THR",
             0x04 => "IER",
             0x08 => "FCR/IIR",
@@ -42,7 +296,7 @@ impl Device for Serial {
             0x10 => "MCR",
             0x14 => "LSR",
             0x18 => "MSR",
-            0x1c => "SPR",
+            0x1c => "SCR",
             _ => return Probe::Unmapped,
         };
 
@@ -52,43 +306,83 @@ impl Device for Serial {
 
 impl Memory for Serial {
     fn r32(&mut self, offset: u32) -> MemResult<u32> {
-        match offset {
+        let val = match offset {
+            0x00 if self.dlab() => self.dll,
+            0x04 if self.dlab() => self.dlm,
             0x00 => {
-                // TODO: properly wire up uart
-                Err(StubRead(Info, 0))
+                let val = self.rx_fifo.pop_front().unwrap_or(0);
+                // reading RBR resets the character timeout
+                self.rx_idle = false;
+                self.update_irq();
+                val
             }
-            0x04 => Err(StubRead(Info, self.ier as u32)),
-            0x08 => Err(StubRead(Info, self.fcr as u32)),
-            0x0c => Err(StubRead(Info, self.lcr as u32)),
-            0x10 => Err(StubRead(Info, self.mcr as u32)),
-            // always ready to tx and rx
-            0x14 => Ok(0x21),
-            0x18 => Err(Unimplemented),
-            0x1c => Err(Unimplemented),
-            _ => Err(Unexpected),
-        }
+            0x04 => self.ier,
+            0x08 => {
+                let iir = self.iir();
+                if iir == flags::IIR_THRE {
+                    self.thre_pending = false;
+                    self.update_irq();
+                }
+                if self.fifo_enabled() {
+                    iir | flags::IIR_FIFO_EN
+                } else {
+                    iir
+                }
+            }
+            0x0c => self.lcr,
+            0x10 => self.mcr,
+            0x14 => {
+                let val = self.lsr();
+                self.update_irq();
+                val
+            }
+            0x18 => self.msr(),
+            0x1c => self.scr,
+            _ => return Err(Unexpected),
+        };
+
+        Ok(val as u32)
     }
 
     fn w32(&mut self, offset: u32, val: u32) -> MemResult<()> {
         let val = val.trunc_to_u8()?;
 
         match offset {
-            0x0 => Ok({
-                // TODO: properly wire up uart
-                if val.is_ascii() {
-                    print!("{}", val as char);
-                } else {
-                    print!("\\x{:02x}", val);
+            0x00 if self.dlab() => self.dll = val,
+            0x04 if self.dlab() => self.dlm = val,
+            0x00 => self.transmit(val),
+            0x04 => {
+                let val = val & flags::IER_MASK;
+                // enabling the THRE interrupt while the THR is empty
+                // immediately triggers an interrupt
+                if val.get_bit(flags::IER_THRE) && !self.ier.get_bit(flags::IER_THRE) {
+                    self.thre_pending = true;
                 }
-            }),
-            0x04 => Err(StubWrite(Info, self.ier = val)),
-            0x08 => Err(StubWrite(Info, self.fcr = val)),
-            0x0c => Err(StubWrite(Info, self.lcr = val)),
-            0x10 => Err(StubWrite(Info, self.mcr = val)),
-            0x14 => Err(InvalidAccess),
-            0x18 => Err(Unimplemented),
-            0x1c => Err(Unimplemented),
-            _ => Err(Unexpected),
+                self.ier = val;
+            }
+            0x08 => {
+                if val.get_bit(flags::FCR_EN) != self.fifo_enabled()
+                    || val.get_bit(flags::FCR_RX_CLEAR)
+                {
+                    self.rx_fifo.clear();
+                }
+                if val.get_bit(flags::FCR_TX_CLEAR) {
+                    self.thre_pending = true;
+                }
+                let mut val = val;
+                self.fcr = *val
+                    .set_bit(flags::FCR_RX_CLEAR, false)
+                    .set_bit(flags::FCR_TX_CLEAR, false);
+            }
+            0x0c => self.lcr = val,
+            0x10 => self.mcr = val,
+            0x14 => return Err(InvalidAccess),
+            0x18 => return Err(InvalidAccess),
+            0x1c => self.scr = val,
+            _ => return Err(Unexpected),
         }
+
+        self.update_irq();
+        Ok(())
     }
 }
@@ -1,19 +1,36 @@
 use crate::devices::prelude::*;
 
-use relativity::Instant;
+use std::time::Duration;
+
+use crate::clock::Clock;
 
 /// 32 bit timer which ticks every usec.
 #[derive(Debug)]
 pub struct UsecTimer {
+    clock: Clock,
     val: u32,
-    last: Instant,
+    last: Duration,
 }
 
+impl_snapshot!(UsecTimer { val, last });
+
 impl UsecTimer {
-    pub fn new() -> UsecTimer {
+    pub fn new(clock: Clock) -> UsecTimer {
         UsecTimer {
             val: 0,
-            last: Instant::now(),
+            last: clock.now(),
+            clock,
+        }
+    }
+
+    fn update_val(&mut self) {
+        let elapsed = self.clock.now().saturating_sub(self.last);
+        let elapsed_as_micros = elapsed.as_micros() as u32;
+        // Reading the timer value in a tight loop could result in a delta time of 0
+        if elapsed_as_micros != 0 {
+            self.val = self.val.wrapping_add(elapsed_as_micros);
+            // don't discard any sub-microsecond remainder
+            self.last += Duration::from_micros(elapsed_as_micros as u64);
         }
     }
 }
@@ -37,15 +54,7 @@ impl Memory for UsecTimer {
     fn r32(&mut self, offset: u32) -> MemResult<u32> {
         match offset {
             0x0 => {
-                let now = Instant::now();
-                let elapsed = now.duration_since(self.last);
-                let elapsed_as_micros = elapsed.as_micros() as u32;
-                // Reading the timer value in a tight loop could result in a delta time of 0
-                if elapsed_as_micros != 0 {
-                    self.val = self.val.wrapping_add(elapsed_as_micros);
-                    self.last = now;
-                }
-
+                self.update_val();
                 Ok(self.val)
             }
             _ => Err(Unexpected),
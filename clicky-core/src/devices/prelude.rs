@@ -12,7 +12,7 @@ pub use crate::error::{
     MemResult,
 };
 pub use crate::executor::*;
-pub use crate::memory::Memory;
+pub use crate::memory::{peek_result, Memory};
 pub use crate::signal::{self, irq};
 
 // XXX: the fact that this is required is indicative of the need to rework the
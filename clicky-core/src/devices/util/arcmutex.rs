@@ -1,8 +1,11 @@
 use crate::devices::prelude::*;
 
+use std::io::{self, Read, Write};
 use std::sync::Arc;
 use std::sync::{LockResult, Mutex, MutexGuard};
 
+use crate::snapshot::Snapshot;
+
 /// Wrapper around Arc<Mutex<_>> which implements the `Device` and `Memory`
 /// traits without having to explicitly deref + lock the underlying device.
 #[derive(Debug)]
@@ -32,6 +35,16 @@ impl<D> ArcMutexDevice<D> {
     }
 }
 
+impl<D: Snapshot> Snapshot for ArcMutexDevice<D> {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.device.lock().unwrap().save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        self.device.lock().unwrap().load(r)
+    }
+}
+
 impl<D: Device> Device for ArcMutexDevice<D> {
     fn kind(&self) -> &'static str {
         self.device.lock().unwrap().kind()
@@ -70,4 +83,12 @@ impl<D: Memory> Memory for ArcMutexDevice<D> {
     fn w16(&mut self, offset: u32, val: u16) -> MemResult<()> {
         self.device.lock().unwrap().w16(offset, val)
     }
+
+    fn peek32(&self, offset: u32) -> Option<u32> {
+        self.device.lock().unwrap().peek32(offset)
+    }
+
+    fn peek8(&self, offset: u32) -> Option<u8> {
+        self.device.lock().unwrap().peek8(offset)
+    }
 }
@@ -4,28 +4,49 @@ use crate::memory::{MemAccess, MemAccessKind, ToMemAccess};
 
 /// `MemSniffer` wraps a `Memory` object, forwarding requests to the underlying
 /// memory object, while also logging accesses with the provided callback.
-#[derive(Debug)]
 pub struct MemSniffer<'a, M, F: FnMut(MemAccess)> {
     mem: &'a mut M,
-    addrs: &'a [u32],
+    /// Filter on the `(addr, len)` of accesses (`None` == log all accesses)
+    filter: Option<&'a (dyn Fn(u32, u32) -> bool + Sync)>,
     on_access: F,
 }
 
 impl<'a, M: Memory, F: FnMut(MemAccess)> MemSniffer<'a, M, F> {
-    pub fn new(mem: &'a mut M, addrs: &'a [u32], on_access: F) -> MemSniffer<'a, M, F> {
+    /// Log accesses for which `filter(addr, len)` returns true.
+    pub fn new(
+        mem: &'a mut M,
+        filter: &'a (dyn Fn(u32, u32) -> bool + Sync),
+        on_access: F,
+    ) -> MemSniffer<'a, M, F> {
         MemSniffer {
             mem,
-            addrs,
+            filter: Some(filter),
             on_access,
         }
     }
+
+    /// Log all accesses.
+    pub fn new_all(mem: &'a mut M, on_access: F) -> MemSniffer<'a, M, F> {
+        MemSniffer {
+            mem,
+            filter: None,
+            on_access,
+        }
+    }
+
+    fn wants(&self, addr: u32, len: u32) -> bool {
+        match self.filter {
+            Some(filter) => filter(addr, len),
+            None => true,
+        }
+    }
 }
 
 macro_rules! impl_memsniff_r {
     ($fn:ident, $ret:ty) => {
         fn $fn(&mut self, addr: u32) -> MemResult<$ret> {
             let ret = self.mem.$fn(addr)?;
-            if self.addrs.contains(&addr) {
+            if self.wants(addr, std::mem::size_of::<$ret>() as u32) {
                 (self.on_access)(ret.to_memaccess(addr, MemAccessKind::Read));
             }
             Ok(ret)
@@ -37,7 +58,7 @@ macro_rules! impl_memsniff_w {
     ($fn:ident, $val:ty) => {
         fn $fn(&mut self, addr: u32, val: $val) -> MemResult<()> {
             self.mem.$fn(addr, val)?;
-            if self.addrs.contains(&addr) {
+            if self.wants(addr, std::mem::size_of::<$val>() as u32) {
                 (self.on_access)(val.to_memaccess(addr, MemAccessKind::Write));
             }
             Ok(())
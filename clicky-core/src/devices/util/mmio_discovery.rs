@@ -0,0 +1,228 @@
+use crate::devices::prelude::*;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::memory::{MemAccess, MemAccessKind, MemAccessVal, ToMemAccess};
+
+/// Maximum number of distinct values / PCs recorded per address.
+const MAX_SAMPLES: usize = 16;
+
+/// How a discovered address was backed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoveredKind {
+    /// Not mapped to any device (or mapped to an unimplemented register), and
+    /// automatically backed by a recording stub.
+    Unmapped,
+    /// Backed by a stubbed register implementation.
+    Stubbed,
+}
+
+/// A bounded set of samples, which remembers if any samples were dropped.
+#[derive(Debug, Default)]
+struct Samples {
+    set: BTreeSet<u32>,
+    overflow: bool,
+}
+
+impl Samples {
+    fn insert(&mut self, val: u32) {
+        if self.set.len() < MAX_SAMPLES || self.set.contains(&val) {
+            self.set.insert(val);
+        } else {
+            self.overflow = true;
+        }
+    }
+}
+
+impl std::fmt::Display for Samples {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for val in &self.set {
+            write!(f, " {:#010x}", val)?;
+        }
+        if self.overflow {
+            write!(f, " ...")?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything known about a discovered address.
+#[derive(Debug)]
+struct AddrInfo {
+    kind: DiscoveredKind,
+    device: String,
+    reads: usize,
+    writes: usize,
+    /// Bitmask of access widths (in bytes)
+    widths: u8,
+    /// Last value written to an unmapped address
+    last_val: u32,
+    values: Samples,
+    pcs: Samples,
+}
+
+/// Records accesses to unmapped / stubbed memory locations, automatically
+/// backing unmapped locations with a stub which returns the last value written
+/// to it (or zero).
+///
+/// Used by [MmioDiscoveryMem] to help discover which (unimplemented) devices
+/// software expects to be present. The `Display` implementation generates a
+/// report of all discovered addresses.
+#[derive(Debug, Default)]
+pub struct MmioDiscovery {
+    addrs: BTreeMap<u32, AddrInfo>,
+}
+
+impl MmioDiscovery {
+    pub fn new() -> MmioDiscovery {
+        MmioDiscovery::default()
+    }
+
+    /// Return the value most recently written to an unmapped address.
+    fn last_val(&self, addr: u32) -> u32 {
+        self.addrs.get(&addr).map(|info| info.last_val).unwrap_or(0)
+    }
+
+    fn record(&mut self, kind: DiscoveredKind, device: &dyn Device, access: MemAccess, pc: u32) {
+        let info = self.addrs.entry(access.offset).or_insert_with(|| AddrInfo {
+            kind,
+            device: device.probe(access.offset).to_string(),
+            reads: 0,
+            writes: 0,
+            widths: 0,
+            last_val: 0,
+            values: Samples::default(),
+            pcs: Samples::default(),
+        });
+
+        let (width, val) = match access.val {
+            MemAccessVal::U8(v) => (1, v as u32),
+            MemAccessVal::U16(v) => (2, v as u32),
+            MemAccessVal::U32(v) => (4, v),
+        };
+
+        match access.kind {
+            MemAccessKind::Read => info.reads += 1,
+            MemAccessKind::Write => {
+                info.writes += 1;
+                info.last_val = val;
+            }
+        }
+        info.widths |= width;
+        info.values.insert(val);
+        info.pcs.insert(pc);
+    }
+}
+
+impl std::fmt::Display for MmioDiscovery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "MMIO discovery report: {} address(es)", self.addrs.len())?;
+        for (addr, info) in &self.addrs {
+            let widths = [(1, "8"), (2, "16"), (4, "32")]
+                .iter()
+                .filter(|(mask, _)| info.widths & mask != 0)
+                .map(|(_, width)| *width)
+                .collect::<Vec<_>>()
+                .join("/");
+
+            writeln!(
+                f,
+                "{:#010x} {:<8} reads={:<8} writes={:<8} widths={:<8} [{}]",
+                addr,
+                match info.kind {
+                    DiscoveredKind::Unmapped => "unmapped",
+                    DiscoveredKind::Stubbed => "stubbed",
+                },
+                info.reads,
+                info.writes,
+                widths,
+                info.device
+            )?;
+            writeln!(f, "    values:{}", info.values)?;
+            writeln!(f, "    pcs:   {}", info.pcs)?;
+        }
+        Ok(())
+    }
+}
+
+/// `MmioDiscoveryMem` wraps a `Memory` object, forwarding requests to the
+/// underlying memory object, while recording unmapped / stubbed accesses to a
+/// [MmioDiscovery]. If no `MmioDiscovery` is provided, all accesses are passed
+/// through as-is.
+pub struct MmioDiscoveryMem<'a, M> {
+    mem: &'a mut M,
+    discovery: Option<&'a mut MmioDiscovery>,
+    /// PC of the instruction performing the accesses
+    pc: u32,
+}
+
+impl<'a, M: Memory + Device> MmioDiscoveryMem<'a, M> {
+    pub fn new(
+        mem: &'a mut M,
+        discovery: Option<&'a mut MmioDiscovery>,
+        pc: u32,
+    ) -> MmioDiscoveryMem<'a, M> {
+        MmioDiscoveryMem { mem, discovery, pc }
+    }
+}
+
+macro_rules! impl_discovery_r {
+    ($fn:ident, $ret:ty) => {
+        fn $fn(&mut self, addr: u32) -> MemResult<$ret> {
+            let res = self.mem.$fn(addr);
+            let discovery = match &mut self.discovery {
+                Some(discovery) => discovery,
+                None => return res,
+            };
+
+            match res {
+                Err(Unexpected) | Err(Unimplemented) => {
+                    let val = discovery.last_val(addr) as $ret;
+                    let access = val.to_memaccess(addr, MemAccessKind::Read);
+                    discovery.record(DiscoveredKind::Unmapped, &*self.mem, access, self.pc);
+                    Ok(val)
+                }
+                Err(StubRead(level, val)) => {
+                    let access = (val as $ret).to_memaccess(addr, MemAccessKind::Read);
+                    discovery.record(DiscoveredKind::Stubbed, &*self.mem, access, self.pc);
+                    Err(StubRead(level, val))
+                }
+                res => res,
+            }
+        }
+    };
+}
+
+macro_rules! impl_discovery_w {
+    ($fn:ident, $val:ty) => {
+        fn $fn(&mut self, addr: u32, val: $val) -> MemResult<()> {
+            let res = self.mem.$fn(addr, val);
+            let discovery = match &mut self.discovery {
+                Some(discovery) => discovery,
+                None => return res,
+            };
+
+            let access = val.to_memaccess(addr, MemAccessKind::Write);
+            match res {
+                Err(Unexpected) | Err(Unimplemented) => {
+                    discovery.record(DiscoveredKind::Unmapped, &*self.mem, access, self.pc);
+                    Ok(())
+                }
+                Err(StubWrite(level, ())) => {
+                    discovery.record(DiscoveredKind::Stubbed, &*self.mem, access, self.pc);
+                    Err(StubWrite(level, ()))
+                }
+                res => res,
+            }
+        }
+    };
+}
+
+impl<'a, M: Memory + Device> Memory for MmioDiscoveryMem<'a, M> {
+    impl_discovery_r!(r8, u8);
+    impl_discovery_r!(r16, u16);
+    impl_discovery_r!(r32, u32);
+    impl_discovery_w!(w8, u8);
+    impl_discovery_w!(w16, u16);
+    impl_discovery_w!(w32, u32);
+}
@@ -2,6 +2,8 @@
 
 mod arcmutex;
 mod mem_sniffer;
+mod mmio_discovery;
 
 pub use arcmutex::*;
 pub use mem_sniffer::*;
+pub use mmio_discovery::*;
@@ -1,5 +1,9 @@
 use crate::memory::MemAccess;
 
+mod policy;
+
+pub use policy::{ErrorAction, ErrorClass, ErrorPolicy, ErrorScope};
+
 pub type MemResult<T> = Result<T, MemException>;
 pub type FatalMemResult<T> = Result<T, FatalMemException>;
 
@@ -61,16 +65,22 @@ impl From<std::io::Error> for MemException {
 #[derive(Debug, Clone)]
 pub struct MemExceptionCtx {
     pub pc: u32,
+    /// Symbolized PC (if symbols are available)
+    pub pc_sym: Option<String>,
     pub access: MemAccess,
     pub in_device: String,
 }
 
 impl std::fmt::Display for MemExceptionCtx {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[pc {:#010x?}", self.pc)?;
+        if let Some(sym) = &self.pc_sym {
+            write!(f, " <{}>", sym)?;
+        }
         write!(
             f,
-            "[pc {:#010x?}][addr {:#010x?}][{}]",
-            self.pc, self.access.offset, self.in_device
+            "][addr {:#010x?}][{}]",
+            self.access.offset, self.in_device
         )
     }
 }
@@ -84,29 +94,77 @@ pub struct FatalMemException {
 }
 
 impl MemException {
-    /// Handle the memory exception, potentially returning a FatalMemException.
+    /// Check if the exception corresponds to a fault which real hardware would
+    /// signal to the CPU (as a Data / Prefetch Abort), as opposed to an
+    /// emulator-internal error.
+    pub fn is_guest_abort(&self) -> bool {
+        use MemException::*;
+        matches!(self, Misaligned | MmuViolation | Unexpected)
+    }
+
+    /// Handle the memory exception according to the provided `policy`,
+    /// potentially returning a FatalMemException.
     pub fn resolve(
         self,
         target: &'static str,
         ctx: MemExceptionCtx,
+        policy: &mut ErrorPolicy,
     ) -> Result<(), FatalMemException> {
-        macro_rules! mlog {
-            (($level:ident, $ctx:ident) => ($($args:tt)*)) => {
-                if log_enabled!($level) {
-                    let $ctx = $ctx;
-                    log!(target: target, $level, $($args)*)
-                }
-            };
+        let class = match ErrorClass::of(&self) {
+            Some(class) => class,
+            None => return self.resolve_default(target, ctx, policy),
+        };
+
+        match policy.action(class, &ctx) {
+            ErrorAction::Default => self.resolve_default(target, ctx, policy),
+            ErrorAction::Ignore => Ok(()),
+            ErrorAction::Log => {
+                let level = match self {
+                    MemException::StubRead(level, _) | MemException::StubWrite(level, _) => level,
+                    MemException::ContractViolation { severity, .. } => severity,
+                    _ => log::Level::Error,
+                };
+                self.log(target, level, &ctx);
+                Ok(())
+            }
+            ErrorAction::Count => {
+                policy.count(class, &ctx);
+                Ok(())
+            }
+            ErrorAction::Fatal => Err(FatalMemException {
+                context: ctx,
+                reason: self,
+            }),
+        }
+    }
+
+    fn log(&self, target: &'static str, level: log::Level, ctx: &MemExceptionCtx) {
+        if !log_enabled!(level) {
+            return;
         }
 
         use MemException::*;
         match self {
-            StubRead(level, _) => {
-                mlog! { (level, ctx) => ("{} stubbed read ({})", ctx, ctx.access.val) }
+            StubRead(..) => {
+                log!(target: target, level, "{} stubbed read ({})", ctx, ctx.access.val)
             }
-            StubWrite(level, ()) => {
-                mlog! { (level, ctx) => ("{} stubbed write ({})", ctx, ctx.access.val) }
+            StubWrite(..) => {
+                log!(target: target, level, "{} stubbed write ({})", ctx, ctx.access.val)
             }
+            ContractViolation { msg, .. } => log!(target: target, level, "{} {}", ctx, msg),
+            e => log!(target: target, level, "{} {:?} ({})", ctx, e, ctx.access.val),
+        }
+    }
+
+    fn resolve_default(
+        self,
+        target: &'static str,
+        ctx: MemExceptionCtx,
+        policy: &mut ErrorPolicy,
+    ) -> Result<(), FatalMemException> {
+        use MemException::*;
+        match self {
+            StubRead(level, _) | StubWrite(level, ()) => self.log(target, level, &ctx),
             // XXX: absolutely disgusting way to handle i2c exceptions, yikes
             I2CException {
                 e,
@@ -116,35 +174,30 @@ impl MemException {
                 "I2C",
                 MemExceptionCtx {
                     pc: ctx.pc,
+                    pc_sym: ctx.pc_sym,
                     access,
                     in_device,
                 },
+                policy,
             )?,
-            // FIXME?: Misaligned access (i.e: Data Abort) should be a CPU exception
+            // NOTE: when guest aborts are enabled, the system delivers these to the CPU
+            // as Data / Prefetch Aborts instead (see `is_guest_abort`)
             Misaligned => {
                 return Err(FatalMemException {
                     context: ctx,
                     reason: self,
                 });
             }
-            ContractViolation {
-                msg,
-                severity,
-                stub_val,
-            } => {
-                // TODO: use config to determine what Error-level ContractViolation should
-                // terminate execution
+            ContractViolation { severity, .. } => {
+                // Error-level violations terminate execution, unless overridden by the
+                // ErrorPolicy
                 if severity == log::Level::Error {
                     return Err(FatalMemException {
                         context: ctx,
-                        reason: ContractViolation {
-                            msg,
-                            severity,
-                            stub_val,
-                        },
+                        reason: self,
                     });
                 } else {
-                    mlog! { (severity, ctx) => ("{} {}", ctx, msg) }
+                    self.log(target, severity, &ctx)
                 }
             }
             Unexpected | Unimplemented | Fatal(_) | MmuViolation | InvalidAccess => {
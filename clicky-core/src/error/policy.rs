@@ -0,0 +1,171 @@
+//! Configurable handling of non-fatal memory exceptions.
+
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use super::{MemException, MemExceptionCtx};
+
+/// Categories of memory exceptions which can be handled according to an
+/// [ErrorPolicy].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorClass {
+    /// Accesses to stubbed-out registers ([MemException::StubRead] /
+    /// [MemException::StubWrite]).
+    Stub,
+    /// Accesses to unimplemented registers ([MemException::Unimplemented] /
+    /// [MemException::Unexpected]).
+    Unimplemented,
+    /// Guest misusing a device ([MemException::ContractViolation] /
+    /// [MemException::InvalidAccess]).
+    ContractViolation,
+}
+
+impl ErrorClass {
+    /// Return the class of the exception, or `None` if the exception isn't
+    /// subject to an [ErrorPolicy] (e.g: internal emulator errors).
+    pub fn of(e: &MemException) -> Option<ErrorClass> {
+        use MemException::*;
+        match e {
+            StubRead(..) | StubWrite(..) => Some(ErrorClass::Stub),
+            Unimplemented | Unexpected => Some(ErrorClass::Unimplemented),
+            ContractViolation { .. } | InvalidAccess => Some(ErrorClass::ContractViolation),
+            Fatal(_) | I2CException { .. } | Misaligned | MmuViolation => None,
+        }
+    }
+}
+
+/// What to do when a memory exception occurs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorAction {
+    /// The exception's default handling: stubs and non-Error level contract
+    /// violations are logged, everything else terminates execution.
+    Default,
+    /// Silently continue execution.
+    Ignore,
+    /// Log the exception, and continue execution.
+    Log,
+    /// Tally the exception (see [ErrorPolicy::counts]), and continue execution.
+    Count,
+    /// Terminate execution.
+    Fatal,
+}
+
+/// Which accesses an [ErrorPolicy] rule applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorScope {
+    /// All accesses.
+    Any,
+    /// Accesses to the specified device kind (e.g: `IDE`, `GPIO`). Matches any
+    /// device along the probed path (see [Probe](crate::devices::Probe)).
+    Device(String),
+    /// Accesses to the specified (inclusive) address range.
+    Range(RangeInclusive<u32>),
+}
+
+impl ErrorScope {
+    fn matches(&self, ctx: &MemExceptionCtx) -> bool {
+        match self {
+            ErrorScope::Any => true,
+            ErrorScope::Device(kind) => ctx.in_device.split(" > ").any(|dev| {
+                let dev_kind = dev.split(':').next().unwrap_or(dev);
+                dev_kind.eq_ignore_ascii_case(kind)
+            }),
+            ErrorScope::Range(range) => range.contains(&ctx.access.offset),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    class: Option<ErrorClass>,
+    scope: ErrorScope,
+    action: ErrorAction,
+}
+
+/// Decides how stubbed, unimplemented, and contract-violating memory accesses
+/// are handled.
+///
+/// A policy is an ordered list of rules, where the _last_ matching rule
+/// determines the action taken. Accesses which don't match any rule use
+/// [ErrorAction::Default].
+#[derive(Debug, Clone, Default)]
+pub struct ErrorPolicy {
+    rules: Vec<Rule>,
+    counts: BTreeMap<(ErrorClass, String), usize>,
+}
+
+impl ErrorPolicy {
+    /// A policy which applies the default action to all exceptions.
+    pub fn new() -> ErrorPolicy {
+        ErrorPolicy::default()
+    }
+
+    /// A policy which terminates execution on any unimplemented access or
+    /// contract violation (regardless of severity).
+    pub fn strict() -> ErrorPolicy {
+        let mut policy = ErrorPolicy::new();
+        policy.add_rule(
+            Some(ErrorClass::Unimplemented),
+            ErrorScope::Any,
+            ErrorAction::Fatal,
+        );
+        policy.add_rule(
+            Some(ErrorClass::ContractViolation),
+            ErrorScope::Any,
+            ErrorAction::Fatal,
+        );
+        policy
+    }
+
+    /// A policy which keeps going at all costs, logging any unimplemented
+    /// accesses and contract violations.
+    pub fn permissive() -> ErrorPolicy {
+        let mut policy = ErrorPolicy::new();
+        policy.add_rule(
+            Some(ErrorClass::Unimplemented),
+            ErrorScope::Any,
+            ErrorAction::Log,
+        );
+        policy.add_rule(
+            Some(ErrorClass::ContractViolation),
+            ErrorScope::Any,
+            ErrorAction::Log,
+        );
+        policy
+    }
+
+    /// Append a rule to the policy, overriding any existing rules which match
+    /// the same accesses. `class: None` applies the rule to all classes.
+    pub fn add_rule(&mut self, class: Option<ErrorClass>, scope: ErrorScope, action: ErrorAction) {
+        self.rules.push(Rule {
+            class,
+            scope,
+            action,
+        })
+    }
+
+    /// Determine the action to take for an exception of the given class.
+    pub fn action(&self, class: ErrorClass, ctx: &MemExceptionCtx) -> ErrorAction {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.class.map_or(true, |c| c == class) && rule.scope.matches(ctx))
+            .map(|rule| rule.action)
+            .unwrap_or(ErrorAction::Default)
+    }
+
+    pub(super) fn count(&mut self, class: ErrorClass, ctx: &MemExceptionCtx) {
+        *self
+            .counts
+            .entry((class, ctx.in_device.clone()))
+            .or_default() += 1;
+    }
+
+    /// Iterate over the number of exceptions tallied by [ErrorAction::Count]
+    /// rules, grouped by class and device.
+    pub fn counts(&self) -> impl Iterator<Item = (ErrorClass, &str, usize)> {
+        self.counts
+            .iter()
+            .map(|((class, dev), n)| (*class, dev.as_str(), *n))
+    }
+}
@@ -4,6 +4,10 @@
 /// dimensions of the image.
 pub type RenderCallback =
     Box<dyn FnMut(/* rgba_framebuffer: */ &mut Vec<u32>) -> (usize, usize) + Send>;
+/// `AudioCallback` is called with a buffer of interleaved (L, R) 16-bit PCM
+/// samples, which it replaces with any samples produced since the last call.
+/// Returns the current sample rate (in Hz).
+pub type AudioCallback = Box<dyn FnMut(/* pcm_samples: */ &mut Vec<i16>) -> u32 + Send>;
 /// `ButtonCallback` should be called whenever a button is pressed and released
 /// (passing `true` and `false` respectively)
 pub type ButtonCallback = Box<dyn FnMut(/* pressed: */ bool) + Send>;
@@ -4,11 +4,21 @@ extern crate static_assertions;
 #[macro_use]
 extern crate log;
 
+// must come first, as it exports macros used by other modules
+#[macro_use]
+pub mod snapshot;
+
 pub mod block;
+pub mod clock;
+pub mod coverage;
 pub mod devices;
 pub mod error;
 pub mod executor;
 pub mod gui;
 pub mod memory;
+pub mod profiler;
+pub mod serial;
 pub mod signal;
+pub mod symbols;
 pub mod sys;
+pub mod trace;
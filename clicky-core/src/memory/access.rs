@@ -6,6 +6,26 @@ pub enum MemAccessVal {
     U32(u32),
 }
 
+impl MemAccessVal {
+    /// Size of the access (in bytes).
+    pub fn size(&self) -> u32 {
+        match self {
+            MemAccessVal::U8(_) => 1,
+            MemAccessVal::U16(_) => 2,
+            MemAccessVal::U32(_) => 4,
+        }
+    }
+
+    /// The accessed value, zero-extended to 32 bits.
+    pub fn as_u32(&self) -> u32 {
+        match *self {
+            MemAccessVal::U8(v) => v as u32,
+            MemAccessVal::U16(v) => v as u32,
+            MemAccessVal::U32(v) => v,
+        }
+    }
+}
+
 /// Memory Access Kind (Read or Write)
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MemAccessKind {
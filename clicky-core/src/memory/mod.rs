@@ -11,6 +11,10 @@ pub use access::{MemAccess, MemAccessKind, MemAccessVal, ToMemAccess};
 ///
 /// Default implementations for 8-bit and 16-bit read/write return a
 /// [MemException::Misaligned] if the address isn't aligned properly.
+///
+/// Registers can also be _peeked_ at (e.g: by a debugger), which must not have
+/// any side effects on the system (such as clearing a status register, or
+/// popping a FIFO). By default, nothing can be peeked at.
 pub trait Memory {
     /// Read a 32 bit value at a given offset
     fn r32(&mut self, offset: u32) -> MemResult<u32>;
@@ -52,6 +56,27 @@ pub trait Memory {
             self.w32(offset, val as u32)
         }
     }
+
+    /// Read a 32 bit value at a given (word-aligned) offset without any side
+    /// effects, returning `None` if the value can't be read that way.
+    fn peek32(&self, _offset: u32) -> Option<u32> {
+        None
+    }
+
+    /// Read a 8 bit value at a given offset without any side effects,
+    /// returning `None` if the value can't be read that way.
+    fn peek8(&self, offset: u32) -> Option<u8> {
+        (self.peek32(offset & !0x3)).map(|v| (v >> ((offset & 0x3) * 8)) as u8)
+    }
+}
+
+/// Convert the result of a side-effect free read into a peeked value. Stubbed
+/// registers are treated as readable.
+pub fn peek_result(res: MemResult<u32>) -> Option<u32> {
+    match res {
+        Ok(val) | Err(MemException::StubRead(_, val)) => Some(val),
+        Err(_) => None,
+    }
 }
 
 macro_rules! impl_memfwd {
@@ -80,6 +105,14 @@ macro_rules! impl_memfwd {
             fn w16(&mut self, offset: u32, val: u16) -> MemResult<()> {
                 (**self).w16(offset, val)
             }
+
+            fn peek32(&self, offset: u32) -> Option<u32> {
+                (**self).peek32(offset)
+            }
+
+            fn peek8(&self, offset: u32) -> Option<u8> {
+                (**self).peek8(offset)
+            }
         }
     };
 }
@@ -0,0 +1,380 @@
+//! Sampling profiler.
+//!
+//! Every `interval` instructions (per core), the [Profiler] records the core's
+//! PC, along with its current call stack (if enabled). Samples can then be
+//! exported as collapsed stacks (for use with `flamegraph.pl` / `inferno` /
+//! speedscope), or in callgrind format (for use with KCachegrind).
+//!
+//! Call stacks are tracked using a per-core "shadow stack": whenever an
+//! instruction leaves LR pointing to the instruction after it while branching
+//! elsewhere (i.e: `bl`, or `mov lr, pc; bx rN`), the return address is pushed,
+//! and popped once execution returns to it. Exceptions / context switches are
+//! not modeled, and may result in some nonsensical stacks.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+
+use crate::devices::platform::pp::common::CpuId;
+use crate::symbols::SymbolTable;
+
+/// Maximum depth of the shadow call stack.
+const MAX_STACK_DEPTH: usize = 128;
+
+/// Format an address as a function name (falling back to a raw address if the
+/// address doesn't correspond to a known symbol).
+fn fn_name(symbols: &SymbolTable, addr: u32) -> String {
+    match symbols.lookup(addr) {
+        Some((sym, _)) => sym.name.clone(),
+        None => format!("{:#010x}", addr),
+    }
+}
+
+fn core_name(core: CpuId) -> &'static str {
+    match core {
+        CpuId::Cpu => "cpu",
+        CpuId::Cop => "cop",
+    }
+}
+
+/// Profile output formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileFormat {
+    /// Collapsed stacks (i.e: one `cpu;outer;inner <count>` line per stack)
+    Collapsed,
+    /// Callgrind (i.e: for use with KCachegrind)
+    Callgrind,
+}
+
+impl std::str::FromStr for ProfileFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<ProfileFormat, &'static str> {
+        match s {
+            "collapsed" => Ok(ProfileFormat::Collapsed),
+            "callgrind" => Ok(ProfileFormat::Callgrind),
+            _ => Err("expected `collapsed` or `callgrind`"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct CoreState {
+    /// Instructions executed since the last sample
+    count: u64,
+    /// (call site, return address) of each frame in the current call stack
+    /// (innermost last)
+    stack: VecDeque<(u32, u32)>,
+}
+
+/// Samples where each core spends its time.
+#[derive(Debug)]
+pub struct Profiler {
+    interval: u64,
+    call_stacks: bool,
+    cores: [CoreState; 2],
+    /// Sample counts, keyed by the call-site addresses of each frame (outermost
+    /// first), and the PC (last).
+    samples: HashMap<(CpuId, Vec<u32>), u64>,
+}
+
+impl Profiler {
+    /// Create a new profiler which samples each core every `interval`
+    /// instructions. If `call_stacks` is false, only PCs are sampled.
+    pub fn new(interval: u64, call_stacks: bool) -> Profiler {
+        Profiler {
+            interval: interval.max(1),
+            call_stacks,
+            cores: Default::default(),
+            samples: HashMap::new(),
+        }
+    }
+
+    /// Return the sampling interval (in instructions).
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Check if call stacks are being tracked.
+    pub fn call_stacks(&self) -> bool {
+        self.call_stacks
+    }
+
+    /// Total number of samples collected.
+    pub fn num_samples(&self) -> u64 {
+        self.samples.values().sum()
+    }
+
+    /// Called after a core executes an instruction.
+    ///
+    /// `pc` and `thumb` describe the executed instruction, while `next_pc` and
+    /// `lr` are the core's state after executing it.
+    pub fn on_step(&mut self, core: CpuId, pc: u32, thumb: bool, next_pc: u32, lr: u32) {
+        let state = &mut self.cores[core as usize];
+
+        if self.call_stacks {
+            let ret_addr = pc.wrapping_add(if thumb { 2 } else { 4 });
+            if lr & !1 == ret_addr && next_pc != ret_addr {
+                if state.stack.len() == MAX_STACK_DEPTH {
+                    state.stack.pop_front();
+                }
+                state.stack.push_back((pc, ret_addr));
+            } else if let Some(depth) = state.stack.iter().rposition(|&(_, ret)| ret == next_pc) {
+                state.stack.truncate(depth);
+            }
+        }
+
+        state.count += 1;
+        if state.count < self.interval {
+            return;
+        }
+        state.count = 0;
+
+        // the sampled instruction is the one which is _about_ to execute
+        let mut frames = Vec::with_capacity(state.stack.len() + 1);
+        frames.extend(state.stack.iter().map(|&(site, _)| site));
+        frames.push(next_pc);
+        *self.samples.entry((core, frames)).or_default() += 1;
+    }
+
+    /// Write samples in the specified format, using `symbols` to aggregate
+    /// samples by function.
+    pub fn write(
+        &self,
+        w: impl Write,
+        format: ProfileFormat,
+        symbols: &SymbolTable,
+    ) -> io::Result<()> {
+        match format {
+            ProfileFormat::Collapsed => self.write_collapsed(w, symbols),
+            ProfileFormat::Callgrind => self.write_callgrind(w, symbols),
+        }
+    }
+
+    /// Aggregate samples by function.
+    fn by_function(&self, symbols: &SymbolTable) -> HashMap<Vec<String>, u64> {
+        let mut stacks: HashMap<Vec<String>, u64> = HashMap::new();
+        for ((core, frames), count) in &self.samples {
+            let stack = std::iter::once(core_name(*core).to_string())
+                .chain(frames.iter().map(|&addr| fn_name(symbols, addr)))
+                .collect();
+            *stacks.entry(stack).or_default() += count;
+        }
+        stacks
+    }
+
+    /// Write samples in "collapsed stack" format (i.e: one
+    /// `cpu;outer;inner <count>` line per unique stack).
+    pub fn write_collapsed(&self, mut w: impl Write, symbols: &SymbolTable) -> io::Result<()> {
+        let mut stacks = self.by_function(symbols).into_iter().collect::<Vec<_>>();
+        stacks.sort();
+        for (stack, count) in stacks {
+            writeln!(w, "{} {}", stack.join(";"), count)?;
+        }
+        Ok(())
+    }
+
+    /// Write samples in callgrind format.
+    ///
+    /// Each sample is attributed to the sampled instruction (self cost), and
+    /// to each call site along its call stack (inclusive cost). Since calls
+    /// aren't counted, call counts are reported as the number of samples
+    /// taken through each call site.
+    pub fn write_callgrind(&self, mut w: impl Write, symbols: &SymbolTable) -> io::Result<()> {
+        // fn -> addr -> self cost
+        let mut self_cost: HashMap<String, HashMap<u32, u64>> = HashMap::new();
+        // (caller fn, call site, callee fn) -> (calls, inclusive cost, callee addr)
+        let mut calls: HashMap<(String, u32, String), (u64, u64, u32)> = HashMap::new();
+
+        for ((_, frames), &count) in &self.samples {
+            let (&pc, callers) = frames.split_last().unwrap();
+            *self_cost
+                .entry(fn_name(symbols, pc))
+                .or_default()
+                .entry(pc)
+                .or_default() += count;
+
+            for (i, &site) in callers.iter().enumerate() {
+                let callee_addr = frames[i + 1];
+                let callee_entry = symbols
+                    .lookup(callee_addr)
+                    .map(|(sym, _)| sym.addr)
+                    .unwrap_or(callee_addr);
+                let call = calls
+                    .entry((fn_name(symbols, site), site, fn_name(symbols, callee_addr)))
+                    .or_insert((0, 0, callee_entry));
+                call.0 += count;
+                call.1 += count;
+            }
+        }
+
+        writeln!(w, "# callgrind format")?;
+        writeln!(w, "version: 1")?;
+        writeln!(w, "creator: clicky")?;
+        writeln!(w, "positions: instr")?;
+        writeln!(w, "events: Samples")?;
+        writeln!(w, "summary: {}", self.num_samples())?;
+
+        let mut fns = self_cost.keys().cloned().collect::<Vec<_>>();
+        fns.extend(calls.keys().map(|(caller, _, _)| caller.clone()));
+        fns.sort();
+        fns.dedup();
+
+        for f in fns {
+            writeln!(w)?;
+            writeln!(w, "fn={}", f)?;
+
+            let mut costs = self_cost
+                .get(&f)
+                .map(|costs| costs.iter().collect::<Vec<_>>())
+                .unwrap_or_default();
+            costs.sort();
+            for (addr, count) in costs {
+                writeln!(w, "{:#x} {}", addr, count)?;
+            }
+
+            let mut fn_calls = calls
+                .iter()
+                .filter(|((caller, _, _), _)| *caller == f)
+                .collect::<Vec<_>>();
+            fn_calls.sort_by_key(|((_, site, callee), _)| (*site, callee.clone()));
+            for ((_, site, callee), (n, cost, callee_addr)) in fn_calls {
+                writeln!(w, "cfn={}", callee)?;
+                writeln!(w, "calls={} {:#x}", n, callee_addr)?;
+                writeln!(w, "{:#x} {}", site, cost)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::symbols::Symbol;
+
+    fn symbols() -> SymbolTable {
+        let sym = |name: &str, addr| Symbol {
+            name: name.into(),
+            addr,
+            size: 0x100,
+        };
+        SymbolTable::from_symbols(vec![
+            sym("main", 0x1000),
+            sym("foo", 0x2000),
+            sym("bar", 0x3000),
+        ])
+    }
+
+    /// `main` calls `foo`, which calls `bar`, sampling every instruction.
+    fn profile() -> Profiler {
+        let mut profiler = Profiler::new(1, true);
+        // (pc, next_pc, lr)
+        let steps = [
+            (0x1000, 0x2000, 0x1004), // main: bl foo
+            (0x2000, 0x2004, 0x1004), // foo: nop
+            (0x2004, 0x3000, 0x2008), // foo: bl bar
+            (0x3000, 0x2008, 0x2008), // bar: bx lr
+            (0x2008, 0x1004, 0x1004), // foo: bx lr
+        ];
+        for (pc, next_pc, lr) in steps {
+            profiler.on_step(CpuId::Cpu, pc, false, next_pc, lr);
+        }
+        profiler.on_step(CpuId::Cop, 0x1000, false, 0x1004, 0);
+        profiler
+    }
+
+    #[test]
+    fn collapsed() {
+        let profiler = profile();
+        assert_eq!(profiler.num_samples(), 6);
+
+        let mut out = Vec::new();
+        profiler
+            .write(&mut out, ProfileFormat::Collapsed, &symbols())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "cop;main 1\n\
+             cpu;main 1\n\
+             cpu;main;foo 3\n\
+             cpu;main;foo;bar 1\n"
+        );
+
+        // unknown addresses are reported as-is
+        let mut out = Vec::new();
+        profiler
+            .write_collapsed(&mut out, &SymbolTable::new())
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("cpu;0x00001000;0x00002004;0x00003000 1\n"));
+    }
+
+    #[test]
+    fn callgrind() {
+        let mut out = Vec::new();
+        profile()
+            .write(&mut out, ProfileFormat::Callgrind, &symbols())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "# callgrind format\n\
+             version: 1\n\
+             creator: clicky\n\
+             positions: instr\n\
+             events: Samples\n\
+             summary: 6\n\
+             \n\
+             fn=bar\n\
+             0x3000 1\n\
+             \n\
+             fn=foo\n\
+             0x2000 1\n\
+             0x2004 1\n\
+             0x2008 1\n\
+             cfn=bar\n\
+             calls=1 0x3000\n\
+             0x2004 1\n\
+             \n\
+             fn=main\n\
+             0x1004 2\n\
+             cfn=foo\n\
+             calls=4 0x2000\n\
+             0x1000 4\n"
+        );
+    }
+
+    #[test]
+    fn sampling_interval() {
+        let mut profiler = Profiler::new(3, false);
+        for i in 0..10 {
+            // calls aren't tracked without call stacks
+            profiler.on_step(CpuId::Cpu, 0x1000 + i * 4, false, 0x2000, 0x1004 + i * 4);
+        }
+        assert_eq!(profiler.num_samples(), 3);
+        assert!(profiler.cores[0].stack.is_empty());
+    }
+
+    #[test]
+    fn stack_depth_is_bounded() {
+        let mut profiler = Profiler::new(u64::MAX, true);
+        // thumb `bl`s set the low bit of LR
+        for i in 0..MAX_STACK_DEPTH as u32 + 2 {
+            let pc = 0x1000 + i * 0x10;
+            profiler.on_step(CpuId::Cpu, pc, true, pc + 0x10, (pc + 2) | 1);
+        }
+
+        let stack = &profiler.cores[0].stack;
+        assert_eq!(stack.len(), MAX_STACK_DEPTH);
+        // the outermost frames are dropped
+        assert_eq!(stack.front(), Some(&(0x1020, 0x1022)));
+
+        // returning past the dropped frames doesn't unwind anything
+        profiler.on_step(CpuId::Cpu, 0x9000, true, 0x1002, 0);
+        assert_eq!(profiler.cores[0].stack.len(), MAX_STACK_DEPTH);
+        // ...but returning to a tracked frame does
+        profiler.on_step(CpuId::Cpu, 0x9000, true, 0x1022, 0);
+        assert!(profiler.cores[0].stack.is_empty());
+    }
+}
@@ -0,0 +1,25 @@
+use std::io::{self, Write};
+
+use crate::serial::SerialBackend;
+
+/// Write-only, file-backed serial backend. Useful for logging serial output.
+#[derive(Debug)]
+pub struct File {
+    file: std::fs::File,
+}
+
+impl File {
+    pub fn new(file: std::fs::File) -> File {
+        File { file }
+    }
+}
+
+impl SerialBackend for File {
+    fn read(&mut self) -> Option<u8> {
+        None
+    }
+
+    fn write(&mut self, val: u8) -> io::Result<()> {
+        self.file.write_all(&[val])
+    }
+}
@@ -0,0 +1,62 @@
+//! Serial port backends.
+
+mod file;
+mod null;
+#[cfg(unix)]
+mod pty;
+mod stdio;
+mod tcp;
+
+pub use file::File;
+pub use null::Null;
+#[cfg(unix)]
+pub use pty::Pty;
+pub use stdio::Stdio;
+pub use tcp::Tcp;
+
+use std::io::{self, Read};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How long to wait before retrying a read from a non-blocking (or temporarily
+/// disconnected) reader.
+const RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// Spawn a thread which forwards all bytes read from `reader` over a channel,
+/// returning the receiving end of the channel.
+///
+/// Reads which fail with an error that `is_transient` accepts (e.g: a pty's
+/// client detaching) are retried, instead of stopping the thread.
+fn spawn_reader(
+    label: &'static str,
+    mut reader: impl Read + Send + 'static,
+    is_transient: fn(&io::Error) -> bool,
+) -> mpsc::Receiver<u8> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buf = [0; 256];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => return,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock || is_transient(&e) => {
+                    std::thread::sleep(RETRY_DELAY);
+                    continue;
+                }
+                Err(e) => {
+                    debug!("{} serial backend read error: {}", label, e);
+                    return;
+                }
+            };
+
+            for &b in &buf[..n] {
+                if tx.send(b).is_err() {
+                    // serial port was dropped
+                    return;
+                }
+            }
+        }
+    });
+    rx
+}
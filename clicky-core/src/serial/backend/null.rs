@@ -0,0 +1,24 @@
+use std::io;
+
+use crate::serial::SerialBackend;
+
+/// Null serial backend. Never receives any data, and discards any data sent to
+/// it.
+#[derive(Debug, Default)]
+pub struct Null {}
+
+impl Null {
+    pub fn new() -> Null {
+        Null {}
+    }
+}
+
+impl SerialBackend for Null {
+    fn read(&mut self) -> Option<u8> {
+        None
+    }
+
+    fn write(&mut self, _val: u8) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use std::ffi::CStr;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::io::FromRawFd;
+use std::path::PathBuf;
+use std::sync::{mpsc, Mutex};
+
+use crate::serial::SerialBackend;
+
+/// Serial backend connected to a newly allocated pseudo-terminal.
+///
+/// Use the path returned by `path` to connect to the serial port (e.g: via
+/// `screen` or `minicom`).
+#[derive(Debug)]
+pub struct Pty {
+    path: PathBuf,
+    master: fs::File,
+    // `Receiver` isn't `Sync`
+    rx: Mutex<mpsc::Receiver<u8>>,
+    // number of bytes dropped since the pty last accepted a write
+    dropped: usize,
+}
+
+impl Pty {
+    pub fn new() -> io::Result<Pty> {
+        // SAFETY: standard POSIX pty allocation dance. `ptsname` returns a
+        // pointer to a static buffer, which is immediately copied.
+        let (master, path) = unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let master = fs::File::from_raw_fd(fd);
+
+            if libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            // writes must never stall the guest (e.g: when no client is
+            // attached to drain the pty's buffer)
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            // disable echo and line buffering, otherwise any guest output would
+            // be echoed straight back into the guest
+            let mut termios = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(fd, &mut termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::cfmakeraw(&mut termios);
+            if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let name = libc::ptsname(fd);
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let path = PathBuf::from(CStr::from_ptr(name).to_string_lossy().into_owned());
+
+            (master, path)
+        };
+
+        info!("serial: connected to {}", path.display());
+
+        Ok(Pty {
+            // reads fail with EIO whenever no client is attached
+            rx: Mutex::new(super::spawn_reader("pty", master.try_clone()?, |e| {
+                e.raw_os_error() == Some(libc::EIO)
+            })),
+            path,
+            master,
+            dropped: 0,
+        })
+    }
+
+    /// Returns the path to the pseudo-terminal's slave device.
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+impl SerialBackend for Pty {
+    fn read(&mut self) -> Option<u8> {
+        self.rx.get_mut().unwrap().try_recv().ok()
+    }
+
+    fn write(&mut self, val: u8) -> io::Result<()> {
+        match self.master.write(&[val]) {
+            Ok(_) => {
+                if self.dropped != 0 {
+                    info!(
+                        "serial: {} is accepting output again ({} bytes dropped)",
+                        self.path.display(),
+                        self.dropped
+                    );
+                    self.dropped = 0;
+                }
+                Ok(())
+            }
+            // the pty's buffer is full (or no client is attached), so drop the
+            // byte, just like a real serial port with nothing listening
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.raw_os_error() == Some(libc::EIO) =>
+            {
+                if self.dropped == 0 {
+                    warn!("serial: dropping output to {} ({})", self.path.display(), e);
+                }
+                trace!("serial: dropped {:#04x?}", val);
+                self.dropped += 1;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
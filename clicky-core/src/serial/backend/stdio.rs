@@ -0,0 +1,39 @@
+use std::io::{self, Write};
+use std::sync::{mpsc, Mutex};
+
+use crate::serial::SerialBackend;
+
+/// Serial backend connected to the host's stdin / stdout.
+///
+/// Note that stdin is usually line-buffered by the host's terminal.
+#[derive(Debug)]
+pub struct Stdio {
+    // `Receiver` isn't `Sync`
+    rx: Mutex<mpsc::Receiver<u8>>,
+}
+
+impl Stdio {
+    pub fn new() -> Stdio {
+        Stdio {
+            rx: Mutex::new(super::spawn_reader("stdio", io::stdin(), |_| false)),
+        }
+    }
+}
+
+impl Default for Stdio {
+    fn default() -> Stdio {
+        Stdio::new()
+    }
+}
+
+impl SerialBackend for Stdio {
+    fn read(&mut self) -> Option<u8> {
+        self.rx.get_mut().unwrap().try_recv().ok()
+    }
+
+    fn write(&mut self, val: u8) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        stdout.write_all(&[val])?;
+        stdout.flush()
+    }
+}
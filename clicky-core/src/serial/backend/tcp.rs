@@ -0,0 +1,86 @@
+use std::io::{self, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{mpsc, Arc, Mutex};
+
+use crate::serial::SerialBackend;
+
+/// Serial backend which accepts connections over TCP.
+///
+/// Only a single client can be connected at a time. Data sent while no client
+/// is connected is discarded.
+#[derive(Debug)]
+pub struct Tcp {
+    stream: Arc<Mutex<Option<TcpStream>>>,
+    // `Receiver` isn't `Sync`
+    rx: Mutex<mpsc::Receiver<u8>>,
+}
+
+impl Tcp {
+    /// Start listening for connections on the given address.
+    pub fn new(addr: impl ToSocketAddrs) -> io::Result<Tcp> {
+        let listener = TcpListener::bind(addr)?;
+        info!("serial: listening on {}", listener.local_addr()?);
+
+        let stream = Arc::new(Mutex::new(None));
+        let (tx, rx) = mpsc::channel();
+
+        std::thread::spawn({
+            let stream = Arc::clone(&stream);
+            move || {
+                for conn in listener.incoming() {
+                    let conn = match conn {
+                        Ok(conn) => conn,
+                        Err(e) => {
+                            warn!("serial: failed to accept connection: {}", e);
+                            continue;
+                        }
+                    };
+                    let reader = match conn.try_clone() {
+                        Ok(reader) => reader,
+                        Err(e) => {
+                            warn!("serial: failed to accept connection: {}", e);
+                            continue;
+                        }
+                    };
+
+                    info!("serial: accepted connection from {:?}", conn.peer_addr());
+                    *stream.lock().unwrap() = Some(conn);
+
+                    // forward data until the client disconnects
+                    let client_rx = super::spawn_reader("tcp", reader, |_| false);
+                    for b in client_rx.iter() {
+                        if tx.send(b).is_err() {
+                            // serial port was dropped
+                            return;
+                        }
+                    }
+
+                    info!("serial: client disconnected");
+                    *stream.lock().unwrap() = None;
+                }
+            }
+        });
+
+        Ok(Tcp {
+            stream,
+            rx: Mutex::new(rx),
+        })
+    }
+}
+
+impl SerialBackend for Tcp {
+    fn read(&mut self) -> Option<u8> {
+        self.rx.get_mut().unwrap().try_recv().ok()
+    }
+
+    fn write(&mut self, val: u8) -> io::Result<()> {
+        let mut stream = self.stream.lock().unwrap();
+        if let Some(conn) = stream.as_mut() {
+            if conn.write_all(&[val]).is_err() {
+                // the reader thread will notice the disconnect
+                *stream = None;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,15 @@
+//! Serial port interface and host backend implementations.
+
+use std::fmt::Debug;
+use std::io;
+
+pub mod backend;
+
+/// Abstraction over different host-side serial port backends.
+pub trait SerialBackend: Send + Sync + Debug {
+    /// Read a single byte from the host, returning `None` if no data is
+    /// currently available. Must not block.
+    fn read(&mut self) -> Option<u8>;
+    /// Send a single byte to the host.
+    fn write(&mut self, val: u8) -> io::Result<()>;
+}
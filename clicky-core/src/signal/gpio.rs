@@ -40,6 +40,8 @@ impl Changed {
     }
 }
 
+impl_snapshot!(Changed { trigger });
+
 /// The receiving side of a GPIO line.
 #[derive(Debug, Clone)]
 pub struct Reciever {
@@ -81,3 +83,5 @@ impl Sender {
         self.master.is_asserting()
     }
 }
+
+impl_snapshot!(Sender { master });
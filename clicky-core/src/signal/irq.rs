@@ -7,7 +7,10 @@ pub fn new(notify: Pending, debug_label: &'static str) -> (Sender, Reciever) {
     let (master, slave) = new_signal(notify.trigger, "IRQ", debug_label);
 
     let sender = Sender { master };
-    let reciever = Reciever { slave };
+    let reciever = Reciever {
+        slave,
+        label: debug_label,
+    };
 
     (sender, reciever)
 }
@@ -45,13 +48,21 @@ impl Pending {
     }
 }
 
+impl_snapshot!(Pending { trigger });
+
 /// The receiving side of an IRQ line.
 #[derive(Debug, Clone)]
 pub struct Reciever {
     slave: Slave,
+    label: &'static str,
 }
 
 impl Reciever {
+    /// The IRQ's debug label (e.g: "Timer1").
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+
     /// Checks if the IRQ has been set.
     #[inline]
     pub fn asserted(&self) -> bool {
@@ -86,3 +97,5 @@ impl Sender {
         self.master.is_asserting()
     }
 }
+
+impl_snapshot!(Sender { master });
@@ -1,9 +1,12 @@
 //! General signaling and notification mechanism. Used to implement GPIO, IRQs,
 //! etc...
 
+use std::io::{self, Read, Write};
 use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
 use std::sync::Arc;
 
+use crate::snapshot::Snapshot;
+
 pub mod gpio;
 pub mod irq;
 
@@ -95,6 +98,26 @@ impl Trigger {
     pub fn clear(&self) {
         self.trigger.store(false, Ordering::SeqCst)
     }
+
+    /// Sets the trigger directly (i.e: for devices which notify the system of
+    /// an event without going through a signal line).
+    #[inline]
+    pub fn set(&self) {
+        self.trigger.store(true, Ordering::SeqCst)
+    }
+}
+
+impl Snapshot for Trigger {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.check().save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let mut triggered = false;
+        triggered.load(r)?;
+        self.trigger.store(triggered, Ordering::SeqCst);
+        Ok(())
+    }
 }
 
 /// The receiving side of a signal line. Able to query the signal level, but not
@@ -163,3 +186,19 @@ impl Master {
         self.own_signal.load(Ordering::SeqCst)
     }
 }
+
+impl Snapshot for Master {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.is_asserting().save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let mut asserting = false;
+        asserting.load(r)?;
+        match asserting {
+            true => self.assert(),
+            false => self.clear(),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+//! System save-states.
+//!
+//! Every piece of emulated state that should survive a save/load cycle
+//! implements the [Snapshot] trait, which (de)serializes the object to/from a
+//! simple little-endian binary stream. There's no self-describing metadata in
+//! the stream, so `load` must read back fields in _exactly_ the same order
+//! they were written by `save`.
+//!
+//! Note that snapshots only capture _emulated_ state. Host resources (e.g: the
+//! contents of a block device's backing file) are not included, and must be
+//! provided separately when restoring a snapshot.
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use armv4t_emu::{reg, Cpu, Mode};
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use thiserror::Error;
+
+/// Magic bytes at the start of every snapshot file.
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"CLKYSNAP";
+
+/// Snapshot format version. Must be bumped whenever the layout of _any_
+/// [Snapshot] implementation changes.
+pub const SNAPSHOT_VERSION: u32 = 7;
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("not a clicky snapshot (bad magic)")]
+    BadMagic,
+    #[error("snapshot version {0} isn't supported (expected {})", SNAPSHOT_VERSION)]
+    UnsupportedVersion(u32),
+    #[error("snapshot was taken on a different system ({0})")]
+    WrongSystem(String),
+}
+
+/// Save / restore an object's emulated state.
+///
+/// `save` takes `&mut self`, as some objects need to query their underlying
+/// host resources (e.g: the current seek position of a block device).
+pub trait Snapshot {
+    /// Serialize the object's state.
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()>;
+    /// Restore the object's state from a stream created by `save`.
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()>;
+}
+
+/// Shorthand for an `InvalidData` io::Error.
+pub fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Write the snapshot header (magic, version, and system kind).
+pub fn write_header(w: &mut dyn Write, system: &str) -> io::Result<()> {
+    w.write_all(&SNAPSHOT_MAGIC)?;
+    w.write_u32::<LE>(SNAPSHOT_VERSION)?;
+    w.write_u32::<LE>(system.len() as u32)?;
+    w.write_all(system.as_bytes())
+}
+
+/// Read and validate the snapshot header.
+pub fn read_header(r: &mut dyn Read, system: &str) -> Result<(), SnapshotError> {
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if magic != SNAPSHOT_MAGIC {
+        return Err(SnapshotError::BadMagic);
+    }
+
+    let version = r.read_u32::<LE>()?;
+    if version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    let len = r.read_u32::<LE>()? as usize;
+    let mut kind = vec![0; len];
+    r.read_exact(&mut kind)?;
+    let kind = String::from_utf8_lossy(&kind);
+    if kind != system {
+        return Err(SnapshotError::WrongSystem(kind.into()));
+    }
+
+    Ok(())
+}
+
+/// Implement [Snapshot] for a struct by (de)serializing the listed fields in
+/// order. Each field must implement [Snapshot].
+macro_rules! impl_snapshot {
+    ($type:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::snapshot::Snapshot for $type {
+            fn save(&mut self, w: &mut dyn std::io::Write) -> std::io::Result<()> {
+                $($crate::snapshot::Snapshot::save(&mut self.$field, w)?;)*
+                Ok(())
+            }
+
+            fn load(&mut self, r: &mut dyn std::io::Read) -> std::io::Result<()> {
+                $($crate::snapshot::Snapshot::load(&mut self.$field, r)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+macro_rules! impl_snapshot_int {
+    ($($type:ty => ($write:ident, $read:ident)),* $(,)?) => {
+        $(
+            impl Snapshot for $type {
+                fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+                    w.$write::<LE>(*self)
+                }
+
+                fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+                    *self = r.$read::<LE>()?;
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_snapshot_int! {
+    u16 => (write_u16, read_u16),
+    u32 => (write_u32, read_u32),
+    u64 => (write_u64, read_u64),
+}
+
+impl Snapshot for u8 {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_u8(*self)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        *self = r.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for bool {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_u8(*self as u8)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        *self = match r.read_u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(invalid_data("invalid bool")),
+        };
+        Ok(())
+    }
+}
+
+impl Snapshot for usize {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_u64::<LE>(*self as u64)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        *self = r.read_u64::<LE>()? as usize;
+        Ok(())
+    }
+}
+
+impl Snapshot for Duration {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_u64::<LE>(self.as_nanos() as u64)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        *self = Duration::from_nanos(r.read_u64::<LE>()?);
+        Ok(())
+    }
+}
+
+impl<T: Snapshot, const N: usize> Snapshot for [T; N] {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        for x in self.iter_mut() {
+            x.save(w)?;
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        for x in self.iter_mut() {
+            x.load(r)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Snapshot + ?Sized> Snapshot for Box<T> {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        (**self).save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        (**self).load(r)
+    }
+}
+
+impl<T: Snapshot + Default> Snapshot for Option<T> {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        match self {
+            None => false.save(w),
+            Some(val) => {
+                true.save(w)?;
+                val.save(w)
+            }
+        }
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let mut is_some = false;
+        is_some.load(r)?;
+        *self = match is_some {
+            false => None,
+            true => {
+                let mut val = T::default();
+                val.load(r)?;
+                Some(val)
+            }
+        };
+        Ok(())
+    }
+}
+
+/// Save a buffer of bytes, prefixed with its length.
+pub fn save_bytes(w: &mut dyn Write, buf: &[u8]) -> io::Result<()> {
+    w.write_u64::<LE>(buf.len() as u64)?;
+    w.write_all(buf)
+}
+
+/// Load a buffer of bytes saved via [save_bytes]. The length of the saved
+/// buffer must match the length of `buf`.
+pub fn load_bytes(r: &mut dyn Read, buf: &mut [u8]) -> io::Result<()> {
+    let len = r.read_u64::<LE>()? as usize;
+    if len != buf.len() {
+        return Err(invalid_data(format!(
+            "buffer length mismatch (expected {}, got {})",
+            buf.len(),
+            len
+        )));
+    }
+    r.read_exact(buf)
+}
+
+/// Save a slice of bools as a packed bitmap.
+pub fn save_bitmap(w: &mut dyn Write, bits: &[bool]) -> io::Result<()> {
+    let packed = bits
+        .chunks(8)
+        .map(|c| c.iter().rev().fold(0u8, |acc, b| (acc << 1) | *b as u8))
+        .collect::<Vec<_>>();
+    w.write_u64::<LE>(bits.len() as u64)?;
+    w.write_all(&packed)
+}
+
+/// Load a packed bitmap saved via [save_bitmap].
+pub fn load_bitmap(r: &mut dyn Read, bits: &mut [bool]) -> io::Result<()> {
+    let len = r.read_u64::<LE>()? as usize;
+    if len != bits.len() {
+        return Err(invalid_data(format!(
+            "bitmap length mismatch (expected {}, got {})",
+            bits.len(),
+            len
+        )));
+    }
+
+    let mut packed = vec![0; len.div_ceil(8)];
+    r.read_exact(&mut packed)?;
+    for (chunk, byte) in bits.chunks_mut(8).zip(packed) {
+        for (i, b) in chunk.iter_mut().enumerate() {
+            *b = (byte >> i) & 1 != 0;
+        }
+    }
+    Ok(())
+}
+
+/// Every banked register in the ARMv4T register file.
+const BANKED_MODES: [(Mode, &[u8]); 5] = [
+    (Mode::Fiq, &[8, 9, 10, 11, 12, reg::SP, reg::LR, reg::SPSR]),
+    (Mode::Irq, &[reg::SP, reg::LR, reg::SPSR]),
+    (Mode::Supervisor, &[reg::SP, reg::LR, reg::SPSR]),
+    (Mode::Abort, &[reg::SP, reg::LR, reg::SPSR]),
+    (Mode::Undefined, &[reg::SP, reg::LR, reg::SPSR]),
+];
+
+impl Snapshot for Cpu {
+    fn save(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        for reg in 0..=reg::CPSR {
+            self.reg_get(Mode::User, reg).save(w)?;
+        }
+        for (mode, regs) in BANKED_MODES.iter() {
+            for &reg in regs.iter() {
+                self.reg_get(*mode, reg).save(w)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let mut val = 0u32;
+        for reg in 0..=reg::CPSR {
+            val.load(r)?;
+            self.reg_set(Mode::User, reg, val);
+        }
+        for (mode, regs) in BANKED_MODES.iter() {
+            for &reg in regs.iter() {
+                val.load(r)?;
+                self.reg_set(*mode, reg, val);
+            }
+        }
+        Ok(())
+    }
+}
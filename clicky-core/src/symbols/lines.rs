@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use gimli::{EndianSlice, LittleEndian};
+
+type Reader<'a> = EndianSlice<'a, LittleEndian>;
+
+#[derive(Debug, Clone)]
+struct LineRange {
+    addrs: Range<u32>,
+    /// Index into `LineTable::files`
+    file: usize,
+    line: u32,
+}
+
+/// Source line info loaded from an ELF file's DWARF `.debug_line` section,
+/// which can be used to translate addresses into `file:line` pairs.
+#[derive(Default)]
+pub struct LineTable {
+    files: Vec<String>,
+    /// Sorted by start address
+    ranges: Vec<LineRange>,
+}
+
+impl std::fmt::Debug for LineTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LineTable")
+            .field("files", &format_args!("[{} files]", self.files.len()))
+            .field("ranges", &format_args!("[{} ranges]", self.ranges.len()))
+            .finish()
+    }
+}
+
+/// Reconstruct the full path of a file in a line program's file table.
+fn file_path(
+    dwarf: &gimli::Dwarf<Reader<'_>>,
+    unit: &gimli::Unit<Reader<'_>>,
+    header: &gimli::LineProgramHeader<Reader<'_>>,
+    file: &gimli::FileEntry<Reader<'_>>,
+) -> Result<String, gimli::Error> {
+    // `PathBuf::push` replaces the path when pushing an absolute path, which is
+    // exactly how DWARF file paths are meant to be resolved.
+    let mut path = PathBuf::new();
+    if let Some(comp_dir) = &unit.comp_dir {
+        path.push(&*comp_dir.to_string_lossy());
+    }
+    if let Some(dir) = file.directory(header) {
+        path.push(&*dwarf.attr_string(unit, dir)?.to_string_lossy());
+    }
+    path.push(&*dwarf.attr_string(unit, file.path_name())?.to_string_lossy());
+    Ok(path.to_string_lossy().into_owned())
+}
+
+impl LineTable {
+    /// Create a line table from a list of `(file, line, address range)`
+    /// entries.
+    #[cfg(test)]
+    pub(crate) fn from_lines(lines: &[(&str, u32, Range<u32>)]) -> LineTable {
+        let mut table = LineTable::default();
+        for (file, line, addrs) in lines {
+            let file = match table.files.iter().position(|f| f == file) {
+                Some(file) => file,
+                None => {
+                    table.files.push(file.to_string());
+                    table.files.len() - 1
+                }
+            };
+            table.ranges.push(LineRange {
+                addrs: addrs.clone(),
+                file,
+                line: *line,
+            });
+        }
+        table.ranges.sort_by_key(|r| r.addrs.start);
+        table
+    }
+
+    /// Check if the line table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Load line info from an ELF file's DWARF sections (as returned by
+    /// `section`, which should return an empty slice for missing sections),
+    /// shifting all addresses by `offset`. Returns the number of loaded
+    /// address ranges.
+    pub(super) fn load<'a>(
+        &mut self,
+        section: impl Fn(&str) -> &'a [u8],
+        offset: u32,
+    ) -> Result<usize, gimli::Error> {
+        let dwarf = gimli::Dwarf::load(|id| -> Result<_, gimli::Error> {
+            Ok(EndianSlice::new(section(id.name()), LittleEndian))
+        })?;
+
+        let mut file_idx = (self.files.iter().enumerate())
+            .map(|(i, path)| (path.clone(), i))
+            .collect::<HashMap<_, _>>();
+
+        let mut ranges = Vec::new();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let program = match unit.line_program.clone() {
+                Some(program) => program,
+                None => continue,
+            };
+
+            // line program file index -> `self.files` index
+            let mut unit_files = HashMap::new();
+            // (start addr, file, line) of the previous row in the sequence
+            let mut prev: Option<(u32, usize, u32)> = None;
+
+            let mut rows = program.rows();
+            while let Some((header, row)) = rows.next_row()? {
+                let addr = (row.address() as u32).wrapping_add(offset);
+                if let Some((start, file, line)) = prev.take() {
+                    if addr > start {
+                        ranges.push(LineRange {
+                            addrs: start..addr,
+                            file,
+                            line,
+                        });
+                    }
+                }
+
+                if row.end_sequence() {
+                    continue;
+                }
+                let line = match row.line() {
+                    Some(line) => line.get() as u32,
+                    None => continue,
+                };
+
+                let file = match unit_files.get(&row.file_index()) {
+                    Some(&file) => file,
+                    None => {
+                        let path = match row.file(header) {
+                            Some(file) => file_path(&dwarf, &unit, header, file)?,
+                            None => continue,
+                        };
+                        let file = match file_idx.get(&path) {
+                            Some(&file) => file,
+                            None => {
+                                self.files.push(path.clone());
+                                file_idx.insert(path, self.files.len() - 1);
+                                self.files.len() - 1
+                            }
+                        };
+                        unit_files.insert(row.file_index(), file);
+                        file
+                    }
+                };
+
+                prev = Some((addr, file, line));
+            }
+        }
+
+        let loaded = ranges.len();
+        self.ranges.extend(ranges);
+        self.ranges.sort_by_key(|r| r.addrs.start);
+        Ok(loaded)
+    }
+
+    /// Find the source file and line corresponding to `addr`.
+    pub fn lookup(&self, addr: u32) -> Option<(&str, u32)> {
+        let idx = self
+            .ranges
+            .partition_point(|r| r.addrs.start <= addr)
+            .checked_sub(1)?;
+        let range = &self.ranges[idx];
+        if !range.addrs.contains(&addr) {
+            return None;
+        }
+        Some((&self.files[range.file], range.line))
+    }
+
+    /// Iterate over every `(file, line, address range)` in the table, in order
+    /// of address.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32, Range<u32>)> + '_ {
+        (self.ranges.iter()).map(move |r| (self.files[r.file].as_str(), r.line, r.addrs.clone()))
+    }
+}
@@ -0,0 +1,484 @@
+//! Symbol tables, for symbolizing guest addresses.
+//!
+//! Symbols are loaded from the `.symtab` section of 32-bit little-endian ELF
+//! files (e.g: `rockbox.elf`). If present, DWARF line info is loaded as well
+//! (see [LineTable]).
+
+use byteorder::{ByteOrder, LE};
+use thiserror::Error;
+
+mod lines;
+
+pub use lines::LineTable;
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+const SHN_UNDEF: u16 = 0;
+
+/// Upper bound on how far an unsized symbol (e.g: an assembly label) extends.
+const MAX_UNSIZED_LEN: u32 = 0x10000;
+
+#[derive(Error, Debug)]
+pub enum ElfError {
+    #[error("not an ELF file")]
+    NotElf,
+    #[error("unsupported ELF file: {0}")]
+    Unsupported(&'static str),
+    #[error("ELF file is truncated / malformed")]
+    Truncated,
+    #[error("malformed DWARF line info: {0}")]
+    Dwarf(#[from] gimli::Error),
+}
+
+/// A named address range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u32,
+    /// Size of the symbol. `0` if unknown, in which case the symbol is assumed
+    /// to extend up until the next symbol (within reason).
+    pub size: u32,
+}
+
+/// A collection of symbols, which can be used to translate addresses into
+/// `symbol+offset` pairs (and vice versa).
+#[derive(Default)]
+pub struct SymbolTable {
+    /// Sorted by address
+    syms: Vec<Symbol>,
+    lines: LineTable,
+}
+
+impl std::fmt::Debug for SymbolTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SymbolTable")
+            .field("syms", &format_args!("[{} symbols]", self.syms.len()))
+            .field("lines", &self.lines)
+            .finish()
+    }
+}
+
+fn slice(data: &[u8], offset: u32, len: u32) -> Result<&[u8], ElfError> {
+    let start = offset as usize;
+    let end = start.checked_add(len as usize).ok_or(ElfError::Truncated)?;
+    data.get(start..end).ok_or(ElfError::Truncated)
+}
+
+/// A single ELF section header.
+struct Section<'a> {
+    name: &'a [u8],
+    kind: u32,
+    data: &'a [u8],
+    link: u32,
+    entsize: u32,
+}
+
+/// Parse the section headers of a 32-bit little-endian ELF file.
+fn sections(data: &[u8]) -> Result<Vec<Section<'_>>, ElfError> {
+    let ident = slice(data, 0, 0x34).map_err(|_| ElfError::NotElf)?;
+    if &ident[..4] != b"\x7fELF" {
+        return Err(ElfError::NotElf);
+    }
+    if ident[4] != 1 {
+        return Err(ElfError::Unsupported("only 32-bit ELF files are supported"));
+    }
+    if ident[5] != 1 {
+        return Err(ElfError::Unsupported(
+            "only little-endian ELF files are supported",
+        ));
+    }
+
+    let shoff = LE::read_u32(&ident[0x20..]);
+    let shentsize = LE::read_u16(&ident[0x2e..]) as u32;
+    let shnum = LE::read_u16(&ident[0x30..]) as u32;
+    let shstrndx = LE::read_u16(&ident[0x32..]) as u32;
+
+    let header = |idx: u32| {
+        let offset = idx
+            .checked_mul(shentsize)
+            .and_then(|off| off.checked_add(shoff))
+            .ok_or(ElfError::Truncated)?;
+        slice(data, offset, shentsize.max(0x28))
+    };
+
+    // SHT_NOBITS sections (e.g: `.bss`) don't have any data in the file
+    let contents = |sh: &[u8]| match LE::read_u32(&sh[0x04..]) {
+        SHT_NOBITS => Ok(&[][..]),
+        _ => slice(data, LE::read_u32(&sh[0x10..]), LE::read_u32(&sh[0x14..])),
+    };
+
+    let shstrtab = match shnum {
+        0 => &[][..],
+        _ => contents(header(shstrndx)?)?,
+    };
+
+    (0..shnum)
+        .map(|idx| {
+            let sh = header(idx)?;
+            let name = shstrtab
+                .get(LE::read_u32(&sh[0x00..]) as usize..)
+                .unwrap_or_default();
+            Ok(Section {
+                name: &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())],
+                kind: LE::read_u32(&sh[0x04..]),
+                data: contents(sh)?,
+                link: LE::read_u32(&sh[0x18..]),
+                entsize: LE::read_u32(&sh[0x24..]),
+            })
+        })
+        .collect()
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    /// Create a symbol table from a list of symbols.
+    #[cfg(test)]
+    pub(crate) fn from_symbols(mut syms: Vec<Symbol>) -> SymbolTable {
+        syms.sort_by_key(|s| s.addr);
+        SymbolTable {
+            syms,
+            lines: LineTable::default(),
+        }
+    }
+
+    /// Check if the symbol table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.syms.is_empty()
+    }
+
+    /// Load all function / object symbols (along with any DWARF line info)
+    /// from an ELF file, shifting their addresses by `offset` (e.g: for
+    /// position-independent code which has been relocated). Returns the number
+    /// of loaded symbols.
+    pub fn load_elf(&mut self, data: &[u8], offset: u32) -> Result<usize, ElfError> {
+        let sections = sections(data)?;
+
+        let mut syms = Vec::new();
+        for sh in sections.iter().filter(|sh| sh.kind == SHT_SYMTAB) {
+            let symtab = sh.data;
+            let strtab = sections
+                .get(sh.link as usize)
+                .ok_or(ElfError::Truncated)?
+                .data;
+            let entsize = match sh.entsize {
+                0 => 16,
+                n => n as usize,
+            };
+
+            for sym in symtab.chunks_exact(entsize) {
+                if sym.len() < 16 {
+                    return Err(ElfError::Truncated);
+                }
+
+                let kind = sym[12] & 0xf;
+                let shndx = LE::read_u16(&sym[14..]);
+                if !matches!(kind, STT_NOTYPE | STT_OBJECT | STT_FUNC) || shndx == SHN_UNDEF {
+                    continue;
+                }
+
+                let name = strtab
+                    .get(LE::read_u32(&sym[0..]) as usize..)
+                    .ok_or(ElfError::Truncated)?;
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                // skip ARM mapping symbols (e.g: `$a`, `$t`, `$d`)
+                if name.is_empty() || name[0] == b'$' {
+                    continue;
+                }
+
+                let mut addr = LE::read_u32(&sym[4..]);
+                if kind == STT_FUNC {
+                    // thumb functions have the low bit set
+                    addr &= !1;
+                }
+
+                syms.push(Symbol {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    addr: addr.wrapping_add(offset),
+                    size: LE::read_u32(&sym[8..]),
+                });
+            }
+        }
+
+        self.lines.load(
+            |name| {
+                (sections.iter())
+                    .find(|sh| sh.name == name.as_bytes())
+                    .map(|sh| sh.data)
+                    .unwrap_or_default()
+            },
+            offset,
+        )?;
+
+        let loaded = syms.len();
+        self.syms.extend(syms);
+        self.syms.sort_by_key(|s| s.addr);
+        Ok(loaded)
+    }
+
+    /// Return the table's DWARF line info.
+    pub fn lines(&self) -> &LineTable {
+        &self.lines
+    }
+
+    /// Find the symbol containing `addr`, returning the symbol and the offset
+    /// of `addr` within it.
+    pub fn lookup(&self, addr: u32) -> Option<(&Symbol, u32)> {
+        let idx = self
+            .syms
+            .partition_point(|s| s.addr <= addr)
+            .checked_sub(1)?;
+        // prefer sized symbols when multiple symbols share an address
+        let sym = self.syms[..=idx]
+            .iter()
+            .rev()
+            .take_while(|s| s.addr == self.syms[idx].addr)
+            .find(|s| s.size != 0)
+            .unwrap_or(&self.syms[idx]);
+
+        let offset = addr - sym.addr;
+        let len = match sym.size {
+            0 => MAX_UNSIZED_LEN,
+            size => size,
+        };
+        if offset >= len {
+            return None;
+        }
+        Some((sym, offset))
+    }
+
+    /// Find the address of the symbol with the specified name.
+    pub fn find(&self, name: &str) -> Option<&Symbol> {
+        self.syms.iter().find(|s| s.name == name)
+    }
+
+    /// Return a `name+offset` string for the specified address (if it
+    /// corresponds to a known symbol).
+    pub fn symbolize(&self, addr: u32) -> Option<String> {
+        let (sym, offset) = self.lookup(addr)?;
+        Some(match offset {
+            0 => sym.name.clone(),
+            offset => format!("{}+{:#x}", sym.name, offset),
+        })
+    }
+}
+
+/// Parse a `<path>[@<offset>]` ELF file specification, where `offset` may be
+/// specified in decimal, or in hex with a `0x` prefix.
+pub fn parse_elf_spec(s: &str) -> Result<(String, u32), String> {
+    let mut s = s.rsplitn(2, '@');
+    let (offset, path) = match (s.next().unwrap(), s.next()) {
+        (offset, Some(path)) => (Some(offset), path),
+        (path, None) => (None, path),
+    };
+
+    let offset = match offset {
+        None => 0,
+        Some(offset) => {
+            let res = match offset.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => offset.parse(),
+            };
+            res.map_err(|e| format!("couldn't parse offset '{}': {}", offset, e))?
+        }
+    };
+
+    Ok((path.to_string(), offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHT_STRTAB: u32 = 3;
+
+    /// Build a minimal ELF file with a `.symtab` containing the specified
+    /// `(name, addr, size, kind)` symbols (all defined in section 1).
+    fn elf(syms: &[(&str, u32, u32, u8)]) -> Vec<u8> {
+        let shstrtab = b"\0.symtab\0.strtab\0.shstrtab\0";
+
+        let mut strtab = vec![0];
+        let mut symtab = vec![0; 16];
+        for &(name, addr, size, kind) in syms {
+            let mut sym = [0; 16];
+            LE::write_u32(&mut sym[0..], strtab.len() as u32);
+            LE::write_u32(&mut sym[4..], addr);
+            LE::write_u32(&mut sym[8..], size);
+            sym[12] = kind;
+            LE::write_u16(&mut sym[14..], 1);
+            symtab.extend_from_slice(&sym);
+            strtab.extend_from_slice(name.as_bytes());
+            strtab.push(0);
+        }
+
+        let mut data = vec![0; 0x34];
+        data[..7].copy_from_slice(b"\x7fELF\x01\x01\x01");
+        let mut contents = |buf: &[u8]| {
+            let offset = data.len() as u32;
+            data.extend_from_slice(buf);
+            (offset, buf.len() as u32)
+        };
+        let symtab = contents(&symtab);
+        let strtab = contents(&strtab);
+        let shstrtab = contents(shstrtab);
+
+        // (name, kind, (offset, size), link, entsize)
+        let headers = [
+            (0, 0, (0, 0), 0, 0),
+            (1, SHT_SYMTAB, symtab, 2, 16),
+            (9, SHT_STRTAB, strtab, 0, 0),
+            (17, SHT_STRTAB, shstrtab, 0, 0),
+        ];
+        let shoff = data.len() as u32;
+        for (name, kind, (offset, size), link, entsize) in headers {
+            let mut sh = [0; 0x28];
+            LE::write_u32(&mut sh[0x00..], name);
+            LE::write_u32(&mut sh[0x04..], kind);
+            LE::write_u32(&mut sh[0x10..], offset);
+            LE::write_u32(&mut sh[0x14..], size);
+            LE::write_u32(&mut sh[0x18..], link);
+            LE::write_u32(&mut sh[0x24..], entsize);
+            data.extend_from_slice(&sh);
+        }
+
+        LE::write_u32(&mut data[0x20..], shoff);
+        LE::write_u16(&mut data[0x2e..], 0x28);
+        LE::write_u16(&mut data[0x30..], headers.len() as u16);
+        LE::write_u16(&mut data[0x32..], 3);
+        data
+    }
+
+    fn test_elf() -> Vec<u8> {
+        elf(&[
+            ("_start", 0x1000_0000, 0x10, STT_FUNC),
+            // back-to-back symbols (e.g: at a section boundary)
+            ("thumb_func", 0x1000_0011, 0x20, STT_FUNC),
+            ("table", 0x1000_0030, 0x100, STT_OBJECT),
+            // unsized labels
+            ("loop", 0x1000_0200, 0, STT_NOTYPE),
+            ("next", 0x1000_0208, 0, STT_NOTYPE),
+            // sized symbols take precedence over labels at the same address
+            ("alias", 0x2000_0000, 0, STT_NOTYPE),
+            ("sized", 0x2000_0000, 8, STT_OBJECT),
+            // skipped
+            ("$a", 0x1000_0000, 0, STT_NOTYPE),
+            ("section", 0x1000_0000, 0, 3),
+        ])
+    }
+
+    #[test]
+    fn load_and_lookup() {
+        let mut table = SymbolTable::new();
+        assert_eq!(table.load_elf(&test_elf(), 0).unwrap(), 7);
+
+        let cases: &[(u32, Option<&str>)] = &[
+            (0x0fff_ffff, None),
+            (0x1000_0000, Some("_start")),
+            (0x1000_000f, Some("_start+0xf")),
+            // the thumb bit is stripped from function addresses
+            (0x1000_0010, Some("thumb_func")),
+            (0x1000_002f, Some("thumb_func+0x1f")),
+            (0x1000_0030, Some("table")),
+            (0x1000_012f, Some("table+0xff")),
+            // gap between the end of a sized symbol and the next symbol
+            (0x1000_0130, None),
+            (0x1000_01ff, None),
+            (0x1000_0204, Some("loop+0x4")),
+            (0x1000_0208, Some("next")),
+            (0x1000_0208 + MAX_UNSIZED_LEN - 1, Some("next+0xffff")),
+            (0x1000_0208 + MAX_UNSIZED_LEN, None),
+            (0x2000_0004, Some("sized+0x4")),
+            (0x2000_0008, None),
+        ];
+        for &(addr, expected) in cases {
+            assert_eq!(table.symbolize(addr).as_deref(), expected, "{:#010x}", addr);
+        }
+
+        assert_eq!(table.find("table").map(|s| s.addr), Some(0x1000_0030));
+        assert!(table.find("$a").is_none());
+        assert!(table.find("section").is_none());
+    }
+
+    #[test]
+    fn load_with_offset() {
+        let mut table = SymbolTable::new();
+        table.load_elf(&test_elf(), 0x100).unwrap();
+        assert_eq!(table.symbolize(0x1000_0100).as_deref(), Some("_start"));
+        assert_eq!(table.symbolize(0x1000_0000), None);
+
+        // symbols from multiple files are merged
+        table
+            .load_elf(&elf(&[("other", 0x1000_0000, 4, STT_FUNC)]), 0)
+            .unwrap();
+        assert_eq!(table.symbolize(0x1000_0000).as_deref(), Some("other"));
+        assert_eq!(table.symbolize(0x1000_0104).as_deref(), Some("_start+0x4"));
+    }
+
+    #[test]
+    fn rejects_malformed_elfs() {
+        let data = test_elf();
+
+        let mut bad_ident = data.clone();
+        bad_ident[0] = 0;
+        assert!(matches!(
+            SymbolTable::new().load_elf(&bad_ident, 0),
+            Err(ElfError::NotElf)
+        ));
+        let mut elf64 = data.clone();
+        elf64[4] = 2;
+        assert!(matches!(
+            SymbolTable::new().load_elf(&elf64, 0),
+            Err(ElfError::Unsupported(_))
+        ));
+        let mut big_endian = data.clone();
+        big_endian[5] = 2;
+        assert!(matches!(
+            SymbolTable::new().load_elf(&big_endian, 0),
+            Err(ElfError::Unsupported(_))
+        ));
+
+        // every truncation of the file is rejected (the section headers are at
+        // the very end of the file)
+        for len in 0..data.len() {
+            assert!(
+                SymbolTable::new().load_elf(&data[..len], 0).is_err(),
+                "truncated to {} bytes",
+                len
+            );
+        }
+
+        // corrupting any single field must never panic
+        for offset in 0..data.len() {
+            for val in [0x00, 0x01, 0x0f, 0x7f, 0xff] {
+                let mut data = data.clone();
+                data[offset] = val;
+                let _ = SymbolTable::new().load_elf(&data, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn elf_spec() {
+        let cases: &[(&str, Option<(&str, u32)>)] = &[
+            ("rockbox.elf", Some(("rockbox.elf", 0))),
+            ("rockbox.elf@0x10000", Some(("rockbox.elf", 0x10000))),
+            ("rockbox.elf@4096", Some(("rockbox.elf", 4096))),
+            ("dir@2/a.elf@0x20", Some(("dir@2/a.elf", 0x20))),
+            ("rockbox.elf@", None),
+            ("rockbox.elf@0xzz", None),
+            ("rockbox.elf@0x100000000", None),
+        ];
+        for &(spec, expected) in cases {
+            let res = parse_elf_spec(spec).ok();
+            let res = res.as_ref().map(|(path, off)| (path.as_str(), *off));
+            assert_eq!(res, expected, "{}", spec);
+        }
+    }
+}
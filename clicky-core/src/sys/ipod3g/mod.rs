@@ -0,0 +1,76 @@
+//! The iPod 3g.
+//!
+//! Built around the PP5002, with the same 160x128 grayscale screen as the
+//! iPod 4g, and a "touch wheel" whose buttons and scroll wheel are wired
+//! directly to GPIO lines.
+
+use crate::devices::display::hd66753::Hd66753;
+use crate::devices::i2c::devices::WmCodecKind;
+use crate::gui::RenderCallback;
+use crate::sys::pp::{
+    Pp5002Devices, Pp5002DevicesCfg, PpBoard, PpBoardCtx, PpGdb, PpInput, PpSystem,
+};
+
+pub use crate::sys::pp::{
+    BootKind, PpBinds as Ipod3gBinds, PpBuildError as Ipod3gBuildError, PpCfg as Ipod3gCfg,
+    PpKey as Ipod3gKey,
+};
+
+/// A Ipod3g system
+pub type Ipod3g = PpSystem<Ipod3gBus>;
+/// A Ipod3g system, wrapped in a GDB stub target.
+pub type Ipod3gGdb = PpGdb<Ipod3gBus>;
+
+/// The main Ipod3g memory bus.
+///
+/// Maps the entire 32 bit address space to the Ipod3g's various devices.
+#[derive(Debug)]
+pub struct Ipod3gBus {
+    pub pp: Pp5002Devices,
+    pub hd66753: Hd66753,
+}
+
+impl PpBoard for Ipod3gBus {
+    type Soc = Pp5002Devices;
+
+    const NAME: &'static str = "Ipod3g";
+    const SCREEN_DIMS: (usize, usize) = (160, 128);
+    const HW_REV: u32 = 0x30000;
+    const HOLD_GPIO: usize = 5; // GPIOA:5
+    const INPUT: PpInput = PpInput::GpioKeypad { wheel: [6, 7] }; // GPIOA:6 and GPIOA:7
+
+    fn new(ctx: PpBoardCtx) -> Ipod3gBus {
+        Ipod3gBus {
+            pp: Pp5002Devices::new(
+                ctx,
+                Pp5002DevicesCfg {
+                    sdram_size: 32 * 1024 * 1024, // 32 MB
+                    codec: WmCodecKind::Wm8731,
+                },
+            ),
+            hd66753: Hd66753::new(),
+        }
+    }
+
+    fn pp(&self) -> &Pp5002Devices {
+        &self.pp
+    }
+
+    fn pp_mut(&mut self) -> &mut Pp5002Devices {
+        &mut self.pp
+    }
+
+    fn render_callback(&self) -> RenderCallback {
+        self.hd66753.render_callback()
+    }
+}
+
+impl_snapshot!(Ipod3gBus { pp, hd66753 });
+
+mmap! {
+    Ipod3gBus;
+
+    DEVICES {
+        0xc000_1000..=0xc000_101f => hd66753,
+    }
+}
@@ -0,0 +1,81 @@
+//! The iPod 5g (Video).
+//!
+//! Built around the PP5021C (which is functionally identical to the PP5020D
+//! used in the iPod 4g), with the 320x240 colour LCD hanging off a Broadcom
+//! BCM2722 video processor.
+//!
+//! Only the BCM2722's LCD update commands are emulated, which is enough for
+//! Rockbox and the Apple firmware's UI. Video playback (i.e: anything which
+//! relies on the VideoCore firmware) is not supported.
+//!
+//! The BCM2722 power / reset lines (GPO32 bits 14 and 15) are not modelled:
+//! the emulated BCM is always powered on and ready.
+
+use crate::devices::display::bcm2722::Bcm2722;
+use crate::devices::i2c::devices::WmCodecKind;
+use crate::gui::RenderCallback;
+use crate::sys::pp::{PpBoard, PpBoardCtx, PpDevices, PpDevicesCfg, PpGdb, PpSystem};
+
+pub use crate::sys::pp::{
+    BootKind, PpBinds as Ipod5gBinds, PpBuildError as Ipod5gBuildError, PpCfg as Ipod5gCfg,
+    PpKey as Ipod5gKey,
+};
+
+/// An iPod 5G system
+pub type Ipod5g = PpSystem<Ipod5gBus>;
+/// An iPod 5G system, wrapped in a GDB stub target.
+pub type Ipod5gGdb = PpGdb<Ipod5gBus>;
+
+/// The main iPod 5G memory bus.
+///
+/// Maps the entire 32 bit address space to the iPod 5G's various devices.
+#[derive(Debug)]
+pub struct Ipod5gBus {
+    pub pp: PpDevices,
+    pub bcm: Bcm2722,
+}
+
+impl PpBoard for Ipod5gBus {
+    type Soc = PpDevices;
+
+    const NAME: &'static str = "Ipod5g";
+    const SCREEN_DIMS: (usize, usize) = (320, 240);
+    const HW_REV: u32 = 0xB0005;
+    const HOLD_GPIO: usize = 5; // GPIOA:5
+
+    fn new(ctx: PpBoardCtx) -> Ipod5gBus {
+        Ipod5gBus {
+            pp: PpDevices::new(
+                ctx,
+                PpDevicesCfg {
+                    sdram_size: 32 * 1024 * 1024, // 32 MB (30GB model)
+                    chip_id: *b"PP5021C ",
+                    codec: WmCodecKind::Wm8758,
+                },
+            ),
+            bcm: Bcm2722::new(),
+        }
+    }
+
+    fn pp(&self) -> &PpDevices {
+        &self.pp
+    }
+
+    fn pp_mut(&mut self) -> &mut PpDevices {
+        &mut self.pp
+    }
+
+    fn render_callback(&self) -> RenderCallback {
+        self.bcm.render_callback()
+    }
+}
+
+impl_snapshot!(Ipod5gBus { pp, bcm });
+
+mmap! {
+    Ipod5gBus;
+
+    DEVICES {
+        0x3000_0000..=0x3007_ffff => bcm,
+    }
+}
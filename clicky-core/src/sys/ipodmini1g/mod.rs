@@ -0,0 +1,76 @@
+//! The iPod mini (1st generation).
+//!
+//! Identical to the iPod 4g as far as the SoC is concerned, but with a smaller
+//! 138x110 screen, a WM8731 codec, and a "touch wheel" whose buttons and
+//! scroll wheel are wired directly to GPIO lines (instead of going through the
+//! clickwheel controller).
+
+use crate::devices::display::hd66753::Hd66753;
+use crate::devices::i2c::devices::WmCodecKind;
+use crate::gui::RenderCallback;
+use crate::sys::pp::{PpBoard, PpBoardCtx, PpDevices, PpDevicesCfg, PpGdb, PpInput, PpSystem};
+
+pub use crate::sys::pp::{
+    BootKind, PpBinds as IpodMini1gBinds, PpBuildError as IpodMini1gBuildError,
+    PpCfg as IpodMini1gCfg, PpKey as IpodMini1gKey,
+};
+
+/// An iPod mini 1G system
+pub type IpodMini1g = PpSystem<IpodMini1gBus>;
+/// An iPod mini 1G system, wrapped in a GDB stub target.
+pub type IpodMini1gGdb = PpGdb<IpodMini1gBus>;
+
+/// The main iPod mini 1G memory bus.
+///
+/// Maps the entire 32 bit address space to the iPod mini 1G's various devices.
+#[derive(Debug)]
+pub struct IpodMini1gBus {
+    pub pp: PpDevices,
+    pub hd66753: Hd66753,
+}
+
+impl PpBoard for IpodMini1gBus {
+    type Soc = PpDevices;
+
+    const NAME: &'static str = "IpodMini1g";
+    const SCREEN_DIMS: (usize, usize) = (138, 110);
+    const HW_REV: u32 = 0x40000;
+    const HOLD_GPIO: usize = 5; // GPIOA:5
+    const INPUT: PpInput = PpInput::GpioKeypad { wheel: [12, 13] }; // GPIOB:4 and GPIOB:5
+
+    fn new(ctx: PpBoardCtx) -> IpodMini1gBus {
+        IpodMini1gBus {
+            pp: PpDevices::new(
+                ctx,
+                PpDevicesCfg {
+                    sdram_size: 32 * 1024 * 1024, // 32 MB
+                    chip_id: *b"PP5020D ",
+                    codec: WmCodecKind::Wm8731,
+                },
+            ),
+            hd66753: Hd66753::new(),
+        }
+    }
+
+    fn pp(&self) -> &PpDevices {
+        &self.pp
+    }
+
+    fn pp_mut(&mut self) -> &mut PpDevices {
+        &mut self.pp
+    }
+
+    fn render_callback(&self) -> RenderCallback {
+        self.hd66753.render_callback()
+    }
+}
+
+impl_snapshot!(IpodMini1gBus { pp, hd66753 });
+
+mmap! {
+    IpodMini1gBus;
+
+    DEVICES {
+        0x7000_3000..=0x7000_301f => hd66753,
+    }
+}
@@ -0,0 +1,74 @@
+//! The iPod mini (2nd generation).
+//!
+//! Uses a proper clickwheel (like the iPod 4g), but the LCD controller is
+//! driven through the PP502x's LCD "bridge" instead of being accessed
+//! directly (see [Hd66753]).
+
+use crate::devices::display::hd66753::Hd66753;
+use crate::devices::i2c::devices::WmCodecKind;
+use crate::gui::RenderCallback;
+use crate::sys::pp::{PpBoard, PpBoardCtx, PpDevices, PpDevicesCfg, PpGdb, PpSystem};
+
+pub use crate::sys::pp::{
+    BootKind, PpBinds as IpodMini2gBinds, PpBuildError as IpodMini2gBuildError,
+    PpCfg as IpodMini2gCfg, PpKey as IpodMini2gKey,
+};
+
+/// An iPod mini 2G system
+pub type IpodMini2g = PpSystem<IpodMini2gBus>;
+/// An iPod mini 2G system, wrapped in a GDB stub target.
+pub type IpodMini2gGdb = PpGdb<IpodMini2gBus>;
+
+/// The main iPod mini 2G memory bus.
+///
+/// Maps the entire 32 bit address space to the iPod mini 2G's various devices.
+#[derive(Debug)]
+pub struct IpodMini2gBus {
+    pub pp: PpDevices,
+    pub hd66753: Hd66753,
+}
+
+impl PpBoard for IpodMini2gBus {
+    type Soc = PpDevices;
+
+    const NAME: &'static str = "IpodMini2g";
+    const SCREEN_DIMS: (usize, usize) = (138, 110);
+    const HW_REV: u32 = 0x70000;
+    const HOLD_GPIO: usize = 5; // GPIOA:5
+
+    fn new(ctx: PpBoardCtx) -> IpodMini2gBus {
+        IpodMini2gBus {
+            pp: PpDevices::new(
+                ctx,
+                PpDevicesCfg {
+                    sdram_size: 32 * 1024 * 1024, // 32 MB
+                    chip_id: *b"PP5020D ",
+                    codec: WmCodecKind::Wm8731,
+                },
+            ),
+            hd66753: Hd66753::new(),
+        }
+    }
+
+    fn pp(&self) -> &PpDevices {
+        &self.pp
+    }
+
+    fn pp_mut(&mut self) -> &mut PpDevices {
+        &mut self.pp
+    }
+
+    fn render_callback(&self) -> RenderCallback {
+        self.hd66753.render_callback()
+    }
+}
+
+impl_snapshot!(IpodMini2gBus { pp, hd66753 });
+
+mmap! {
+    IpodMini2gBus;
+
+    DEVICES {
+        0x7000_3000..=0x7000_301f => hd66753,
+    }
+}
@@ -0,0 +1,74 @@
+//! The iPod photo (a.k.a: iPod 4g Color).
+//!
+//! Same SoC and clickwheel as the iPod 4g, but with a 220x176 colour LCD
+//! driven through the PP502x's LCD2 interface (see [PhotoLcd]).
+
+use crate::devices::display::photo_lcd::PhotoLcd;
+use crate::devices::i2c::devices::WmCodecKind;
+use crate::gui::RenderCallback;
+use crate::sys::pp::{PpBoard, PpBoardCtx, PpDevices, PpDevicesCfg, PpGdb, PpSystem};
+
+pub use crate::sys::pp::{
+    BootKind, PpBinds as IpodPhotoBinds, PpBuildError as IpodPhotoBuildError,
+    PpCfg as IpodPhotoCfg, PpKey as IpodPhotoKey,
+};
+
+/// An iPod Photo system
+pub type IpodPhoto = PpSystem<IpodPhotoBus>;
+/// An iPod Photo system, wrapped in a GDB stub target.
+pub type IpodPhotoGdb = PpGdb<IpodPhotoBus>;
+
+/// The main iPod Photo memory bus.
+///
+/// Maps the entire 32 bit address space to the iPod Photo's various devices.
+#[derive(Debug)]
+pub struct IpodPhotoBus {
+    pub pp: PpDevices,
+    pub lcd: PhotoLcd,
+}
+
+impl PpBoard for IpodPhotoBus {
+    type Soc = PpDevices;
+
+    const NAME: &'static str = "IpodPhoto";
+    const SCREEN_DIMS: (usize, usize) = (220, 176);
+    // the hw revision's low bits select the LCD type. Only "type 0" is emulated.
+    const HW_REV: u32 = 0x60000;
+    const HOLD_GPIO: usize = 5; // GPIOA:5
+
+    fn new(ctx: PpBoardCtx) -> IpodPhotoBus {
+        IpodPhotoBus {
+            pp: PpDevices::new(
+                ctx,
+                PpDevicesCfg {
+                    sdram_size: 32 * 1024 * 1024, // 32 MB
+                    chip_id: *b"PP5020D ",
+                    codec: WmCodecKind::Wm8975,
+                },
+            ),
+            lcd: PhotoLcd::new(),
+        }
+    }
+
+    fn pp(&self) -> &PpDevices {
+        &self.pp
+    }
+
+    fn pp_mut(&mut self) -> &mut PpDevices {
+        &mut self.pp
+    }
+
+    fn render_callback(&self) -> RenderCallback {
+        self.lcd.render_callback()
+    }
+}
+
+impl_snapshot!(IpodPhotoBus { pp, lcd });
+
+mmap! {
+    IpodPhotoBus;
+
+    DEVICES {
+        0x7000_8a00..=0x7000_8bff => lcd,
+    }
+}
@@ -1,6 +1,14 @@
 //! Concrete system implementations.
 
+#[macro_use]
+pub mod pp;
+
+pub mod ipod3g;
 pub mod ipod4g;
+pub mod ipod5g;
+pub mod ipodmini1g;
+pub mod ipodmini2g;
+pub mod ipodphoto;
 
 #[allow(dead_code)]
 mod size_asserts {
@@ -13,5 +21,10 @@ mod size_asserts {
     /// the stack when being constructed.
     const MAX_SYS_SIZE: usize = DEFAULT_WASM_STACK_SIZE / 4;
 
+    const_assert!(std::mem::size_of::<ipod3g::Ipod3g>() < MAX_SYS_SIZE);
     const_assert!(std::mem::size_of::<ipod4g::Ipod4g>() < MAX_SYS_SIZE);
+    const_assert!(std::mem::size_of::<ipod5g::Ipod5g>() < MAX_SYS_SIZE);
+    const_assert!(std::mem::size_of::<ipodmini1g::IpodMini1g>() < MAX_SYS_SIZE);
+    const_assert!(std::mem::size_of::<ipodmini2g::IpodMini2g>() < MAX_SYS_SIZE);
+    const_assert!(std::mem::size_of::<ipodphoto::IpodPhoto>() < MAX_SYS_SIZE);
 }
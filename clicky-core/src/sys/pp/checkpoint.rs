@@ -0,0 +1,309 @@
+//! In-memory checkpoints, used to implement reverse execution.
+//!
+//! While recording, a full snapshot of the system is taken every `interval`
+//! cycles. Stepping backwards is implemented by restoring the closest
+//! checkpoint prior to the target cycle, and deterministically re-executing
+//! forwards from there. As such, reverse execution requires a virtual clock.
+//!
+//! Only the most recent `max` checkpoints are kept around, as each checkpoint
+//! includes a full copy of the system's RAM.
+//!
+//! Checkpoints don't include the contents of the HDD, or any pending input
+//! events. Instead, while recording, the system journals every write made to
+//! the HDD (which are rolled back when restoring a checkpoint), and routes all
+//! input through an `InputLog` (which is re-delivered at the same cycles when
+//! re-executing).
+//!
+//! Resuming with one of the cores held can't be re-executed either (as history
+//! is always re-executed with both cores running). Instead, a checkpoint is
+//! taken at the end of the held span, and re-execution simply stops at the
+//! start of the span.
+
+use std::collections::VecDeque;
+
+use crate::devices::generic::ide::IdeJournalPos;
+
+/// A snapshot of the system's state at a particular cycle.
+pub(super) struct Checkpoint {
+    pub cycle: u64,
+    pub state: Vec<u8>,
+    /// Position in the HDD's write journal, used to undo any writes made after
+    /// the checkpoint was taken.
+    pub hdd_journal: IdeJournalPos,
+    /// If set, the cycles between `held_from` and the checkpoint were executed
+    /// with one of the cores held.
+    pub held_from: Option<u64>,
+}
+
+pub(super) struct Checkpoints {
+    interval: u64,
+    max: usize,
+    /// Sorted by cycle
+    saved: VecDeque<Checkpoint>,
+}
+
+impl Checkpoints {
+    pub fn new(interval: u64, max: usize) -> Checkpoints {
+        Checkpoints {
+            interval: interval.max(1),
+            max: max.max(1),
+            saved: VecDeque::new(),
+        }
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    pub fn len(&self) -> usize {
+        self.saved.len()
+    }
+
+    /// Total size of all checkpoints (in bytes).
+    pub fn size(&self) -> usize {
+        self.saved.iter().map(|cp| cp.state.len()).sum()
+    }
+
+    /// Return the earliest checkpoint (i.e: the start of the recorded
+    /// history).
+    pub fn first(&self) -> Option<&Checkpoint> {
+        self.saved.front()
+    }
+
+    /// Check if a new checkpoint should be taken at the specified cycle.
+    pub fn due(&self, cycle: u64) -> bool {
+        (self.saved.back()).map_or(true, |cp| cycle >= cp.cycle + self.interval)
+    }
+
+    pub fn push(&mut self, checkpoint: Checkpoint) {
+        self.saved.push_back(checkpoint);
+        if self.saved.len() > self.max {
+            self.saved.pop_front();
+        }
+    }
+
+    /// Check if a held span starts at the specified cycle (i.e: the recorded
+    /// history past this cycle can't be re-executed).
+    pub fn held_at(&self, cycle: u64) -> bool {
+        self.saved.iter().any(|cp| cp.held_from == Some(cycle))
+    }
+
+    /// Return the furthest cycle (up to `to`) which can be reached by
+    /// re-executing forwards from `from`.
+    pub fn replay_limit(&self, from: u64, to: u64) -> u64 {
+        (self.saved.iter())
+            .filter_map(|cp| cp.held_from)
+            .find(|&start| start >= from && start < to)
+            .unwrap_or(to)
+    }
+
+    /// Return the latest checkpoint taken strictly before the specified cycle.
+    pub fn before(&self, cycle: u64) -> Option<&Checkpoint> {
+        self.saved.iter().rev().find(|cp| cp.cycle < cycle)
+    }
+
+    /// Return the latest checkpoint taken at (or before) the specified cycle.
+    pub fn at_or_before(&self, cycle: u64) -> Option<&Checkpoint> {
+        self.before(cycle.saturating_add(1))
+    }
+
+    pub fn clear(&mut self) {
+        self.saved.clear();
+    }
+
+    /// Discard all checkpoints taken after the specified cycle (i.e: when the
+    /// system's state is modified out from under the recorded history).
+    pub fn discard_after(&mut self, cycle: u64) {
+        while matches!(self.saved.back(), Some(cp) if cp.cycle > cycle) {
+            self.saved.pop_back();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use armv4t_emu::reg;
+
+    use crate::block::backend::Mem;
+    use crate::devices::generic::ide::IdeReg;
+    use crate::memory::Memory;
+    use crate::sys::ipod4g::Ipod4gBus;
+    use crate::sys::pp::tests::new_system_with_hdd;
+    use crate::sys::pp::{PpBoard, PpSoc, PpSystem};
+
+    fn checkpoint(cycle: u64, held_from: Option<u64>) -> Checkpoint {
+        Checkpoint {
+            cycle,
+            state: Vec::new(),
+            hdd_journal: [0, 0],
+            held_from,
+        }
+    }
+
+    fn cycles(checkpoints: &Checkpoints) -> Vec<u64> {
+        checkpoints.saved.iter().map(|cp| cp.cycle).collect()
+    }
+
+    #[test]
+    fn due() {
+        let mut checkpoints = Checkpoints::new(10, 4);
+        assert!(checkpoints.due(0));
+        checkpoints.push(checkpoint(5, None));
+        assert!(!checkpoints.due(5));
+        assert!(!checkpoints.due(14));
+        assert!(checkpoints.due(15));
+        assert!(checkpoints.due(100));
+
+        // a zero interval is clamped, so that checkpoints are never taken twice
+        // at the same cycle
+        let mut checkpoints = Checkpoints::new(0, 4);
+        checkpoints.push(checkpoint(5, None));
+        assert!(!checkpoints.due(5));
+        assert!(checkpoints.due(6));
+    }
+
+    #[test]
+    fn lookup_and_eviction() {
+        let mut checkpoints = Checkpoints::new(10, 3);
+        for cycle in [0, 10, 20, 30] {
+            checkpoints.push(checkpoint(cycle, None));
+        }
+        // only the most recent checkpoints are kept
+        assert_eq!(cycles(&checkpoints), [10, 20, 30]);
+        assert_eq!(checkpoints.first().map(|cp| cp.cycle), Some(10));
+
+        assert!(checkpoints.before(10).is_none());
+        assert_eq!(checkpoints.before(11).map(|cp| cp.cycle), Some(10));
+        assert_eq!(checkpoints.before(30).map(|cp| cp.cycle), Some(20));
+        assert_eq!(checkpoints.at_or_before(30).map(|cp| cp.cycle), Some(30));
+        assert_eq!(checkpoints.at_or_before(99).map(|cp| cp.cycle), Some(30));
+        assert!(checkpoints.at_or_before(9).is_none());
+
+        checkpoints.discard_after(20);
+        assert_eq!(cycles(&checkpoints), [10, 20]);
+        checkpoints.discard_after(0);
+        assert!(checkpoints.first().is_none());
+    }
+
+    #[test]
+    fn held_spans() {
+        let mut checkpoints = Checkpoints::new(10, 8);
+        checkpoints.push(checkpoint(0, None));
+        checkpoints.push(checkpoint(10, None));
+        // cycles 13..17 were executed with a core held
+        checkpoints.push(checkpoint(17, Some(13)));
+
+        assert!(checkpoints.held_at(13));
+        assert!(!checkpoints.held_at(17));
+        assert_eq!(checkpoints.replay_limit(0, 12), 12);
+        assert_eq!(checkpoints.replay_limit(10, 20), 13);
+        assert_eq!(checkpoints.replay_limit(13, 20), 13);
+        assert_eq!(checkpoints.replay_limit(17, 20), 20);
+    }
+
+    const SECTORS: usize = 8;
+
+    fn ide_cmd(sys: &mut PpSystem<Ipod4gBus>, cmd: u8, lba: u8) {
+        let ide = sys.devices.pp_mut().ide();
+        ide.write8(IdeReg::DeviceHead, 0xe0).unwrap();
+        ide.write8(IdeReg::SectorCount, 1).unwrap();
+        ide.write8(IdeReg::SectorNo, lba).unwrap();
+        ide.write8(IdeReg::CylinderLo, 0).unwrap();
+        ide.write8(IdeReg::CylinderHi, 0).unwrap();
+        ide.write8(IdeReg::Command, cmd).unwrap();
+    }
+
+    fn write_sector(sys: &mut PpSystem<Ipod4gBus>, lba: u8, fill: u8) {
+        ide_cmd(sys, 0x30, lba);
+        let ide = sys.devices.pp_mut().ide();
+        for _ in 0..256 {
+            ide.write16(IdeReg::Data, u16::from_le_bytes([fill, fill]))
+                .unwrap();
+        }
+    }
+
+    fn read_sector(sys: &mut PpSystem<Ipod4gBus>, lba: u8) -> Vec<u8> {
+        ide_cmd(sys, 0x20, lba);
+        let ide = sys.devices.pp_mut().ide();
+        (0..256)
+            .flat_map(|_| ide.read16(IdeReg::Data).unwrap().to_le_bytes())
+            .collect()
+    }
+
+    fn recording_system() -> PpSystem<Ipod4gBus> {
+        let hdd = Mem::new(vec![0xaa; SECTORS * 512].into_boxed_slice());
+        let mut sys = new_system_with_hdd(Box::new(hdd));
+        sys.start_history().unwrap();
+        sys
+    }
+
+    #[test]
+    fn restore() {
+        let mut sys = recording_system();
+        sys.devices.w32(0x1000_0000, 0x1111_1111).unwrap();
+        sys.cpu.reg_set(sys.cpu.mode(), 4, 0x0404_0404);
+        let cp = sys.checkpoint();
+        assert_eq!(cp.cycle, sys.cycles());
+
+        sys.devices.w32(0x1000_0000, 0x2222_2222).unwrap();
+        sys.cpu.reg_set(sys.cpu.mode(), 4, 0);
+        sys.cycles += 100;
+
+        sys.restore(&cp).unwrap();
+        assert_eq!(sys.devices.r32(0x1000_0000).unwrap(), 0x1111_1111);
+        assert_eq!(sys.cpu.reg_get(sys.cpu.mode(), 4), 0x0404_0404);
+        assert_eq!(sys.cycles(), cp.cycle);
+
+        // checkpoints can be restored more than once
+        let pc = sys.cpu.reg_get(sys.cpu.mode(), reg::PC);
+        sys.cpu.reg_set(sys.cpu.mode(), reg::PC, 0x4000_0000);
+        sys.restore(&cp).unwrap();
+        assert_eq!(sys.cpu.reg_get(sys.cpu.mode(), reg::PC), pc);
+    }
+
+    #[test]
+    fn hdd_journal_rollback() {
+        let mut sys = recording_system();
+        let cp0 = sys.checkpoint();
+        write_sector(&mut sys, 1, 0x11);
+        write_sector(&mut sys, 2, 0x22);
+        let cp1 = sys.checkpoint();
+        write_sector(&mut sys, 1, 0x33);
+        write_sector(&mut sys, 3, 0x44);
+        assert_eq!(read_sector(&mut sys, 1), [0x33; 512]);
+
+        // writes made after the checkpoint are undone (in reverse order)...
+        sys.restore(&cp1).unwrap();
+        assert_eq!(read_sector(&mut sys, 1), [0x11; 512]);
+        assert_eq!(read_sector(&mut sys, 2), [0x22; 512]);
+        assert_eq!(read_sector(&mut sys, 3), [0xaa; 512]);
+
+        // ...and new writes can be undone in turn
+        write_sector(&mut sys, 2, 0x55);
+        sys.restore(&cp0).unwrap();
+        for lba in 0..SECTORS as u8 {
+            assert_eq!(read_sector(&mut sys, lba), [0xaa; 512], "sector {}", lba);
+        }
+    }
+
+    #[test]
+    fn hdd_journal_pruning() {
+        let mut sys = recording_system();
+        let cp0 = sys.checkpoint();
+        write_sector(&mut sys, 1, 0x11);
+        let cp1 = sys.checkpoint();
+        write_sector(&mut sys, 1, 0x22);
+
+        // once cp1 is the earliest checkpoint, cp0 can no longer be reached
+        sys.forget_before(&cp1);
+        assert!(sys.restore(&cp0).is_err());
+        sys.restore(&cp1).unwrap();
+        assert_eq!(read_sector(&mut sys, 1), [0x11; 512]);
+    }
+}
@@ -0,0 +1,269 @@
+use super::{PpBoard, PpControls, PpInputControls, PpSystem};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::devices::platform::pp::Controls;
+use crate::gui::{ButtonCallback, ScrollCallback, TakeControls};
+use crate::signal::gpio;
+
+/// GPIO lines used by the keypad's buttons, as indexes into GPIO ports A-D.
+/// Buttons are active-low.
+mod keypad_gpio {
+    pub const RIGHT: usize = 0;
+    pub const SELECT: usize = 1;
+    pub const PLAY: usize = 2;
+    pub const LEFT: usize = 3;
+    pub const MENU: usize = 4;
+}
+
+/// Buttons and scroll wheel which are wired directly to GPIO lines (i.e: the
+/// iPod mini 1g and iPod 3g).
+#[derive(Debug)]
+pub(super) struct GpioKeypad {
+    keys: Vec<(PpKey, gpio::Sender)>,
+    wheel: [gpio::Sender; 2],
+}
+
+impl GpioKeypad {
+    /// Create a new keypad (with the scroll wheel's quadrature outputs on the
+    /// `wheel` lines), registering each of it's lines with the GPIO controller.
+    pub fn new(
+        changed: &gpio::Changed,
+        wheel: [usize; 2],
+        mut register_in: impl FnMut(usize, gpio::Reciever),
+    ) -> GpioKeypad {
+        let mut new_line = |idx: usize, label: &'static str| {
+            let (mut tx, rx) = gpio::new(changed.clone(), label);
+            register_in(idx, rx);
+            // active-low
+            tx.set_high();
+            tx
+        };
+
+        let keys = vec![
+            (PpKey::Up, new_line(keypad_gpio::MENU, "Menu")),
+            (PpKey::Down, new_line(keypad_gpio::PLAY, "Play")),
+            (PpKey::Left, new_line(keypad_gpio::LEFT, "Left")),
+            (PpKey::Right, new_line(keypad_gpio::RIGHT, "Right")),
+            (PpKey::Action, new_line(keypad_gpio::SELECT, "Select")),
+        ];
+
+        let wheel = [new_line(wheel[0], "WheelA"), new_line(wheel[1], "WheelB")];
+
+        GpioKeypad { keys, wheel }
+    }
+}
+
+/// Physical controls shared by all iPods.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum PpKey {
+    Up,
+    Down,
+    Left,
+    Right,
+    Action,
+    Hold,
+}
+
+impl std::str::FromStr for PpKey {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<PpKey, &'static str> {
+        let key = match s {
+            "up" => PpKey::Up,
+            "down" => PpKey::Down,
+            "left" => PpKey::Left,
+            "right" => PpKey::Right,
+            "action" => PpKey::Action,
+            "hold" => PpKey::Hold,
+            _ => return Err("expected one of `up`, `down`, `left`, `right`, `action`, or `hold`"),
+        };
+        Ok(key)
+    }
+}
+
+impl std::fmt::Display for PpKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            PpKey::Up => "up",
+            PpKey::Down => "down",
+            PpKey::Left => "left",
+            PpKey::Right => "right",
+            PpKey::Action => "action",
+            PpKey::Hold => "hold",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Default)]
+pub struct PpBinds {
+    pub keys: HashMap<PpKey, ButtonCallback>,
+    pub wheel: Option<ScrollCallback>,
+}
+
+impl fmt::Debug for PpBinds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PpBinds")
+            .field("keys", &self.keys.keys())
+            .field("wheel", &self.wheel.is_some())
+            .finish()
+    }
+}
+
+impl<B: PpBoard> TakeControls for PpSystem<B> {
+    type Controls = PpBinds;
+
+    fn take_controls(&mut self) -> Option<PpBinds> {
+        // the system holds on to the actual binds, so that input can also be
+        // injected via `inject_input` (e.g: from the GDB stub)
+        let binds = Arc::new(Mutex::new(self.take_binds()?));
+
+        let mut proxy = PpBinds::default();
+        for &key in binds.lock().unwrap().keys.keys() {
+            let binds = binds.clone();
+            proxy.keys.insert(
+                key,
+                Box::new(move |pressed| {
+                    if let Some(cb) = binds.lock().unwrap().keys.get_mut(&key) {
+                        cb(pressed)
+                    }
+                }),
+            );
+        }
+        if binds.lock().unwrap().wheel.is_some() {
+            let binds = binds.clone();
+            proxy.wheel = Some(Box::new(move |delta| {
+                if let Some(cb) = &mut binds.lock().unwrap().wheel {
+                    cb(delta)
+                }
+            }));
+        }
+
+        self.binds = Some(binds);
+        Some(proxy)
+    }
+}
+
+impl<B: PpBoard> PpSystem<B> {
+    /// Take the system's controls, returning binds which drive the underlying
+    /// devices directly.
+    pub(super) fn take_binds(&mut self) -> Option<PpBinds> {
+        let PpControls { mut hold, input } = self.controls.take()?;
+
+        let mut controls = PpBinds::default();
+
+        controls.keys.insert(
+            PpKey::Hold,
+            Box::new(move |pressed| {
+                if pressed {
+                    // toggle on and off
+                    match hold.is_set_high() {
+                        false => hold.set_high(),
+                        true => hold.set_low(),
+                    }
+                }
+            }),
+        );
+
+        let input = match input {
+            PpInputControls::ClickWheel(input) => *input,
+            PpInputControls::GpioKeypad(keypad) => {
+                connect_gpio_keypad(&mut controls, keypad);
+                return Some(controls);
+            }
+        };
+
+        let Controls {
+            mut action,
+            mut up,
+            mut down,
+            mut left,
+            mut right,
+            wheel: (mut wheel_active, wheel_data),
+        } = input;
+
+        macro_rules! connect_controls_btn {
+            ($key:expr, $signal:expr) => {
+                controls.keys.insert(
+                    $key,
+                    Box::new(move |pressed| {
+                        if pressed {
+                            $signal.assert()
+                        } else {
+                            $signal.clear()
+                        }
+                    }),
+                );
+            };
+        }
+
+        connect_controls_btn!(PpKey::Up, up);
+        connect_controls_btn!(PpKey::Down, down);
+        connect_controls_btn!(PpKey::Left, left);
+        connect_controls_btn!(PpKey::Right, right);
+        connect_controls_btn!(PpKey::Action, action);
+
+        // TODO: make sensitivity adjustable based on user's scroll speed
+        controls.wheel = Some({
+            Box::new(move |(_dx, dy)| {
+                // HACK: the signal is edge-triggered
+                // TODO: i really aught to rework how input works...
+                if wheel_active.is_asserting() {
+                    wheel_active.clear();
+                } else {
+                    wheel_active.assert();
+                }
+
+                let mut wheel_data = wheel_data.lock().unwrap();
+                // from rockbox button-clickwheel.c
+                // #define WHEELCLICKS_PER_ROTATION     96 /* wheelclicks per full rotation */
+                *wheel_data = wheel_data.wrapping_add((-dy * 2.) as i8 as u8) % 96;
+            })
+        });
+
+        Some(controls)
+    }
+}
+
+fn connect_gpio_keypad(controls: &mut PpBinds, keypad: GpioKeypad) {
+    let GpioKeypad { keys, mut wheel } = keypad;
+
+    for (key, mut line) in keys {
+        controls.keys.insert(
+            key,
+            Box::new(move |pressed| {
+                // active-low
+                if pressed {
+                    line.set_low()
+                } else {
+                    line.set_high()
+                }
+            }),
+        );
+    }
+
+    // the wheel outputs a 2-bit gray code, which advances by one step per
+    // scroll event (in the direction of the scroll).
+    let mut phase: u8 = 2; // both lines start off high
+    controls.wheel = Some(Box::new(move |(_dx, dy)| {
+        if dy == 0. {
+            return;
+        }
+
+        phase = match dy < 0. {
+            true => phase.wrapping_add(1),
+            false => phase.wrapping_sub(1),
+        } % 4;
+
+        let gray = phase ^ (phase >> 1);
+        for (i, line) in wheel.iter_mut().enumerate() {
+            match gray & (1 << i) != 0 {
+                true => line.set_high(),
+                false => line.set_low(),
+            }
+        }
+    }));
+}
@@ -0,0 +1,1606 @@
+use std::collections::HashSet;
+use std::marker::PhantomData;
+
+use armv4t_emu::reg;
+use gdbstub::common::{Signal, Tid};
+use gdbstub::conn::ConnectionExt;
+use gdbstub::outputln;
+use gdbstub::stub::run_blocking::{self, BlockingEventLoop};
+use gdbstub::stub::MultiThreadStopReason;
+use gdbstub::target;
+use gdbstub::target::ext::base::multithread::{MultiThreadBase, MultiThreadResume};
+use gdbstub::target::ext::base::reverse_exec::ReplayLogPosition;
+use gdbstub::target::ext::breakpoints::WatchKind;
+use gdbstub::target::ext::monitor_cmd::ConsoleOutput;
+use gdbstub::target::{Target, TargetResult};
+use gdbstub_arch::arm::{reg::ArmCoreRegs, ArmBreakpointKind, Armv4t};
+
+use crate::coverage::{Coverage, CoverageOpts};
+use crate::error::*;
+use crate::memory::MemAccessKind;
+use crate::profiler::{ProfileFormat, Profiler};
+use crate::trace::{self, Tracer};
+
+use super::checkpoint::Checkpoints;
+use super::watchpoints::{Watchpoint, Watchpoints};
+use super::{BlockMode, CpuId, InputEvent, PpBoard, PpKey, PpSoc, PpSystem};
+
+/// Default number of cycles between checkpoints while recording.
+const DEFAULT_CHECKPOINT_INTERVAL: u64 = 1_000_000;
+/// Default number of checkpoints kept around while recording.
+const DEFAULT_MAX_CHECKPOINTS: usize = 8;
+
+/// Updates the frontend's log filters using `RUST_LOG` style directives,
+/// returning the resulting set of filters. Called with `None` to query the
+/// current filters.
+pub type LogFilterCallback = Box<dyn FnMut(Option<&str>) -> Result<String, String> + Send>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Event {
+    Halted,
+    Break,
+    Watch {
+        kind: WatchKind,
+        addr: u32,
+        access: MemAccessKind,
+    },
+}
+
+/// How a core should be resumed, as requested by GDB.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ResumeAction {
+    Step,
+    Continue,
+}
+
+/// Which way the system should be run backwards, as requested by GDB.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ReverseAction {
+    Step,
+    Continue,
+}
+
+pub struct PpGdb<B: PpBoard> {
+    sys: PpSystem<B>,
+
+    watchpoints: Watchpoints,
+    breakpoints: HashSet<u32>,
+
+    resume_actions: [Option<ResumeAction>; 2], // indexed by CpuId
+    scheduler_lock: bool,                      // cores without a resume action are held in place
+    reverse_action: Option<ReverseAction>,
+
+    single_step_irq: bool,
+    checkpoints: Option<Checkpoints>, // set while recording execution history
+    log_filter: Option<LogFilterCallback>,
+}
+
+impl<B: PpBoard> PpGdb<B> {
+    pub fn new(sys: PpSystem<B>) -> PpGdb<B> {
+        PpGdb {
+            sys,
+            watchpoints: Watchpoints::default(),
+            breakpoints: HashSet::new(),
+            resume_actions: [None, None],
+            scheduler_lock: false,
+            reverse_action: None,
+            single_step_irq: false,
+            checkpoints: None,
+            log_filter: None,
+        }
+    }
+
+    /// Set the callback used by the `log` monitor command to update the
+    /// frontend's log filters.
+    pub fn set_log_filter_callback(&mut self, callback: LogFilterCallback) {
+        self.log_filter = Some(callback);
+    }
+
+    pub fn sys_ref(&self) -> &PpSystem<B> {
+        &self.sys
+    }
+
+    pub fn sys_mut(&mut self) -> &mut PpSystem<B> {
+        &mut self.sys
+    }
+
+    /// Step the system (taking a checkpoint first, if one is due), returning
+    /// `Event::Halted` if the system exited, or the watchpoint hit during the
+    /// step (if any).
+    fn step_watched(&mut self) -> Result<Option<(Event, CpuId)>, FatalMemException> {
+        if let Some(checkpoints) = &self.checkpoints {
+            // the recorded history past a held span can't be re-executed, so
+            // executing past its start diverges from the recorded history
+            if checkpoints.held_at(self.sys.cycles) {
+                self.discard_history_after_now();
+            }
+        }
+
+        if let Some(checkpoints) = &mut self.checkpoints {
+            // (held spans are followed by a checkpoint anyway)
+            if checkpoints.due(self.sys.cycles) && !self.sys.held.contains(&true) {
+                checkpoints.push(self.sys.checkpoint());
+                self.sys.forget_before(checkpoints.first().unwrap());
+            }
+        }
+
+        let mut hit_watchpoint = None;
+
+        let watchpoints = &self.watchpoints;
+        let exit = self.sys.step(
+            BlockMode::NonBlocking,
+            (
+                &|addr, len| watchpoints.wants(addr, len),
+                |cpuid, access| {
+                    if let Some((wp, addr)) = watchpoints.hit(&access) {
+                        let event = Event::Watch {
+                            kind: wp.kind,
+                            addr,
+                            access: access.kind,
+                        };
+                        hit_watchpoint.get_or_insert((event, cpuid));
+                    }
+                },
+            ),
+        )?;
+
+        if exit.is_some() {
+            return Ok(Some((Event::Halted, CpuId::Cpu)));
+        }
+
+        Ok(hit_watchpoint)
+    }
+
+    fn step(&mut self) -> Result<Option<(Event, CpuId)>, FatalMemException> {
+        let (event, id) = match self.step_watched()? {
+            None => return Ok(self.hit_breakpoint().map(|id| (Event::Break, id))),
+            Some((Event::Halted, id)) => return Ok(Some((Event::Halted, id))),
+            Some(hit) => hit,
+        };
+
+        // rewind the PC, so that execution stops right before the access
+        let cpu = match id {
+            CpuId::Cpu => &mut self.sys.cpu,
+            CpuId::Cop => &mut self.sys.cop,
+        };
+
+        let pc = cpu.reg_get(cpu.mode(), reg::PC);
+        cpu.reg_set(
+            cpu.mode(),
+            reg::PC,
+            pc - if cpu.thumb_mode() { 2 } else { 4 },
+        );
+        // re-executing the access diverges from the recorded history
+        self.discard_history_after_now();
+
+        Ok(Some((event, id)))
+    }
+
+    /// Resume the system according to the actions requested by GDB. Returns
+    /// `None` if execution was interrupted by incoming data from GDB.
+    fn run(
+        &mut self,
+        mut poll_incoming_data: impl FnMut() -> bool,
+    ) -> Result<Option<MultiThreadStopReason<u32>>, FatalMemException> {
+        if let Some(action) = self.reverse_action {
+            return Ok(Some(self.resume_reverse(action)));
+        }
+
+        // unless GDB asked for scheduler locking, cores without a
+        // corresponding action are continued
+        let held = match self.scheduler_lock {
+            true => [
+                self.resume_actions[0].is_none(),
+                self.resume_actions[1].is_none(),
+            ],
+            false => [false, false],
+        };
+        let step = self.resume_actions.contains(&Some(ResumeAction::Step));
+
+        let start = self.sys.cycles;
+        self.sys.held = held;
+        let res = match step {
+            true => self.resume_step().map(Some),
+            false => self.resume_continue(&mut poll_incoming_data),
+        };
+        self.sys.held = [false, false];
+
+        if held.contains(&true) && self.sys.cycles > start {
+            // recorded history can only be re-executed with both cores running,
+            // so re-execution has to stop at the start of the held span
+            if let Some(checkpoints) = &mut self.checkpoints {
+                let mut checkpoint = self.sys.checkpoint();
+                checkpoint.held_from = Some(start);
+                checkpoints.push(checkpoint);
+                self.sys.forget_before(checkpoints.first().unwrap());
+            }
+        }
+
+        res
+    }
+
+    /// Step the system by a single cycle. Any cores which are being continued
+    /// (as opposed to stepped) are stepped alongside it.
+    fn resume_step(&mut self) -> Result<MultiThreadStopReason<u32>, FatalMemException> {
+        // skipping IRQ checks would make execution impossible to replay
+        let skip_irq_check = !self.single_step_irq && self.checkpoints.is_none();
+        self.sys.skip_irq_check = skip_irq_check;
+        let res = match self.step() {
+            Ok(Some((event, cpuid))) => Ok(self.stop_reason(event, cpuid)),
+            Ok(None) => Ok(MultiThreadStopReason::DoneStep),
+            Err(e) => Err(e),
+        };
+        self.sys.skip_irq_check = false;
+        res
+    }
+
+    fn resume_continue(
+        &mut self,
+        poll_incoming_data: &mut dyn FnMut() -> bool,
+    ) -> Result<Option<MultiThreadStopReason<u32>>, FatalMemException> {
+        let mut cycles: usize = 0;
+        loop {
+            // check for GDB interrupt every 1024 instructions
+            if cycles % 1024 == 0 && poll_incoming_data() {
+                return Ok(None);
+            }
+            cycles += 1;
+
+            if let Some((event, cpuid)) = self.step()? {
+                return Ok(Some(self.stop_reason(event, cpuid)));
+            };
+        }
+    }
+
+    /// Run the system backwards. Reaching the start of the recorded history
+    /// (or not having recorded any history at all) is reported to GDB as
+    /// running out of history.
+    fn resume_reverse(&mut self, action: ReverseAction) -> MultiThreadStopReason<u32> {
+        let res = match action {
+            ReverseAction::Step => match self.sys.cycles.checked_sub(1) {
+                None => Ok(None),
+                // stepping back into a held span ends up at its start
+                Some(target) => (self.seek(target)).map(|cycle| {
+                    let start = self.checkpoints.as_ref().and_then(|cps| cps.first());
+                    (cycle == target || start.is_some_and(|cp| cp.cycle < cycle))
+                        .then_some(MultiThreadStopReason::DoneStep)
+                }),
+            },
+            ReverseAction::Continue => (self.reverse_continue())
+                .map(|hit| hit.map(|(event, cpuid)| self.stop_reason(event, cpuid))),
+        };
+
+        match res {
+            Ok(Some(stop_reason)) => stop_reason,
+            Ok(None) => MultiThreadStopReason::ReplayLog {
+                tid: None,
+                pos: ReplayLogPosition::Begin,
+            },
+            Err(e) => {
+                warn!("couldn't run backwards: {}", e);
+                MultiThreadStopReason::ReplayLog {
+                    tid: None,
+                    pos: ReplayLogPosition::Begin,
+                }
+            }
+        }
+    }
+
+    fn stop_reason(&self, e: Event, id: CpuId) -> MultiThreadStopReason<u32> {
+        let tid = cpuid_to_tid(id);
+        match e {
+            Event::Halted => {
+                let code = self.sys.exit.map_or(0, |exit| exit.exit_code());
+                MultiThreadStopReason::Exited(code as u8)
+            }
+            Event::Break => MultiThreadStopReason::SwBreak(tid),
+            Event::Watch { kind, addr, .. } => MultiThreadStopReason::Watch { tid, kind, addr },
+        }
+    }
+
+    /// Check if either (non-held) core is sitting on a breakpoint.
+    fn hit_breakpoint(&self) -> Option<CpuId> {
+        for (id, cpu) in &[(CpuId::Cpu, &self.sys.cpu), (CpuId::Cop, &self.sys.cop)] {
+            if self.sys.held[*id as usize] {
+                continue;
+            }
+            let pc = cpu.reg_get(cpu.mode(), reg::PC);
+            if self.breakpoints.contains(&pc) {
+                return Some(*id);
+            }
+        }
+        None
+    }
+
+    /// Called whenever the system's state is modified by the debugger, as any
+    /// recorded history past the current cycle is no longer reachable.
+    fn discard_history_after_now(&mut self) {
+        if let Some(checkpoints) = &mut self.checkpoints {
+            checkpoints.discard_after(self.sys.cycles);
+            self.sys.discard_history_after_now();
+        }
+    }
+
+    /// Step the system forwards while re-executing recorded history.
+    ///
+    /// Unlike `step`, watchpoint hits don't perturb the system's state.
+    /// Returns `None` if the system exited, or the watchpoint (if any) which
+    /// was hit during the step.
+    fn replay_step(&mut self) -> Result<Option<Option<(Event, CpuId)>>, String> {
+        match self.step_watched() {
+            Err(e) => Err(format!("fatal error while re-executing history: {:?}", e)),
+            Ok(Some((Event::Halted, _))) => Ok(None),
+            Ok(hit_watchpoint) => Ok(Some(hit_watchpoint)),
+        }
+    }
+
+    /// Move the system to the specified cycle, restoring a checkpoint if the
+    /// cycle is in the past. Returns the cycle the system ended up at (which
+    /// may differ from `target` if the system exits, if `target` predates the
+    /// recorded history, or if reaching `target` requires re-executing a span
+    /// where one of the cores was held).
+    fn seek(&mut self, target: u64) -> Result<u64, String> {
+        if self.sys.frozen {
+            return Err("system is frozen".into());
+        }
+
+        if target < self.sys.cycles {
+            let checkpoints = self.checkpoints.as_ref().ok_or("not recording")?;
+            // targets predating the recorded history end up at its start
+            let checkpoint = (checkpoints.at_or_before(target))
+                .or_else(|| checkpoints.first())
+                .ok_or("no execution history has been recorded")?;
+            (self.sys.restore(checkpoint))
+                .map_err(|e| format!("couldn't restore checkpoint: {}", e))?;
+        }
+
+        let target = match &self.checkpoints {
+            Some(checkpoints) => checkpoints.replay_limit(self.sys.cycles, target),
+            None => target,
+        };
+        while self.sys.cycles < target {
+            if self.replay_step()?.is_none() {
+                break;
+            }
+        }
+
+        Ok(self.sys.cycles)
+    }
+
+    /// Run backwards until the most recent breakpoint / watchpoint hit (or
+    /// until the start of the recorded history).
+    fn reverse_continue(&mut self) -> Result<Option<(Event, CpuId)>, String> {
+        if self.sys.frozen {
+            return Err("system is frozen".into());
+        }
+
+        let end = self.sys.cycles;
+        let mut segment_end = end;
+        loop {
+            let checkpoints = self.checkpoints.as_ref().ok_or("not recording")?;
+            let checkpoint = match checkpoints.before(segment_end) {
+                Some(checkpoint) => checkpoint,
+                None => {
+                    let start = checkpoints.first().map_or(end, |cp| cp.cycle);
+                    self.seek(start)?;
+                    return Ok(None);
+                }
+            };
+            let segment_start = checkpoint.cycle;
+            let replay_end = checkpoints.replay_limit(segment_start, segment_end);
+            (self.sys.restore(checkpoint))
+                .map_err(|e| format!("couldn't restore checkpoint: {}", e))?;
+
+            // re-execute the segment, keeping track of the last hit. The segment's
+            // first cycle isn't stepped into, so check it up-front (otherwise a
+            // breakpoint right at the earliest checkpoint would never be hit).
+            let mut last_hit = None;
+            if segment_start < end {
+                if let Some(id) = self.hit_breakpoint() {
+                    last_hit = Some((segment_start, (Event::Break, id)));
+                }
+            }
+            while self.sys.cycles < replay_end {
+                let watch = match self.replay_step()? {
+                    Some(watch) => watch,
+                    None => break,
+                };
+                let cycle = self.sys.cycles;
+                // a watchpoint stops execution right before the access
+                if let Some(hit) = watch {
+                    last_hit = Some((cycle - 1, hit));
+                }
+                if cycle < end {
+                    if let Some(id) = self.hit_breakpoint() {
+                        last_hit = Some((cycle, (Event::Break, id)));
+                    }
+                }
+            }
+
+            if let Some((cycle, hit)) = last_hit {
+                self.seek(cycle)?;
+                return Ok(Some(hit));
+            }
+
+            segment_end = segment_start;
+        }
+    }
+
+    fn exec_dbg_command(&mut self, cmd: &str, out: &mut ConsoleOutput) -> Result<(), String> {
+        const HELP: &str = r#"
+Available commands:
+===================
+
+Query System State
+--------------------------------------------------------------------------------
+  dumpsys            - pretty-print a debug view of the system
+  probe <addr>       - probe what device is at the specified address
+  sym <addr|name>    - look up the symbol at the specified address (or the
+                       address of the specified symbol)
+  sym load <file> [offset]
+                     - load symbols from an ELF file, shifting them by offset
+  mmio_report        - show all accesses to unmapped / stubbed addresses
+                       (requires `--mmio-report`)
+  abort              - show the last guest fault delivered to each core as an
+                       abort (requires `--guest-aborts`)
+
+Devices
+--------------------------------------------------------------------------------
+  dev                         - list all devices
+  dev <name>                  - pretty-print a debug view of a single device
+                                (e.g: `dev intcon`, `dev timer1`)
+  irq                         - show whether each core is being interrupted,
+                                along with the state of each IRQ line
+  peek <addr> [n]             - read <n> words (default: 1) starting at <addr>
+  poke <addr> <val> [8|16|32] - write a <val> to <addr> (default: 32 bits)
+
+    Unlike regular memory accesses, reading from MMIO via `peek` (or via GDB)
+    doesn't have any side effects (e.g: clearing a device's status register).
+    Registers which can't be read without side effects are shown as `????????`
+    (or as an error in GDB).
+
+Input / Output
+--------------------------------------------------------------------------------
+  input press <key>           - press a key (up, down, left, right, action,
+                                hold)
+  input release <key>         - release a key
+  input scroll <dx> <dy>      - scroll the click wheel
+  screenshot <file>           - save the contents of the LCD to a PNG <file>
+
+    If input is being recorded (`--record-input`), injected input is recorded
+    as well, and is delivered to the system when it next resumes.
+
+System State
+--------------------------------------------------------------------------------
+  state save <file>           - save the system's state to <file>
+  state load <file>           - restore a state saved via `state save` (or
+                                `--save-state`). Discards recorded history.
+
+Debugging
+--------------------------------------------------------------------------------
+  single_step_irq <bool> - enable IRQs while single-stepping (default: false)
+
+    Setting this to "false" will artificially prevent devices from generating
+    IRQs while single-step debugging. This is great for debugging most
+    user-level code, but should be turned off when debugging working with
+    low-level, IRQ sensitive code (e.g: early boot, context switching, etc...)
+
+Watchpoints
+--------------------------------------------------------------------------------
+  watch                       - list all watchpoints
+  watch <addr|sym> [len] [r|w|rw] [value=<val>]
+                              - stop when any of the <len> bytes starting at
+                                <addr> are read (r), written (w, default), or
+                                either (rw), optionally only when the accessed
+                                value is <val>. <len> defaults to the size of
+                                the symbol (or 4).
+  unwatch <addr|sym|all>      - remove watchpoints set via `watch`
+
+    Watchpoints set via GDB cover the entire watched expression. Use the
+    `watch` command to watch arbitrary ranges / values.
+
+Reverse Execution
+--------------------------------------------------------------------------------
+  record                      - show the state of the recorded history
+  record start [interval] [max]
+                              - start recording execution history, taking a
+                                checkpoint every <interval> cycles (default:
+                                1000000), keeping at most <max> checkpoints
+                                (default: 8). Requires `--virtual-time`.
+  record stop                 - stop recording, and discard all history
+  record goto <cycle>         - move (backwards or forwards) to <cycle>
+
+    While recording, GDB's `reverse-stepi` and `reverse-continue` commands
+    can be used to run backwards. GDB isn't aware that `record goto` modifies
+    the system's state, so follow it up with `flushregs`. While recording,
+    IRQs are never masked while single-stepping (i.e: `single_step_irq` is
+    ignored). Execution where one of the cores was held (e.g: via
+    `set scheduler-locking on`) can't be re-executed, so stepping back into
+    it ends up at the point where the core was first held.
+
+Instruction Tracing
+--------------------------------------------------------------------------------
+  trace                       - show the tracer's status
+  trace start <file>          - start recording a new trace to <file>
+  trace stop                  - stop recording, and close the trace file
+  trace pause / trace resume  - temporarily pause / resume recording
+  trace filter [<start>-<end> ...]
+                              - only record instructions in the specified PC
+                                ranges (no ranges == record everything)
+
+Profiling
+--------------------------------------------------------------------------------
+  profile                     - show the profiler's status
+  profile start [interval] [stacks]
+                              - start sampling each core's PC every <interval>
+                                instructions (default: 1000), optionally
+                                tracking call stacks
+  profile stop                - stop sampling, and discard all samples
+  profile dump <file> [collapsed|callgrind]
+                              - write all samples collected so far to <file>
+
+Code Coverage
+--------------------------------------------------------------------------------
+  coverage                    - show how many basic blocks have been executed
+  coverage start              - start collecting coverage (from scratch)
+  coverage stop               - stop collecting coverage, and discard it
+  coverage clear              - discard all coverage collected so far
+  coverage dump <file> [format=<drcov|lcov>] [core=<cpu|cop>]
+                   [module=<name>] [base=<addr>]
+                              - write all coverage collected so far to <file>
+
+Logging
+--------------------------------------------------------------------------------
+  log                         - show the current log filters
+  log <directives>            - update the log filters, using `RUST_LOG` syntax
+                                (e.g: `log MMIO=trace,clicky::devices=debug`)
+
+Help
+--------------------------------------------------------------------------------
+  help               - show this help message
+"#;
+
+        let mut s = cmd.split(' ');
+        let cmd = match s.next() {
+            None | Some("") => {
+                outputln!(out, "Use `monitor help` to list all available commands.");
+                return Ok(());
+            }
+            Some(cmd) => cmd,
+        };
+
+        match cmd {
+            "help" => outputln!(out, "{}", HELP),
+            "dumpsys" => outputln!(out, "{:#x?}", self.sys),
+            "probe" => {
+                let addr = s.next().ok_or("no addr provided")?;
+                let addr = parse_addr(addr).ok_or("couldn't parse addr")?;
+
+                outputln!(out, "{}", self.sys.devices.probe(addr))
+            }
+            "single_step_irq" => {
+                match s.next() {
+                    None => {}
+                    Some(toggle_s) => {
+                        self.single_step_irq = toggle_s
+                            .parse::<bool>()
+                            .map_err(|_| "couldn't parse <bool>")?
+                    }
+                };
+
+                outputln!(out, "single_step_irq = {}", self.single_step_irq)
+            }
+            "sym" => self.exec_sym_command(s, out)?,
+            "watch" => self.exec_watch_command(s, out)?,
+            "unwatch" => {
+                let target = s.next().ok_or("no addr / symbol provided")?;
+                if target == "all" {
+                    self.watchpoints.clear_user();
+                } else {
+                    let (addr, _) = self.parse_watch_target(target)?;
+                    let mut removed = 0;
+                    while (self.watchpoints).remove(addr, |wp| wp.user) {
+                        removed += 1;
+                    }
+                    if removed == 0 {
+                        return Err(format!("no watchpoints at {:#010x}", addr));
+                    }
+                }
+                outputln!(out, "{} watchpoints remaining", self.watchpoints.len())
+            }
+            "mmio_report" => match self.sys.mmio_discovery() {
+                Some(discovery) => outputln!(out, "{}", discovery),
+                None => return Err("MMIO discovery mode isn't enabled".into()),
+            },
+            "abort" => {
+                for core in [CpuId::Cpu, CpuId::Cop].iter() {
+                    match self.sys.last_abort(*core) {
+                        None => outputln!(out, "{:?}: no aborts", core),
+                        Some(abort) => outputln!(
+                            out,
+                            "{:?}: {:?} abort at pc {:#010x?} ({}) - {:?}",
+                            core,
+                            abort.kind,
+                            abort.pc,
+                            abort.access,
+                            abort.reason
+                        ),
+                    }
+                }
+            }
+            "dev" => match s.next() {
+                None | Some("") => {
+                    for name in self.sys.devices.device_names() {
+                        outputln!(out, "{}", name)
+                    }
+                }
+                Some(name) => {
+                    let dev = (self.sys.devices.find_device(name))
+                        .ok_or_else(|| format!("unknown device '{}' (see `dev`)", name))?;
+                    outputln!(out, "{:#x?}", dev)
+                }
+            },
+            "irq" => self.exec_irq_command(out),
+            "peek" => {
+                let addr = s.next().ok_or("no addr provided")?;
+                let addr = parse_addr(addr).ok_or("couldn't parse addr")?;
+                let n = match s.next() {
+                    None | Some("") => 1,
+                    Some(n) => n.parse::<u32>().map_err(|_| "couldn't parse <n>")?,
+                };
+                if addr % 4 != 0 {
+                    return Err("addr must be word-aligned".into());
+                }
+
+                let end = addr.saturating_add(n.saturating_mul(4));
+                let words = (addr..end)
+                    .step_by(4)
+                    .map(|addr| self.sys.devices.peek32(addr))
+                    .collect::<Vec<_>>();
+                for (i, row) in words.chunks(4).enumerate() {
+                    // registers which can't be read without side effects
+                    let row = row.iter().map(|w| match w {
+                        Some(w) => format!("{:08x}", w),
+                        None => "????????".to_string(),
+                    });
+                    let row = row.collect::<Vec<_>>().join(" ");
+                    outputln!(out, "{:#010x}: {}", addr + i as u32 * 16, row);
+                }
+            }
+            "poke" => {
+                let addr = s.next().ok_or("no addr provided")?;
+                let addr = parse_addr(addr).ok_or("couldn't parse addr")?;
+                let val = s.next().ok_or("no val provided")?;
+                let val = parse_addr(val).ok_or("couldn't parse val")?;
+
+                let res = match s.next() {
+                    None | Some("") | Some("32") => self.sys.devices.w32(addr, val),
+                    Some("16") => self.sys.devices.w16(addr, val as u16),
+                    Some("8") => self.sys.devices.w8(addr, val as u8),
+                    Some(_) => return Err("width must be one of 8, 16, or 32".into()),
+                };
+                match res {
+                    Ok(()) | Err(MemException::StubWrite(..)) => {}
+                    Err(e) => return Err(format!("couldn't write {:#010x}: {:x?}", addr, e)),
+                }
+                self.discard_history_after_now();
+            }
+            "input" => {
+                let event = match (s.next(), s.next()) {
+                    (Some("press"), Some(key)) => InputEvent::Key(parse_key(key)?, true),
+                    (Some("release"), Some(key)) => InputEvent::Key(parse_key(key)?, false),
+                    (Some("scroll"), Some(dx)) => {
+                        let dy = s.next().ok_or("no dy provided")?;
+                        let parse = |s: &str| s.parse::<f32>().map_err(|_| "couldn't parse delta");
+                        InputEvent::Scroll(parse(dx)?, parse(dy)?)
+                    }
+                    (Some(cmd), _) if !["press", "release", "scroll"].contains(&cmd) => {
+                        return Err(format!("unknown input event '{}'", cmd))
+                    }
+                    _ => return Err("missing arguments (see `help`)".into()),
+                };
+                self.sys
+                    .inject_input(event)
+                    .map_err(|e| format!("couldn't inject input: {}", e))?;
+                self.discard_history_after_now();
+            }
+            "screenshot" => {
+                let path = s.next().ok_or("no file provided")?;
+                self.save_screenshot(path)
+                    .map_err(|e| format!("couldn't save screenshot to '{}': {}", path, e))?;
+            }
+            "state" => {
+                let (cmd, path) = match (s.next(), s.next()) {
+                    (Some(cmd), Some(path)) => (cmd, path),
+                    _ => return Err("missing arguments (see `help`)".into()),
+                };
+                match cmd {
+                    "save" => {
+                        let file = std::fs::File::create(path)
+                            .map_err(|e| format!("couldn't create '{}': {}", path, e))?;
+                        (self.sys)
+                            .save_state(std::io::BufWriter::new(file))
+                            .map_err(|e| format!("couldn't save state: {}", e))?;
+                    }
+                    "load" => {
+                        let file = std::fs::File::open(path)
+                            .map_err(|e| format!("couldn't open '{}': {}", path, e))?;
+                        (self.sys)
+                            .load_state(std::io::BufReader::new(file))
+                            .map_err(|e| format!("couldn't load state: {}", e))?;
+                        // the recorded history belongs to a different timeline
+                        if let Some(checkpoints) = &mut self.checkpoints {
+                            (self.sys.start_history())
+                                .map_err(|e| format!("couldn't restart recording: {}", e))?;
+                            checkpoints.clear();
+                            checkpoints.push(self.sys.checkpoint());
+                        }
+                        outputln!(out, "use `flushregs` to refresh GDB's view of the target");
+                    }
+                    _ => return Err(format!("unknown state command '{}'", cmd)),
+                }
+            }
+            "log" => {
+                let log_filter = (self.log_filter.as_mut())
+                    .ok_or("the frontend doesn't support changing log filters")?;
+                let directives = Some(s.collect::<Vec<_>>().join(""));
+                let filters = log_filter(directives.as_deref().filter(|s| !s.is_empty()))?;
+                outputln!(out, "log filters: {}", filters)
+            }
+            "record" => self.exec_record_command(s, out)?,
+            "trace" => self.exec_trace_command(s, out)?,
+            "profile" => self.exec_profile_command(s, out)?,
+            "coverage" => self.exec_coverage_command(s, out)?,
+            _ => {
+                return Err(format!(
+                    "Unsupported command '{}'.\nUse `monitor help` to list all available commands.",
+                    cmd
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn exec_sym_command<'a>(
+        &mut self,
+        mut args: impl Iterator<Item = &'a str>,
+        out: &mut ConsoleOutput,
+    ) -> Result<(), String> {
+        let arg = args.next().ok_or("no addr / symbol provided")?;
+        if arg == "load" {
+            let path = args.next().ok_or("no file provided")?;
+            let offset = match args.next() {
+                None => 0,
+                Some(offset) => parse_addr(offset).ok_or("couldn't parse offset")?,
+            };
+            let data =
+                std::fs::read(path).map_err(|e| format!("couldn't read '{}': {}", path, e))?;
+            let n = (self.sys.symbols_mut())
+                .load_elf(&data, offset)
+                .map_err(|e| format!("couldn't load symbols from '{}': {}", path, e))?;
+            outputln!(out, "loaded {} symbols from {}", n, path);
+            return Ok(());
+        }
+
+        match parse_addr(arg) {
+            Some(addr) => outputln!(out, "{}", self.sys.fmt_addr(addr)),
+            None => match self.sys.symbols().find(arg) {
+                Some(sym) => outputln!(
+                    out,
+                    "{:#010x} <{}> (size {:#x})",
+                    sym.addr,
+                    sym.name,
+                    sym.size
+                ),
+                None => return Err(format!("unknown symbol '{}'", arg)),
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Parse an address / symbol name, returning the address along with the
+    /// default length of a watchpoint on it (i.e: the size of the symbol, or a
+    /// single word).
+    fn parse_watch_target(&self, target: &str) -> Result<(u32, u32), String> {
+        if let Some(addr) = parse_addr(target) {
+            return Ok((addr, 4));
+        }
+        match self.sys.symbols().find(target) {
+            Some(sym) if sym.size != 0 => Ok((sym.addr, sym.size)),
+            Some(sym) => Ok((sym.addr, 4)),
+            None => Err(format!("unknown symbol '{}'", target)),
+        }
+    }
+
+    fn exec_watch_command<'a>(
+        &mut self,
+        args: impl Iterator<Item = &'a str>,
+        out: &mut ConsoleOutput,
+    ) -> Result<(), String> {
+        let mut args = args.filter(|s| !s.is_empty());
+        if let Some(target) = args.next() {
+            let (addr, mut len) = self.parse_watch_target(target)?;
+            let mut kind = WatchKind::Write;
+            let mut value = None;
+            for arg in args {
+                match arg {
+                    "r" => kind = WatchKind::Read,
+                    "w" => kind = WatchKind::Write,
+                    "rw" => kind = WatchKind::ReadWrite,
+                    _ => match arg.strip_prefix("value=") {
+                        Some(val) => value = Some(parse_addr(val).ok_or("couldn't parse value")?),
+                        None => {
+                            len = parse_addr(arg)
+                                .ok_or_else(|| format!("unexpected argument '{}'", arg))?
+                        }
+                    },
+                }
+            }
+            if len == 0 {
+                return Err("len must be non-zero".into());
+            }
+            self.watchpoints.insert(Watchpoint {
+                addr,
+                len,
+                kind,
+                value,
+                user: true,
+            });
+        }
+
+        if self.watchpoints.len() == 0 {
+            outputln!(out, "watchpoints: none");
+        }
+        for wp in self.watchpoints.iter() {
+            outputln!(out, "{}", wp);
+        }
+
+        Ok(())
+    }
+
+    fn exec_profile_command<'a>(
+        &mut self,
+        mut args: impl Iterator<Item = &'a str>,
+        out: &mut ConsoleOutput,
+    ) -> Result<(), String> {
+        match args.next() {
+            None => {}
+            Some("start") => {
+                let mut interval = 1000;
+                let mut call_stacks = false;
+                for arg in args.filter(|s| !s.is_empty()) {
+                    match arg {
+                        "stacks" => call_stacks = true,
+                        _ => interval = arg.parse().map_err(|_| "couldn't parse interval")?,
+                    }
+                }
+                self.sys
+                    .set_profiler(Some(Profiler::new(interval, call_stacks)));
+            }
+            Some("stop") => {
+                self.sys.set_profiler(None);
+            }
+            Some("dump") => {
+                let path = args.next().ok_or("no file provided")?;
+                let format = match args.next() {
+                    None => ProfileFormat::Collapsed,
+                    Some(format) => format.parse::<ProfileFormat>()?,
+                };
+                let profiler = self.sys.profiler().ok_or("profiler isn't running")?;
+                let file = std::fs::File::create(path)
+                    .map_err(|e| format!("couldn't create '{}': {}", path, e))?;
+                profiler
+                    .write(std::io::BufWriter::new(file), format, self.sys.symbols())
+                    .map_err(|e| format!("couldn't write profile: {}", e))?;
+                outputln!(out, "wrote {} samples to {}", profiler.num_samples(), path);
+            }
+            Some(cmd) => return Err(format!("unknown profile command '{}'", cmd)),
+        }
+
+        match self.sys.profiler() {
+            None => outputln!(out, "profiler: stopped"),
+            Some(profiler) => outputln!(
+                out,
+                "profiler: sampling every {} instructions{}, {} samples",
+                profiler.interval(),
+                if profiler.call_stacks() {
+                    " (with call stacks)"
+                } else {
+                    ""
+                },
+                profiler.num_samples()
+            ),
+        }
+
+        Ok(())
+    }
+
+    fn exec_coverage_command<'a>(
+        &mut self,
+        mut args: impl Iterator<Item = &'a str>,
+        out: &mut ConsoleOutput,
+    ) -> Result<(), String> {
+        match args.next() {
+            None => {}
+            Some("start") => {
+                self.sys.set_coverage(Some(Coverage::new()));
+            }
+            Some("stop") => {
+                self.sys.set_coverage(None);
+            }
+            Some("clear") => self
+                .sys
+                .coverage_mut()
+                .ok_or("coverage isn't being collected")?
+                .clear(),
+            Some("dump") => {
+                let path = args.next().ok_or("no file provided")?;
+                let mut opts = CoverageOpts::default();
+                for opt in args.filter(|s| !s.is_empty()) {
+                    opts.parse_opt(opt)?;
+                }
+                let coverage = self
+                    .sys
+                    .coverage()
+                    .ok_or("coverage isn't being collected")?;
+                let file = std::fs::File::create(path)
+                    .map_err(|e| format!("couldn't create '{}': {}", path, e))?;
+                coverage
+                    .write(
+                        std::io::BufWriter::new(file),
+                        &opts,
+                        self.sys.symbols().lines(),
+                    )
+                    .map_err(|e| format!("couldn't write coverage: {}", e))?;
+                outputln!(
+                    out,
+                    "wrote {} blocks to {}",
+                    coverage.num_blocks(opts.core),
+                    path
+                );
+            }
+            Some(cmd) => return Err(format!("unknown coverage command '{}'", cmd)),
+        }
+
+        match self.sys.coverage() {
+            None => outputln!(out, "coverage: stopped"),
+            Some(coverage) => outputln!(
+                out,
+                "coverage: {} blocks (cpu: {}, cop: {})",
+                coverage.num_blocks(None),
+                coverage.num_blocks(Some(CpuId::Cpu)),
+                coverage.num_blocks(Some(CpuId::Cop))
+            ),
+        }
+
+        Ok(())
+    }
+
+    fn exec_record_command<'a>(
+        &mut self,
+        mut args: impl Iterator<Item = &'a str>,
+        out: &mut ConsoleOutput,
+    ) -> Result<(), String> {
+        match args.next() {
+            None => {}
+            Some("start") => {
+                if self.sys.clock_step.is_none() {
+                    return Err(
+                        "reverse execution requires a virtual clock (see `--virtual-time`)".into(),
+                    );
+                }
+                let mut args = args.filter(|s| !s.is_empty());
+                let interval = match args.next() {
+                    None => DEFAULT_CHECKPOINT_INTERVAL,
+                    Some(n) => n.parse().map_err(|_| "couldn't parse interval")?,
+                };
+                let max = match args.next() {
+                    None => DEFAULT_MAX_CHECKPOINTS,
+                    Some(n) => n.parse().map_err(|_| "couldn't parse max")?,
+                };
+                (self.sys.start_history())
+                    .map_err(|e| format!("couldn't start recording: {}", e))?;
+                let mut checkpoints = Checkpoints::new(interval, max);
+                checkpoints.push(self.sys.checkpoint());
+                self.checkpoints = Some(checkpoints);
+            }
+            Some("stop") => {
+                self.sys.stop_history();
+                self.checkpoints = None;
+            }
+            Some("goto") => {
+                let cycle = args.next().ok_or("no cycle provided")?;
+                let cycle = cycle.parse().map_err(|_| "couldn't parse cycle")?;
+                if self.seek(cycle)? != cycle {
+                    outputln!(out, "couldn't reach cycle {}", cycle);
+                }
+                self.output_position(out);
+                return Ok(());
+            }
+            Some(cmd) => return Err(format!("unknown record command '{}'", cmd)),
+        }
+
+        match &self.checkpoints {
+            None => outputln!(out, "record: stopped"),
+            Some(checkpoints) => {
+                outputln!(
+                    out,
+                    "record: checkpoint every {} cycles, {}/{} checkpoints ({} MiB)",
+                    checkpoints.interval(),
+                    checkpoints.len(),
+                    checkpoints.max(),
+                    checkpoints.size() >> 20
+                );
+                if let Some(first) = checkpoints.first() {
+                    outputln!(
+                        out,
+                        "history: cycle {} - {}",
+                        first.cycle,
+                        self.sys.cycles.max(first.cycle)
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn exec_irq_command(&mut self, out: &mut ConsoleOutput) {
+        let pp = self.sys.devices.pp_mut();
+
+        let (cpu, cop) = pp.interrupt_status();
+        for (id, status) in &[(CpuId::Cpu, cpu), (CpuId::Cop, cop)] {
+            outputln!(out, "{:?}: irq={} fiq={}", id, status.irq, status.fiq);
+        }
+
+        outputln!(
+            out,
+            "{:>3} {:<24} {:<16} {:<16}",
+            "irq",
+            "source",
+            "cpu",
+            "cop"
+        );
+        for line in pp.irq_lines() {
+            let core_status = |asserted: bool, enabled: bool, fiq: bool| {
+                let status = match (enabled, fiq) {
+                    (false, _) => "disabled",
+                    (true, false) => "irq",
+                    (true, true) => "fiq",
+                };
+                if asserted && enabled {
+                    format!("{} (pending)", status)
+                } else if asserted {
+                    format!("{} (asserted)", status)
+                } else {
+                    status.to_string()
+                }
+            };
+            outputln!(
+                out,
+                "{:>3} {:<24} {:<16} {:<16}",
+                line.idx,
+                line.label,
+                core_status(line.asserted.0, line.enabled.0, line.fiq.0),
+                core_status(line.asserted.1, line.enabled.1, line.fiq.1),
+            );
+        }
+    }
+
+    fn save_screenshot(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let (width, height) = B::SCREEN_DIMS;
+
+        let mut buf = Vec::new();
+        let (w, _h) = (self.sys.render_callback())(&mut buf);
+
+        // crop the emulated buffer
+        let rgb = buf
+            .chunks_exact(w)
+            .take(height)
+            .flat_map(|row| row.iter().take(width))
+            .flat_map(|argb| {
+                let [_a, r, g, b] = argb.to_be_bytes();
+                vec![r, g, b]
+            })
+            .collect::<Vec<u8>>();
+
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&rgb)?;
+        Ok(())
+    }
+
+    /// Output the current cycle, along with each core's PC.
+    fn output_position(&self, out: &mut ConsoleOutput) {
+        outputln!(out, "cycle {}", self.sys.cycles);
+        for (id, cpu) in &[(CpuId::Cpu, &self.sys.cpu), (CpuId::Cop, &self.sys.cop)] {
+            let pc = cpu.reg_get(cpu.mode(), reg::PC);
+            outputln!(out, "{:?}: pc {}", id, self.sys.fmt_addr(pc));
+        }
+    }
+
+    fn exec_trace_command<'a>(
+        &mut self,
+        mut args: impl Iterator<Item = &'a str>,
+        out: &mut ConsoleOutput,
+    ) -> Result<(), String> {
+        match args.next() {
+            None => {}
+            Some("start") => {
+                let path = args.next().ok_or("no file provided")?;
+                let file = std::fs::File::create(path)
+                    .map_err(|e| format!("couldn't create '{}': {}", path, e))?;
+                let tracer = Tracer::new(Box::new(std::io::BufWriter::new(file)))
+                    .map_err(|e| format!("couldn't start tracer: {}", e))?;
+                self.sys.set_tracer(Some(tracer));
+            }
+            Some("stop") => self.sys.set_tracer(None),
+            Some(cmd @ "pause") | Some(cmd @ "resume") => {
+                let tracer = self.sys.tracer_mut().ok_or("tracer isn't running")?;
+                tracer.set_enabled(cmd == "resume");
+            }
+            Some("filter") => {
+                let filter = args
+                    .filter(|s| !s.is_empty())
+                    .map(trace::parse_pc_range)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let tracer = self.sys.tracer_mut().ok_or("tracer isn't running")?;
+                tracer.set_filter(filter);
+            }
+            Some(cmd) => return Err(format!("unknown trace command '{}'", cmd)),
+        }
+
+        match self.sys.tracer_mut() {
+            None => outputln!(out, "tracer: stopped"),
+            Some(tracer) => {
+                outputln!(
+                    out,
+                    "tracer: {}",
+                    if tracer.enabled() {
+                        "recording"
+                    } else {
+                        "paused"
+                    }
+                );
+                if tracer.filter().is_empty() {
+                    outputln!(out, "filter: none");
+                } else {
+                    for range in tracer.filter() {
+                        outputln!(out, "filter: {:#010x}-{:#010x}", range.start(), range.end());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn cpuid_to_tid(id: CpuId) -> Tid {
+    match id {
+        CpuId::Cpu => Tid::new(1).unwrap(),
+        CpuId::Cop => Tid::new(2).unwrap(),
+    }
+}
+
+fn tid_to_cpuid(tid: Tid) -> Option<CpuId> {
+    Some(match tid.get() {
+        1 => CpuId::Cpu,
+        2 => CpuId::Cop,
+        _ => return None,
+    })
+}
+
+/// Drives a [PpGdb] using `gdbstub`'s blocking event loop (i.e:
+/// `GdbStub::run_blocking`), polling the connection for interrupts while the
+/// system is running.
+pub struct PpGdbEventLoop<B, C>(PhantomData<(B, C)>);
+
+impl<B: PpBoard, C: ConnectionExt> BlockingEventLoop for PpGdbEventLoop<B, C> {
+    type Target = PpGdb<B>;
+    type Connection = C;
+    type StopReason = MultiThreadStopReason<u32>;
+
+    #[allow(clippy::type_complexity)]
+    fn wait_for_stop_reason(
+        target: &mut PpGdb<B>,
+        conn: &mut C,
+    ) -> Result<
+        run_blocking::Event<Self::StopReason>,
+        run_blocking::WaitForStopReasonError<FatalMemException, C::Error>,
+    > {
+        let poll_incoming_data = || conn.peek().map(|b| b.is_some()).unwrap_or(true);
+        match target.run(poll_incoming_data) {
+            Ok(Some(stop_reason)) => Ok(run_blocking::Event::TargetStopped(stop_reason)),
+            Ok(None) => {
+                let byte =
+                    (conn.read()).map_err(run_blocking::WaitForStopReasonError::Connection)?;
+                Ok(run_blocking::Event::IncomingData(byte))
+            }
+            Err(e) => Err(run_blocking::WaitForStopReasonError::Target(e)),
+        }
+    }
+
+    fn on_interrupt(
+        _target: &mut PpGdb<B>,
+    ) -> Result<Option<MultiThreadStopReason<u32>>, FatalMemException> {
+        // the system only runs from within `wait_for_stop_reason`, so it's
+        // already stopped by the time this is called
+        Ok(Some(MultiThreadStopReason::Signal(Signal::SIGINT)))
+    }
+}
+
+impl<B: PpBoard> Target for PpGdb<B> {
+    type Arch = Armv4t;
+    type Error = FatalMemException;
+
+    fn base_ops(&mut self) -> target::ext::base::BaseOps<'_, Self::Arch, Self::Error> {
+        target::ext::base::BaseOps::MultiThread(self)
+    }
+
+    fn support_breakpoints(
+        &mut self,
+    ) -> Option<target::ext::breakpoints::BreakpointsOps<'_, Self>> {
+        Some(self)
+    }
+
+    fn support_monitor_cmd(&mut self) -> Option<target::ext::monitor_cmd::MonitorCmdOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl<B: PpBoard> MultiThreadResume for PpGdb<B> {
+    fn resume(&mut self) -> Result<(), Self::Error> {
+        // the system is run from `PpGdbEventLoop::wait_for_stop_reason`
+        Ok(())
+    }
+
+    fn clear_resume_actions(&mut self) -> Result<(), Self::Error> {
+        self.resume_actions = [None, None];
+        self.scheduler_lock = false;
+        self.reverse_action = None;
+        Ok(())
+    }
+
+    fn set_resume_action_continue(
+        &mut self,
+        tid: Tid,
+        _signal: Option<Signal>,
+    ) -> Result<(), Self::Error> {
+        if let Some(id) = tid_to_cpuid(tid) {
+            self.resume_actions[id as usize] = Some(ResumeAction::Continue);
+        }
+        Ok(())
+    }
+
+    fn support_single_step(
+        &mut self,
+    ) -> Option<target::ext::base::multithread::MultiThreadSingleStepOps<'_, Self>> {
+        Some(self)
+    }
+
+    fn support_scheduler_locking(
+        &mut self,
+    ) -> Option<target::ext::base::multithread::MultiThreadSchedulerLockingOps<'_, Self>> {
+        Some(self)
+    }
+
+    fn support_reverse_step(
+        &mut self,
+    ) -> Option<target::ext::base::reverse_exec::ReverseStepOps<'_, Tid, Self>> {
+        Some(self)
+    }
+
+    fn support_reverse_cont(
+        &mut self,
+    ) -> Option<target::ext::base::reverse_exec::ReverseContOps<'_, Tid, Self>> {
+        Some(self)
+    }
+}
+
+impl<B: PpBoard> target::ext::base::multithread::MultiThreadSingleStep for PpGdb<B> {
+    fn set_resume_action_step(
+        &mut self,
+        tid: Tid,
+        _signal: Option<Signal>,
+    ) -> Result<(), Self::Error> {
+        if let Some(id) = tid_to_cpuid(tid) {
+            self.resume_actions[id as usize] = Some(ResumeAction::Step);
+        }
+        Ok(())
+    }
+}
+
+impl<B: PpBoard> target::ext::base::multithread::MultiThreadSchedulerLocking for PpGdb<B> {
+    fn set_resume_action_scheduler_lock(&mut self) -> Result<(), Self::Error> {
+        self.scheduler_lock = true;
+        Ok(())
+    }
+}
+
+// Both cores are always run backwards in lockstep, as that's how the recorded
+// history was executed in the first place.
+impl<B: PpBoard> target::ext::base::reverse_exec::ReverseStep<Tid> for PpGdb<B> {
+    fn reverse_step(&mut self, _tid: Tid) -> Result<(), Self::Error> {
+        self.reverse_action = Some(ReverseAction::Step);
+        Ok(())
+    }
+}
+
+impl<B: PpBoard> target::ext::base::reverse_exec::ReverseCont<Tid> for PpGdb<B> {
+    fn reverse_cont(&mut self) -> Result<(), Self::Error> {
+        self.reverse_action = Some(ReverseAction::Continue);
+        Ok(())
+    }
+}
+
+impl<B: PpBoard> MultiThreadBase for PpGdb<B> {
+    fn read_registers(&mut self, regs: &mut ArmCoreRegs, tid: Tid) -> TargetResult<(), Self> {
+        let cpu = match tid_to_cpuid(tid).unwrap() {
+            CpuId::Cpu => &mut self.sys.cpu,
+            CpuId::Cop => &mut self.sys.cop,
+        };
+
+        let mode = cpu.mode();
+
+        for i in 0..13 {
+            regs.r[i] = cpu.reg_get(mode, i as u8);
+        }
+        regs.sp = cpu.reg_get(mode, reg::SP);
+        regs.lr = cpu.reg_get(mode, reg::LR);
+        regs.pc = cpu.reg_get(mode, reg::PC);
+        regs.cpsr = cpu.reg_get(mode, reg::CPSR);
+
+        Ok(())
+    }
+
+    fn write_registers(&mut self, regs: &ArmCoreRegs, tid: Tid) -> TargetResult<(), Self> {
+        let cpu = match tid_to_cpuid(tid).unwrap() {
+            CpuId::Cpu => &mut self.sys.cpu,
+            CpuId::Cop => &mut self.sys.cop,
+        };
+
+        let mode = cpu.mode();
+
+        for i in 0..13 {
+            cpu.reg_set(mode, i, regs.r[i as usize]);
+        }
+        cpu.reg_set(mode, reg::SP, regs.sp);
+        cpu.reg_set(mode, reg::LR, regs.lr);
+        cpu.reg_set(mode, reg::PC, regs.pc);
+        cpu.reg_set(mode, reg::CPSR, regs.cpsr);
+
+        self.discard_history_after_now();
+
+        Ok(())
+    }
+
+    fn read_addrs(
+        &mut self,
+        start_addr: u32,
+        data: &mut [u8],
+        tid: Tid,
+    ) -> TargetResult<usize, Self> {
+        (self.sys.devices.pp_mut()).set_cpuid(tid_to_cpuid(tid).unwrap());
+
+        // only report the bytes read before hitting a register which can't be
+        // read without side effects
+        for (i, (addr, val)) in (start_addr..).zip(data.iter_mut()).enumerate() {
+            match self.sys.devices.peek8(addr) {
+                Some(v) => *val = v,
+                None if i == 0 => return Err(().into()),
+                None => return Ok(i),
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn write_addrs(&mut self, start_addr: u32, data: &[u8], tid: Tid) -> TargetResult<(), Self> {
+        (self.sys.devices.pp_mut()).set_cpuid(tid_to_cpuid(tid).unwrap());
+
+        for (addr, val) in (start_addr..).zip(data.iter().copied()) {
+            // TODO: throw a fatal error when accessing non-RAM devices?
+            self.sys.devices.w8(addr, val).map_err(drop)?
+        }
+
+        self.discard_history_after_now();
+        Ok(())
+    }
+
+    fn list_active_threads(
+        &mut self,
+        register_thread: &mut dyn FnMut(Tid),
+    ) -> Result<(), Self::Error> {
+        register_thread(cpuid_to_tid(CpuId::Cpu));
+        register_thread(cpuid_to_tid(CpuId::Cop));
+        Ok(())
+    }
+
+    fn support_resume(
+        &mut self,
+    ) -> Option<target::ext::base::multithread::MultiThreadResumeOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl<B: PpBoard> target::ext::breakpoints::Breakpoints for PpGdb<B> {
+    fn support_sw_breakpoint(
+        &mut self,
+    ) -> Option<target::ext::breakpoints::SwBreakpointOps<'_, Self>> {
+        Some(self)
+    }
+
+    fn support_hw_watchpoint(
+        &mut self,
+    ) -> Option<target::ext::breakpoints::HwWatchpointOps<'_, Self>> {
+        Some(self)
+    }
+}
+
+impl<B: PpBoard> target::ext::breakpoints::SwBreakpoint for PpGdb<B> {
+    fn add_sw_breakpoint(
+        &mut self,
+        addr: u32,
+        _kind: ArmBreakpointKind,
+    ) -> TargetResult<bool, Self> {
+        self.breakpoints.insert(addr);
+        Ok(true)
+    }
+
+    fn remove_sw_breakpoint(
+        &mut self,
+        addr: u32,
+        _kind: ArmBreakpointKind,
+    ) -> TargetResult<bool, Self> {
+        Ok(self.breakpoints.remove(&addr))
+    }
+}
+
+impl<B: PpBoard> target::ext::breakpoints::HwWatchpoint for PpGdb<B> {
+    fn add_hw_watchpoint(
+        &mut self,
+        addr: u32,
+        len: u32,
+        kind: WatchKind,
+    ) -> TargetResult<bool, Self> {
+        self.watchpoints.insert(Watchpoint {
+            addr,
+            len,
+            kind,
+            value: None,
+            user: false,
+        });
+
+        Ok(true)
+    }
+
+    fn remove_hw_watchpoint(
+        &mut self,
+        addr: u32,
+        len: u32,
+        kind: WatchKind,
+    ) -> TargetResult<bool, Self> {
+        Ok((self.watchpoints).remove(addr, |wp| !wp.user && wp.kind == kind && wp.len == len))
+    }
+}
+
+impl<B: PpBoard> target::ext::monitor_cmd::MonitorCmd for PpGdb<B> {
+    fn handle_monitor_cmd(
+        &mut self,
+        cmd: &[u8],
+        mut out: ConsoleOutput<'_>,
+    ) -> Result<(), Self::Error> {
+        let cmd = match core::str::from_utf8(cmd) {
+            Ok(s) => s,
+            Err(_) => {
+                outputln!(out, "command must be valid UTF-8");
+                return Ok(());
+            }
+        };
+
+        if let Err(e) = self.exec_dbg_command(cmd, &mut out) {
+            outputln!(out, "ERROR: {}", e)
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_key(s: &str) -> Result<PpKey, String> {
+    s.parse().map_err(|_| format!("unknown key '{}'", s))
+}
+
+/// Parse an address, specified in decimal, or in hex with a `0x` prefix.
+fn parse_addr(s: &str) -> Option<u32> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::memory::Memory;
+    use crate::sys::ipod4g::Ipod4gBus;
+    use crate::sys::pp::tests::new_system;
+
+    /// A system running the following loop on both cores, recording history:
+    ///
+    /// ```text
+    /// 0x1000_0000: mov r0, #0
+    /// 0x1000_0004: add r0, r0, #1
+    /// 0x1000_0008: b 0x1000_0004
+    /// ```
+    fn recording_system() -> PpGdb<Ipod4gBus> {
+        let mut sys = new_system();
+        for (i, insn) in [0xe3a0_0000, 0xe280_0001, 0xeaff_fffd].iter().enumerate() {
+            sys.devices.w32(0x1000_0000 + i as u32 * 4, *insn).unwrap();
+        }
+        for cpu in [&mut sys.cpu, &mut sys.cop] {
+            cpu.reg_set(cpu.mode(), reg::PC, 0x1000_0000);
+        }
+
+        let mut gdb = PpGdb::new(sys);
+        gdb.sys.start_history().unwrap();
+        let mut checkpoints = Checkpoints::new(16, 16);
+        checkpoints.push(gdb.sys.checkpoint());
+        gdb.checkpoints = Some(checkpoints);
+        gdb
+    }
+
+    fn r0(gdb: &PpGdb<Ipod4gBus>) -> u32 {
+        gdb.sys.cpu.reg_get(gdb.sys.cpu.mode(), 0)
+    }
+
+    #[test]
+    fn reverse_continue() {
+        let mut gdb = recording_system();
+        let start = gdb.sys.cycles;
+        for _ in 0..41 {
+            assert!(gdb.step().unwrap().is_none());
+        }
+        let r0_end = r0(&gdb);
+
+        gdb.breakpoints.insert(0x1000_0008);
+        let hit = gdb.reverse_continue().unwrap();
+        assert!(matches!(hit, Some((Event::Break, _))));
+        assert_eq!(gdb.sys.cycles, start + 40);
+        assert_eq!(r0(&gdb), r0_end);
+
+        // hits spanning multiple checkpoint segments
+        let hit = gdb.reverse_continue().unwrap();
+        assert!(matches!(hit, Some((Event::Break, _))));
+        assert_eq!(gdb.sys.cycles, start + 38);
+        assert_eq!(r0(&gdb), r0_end - 1);
+
+        // no more hits before the start of the recorded history
+        gdb.breakpoints.clear();
+        assert!(gdb.reverse_continue().unwrap().is_none());
+        assert_eq!(gdb.sys.cycles, start);
+    }
+
+    #[test]
+    fn reverse_continue_to_earliest_checkpoint() {
+        let mut gdb = recording_system();
+        let start = gdb.sys.cycles;
+        for _ in 0..20 {
+            gdb.step().unwrap();
+        }
+
+        // the only hit is right at the start of the recorded history
+        gdb.breakpoints.insert(0x1000_0000);
+        let hit = gdb.reverse_continue().unwrap();
+        assert!(matches!(hit, Some((Event::Break, _))));
+        assert_eq!(gdb.sys.cycles, start);
+
+        // ...which isn't reported again
+        assert!(gdb.reverse_continue().unwrap().is_none());
+        assert_eq!(gdb.sys.cycles, start);
+    }
+}
@@ -6,7 +6,7 @@ use thiserror::Error;
 
 use crate::memory::Memory;
 
-use super::Ipod4g;
+use super::{PpBoard, PpSoc, PpSystem};
 
 mod firmware;
 mod sysinfo;
@@ -26,11 +26,11 @@ pub enum HleBootloaderError {
 }
 
 /// Put the system into a state as though the bootloader in Flash ROM was run.
-pub(super) fn run_hle_bootloader(
-    ipod: &mut Ipod4g,
+pub(super) fn run_hle_bootloader<B: PpBoard>(
+    ipod: &mut PpSystem<B>,
     mut fw_file: impl Read + Seek,
 ) -> Result<(), HleBootloaderError> {
-    if !ipod.devices.flash.is_hle() {
+    if !ipod.devices.pp_mut().flash().is_hle() {
         warn!("Running HLE bootloader even though the system is using a real Flash ROM dump!");
     }
 
@@ -49,7 +49,7 @@ pub(super) fn run_hle_bootloader(
     let mut os_image_data = vec![0; os_image.len as usize];
     fw_file.read_exact(&mut os_image_data)?;
 
-    ipod.devices.sdram.bulk_write(0, &os_image_data);
+    ipod.devices.pp_mut().sdram().bulk_write(0, &os_image_data);
 
     // set the CPU to start execution from the image entry address
     ipod.cpu.reg_set(
@@ -68,20 +68,20 @@ pub(super) fn run_hle_bootloader(
     const SYSINFO_PTR: u32 = 0x4001_7f1c;
     const SYSINFO_LOC: u32 = 0x4000_ff18;
     ipod.devices.w32(SYSINFO_PTR, SYSINFO_LOC).unwrap(); // pointer to sysinfo
-    ipod.devices.fastram.bulk_write(
+    ipod.devices.pp_mut().fastram().bulk_write(
         SYSINFO_LOC - 0x4000_0000,
         // FIXME?: this will break on big-endian systems
         bytemuck::bytes_of(&sysinfo_t {
             IsyS: u32::from_le_bytes(*b"IsyS"),
             len: 0x184,
-            boardHwSwInterfaceRev: 0x50014,
+            boardHwSwInterfaceRev: B::HW_REV,
             ..Default::default()
         }),
     );
 
     // The bootloader enables the GPIOA:5 pin (i.e: the Hold button)
-    ipod.devices
-        .gpio_abcd
+    (ipod.devices.pp_mut())
+        .gpio_abcd()
         .lock()
         .unwrap()
         .w32(0x00, 0x20)
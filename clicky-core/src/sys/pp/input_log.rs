@@ -0,0 +1,505 @@
+//! Recording and replaying of user input.
+//!
+//! Input logs are plain-text files with one event per line, each stamped with
+//! the number of system cycles which had been executed when the event was
+//! delivered to the system. Blank lines and lines starting with `#` are
+//! ignored.
+//!
+//! ```text
+//! <cycle> press <key>
+//! <cycle> release <key>
+//! <cycle> scroll <dx> <dy>
+//! ```
+//!
+//! Valid keys are `up`, `down`, `left`, `right`, `action`, and `hold`.
+//!
+//! While recording, events are not delivered to the system immediately, but
+//! are instead queued up and delivered at the start of the next cycle, which
+//! makes it possible to replay them at _exactly_ the same point in execution.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc;
+
+use thiserror::Error;
+
+use super::{PpBinds, PpKey};
+
+#[derive(Error, Debug)]
+pub enum InputLogError {
+    #[error("system controls have already been taken")]
+    ControlsTaken,
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("line {0}: {1}")]
+    Parse(usize, String),
+}
+
+/// A single input event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    /// A key was pressed (`true`) or released (`false`).
+    Key(PpKey, bool),
+    /// The scroll wheel was scrolled by `(dx, dy)`.
+    Scroll(f32, f32),
+}
+
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputEvent::Key(key, true) => write!(f, "press {}", key),
+            InputEvent::Key(key, false) => write!(f, "release {}", key),
+            InputEvent::Scroll(dx, dy) => write!(f, "scroll {} {}", dx, dy),
+        }
+    }
+}
+
+/// Parse a single `<cycle> <event>` line.
+fn parse_line(line: &str) -> Result<(u64, InputEvent), String> {
+    let mut args = line.split_whitespace();
+    let mut arg = |name: &str| {
+        args.next()
+            .ok_or_else(|| format!("missing argument <{}>", name))
+    };
+
+    fn num<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+        s.parse().map_err(|_| format!("could not parse `{}`", s))
+    }
+
+    let cycle = num(arg("cycle")?)?;
+    let event = match arg("event")? {
+        "press" => InputEvent::Key(num(arg("key")?)?, true),
+        "release" => InputEvent::Key(num(arg("key")?)?, false),
+        "scroll" => InputEvent::Scroll(num(arg("dx")?)?, num(arg("dy")?)?),
+        other => return Err(format!("unknown event `{}`", other)),
+    };
+
+    if let Some(extra) = args.next() {
+        return Err(format!("unexpected argument `{}`", extra));
+    }
+
+    Ok((cycle, event))
+}
+
+/// Deliver an event to the system.
+pub(super) fn deliver(binds: &mut PpBinds, event: InputEvent) {
+    match event {
+        InputEvent::Key(key, pressed) => match binds.keys.get_mut(&key) {
+            Some(cb) => cb(pressed),
+            None => warn!("input log: {:?} isn't present on this system", key),
+        },
+        InputEvent::Scroll(dx, dy) => {
+            if let Some(cb) = &mut binds.wheel {
+                cb((dx, dy))
+            }
+        }
+    }
+}
+
+pub(super) enum InputLog {
+    Record {
+        binds: PpBinds,
+        events: mpsc::Receiver<InputEvent>,
+        /// Used to inject events which don't come from the frontend
+        tx: mpsc::Sender<InputEvent>,
+        w: Box<dyn Write + Send>,
+    },
+    Replay {
+        binds: PpBinds,
+        events: VecDeque<(u64, InputEvent)>,
+    },
+}
+
+impl fmt::Debug for InputLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputLog::Record { .. } => f.debug_struct("Record").finish_non_exhaustive(),
+            InputLog::Replay { events, .. } => f
+                .debug_struct("Replay")
+                .field("remaining", &events.len())
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl InputLog {
+    /// Start recording events delivered through the returned binds to `w`.
+    /// `binds` are the system's actual controls.
+    pub fn record(
+        binds: PpBinds,
+        mut w: Box<dyn Write + Send>,
+        board: &str,
+    ) -> io::Result<(InputLog, PpBinds)> {
+        writeln!(w, "# clicky input log ({})", board)?;
+        w.flush()?;
+
+        let (tx, rx) = mpsc::channel();
+        let mut proxy = PpBinds::default();
+        for &key in binds.keys.keys() {
+            let tx = tx.clone();
+            proxy.keys.insert(
+                key,
+                Box::new(move |pressed| {
+                    let _ = tx.send(InputEvent::Key(key, pressed));
+                }),
+            );
+        }
+        if binds.wheel.is_some() {
+            let tx = tx.clone();
+            proxy.wheel = Some(Box::new(move |(dx, dy)| {
+                let _ = tx.send(InputEvent::Scroll(dx, dy));
+            }));
+        }
+
+        let log = InputLog::Record {
+            binds,
+            events: rx,
+            tx,
+            w,
+        };
+        Ok((log, proxy))
+    }
+
+    /// Load an input log to replay using `binds` (the system's actual
+    /// controls).
+    pub fn replay(binds: PpBinds, r: impl BufRead) -> Result<InputLog, InputLogError> {
+        let mut events = VecDeque::new();
+        for (i, line) in r.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (cycle, event) = parse_line(line).map_err(|e| InputLogError::Parse(i + 1, e))?;
+            if matches!(events.back(), Some((prev, _)) if *prev > cycle) {
+                return Err(InputLogError::Parse(
+                    i + 1,
+                    "events are out of order".into(),
+                ));
+            }
+            events.push_back((cycle, event));
+        }
+
+        Ok(InputLog::Replay { binds, events })
+    }
+
+    /// Inject an event which didn't come from the frontend. Recorded events
+    /// are logged as usual, whereas events injected during a replay are
+    /// delivered immediately (and are _not_ part of the replayed log).
+    pub fn inject(&mut self, event: InputEvent) {
+        match self {
+            InputLog::Record { tx, .. } => {
+                let _ = tx.send(event);
+            }
+            InputLog::Replay { binds, .. } => deliver(binds, event),
+        }
+    }
+
+    /// Deliver an event to the system immediately, without logging it.
+    pub fn deliver(&mut self, event: InputEvent) {
+        match self {
+            InputLog::Record { binds, .. } | InputLog::Replay { binds, .. } => {
+                deliver(binds, event)
+            }
+        }
+    }
+
+    /// Deliver any events which are due at the specified cycle, calling
+    /// `on_deliver` with each one.
+    pub fn poll(&mut self, cycle: u64, mut on_deliver: impl FnMut(InputEvent)) {
+        match self {
+            InputLog::Record {
+                binds, events, w, ..
+            } => {
+                for event in events.try_iter() {
+                    deliver(binds, event);
+                    on_deliver(event);
+                    // flushed immediately, so that the log is intact even if the
+                    // emulator crashes
+                    if let Err(e) = writeln!(w, "{} {}", cycle, event).and_then(|_| w.flush()) {
+                        error!("failed to write to input log: {}", e);
+                    }
+                }
+            }
+            InputLog::Replay { binds, events } => {
+                while matches!(events.front(), Some((due, _)) if *due <= cycle) {
+                    let (_, event) = events.pop_front().unwrap();
+                    debug!("replaying input at cycle {}: {}", cycle, event);
+                    deliver(binds, event);
+                    on_deliver(event);
+                    if events.is_empty() {
+                        info!("finished replaying input log (cycle {})", cycle);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Input events delivered while recording execution history, which are
+/// re-delivered at the same cycles whenever that history is re-executed.
+#[derive(Debug)]
+pub(super) struct InputHistory {
+    /// Sorted by cycle
+    events: Vec<(u64, InputEvent)>,
+    /// Index of the next event to re-deliver
+    next: usize,
+    /// Cycles prior to this one have already been executed, and are therefore
+    /// being re-executed.
+    frontier: u64,
+}
+
+impl InputHistory {
+    pub fn new(cycle: u64) -> InputHistory {
+        InputHistory {
+            events: Vec::new(),
+            next: 0,
+            frontier: cycle,
+        }
+    }
+
+    /// Deliver any events which are due at the specified cycle. When
+    /// re-executing, only recorded events are re-delivered, and any new input
+    /// remains queued up in `input_log` until execution catches back up.
+    pub fn poll(&mut self, cycle: u64, input_log: &mut InputLog) {
+        if cycle < self.frontier {
+            while matches!(self.events.get(self.next), Some((due, _)) if *due <= cycle) {
+                input_log.deliver(self.events[self.next].1);
+                self.next += 1;
+            }
+            return;
+        }
+
+        self.frontier = cycle + 1;
+        let events = &mut self.events;
+        input_log.poll(cycle, |event| events.push((cycle, event)));
+        self.next = self.events.len();
+    }
+
+    /// Prepare to re-execute history starting at the specified cycle (i.e:
+    /// after restoring a checkpoint).
+    pub fn rewind(&mut self, cycle: u64) {
+        self.next = self.events.partition_point(|(due, _)| *due < cycle);
+    }
+
+    /// Discard any events which haven't been delivered as of the specified
+    /// cycle (i.e: when the system's state is modified out from under the
+    /// recorded history).
+    pub fn discard_after(&mut self, cycle: u64) {
+        self.rewind(cycle);
+        self.events.truncate(self.next);
+        self.frontier = cycle;
+    }
+
+    /// Forget events prior to the specified cycle, which will never be
+    /// re-executed.
+    pub fn prune_before(&mut self, cycle: u64) {
+        let n = self.events.partition_point(|(due, _)| *due < cycle);
+        self.events.drain(..n);
+        self.next = self.next.saturating_sub(n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    /// An in-memory log which can be inspected while it's being recorded.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Binds which log every event they receive to `delivered`.
+    fn binds(delivered: &Arc<Mutex<Vec<InputEvent>>>) -> PpBinds {
+        let mut binds = PpBinds::default();
+        for key in [PpKey::Up, PpKey::Action, PpKey::Hold] {
+            let delivered = Arc::clone(delivered);
+            binds.keys.insert(
+                key,
+                Box::new(move |pressed| {
+                    delivered
+                        .lock()
+                        .unwrap()
+                        .push(InputEvent::Key(key, pressed))
+                }),
+            );
+        }
+        let delivered = Arc::clone(delivered);
+        binds.wheel = Some(Box::new(move |(dx, dy)| {
+            delivered.lock().unwrap().push(InputEvent::Scroll(dx, dy))
+        }));
+        binds
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let keys = [
+            PpKey::Up,
+            PpKey::Down,
+            PpKey::Left,
+            PpKey::Right,
+            PpKey::Action,
+            PpKey::Hold,
+        ];
+        let mut events = Vec::new();
+        for key in keys {
+            events.push(InputEvent::Key(key, true));
+            events.push(InputEvent::Key(key, false));
+        }
+        events.push(InputEvent::Scroll(0.0, 1.0));
+        events.push(InputEvent::Scroll(-0.125, 3.5e-3));
+
+        for cycle in [0, 1, u64::MAX] {
+            for event in events.iter() {
+                let line = format!("{} {}", cycle, event);
+                assert_eq!(parse_line(&line), Ok((cycle, *event)), "{}", line);
+            }
+        }
+
+        // arbitrary whitespace between arguments is allowed
+        assert_eq!(
+            parse_line("12 \t scroll  1   -2"),
+            Ok((12, InputEvent::Scroll(1.0, -2.0)))
+        );
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn rejects_malformed_lines() {
+        let cases = [
+            "",
+            "100",
+            "press up",
+            "-1 press up",
+            "1.5 press up",
+            "0x10 press up",
+            "100 tap up",
+            "100 press",
+            "100 press select",
+            "100 press Up",
+            "100 press up now",
+            "100 release",
+            "100 scroll",
+            "100 scroll 1",
+            "100 scroll x 1",
+            "100 scroll 1 2 3",
+        ];
+
+        for line in cases.iter() {
+            assert!(parse_line(line).is_err(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn replay() {
+        let log = "\
+            # clicky input log (ipod4g)\n\
+            \n\
+            10 press up\n\
+            10 release up\n\
+            \t 25 scroll 0.5 -1\n\
+            # hold the hold switch\n\
+            40 press hold\n";
+
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let mut input_log = InputLog::replay(binds(&delivered), log.as_bytes()).unwrap();
+
+        let mut polled = Vec::new();
+        for cycle in 0..50 {
+            input_log.poll(cycle, |event| polled.push((cycle, event)));
+        }
+        assert_eq!(
+            polled,
+            [
+                (10, InputEvent::Key(PpKey::Up, true)),
+                (10, InputEvent::Key(PpKey::Up, false)),
+                (25, InputEvent::Scroll(0.5, -1.0)),
+                (40, InputEvent::Key(PpKey::Hold, true)),
+            ]
+        );
+        let delivered = delivered.lock().unwrap();
+        assert_eq!(
+            *delivered,
+            polled.iter().map(|(_, e)| *e).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn replay_errors() {
+        let cases = [
+            ("10 press up\n5 release up\n", 2, "out of order"),
+            ("# comment\n\n10 press up\n10 prod up\n", 4, "unknown event"),
+            ("10 press up\n20 scroll 1\n", 2, "missing argument <dy>"),
+        ];
+
+        for (log, line, msg) in cases.iter() {
+            let delivered = Arc::new(Mutex::new(Vec::new()));
+            match InputLog::replay(binds(&delivered), log.as_bytes()) {
+                Err(InputLogError::Parse(l, e)) => {
+                    assert_eq!(l, *line, "{:?}", log);
+                    assert!(e.contains(msg), "{:?}: {}", log, e);
+                }
+                other => panic!("{:?}: expected a parse error, got {:?}", log, other),
+            }
+        }
+    }
+
+    #[test]
+    fn record_then_replay() {
+        let buf = SharedBuf::default();
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let (mut input_log, mut proxy) =
+            InputLog::record(binds(&delivered), Box::new(buf.clone()), "ipod4g").unwrap();
+
+        // events only take effect once the system is polled
+        (proxy.keys.get_mut(&PpKey::Action).unwrap())(true);
+        (proxy.wheel.as_mut().unwrap())((0.25, -3.0));
+        assert!(delivered.lock().unwrap().is_empty());
+        input_log.poll(7, |_| {});
+        (proxy.keys.get_mut(&PpKey::Action).unwrap())(false);
+        input_log.inject(InputEvent::Key(PpKey::Hold, true));
+        input_log.poll(1234, |_| {});
+
+        let expected = [
+            (7, InputEvent::Key(PpKey::Action, true)),
+            (7, InputEvent::Scroll(0.25, -3.0)),
+            (1234, InputEvent::Key(PpKey::Action, false)),
+            (1234, InputEvent::Key(PpKey::Hold, true)),
+        ];
+        let recorded = delivered.lock().unwrap().clone();
+        assert_eq!(
+            recorded,
+            expected.iter().map(|(_, e)| *e).collect::<Vec<_>>()
+        );
+
+        let text = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            text,
+            "# clicky input log (ipod4g)\n\
+             7 press action\n\
+             7 scroll 0.25 -3\n\
+             1234 release action\n\
+             1234 press hold\n"
+        );
+
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let mut input_log = InputLog::replay(binds(&delivered), text.as_bytes()).unwrap();
+        let mut polled = Vec::new();
+        for cycle in 0..2000 {
+            input_log.poll(cycle, |event| polled.push((cycle, event)));
+        }
+        assert_eq!(polled, expected);
+    }
+}
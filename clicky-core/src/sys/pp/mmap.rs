@@ -0,0 +1,217 @@
+/// Generates the [Device](crate::devices::Device),
+/// [Memory](crate::memory::Memory), and
+/// [DeviceMap](crate::sys::pp::DeviceMap) implementations for a board's memory
+/// bus.
+///
+/// The board must be a [PpBoard](crate::sys::pp::PpBoard), and must have a
+/// `pp` field containing its [PpSoc](crate::sys::pp::PpSoc). Addresses are
+/// translated by the SoC (e.g: by the PP502x's memory controller) before being
+/// dispatched to the board's off-chip devices. Any address which isn't claimed
+/// by the board is forwarded to the SoC's physical memory map.
+macro_rules! mmap {
+    (
+        $bus:ident;
+
+        DEVICES {
+            $($start_dev:literal $(..= $end_dev:literal)? => $($dev:ident).+,)*
+        }
+    ) => {
+        macro_rules! impl_mem_r {
+            ($fn:ident, $ret:ty) => {
+                fn $fn(&mut self, addr: u32) -> $crate::error::MemResult<$ret> {
+                    use $crate::error::MemException;
+
+                    let (addr, prot) = $crate::sys::pp::PpSoc::virt_to_phys(&self.pp, addr);
+                    if !prot.r {
+                        return Err(MemException::MmuViolation)
+                    }
+
+                    match addr {
+                        $($start_dev$(..=$end_dev)? => self.$($dev).+.$fn(addr - $start_dev),)*
+                        _ => self.pp.$fn(addr),
+                    }
+                }
+            };
+        }
+
+        macro_rules! impl_mem_w {
+            ($fn:ident, $val:ty) => {
+                fn $fn(&mut self, addr: u32, val: $val) -> $crate::error::MemResult<()> {
+                    use $crate::error::MemException;
+
+                    let (addr, prot) = $crate::sys::pp::PpSoc::virt_to_phys(&self.pp, addr);
+                    if !prot.w {
+                        return Err(MemException::MmuViolation)
+                    }
+
+                    match addr {
+                        $($start_dev$(..=$end_dev)? => self.$($dev).+.$fn(addr - $start_dev, val),)*
+                        _ => self.pp.$fn(addr, val),
+                    }
+                }
+            };
+        }
+
+        macro_rules! impl_mem_peek {
+            ($fn:ident, $ret:ty) => {
+                fn $fn(&self, addr: u32) -> Option<$ret> {
+                    let (addr, prot) = $crate::sys::pp::PpSoc::virt_to_phys(&self.pp, addr);
+                    if !prot.r {
+                        return None;
+                    }
+
+                    match addr {
+                        $($start_dev$(..=$end_dev)? => self.$($dev).+.$fn(addr - $start_dev),)*
+                        _ => self.pp.$fn(addr),
+                    }
+                }
+            };
+        }
+
+        impl $crate::devices::Device for $bus {
+            fn kind(&self) -> &'static str {
+                <$bus as $crate::sys::pp::PpBoard>::NAME
+            }
+
+            fn probe(&self, addr: u32) -> $crate::devices::Probe {
+                use $crate::devices::Probe;
+
+                let (addr, _) = $crate::sys::pp::PpSoc::virt_to_phys(&self.pp, addr);
+                match addr {
+                    $($start_dev$(..=$end_dev)? => {
+                        Probe::from_device(&self.$($dev).+, addr - $start_dev)
+                    })*
+                    _ => self.pp.probe(addr),
+                }
+            }
+        }
+
+        impl $crate::sys::pp::DeviceMap for $bus {
+            fn device_names(&self) -> Vec<&'static str> {
+                let mut names = vec![$(stringify!($($dev).+),)*];
+                names.extend($crate::sys::pp::DeviceMap::device_names(&self.pp));
+                names
+            }
+
+            fn find_device(&self, name: &str) -> Option<&dyn std::fmt::Debug> {
+                $(if name == stringify!($($dev).+) {
+                    return Some(&self.$($dev).+);
+                })*
+                $crate::sys::pp::DeviceMap::find_device(&self.pp, name)
+            }
+        }
+
+        impl $crate::memory::Memory for $bus {
+            impl_mem_r!(r8, u8);
+            impl_mem_r!(r16, u16);
+            impl_mem_r!(r32, u32);
+            impl_mem_w!(w8, u8);
+            impl_mem_w!(w16, u16);
+            impl_mem_w!(w32, u32);
+            impl_mem_peek!(peek8, u8);
+            impl_mem_peek!(peek32, u32);
+        }
+    };
+}
+
+/// Generates the [Device](crate::devices::Device),
+/// [Memory](crate::memory::Memory), and
+/// [DeviceMap](crate::sys::pp::DeviceMap) implementations for a
+/// [PpSoc](crate::sys::pp::PpSoc)'s _physical_ memory map.
+macro_rules! soc_mmap {
+    (
+        $soc:ident;
+
+        RAM {
+            $($start_ram:literal $(..= $end_ram:literal)? => $ram:ident,)*
+        }
+        DEVICES {
+            $($start_dev:literal $(..= $end_dev:literal)? => $dev:ident,)*
+        }
+    ) => {
+        macro_rules! impl_soc_mem_r {
+            ($fn:ident, $ret:ty) => {
+                fn $fn(&mut self, addr: u32) -> $crate::error::MemResult<$ret> {
+                    match addr {
+                        $($start_ram$(..=$end_ram)? => self.$ram.$fn(addr - $start_ram),)*
+                        $($start_dev$(..=$end_dev)? => self.$dev.$fn(addr - $start_dev),)*
+                        _ => Err($crate::error::MemException::Unexpected),
+                    }
+                }
+            };
+        }
+
+        macro_rules! impl_soc_mem_w {
+            ($fn:ident, $val:ty) => {
+                fn $fn(&mut self, addr: u32, val: $val) -> $crate::error::MemResult<()> {
+                    match addr {
+                        $($start_ram$(..=$end_ram)? => self.$ram.$fn(addr - $start_ram, val),)*
+                        $($start_dev$(..=$end_dev)? => self.$dev.$fn(addr - $start_dev, val),)*
+                        _ => Err($crate::error::MemException::Unexpected),
+                    }
+                }
+            };
+        }
+
+        macro_rules! impl_soc_mem_peek {
+            ($fn:ident, $ret:ty) => {
+                fn $fn(&self, addr: u32) -> Option<$ret> {
+                    match addr {
+                        $($start_ram$(..=$end_ram)? => self.$ram.$fn(addr - $start_ram),)*
+                        $($start_dev$(..=$end_dev)? => self.$dev.$fn(addr - $start_dev),)*
+                        _ => None,
+                    }
+                }
+            };
+        }
+
+        impl $crate::devices::Device for $soc {
+            fn kind(&self) -> &'static str {
+                <$soc as $crate::sys::pp::PpSoc>::NAME
+            }
+
+            fn probe(&self, addr: u32) -> $crate::devices::Probe {
+                use $crate::devices::Probe;
+
+                match addr {
+                    $($start_ram$(..=$end_ram)? => {
+                        Probe::from_device(&self.$ram, addr - $start_ram)
+                    })*
+                    $($start_dev$(..=$end_dev)? => {
+                        Probe::from_device(&self.$dev, addr - $start_dev)
+                    })*
+                    _ => Probe::Unmapped,
+                }
+            }
+        }
+
+        impl $crate::sys::pp::DeviceMap for $soc {
+            fn device_names(&self) -> Vec<&'static str> {
+                let mut names: Vec<&'static str> = Vec::new();
+                // devices may be mapped at multiple addresses
+                $(if !names.contains(&stringify!($dev)) {
+                    names.push(stringify!($dev));
+                })*
+                names
+            }
+
+            fn find_device(&self, name: &str) -> Option<&dyn std::fmt::Debug> {
+                $(if name == stringify!($dev) {
+                    return Some(&self.$dev);
+                })*
+                None
+            }
+        }
+
+        impl $crate::memory::Memory for $soc {
+            impl_soc_mem_r!(r8, u8);
+            impl_soc_mem_r!(r16, u16);
+            impl_soc_mem_r!(r32, u32);
+            impl_soc_mem_w!(w8, u8);
+            impl_soc_mem_w!(w16, u16);
+            impl_soc_mem_w!(w32, u32);
+            impl_soc_mem_peek!(peek8, u8);
+            impl_soc_mem_peek!(peek32, u32);
+        }
+    };
+}
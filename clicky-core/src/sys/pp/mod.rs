@@ -0,0 +1,1991 @@
+//! Common infrastructure for PortalPlayer based systems.
+//!
+//! All PP502x based iPods share the same SoC-level devices (e.g: interrupt
+//! controller, timers, DMA, GPIO, etc...), and only really differ in what
+//! off-chip devices are hooked up to the SoC, and where. The older PP5002 (used
+//! in the iPod 3g) has most of the same peripherals, just at different
+//! addresses. Each SoC's devices are described by a [PpSoc] implementation
+//! (i.e: [PpDevices] and [Pp5002Devices]).
+//!
+//! [PpSystem] implements all the system-level plumbing (e.g: CPU stepping,
+//! IRQ dispatch, DMA transfers), and is parameterized by a [PpBoard], which
+//! owns the system's devices and defines the system's memory map.
+
+use std::io::{BufRead, Read, Seek, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use armv4t_emu::{reg, Cpu, Exception, Mode};
+use thiserror::Error;
+
+use crate::block::BlockDev;
+use crate::clock::{Clock, ClockMode};
+use crate::coverage::Coverage;
+use crate::devices::Device;
+use crate::error::*;
+use crate::executor::*;
+use crate::gui::{AudioCallback, RenderCallback};
+use crate::memory::{
+    armv4t_adaptor::MemoryAdapter, MemAccess, MemAccessKind, MemAccessVal, Memory,
+};
+use crate::profiler::Profiler;
+use crate::serial::{self, SerialBackend};
+use crate::signal::{self, gpio, irq};
+use crate::snapshot::{self, Snapshot, SnapshotError};
+use crate::symbols::SymbolTable;
+use crate::trace::{self, TraceRecord, Tracer};
+
+#[macro_use]
+mod mmap;
+
+mod checkpoint;
+mod controls;
+mod gdb;
+mod hle_bootloader;
+mod input_log;
+mod pp5002;
+mod semihosting;
+mod watchpoints;
+
+pub use controls::{PpBinds, PpKey};
+pub use gdb::{LogFilterCallback, PpGdb, PpGdbEventLoop};
+pub use input_log::{InputEvent, InputLogError};
+pub use pp5002::{Pp5002Devices, Pp5002DevicesCfg};
+
+use checkpoint::Checkpoint;
+use hle_bootloader::run_hle_bootloader;
+use input_log::{InputHistory, InputLog};
+use semihosting::Semihosting;
+
+use crate::devices::platform::pp::common::*;
+use crate::devices::util::{ArcMutexDevice, MemSniffer, MmioDiscovery, MmioDiscoveryMem};
+mod devices {
+    pub mod i2c {
+        pub use crate::devices::i2c::devices::{Pcf5060x, WmCodec, WmCodecKind};
+    }
+
+    pub use crate::devices::{
+        generic::{ide, AsanRam, Stub},
+        platform::pp::*,
+    };
+}
+
+/// What `step` should do when both cores are asleep.
+enum BlockMode {
+    /// Block the host thread until the next device deadline (wall clock), or
+    /// skip ahead to it (virtual clock).
+    Blocking,
+    /// Only skip ahead to the next device deadline when using a virtual clock.
+    /// Never blocks the host thread (e.g: so the GDB stub can poll for
+    /// incoming packets).
+    NonBlocking,
+}
+
+/// Upper bound on how long the host thread is put to sleep while both cores
+/// are asleep, as devices with asynchronous inputs (e.g: controls, serial,
+/// IDE) can raise IRQs at any time.
+const MAX_IDLE_SLEEP: Duration = Duration::from_millis(1);
+
+/// Why a system stopped running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The guest exited via a semihosting `SYS_EXIT` / `SYS_EXIT_EXTENDED` call
+    /// (with the specified exit code).
+    Semihosting(i32),
+    /// The guest put the PMU into standby mode (i.e: powered off the device).
+    PowerOff,
+    /// Both cores were put to sleep via the CPU controller with all interrupts
+    /// disabled (i.e: nothing can ever wake them up again).
+    Halted,
+}
+
+impl ExitReason {
+    /// Return the exit code the emulator should exit with.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            ExitReason::Semihosting(code) => code,
+            ExitReason::PowerOff | ExitReason::Halted => 0,
+        }
+    }
+}
+
+/// How guest-triggered memory faults (e.g: misaligned accesses, MMU
+/// violations, accesses to unmapped memory) are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AbortMode {
+    /// Terminate execution with a [FatalMemException].
+    #[default]
+    Fatal,
+    /// Deliver the fault to the faulting core as a Data / Prefetch Abort.
+    ///
+    /// Faults are still treated as fatal if the guest hasn't installed a
+    /// handler for the abort, or if the core faults while already handling an
+    /// abort.
+    Guest,
+}
+
+/// Which CPU exception a guest fault was delivered as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbortKind {
+    /// Fault while fetching an instruction.
+    Prefetch,
+    /// Fault while loading / storing data.
+    Data,
+}
+
+impl AbortKind {
+    fn exception(self) -> Exception {
+        match self {
+            AbortKind::Prefetch => Exception::PreAbort,
+            AbortKind::Data => Exception::DataAbort,
+        }
+    }
+
+    /// Offset from the aborted instruction to the value stored in `LR_abt`.
+    fn lr_offset(self) -> u32 {
+        match self {
+            AbortKind::Prefetch => 4,
+            AbortKind::Data => 8,
+        }
+    }
+}
+
+/// A guest fault which was delivered to a core as an abort.
+///
+/// The ARM7TDMI doesn't have a fault address / status register, so this is
+/// purely informational (e.g: for debugging abort handlers).
+#[derive(Debug, Clone)]
+pub struct GuestAbort {
+    pub kind: AbortKind,
+    /// Address of the aborted instruction.
+    pub pc: u32,
+    /// The faulting memory access.
+    pub access: MemAccess,
+    pub reason: MemException,
+}
+
+/// Save an (optional) guest abort as part of a snapshot.
+///
+/// Only guest-deliverable exceptions (see `MemException::is_guest_abort`) are
+/// ever recorded as a `GuestAbort`, so those are the only reasons encoded.
+fn save_abort(w: &mut dyn Write, abort: &Option<GuestAbort>) -> std::io::Result<()> {
+    let abort = match abort {
+        None => return false.save(w),
+        Some(abort) => abort,
+    };
+
+    let (mut size, mut val) = match abort.access.val {
+        MemAccessVal::U8(val) => (1u8, val as u32),
+        MemAccessVal::U16(val) => (2, val as u32),
+        MemAccessVal::U32(val) => (4, val),
+    };
+    let mut reason: u8 = match abort.reason {
+        MemException::Misaligned => 0,
+        MemException::MmuViolation => 1,
+        MemException::Unexpected => 2,
+        ref e => {
+            return Err(snapshot::invalid_data(format!(
+                "unexpected guest abort reason: {:?}",
+                e
+            )))
+        }
+    };
+
+    let (mut pc, mut offset) = (abort.pc, abort.access.offset);
+
+    true.save(w)?;
+    (abort.kind == AbortKind::Data).save(w)?;
+    pc.save(w)?;
+    (abort.access.kind == MemAccessKind::Write).save(w)?;
+    offset.save(w)?;
+    size.save(w)?;
+    val.save(w)?;
+    reason.save(w)
+}
+
+/// Load an (optional) guest abort saved via `save_abort`.
+fn load_abort(r: &mut dyn Read) -> std::io::Result<Option<GuestAbort>> {
+    let (mut is_some, mut is_data, mut is_write) = (false, false, false);
+    let (mut pc, mut offset, mut val) = (0u32, 0u32, 0u32);
+    let (mut size, mut reason) = (0u8, 0u8);
+
+    is_some.load(r)?;
+    if !is_some {
+        return Ok(None);
+    }
+    is_data.load(r)?;
+    pc.load(r)?;
+    is_write.load(r)?;
+    offset.load(r)?;
+    size.load(r)?;
+    val.load(r)?;
+    reason.load(r)?;
+
+    Ok(Some(GuestAbort {
+        kind: match is_data {
+            false => AbortKind::Prefetch,
+            true => AbortKind::Data,
+        },
+        pc,
+        access: MemAccess {
+            kind: match is_write {
+                false => MemAccessKind::Read,
+                true => MemAccessKind::Write,
+            },
+            offset,
+            val: match size {
+                1 => MemAccessVal::U8(val as u8),
+                2 => MemAccessVal::U16(val as u16),
+                4 => MemAccessVal::U32(val),
+                _ => return Err(snapshot::invalid_data("invalid guest abort access size")),
+            },
+        },
+        reason: match reason {
+            0 => MemException::Misaligned,
+            1 => MemException::MmuViolation,
+            2 => MemException::Unexpected,
+            _ => return Err(snapshot::invalid_data("invalid guest abort reason")),
+        },
+    }))
+}
+
+pub enum BootKind<F: Read + Seek> {
+    ColdBoot,
+    HLEBoot { fw_file: F },
+}
+
+/// PortalPlayer system configuration.
+#[derive(Debug, Default)]
+pub struct PpCfg {
+    /// How emulated time advances.
+    pub clock: ClockMode,
+    /// Host backend for Serial0 (defaults to a null backend)
+    pub serial0: Option<Box<dyn SerialBackend>>,
+    /// Host backend for Serial1 (defaults to a null backend)
+    pub serial1: Option<Box<dyn SerialBackend>>,
+    /// How guest-triggered memory faults are handled.
+    pub aborts: AbortMode,
+    /// How stubbed / unimplemented / contract-violating accesses are handled.
+    pub error_policy: ErrorPolicy,
+    /// Automatically back unmapped memory accesses with recording stubs, and
+    /// keep track of all accesses to unmapped / stubbed addresses (see
+    /// [PpSystem::mmio_discovery]).
+    pub mmio_discovery: bool,
+    /// Service ARM semihosting calls (`swi 0x123456` / Thumb `swi 0xab` or
+    /// `bkpt 0xab`).
+    pub semihosting: bool,
+}
+
+#[derive(Debug)]
+struct PpControls {
+    hold: gpio::Sender,
+    input: PpInputControls,
+}
+
+#[derive(Debug)]
+enum PpInputControls {
+    ClickWheel(Box<devices::Controls<signal::Master>>),
+    GpioKeypad(controls::GpioKeypad),
+}
+
+/// A PortalPlayer based system, running on the specified board.
+#[derive(Debug)]
+pub struct PpSystem<B: PpBoard> {
+    frozen: bool,         // set after a fatal error to enable post-mortem debugging
+    skip_irq_check: bool, // set by the GDB stub when single-stepping though code
+    held: [bool; 2],      // cores held in place by the GDB stub (indexed by CpuId)
+
+    cpu: Cpu,
+    cop: Cpu,
+    devices: B,
+    controls: Option<PpControls>,
+    binds: Option<Arc<Mutex<PpBinds>>>, // set once the controls have been taken
+    tracer: Option<Tracer>,
+    abort_mode: AbortMode,
+    last_abort: [Option<GuestAbort>; 2], // indexed by CpuId
+    error_policy: ErrorPolicy,
+    mmio_discovery: Option<MmioDiscovery>,
+    symbols: SymbolTable,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    semihosting: Option<Semihosting>,
+    exit: Option<ExitReason>,
+    input_log: Option<InputLog>,
+    input_history: Option<InputHistory>, // set while recording execution history
+    cycles: u64,                         // number of cycles executed since startup
+
+    irq_pending: irq::Pending,
+    dma_pending: irq::Pending,
+    gpio_changed: gpio::Changed,
+    i2c_changed: signal::Trigger,
+    power_off: signal::Trigger,
+
+    clock: Clock,
+    clock_step: Option<Duration>, // set when using a virtual clock
+
+    executor: Executor,
+}
+
+#[derive(Error, Debug)]
+pub enum PpBuildError {
+    #[error("invalid flash dump: {0}")]
+    InvalidDump(&'static str),
+    #[error("HLE bootloader failed! {0}")]
+    HleBootloader(#[from] hle_bootloader::HleBootloaderError),
+}
+
+impl<B: PpBoard> PpSystem<B> {
+    /// Returns a new system instance.
+    pub fn new<F>(
+        hdd: Box<dyn BlockDev>,
+        flash_rom: Option<Box<[u8]>>,
+        boot_kind: BootKind<F>,
+        cfg: PpCfg,
+    ) -> Result<PpSystem<B>, PpBuildError>
+    where
+        F: Read + Seek,
+    {
+        let executor = Executor::new().expect("failed to create task executor");
+
+        let clock = Clock::new(cfg.clock);
+        let clock_step = match cfg.clock {
+            ClockMode::Wall => None,
+            ClockMode::Virtual { nanos_per_step } => {
+                Some(Duration::from_nanos(nanos_per_step as u64))
+            }
+        };
+
+        // initialize base system
+        let irq_pending = irq::Pending::new();
+        let dma_pending = irq::Pending::new();
+        let gpio_changed = gpio::Changed::new();
+        let i2c_changed = signal::Trigger::new(signal::TriggerKind::Edge);
+        let power_off = signal::Trigger::new(signal::TriggerKind::Hi);
+
+        let mut sys = PpSystem {
+            frozen: false,
+            skip_irq_check: false,
+            held: [false, false],
+
+            cpu: Cpu::new(),
+            cop: Cpu::new(),
+            devices: B::new(PpBoardCtx {
+                task_spawner: executor.spawner(),
+                clock: clock.clone(),
+                irq_pending: irq_pending.clone(),
+                dma_pending: dma_pending.clone(),
+                power_off: power_off.clone(),
+                serial0: cfg
+                    .serial0
+                    .unwrap_or_else(|| Box::new(serial::backend::Null::new())),
+                serial1: cfg
+                    .serial1
+                    .unwrap_or_else(|| Box::new(serial::backend::Null::new())),
+            }),
+            controls: None,
+            binds: None,
+            tracer: None,
+            abort_mode: cfg.aborts,
+            last_abort: [None, None],
+            error_policy: cfg.error_policy,
+            mmio_discovery: cfg.mmio_discovery.then(MmioDiscovery::new),
+            symbols: SymbolTable::new(),
+            profiler: None,
+            coverage: None,
+            semihosting: cfg.semihosting.then(Semihosting::new),
+            exit: None,
+            input_log: None,
+            input_history: None,
+            cycles: 0,
+
+            irq_pending,
+            dma_pending,
+            gpio_changed: gpio_changed.clone(),
+            i2c_changed: i2c_changed.clone(),
+            power_off,
+
+            clock,
+            clock_step,
+
+            executor,
+        };
+
+        let pp = sys.devices.pp_mut();
+
+        // connect HDD
+        pp.ide().attach(devices::ide::IdeIdx::IDE0, hdd);
+
+        // Set up flash_rom (if available)
+        if let Some(flash_rom) = flash_rom {
+            pp.flash()
+                .use_dump(flash_rom)
+                .map_err(PpBuildError::InvalidDump)?
+        }
+
+        // hook-up external controls
+        let (mut hold_tx, hold_rx) = gpio::new(gpio_changed, "Hold");
+        let (controls_tx, controls_rx) = devices::Controls::new_tx_rx(i2c_changed);
+
+        {
+            let mut gpio_abcd = pp.gpio_abcd().lock().unwrap();
+            gpio_abcd.register_in(B::HOLD_GPIO, hold_rx.clone());
+        }
+
+        // HACK: Hold is active-low, so set it to high by default
+        hold_tx.set_high();
+
+        let input = match B::INPUT {
+            PpInput::ClickWheel => {
+                pp.register_clickwheel(controls_rx, hold_rx);
+                PpInputControls::ClickWheel(Box::new(controls_tx))
+            }
+            PpInput::GpioKeypad { wheel } => {
+                let mut gpio_abcd = pp.gpio_abcd().lock().unwrap();
+                PpInputControls::GpioKeypad(controls::GpioKeypad::new(
+                    &sys.gpio_changed,
+                    wheel,
+                    |idx, rx| {
+                        gpio_abcd.register_in(idx, rx);
+                    },
+                ))
+            }
+        };
+
+        sys.controls = Some(PpControls {
+            hold: hold_tx,
+            input,
+        });
+
+        // Run the HLE bootloader if an HLE boot was requested
+        if let BootKind::HLEBoot { fw_file } = boot_kind {
+            run_hle_bootloader(&mut sys, fw_file)?
+        }
+
+        Ok(sys)
+    }
+
+    /// Run the system for a single CPU instruction, returning `None` if the
+    /// system is still running, or `Some` upon reaching some sort of "graceful
+    /// exit" condition (e.g: a semihosting exit call, or the guest powering off
+    /// the device).
+    fn step(
+        &mut self,
+        halt_block_mode: BlockMode,
+        mut sniff_memory: (
+            &(dyn Fn(u32, u32) -> bool + Sync),
+            impl FnMut(CpuId, MemAccess),
+        ),
+    ) -> FatalMemResult<Option<ExitReason>> {
+        if self.frozen {
+            return Ok(None);
+        }
+
+        if let Some(exit) = self.exit {
+            return Ok(Some(exit));
+        }
+
+        match (&mut self.input_history, &mut self.input_log) {
+            (Some(history), Some(input_log)) => history.poll(self.cycles, input_log),
+            (None, Some(input_log)) => input_log.poll(self.cycles, |_| {}),
+            _ => {}
+        }
+        self.cycles += 1;
+
+        if self.is_idle() {
+            if self.is_halted() {
+                info!("both cores halted with interrupts disabled");
+                self.exit = Some(ExitReason::Halted);
+                return Ok(self.exit);
+            }
+            self.wait_for_deadline(halt_block_mode);
+        }
+
+        let devices = &mut self.devices;
+        for (cpu, cpuid) in [(&mut self.cpu, CpuId::Cpu), (&mut self.cop, CpuId::Cop)].iter_mut() {
+            if self.held[*cpuid as usize] || !devices.pp_mut().is_cpu_running(*cpuid) {
+                continue;
+            }
+
+            // XXX: armv4t_emu doesn't currently expose any way to differentiate between
+            // instruction-fetch reads, and regular reads. Therefore, it's impossible to
+            // enforce MMU "execute" protection bits...
+
+            // FIXME: this approach is kinda gross. Maybe add a some "ctx" to `Memory`?
+            devices.pp_mut().set_cpuid(*cpuid);
+
+            let pc = cpu.reg_get(cpu.mode(), reg::PC);
+            let cpsr = cpu.reg_get(cpu.mode(), reg::CPSR);
+            let tracing = matches!(&self.tracer, Some(tracer) if tracer.wants(pc));
+            // aborts roll the core's registers back to their prior state
+            let regs_before = if tracing || self.abort_mode == AbortMode::Guest {
+                read_regs(cpu)
+            } else {
+                [0; trace::NUM_REGS]
+            };
+            let insn = if tracing {
+                // instructions aren't fetched from MMIO, so this is side-effect free
+                match cpu.thumb_mode() {
+                    true => devices.r16(pc).map(|v| v as u32),
+                    false => devices.r32(pc),
+                }
+                .unwrap_or(0)
+            } else {
+                0
+            };
+            let banked = self
+                .semihosting
+                .as_ref()
+                .map(|_| semihosting::Banked::save(cpu));
+
+            let mut accesses = Vec::new();
+            let (watch_filter, on_watch) = (sniff_memory.0, &mut sniff_memory.1);
+            let on_access = |access: MemAccess| {
+                if tracing {
+                    accesses.push(access);
+                }
+                if watch_filter(access.offset, access.val.size()) {
+                    on_watch(*cpuid, access)
+                }
+            };
+            let mut discovery_mem =
+                MmioDiscoveryMem::new(devices, self.mmio_discovery.as_mut(), pc);
+            let mut sniffer = match tracing {
+                true => MemSniffer::new_all(&mut discovery_mem, on_access),
+                false => MemSniffer::new(&mut discovery_mem, watch_filter, on_access),
+            };
+            let mut mem = MemoryAdapter::new(&mut sniffer);
+            cpu.step(&mut mem);
+            let exception = mem.exception.take();
+
+            if let (Some(semihosting), Some(banked)) = (&mut self.semihosting, &banked) {
+                if let Some(exit) = semihosting.on_exception(cpu, devices, pc, cpsr, banked) {
+                    if let Some(code) = exit {
+                        self.exit = Some(ExitReason::Semihosting(code));
+                        return Ok(self.exit);
+                    }
+                    continue;
+                }
+            }
+
+            if self.profiler.is_some() || self.coverage.is_some() {
+                let mode = cpu.mode();
+                let thumb = cpsr & (1 << 5) != 0;
+                let next_pc = cpu.reg_get(mode, reg::PC);
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.on_step(*cpuid, pc, thumb, next_pc, cpu.reg_get(mode, reg::LR));
+                }
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.on_step(*cpuid, pc, thumb, next_pc);
+                }
+            }
+
+            if tracing {
+                let pp = devices.pp();
+                let record = TraceRecord {
+                    core: *cpuid,
+                    thumb: regs_before[reg::CPSR as usize] & (1 << 5) != 0,
+                    pc,
+                    insn,
+                    regs: trace::reg_deltas(&regs_before, &read_regs(cpu)),
+                    mmio: accesses
+                        .into_iter()
+                        .filter(|a| pp.is_mmio(a.offset))
+                        .collect(),
+                };
+
+                if let Some(tracer) = &mut self.tracer {
+                    if let Err(e) = tracer.record(&record) {
+                        error!("failed to write trace record, disabling tracer: {}", e);
+                        self.tracer = None;
+                    }
+                }
+            }
+
+            if let Some((access, e)) = exception {
+                let e = match self.abort_mode {
+                    AbortMode::Guest if e.is_guest_abort() => {
+                        match deliver_abort(cpu, devices, pc, &regs_before, access, e) {
+                            Ok(abort) => {
+                                debug!(
+                                    target: "MMIO",
+                                    "[pc {:#010x?}][addr {:#010x?}] {:?} abort ({:?})",
+                                    abort.pc,
+                                    abort.access.offset,
+                                    abort.kind,
+                                    abort.reason
+                                );
+                                self.last_abort[*cpuid as usize] = Some(abort);
+                                continue;
+                            }
+                            Err(e) => e,
+                        }
+                    }
+                    _ => e,
+                };
+
+                let pc = cpu.reg_get(cpu.mode(), reg::PC);
+                e.resolve(
+                    "MMIO",
+                    MemExceptionCtx {
+                        pc,
+                        pc_sym: self.symbols.symbolize(pc),
+                        access,
+                        in_device: format!("{}", devices.probe(access.offset)),
+                    },
+                    &mut self.error_policy,
+                )?;
+            }
+        }
+
+        // when using a virtual clock, time-based devices are driven synchronously
+        if let Some(clock_step) = self.clock_step {
+            self.clock.advance(clock_step);
+            devices.pp_mut().tick();
+        }
+
+        if self.skip_irq_check {
+            return Ok(None);
+        }
+
+        // TODO: don't run this on every cycle?
+        self.executor.run_until_stalled();
+
+        // XXX: this is terrible. truly god awful. it _really_ needs to be rewritten,
+        // reorganized, and moved somewhere more appropriate.
+        if self.dma_pending.check() {
+            self.dma_pending.clear();
+            if let Some((kind, addr)) = devices.pp_mut().next_ide_dma() {
+                match kind {
+                    MemAccessKind::Read => {
+                        let val = (devices.pp_mut().ide())
+                            .read16(devices::ide::IdeReg::Data)
+                            .unwrap();
+                        devices.w16(addr, val).unwrap();
+                    }
+                    MemAccessKind::Write => {
+                        let val = devices.r16(addr).unwrap();
+                        (devices.pp_mut().ide())
+                            .write16(devices::ide::IdeReg::Data, val)
+                            .unwrap();
+                    }
+                }
+            }
+        }
+
+        devices.pp_mut().poll();
+
+        // feed the audio output
+        while let Some((src, dst)) = devices.pp_mut().next_audio_dma() {
+            if let Err(e) = devices.r32(src).and_then(|val| devices.w32(dst, val)) {
+                error!("I2S DMA transfer failed: {:?}", e);
+                devices.pp_mut().abort_audio_dma();
+                break;
+            }
+        }
+
+        let pp = devices.pp_mut();
+
+        // TODO?: explore adding callbacks to the signaling system
+        if self.gpio_changed.check_and_clear() {
+            pp.on_gpio_changed();
+        }
+        if self.i2c_changed.check_and_clear() {
+            pp.on_clickwheel_changed();
+        }
+
+        if self.irq_pending.check() {
+            let (cpu_status, cop_status) = pp.interrupt_status();
+
+            for (core, cpuid, status) in [
+                (&mut self.cpu, CpuId::Cpu, cpu_status),
+                (&mut self.cop, CpuId::Cop, cop_status),
+            ]
+            .iter_mut()
+            {
+                if status.irq {
+                    pp.wake_on_interrupt(*cpuid);
+                    core.exception(Exception::Interrupt);
+
+                    if core.irq_enable() {
+                        self.irq_pending.clear();
+                    }
+                }
+                if status.fiq {
+                    pp.wake_on_interrupt(*cpuid);
+                    core.exception(Exception::FastInterrupt);
+
+                    if core.fiq_enable() {
+                        self.irq_pending.clear();
+                    }
+                }
+            }
+        }
+
+        if self.power_off.check() {
+            self.exit = Some(ExitReason::PowerOff);
+            return Ok(self.exit);
+        }
+
+        Ok(None)
+    }
+
+    /// Check if both cores are asleep, with no outstanding IRQs / DMA requests
+    /// to service.
+    fn is_idle(&mut self) -> bool {
+        let pp = self.devices.pp_mut();
+        !pp.is_cpu_running(CpuId::Cpu)
+            && !pp.is_cpu_running(CpuId::Cop)
+            && !self.irq_pending.check()
+            && !self.dma_pending.check()
+    }
+
+    /// Check if both cores are asleep with no way of ever waking up (i.e: the
+    /// guest has shut down the CPUs).
+    fn is_halted(&mut self) -> bool {
+        self.devices.pp_mut().is_halted()
+    }
+
+    /// Called while both cores are asleep. When using a virtual clock, time is
+    /// fast-forwarded to (just before) the next device deadline. When using a
+    /// wall clock, the host thread is put to sleep until then instead.
+    fn wait_for_deadline(&mut self, block_mode: BlockMode) {
+        let now = self.clock.now();
+        let deadline = self.devices.pp().next_deadline();
+
+        match self.clock_step {
+            Some(clock_step) => {
+                // nothing will wake the cores until some external input arrives
+                let deadline = match deadline {
+                    Some(deadline) => deadline,
+                    None => return,
+                };
+
+                // skip to the step just before the deadline, such that the regular
+                // clock advance in `step` lands on (or just past) it.
+                let step_ns = clock_step.as_nanos().max(1);
+                let steps = deadline.saturating_sub(now).as_nanos().div_ceil(step_ns);
+                if steps > 1 {
+                    self.clock
+                        .advance(Duration::from_nanos(((steps - 1) * step_ns) as u64));
+                }
+            }
+            None => {
+                if let BlockMode::NonBlocking = block_mode {
+                    return;
+                }
+
+                let timeout = match deadline {
+                    Some(deadline) => deadline.saturating_sub(now).min(MAX_IDLE_SLEEP),
+                    None => MAX_IDLE_SLEEP,
+                };
+
+                // wasm can't block the main thread (and timers are driven by the
+                // executor on the same thread anyways)
+                #[cfg(not(target_arch = "wasm32"))]
+                std::thread::sleep(timeout);
+                #[cfg(target_arch = "wasm32")]
+                let _ = timeout;
+            }
+        }
+    }
+
+    /// Return the last guest fault which was delivered to the specified core as
+    /// an abort (see [AbortMode::Guest]).
+    pub fn last_abort(&self, core: CpuId) -> Option<&GuestAbort> {
+        self.last_abort[core as usize].as_ref()
+    }
+
+    /// Return the system's error policy (e.g: to query any tallied exceptions).
+    pub fn error_policy(&self) -> &ErrorPolicy {
+        &self.error_policy
+    }
+
+    /// Return the record of all unmapped / stubbed memory accesses (if MMIO
+    /// discovery mode is enabled).
+    pub fn mmio_discovery(&self) -> Option<&MmioDiscovery> {
+        self.mmio_discovery.as_ref()
+    }
+
+    /// Return the symbol table used to symbolize guest addresses.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Return a mutable reference to the symbol table used to symbolize guest
+    /// addresses (e.g: to load additional symbols).
+    pub fn symbols_mut(&mut self) -> &mut SymbolTable {
+        &mut self.symbols
+    }
+
+    /// Format an address, along with its symbol (if known).
+    pub fn fmt_addr(&self, addr: u32) -> String {
+        match self.symbols.symbolize(addr) {
+            Some(sym) => format!("{:#010x} <{}>", addr, sym),
+            None => format!("{:#010x}", addr),
+        }
+    }
+
+    /// Return a human-readable summary of each core's state (with symbolized
+    /// addresses), e.g: for including in crash dumps.
+    pub fn core_summary(&self) -> String {
+        let mut s = String::new();
+        for (cpu, name) in [(&self.cpu, "CPU"), (&self.cop, "COP")].iter() {
+            let mode = cpu.mode();
+            s += &format!(
+                "{}: mode={:?} thumb={} cpsr={:#010x}\n  pc: {}\n  lr: {}\n  sp: {:#010x}\n",
+                name,
+                mode,
+                cpu.thumb_mode(),
+                cpu.reg_get(mode, reg::CPSR),
+                self.fmt_addr(cpu.reg_get(mode, reg::PC)),
+                self.fmt_addr(cpu.reg_get(mode, reg::LR)),
+                cpu.reg_get(mode, reg::SP),
+            );
+        }
+        s
+    }
+
+    /// Attach (or detach) a sampling profiler, returning the previously attached
+    /// profiler (if any).
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) -> Option<Profiler> {
+        std::mem::replace(&mut self.profiler, profiler)
+    }
+
+    /// Return the attached sampling profiler (if any).
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Enable (or disable) code coverage collection, returning any previously
+    /// collected coverage.
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) -> Option<Coverage> {
+        std::mem::replace(&mut self.coverage, coverage)
+    }
+
+    /// Return the coverage collected so far (if enabled).
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Return the coverage collected so far (if enabled).
+    pub fn coverage_mut(&mut self) -> Option<&mut Coverage> {
+        self.coverage.as_mut()
+    }
+
+    /// Attach (or detach) an instruction tracer.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    /// Return a mutable reference to the attached instruction tracer (if any).
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    /// Run the system, returning successfully on "graceful exit" (e.g: a
+    /// semihosting exit call, or the guest powering off the device).
+    pub fn run(&mut self) -> FatalMemResult<ExitReason> {
+        let dummy_sniff_memory = |_, _| {};
+        loop {
+            if let Some(exit) =
+                self.step(BlockMode::Blocking, (&|_, _| false, dummy_sniff_memory))?
+            {
+                return Ok(exit);
+            }
+        }
+    }
+
+    /// Run the system, returning successfully on "graceful exit" (e.g: a
+    /// semihosting exit call, or the guest powering off the device). This
+    /// method will return after the specified number of cycles have been
+    /// executed.
+    pub fn run_cycles(&mut self, cycles: usize) -> FatalMemResult<Option<ExitReason>> {
+        let dummy_sniff_memory = |_, _| {};
+        for _ in 0..cycles {
+            if let Some(exit) =
+                self.step(BlockMode::Blocking, (&|_, _| false, dummy_sniff_memory))?
+            {
+                return Ok(Some(exit));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Return the number of cycles executed since startup.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Take the system's controls, returning binds which record every input
+    /// event to `w` as a plain-text log (i.e: one `<cycle> press up` /
+    /// `<cycle> scroll <dx> <dy>` line per event).
+    ///
+    /// Events delivered through the returned binds take effect at the start
+    /// of the next cycle.
+    pub fn record_input(&mut self, w: Box<dyn Write + Send>) -> Result<PpBinds, InputLogError> {
+        let binds = self.take_binds().ok_or(InputLogError::ControlsTaken)?;
+        let (input_log, binds) = InputLog::record(binds, w, B::NAME)?;
+        self.input_log = Some(input_log);
+        Ok(binds)
+    }
+
+    /// Take the system's controls, and replay the input events recorded in
+    /// `r` (via `record_input`) at the same points in execution.
+    pub fn replay_input(&mut self, r: impl BufRead) -> Result<(), InputLogError> {
+        let binds = self.take_binds().ok_or(InputLogError::ControlsTaken)?;
+        self.input_log = Some(InputLog::replay(binds, r)?);
+        Ok(())
+    }
+
+    /// Deliver an input event to the system, regardless of whether its
+    /// controls have been taken by a frontend (e.g: to press buttons from the
+    /// GDB stub). Events are recorded if input is being recorded.
+    pub fn inject_input(&mut self, event: InputEvent) -> Result<(), InputLogError> {
+        if let Some(input_log) = &mut self.input_log {
+            input_log.inject(event);
+            return Ok(());
+        }
+
+        if self.binds.is_none() {
+            let binds = self.take_binds().ok_or(InputLogError::ControlsTaken)?;
+            self.binds = Some(Arc::new(Mutex::new(binds)));
+        }
+        let binds = self.binds.as_ref().unwrap();
+        input_log::deliver(&mut binds.lock().unwrap(), event);
+        Ok(())
+    }
+
+    /// Return why the system stopped running (if it has).
+    pub fn exit_reason(&self) -> Option<ExitReason> {
+        self.exit
+    }
+
+    /// Freeze the system such that `step` becomes a noop. Called prior to
+    /// spawning a "post-mortem" GDB session.
+    ///
+    /// WARNING - THERE IS NO WAY TO "THAW" A FROZEN SYSTEM!
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    /// Return the system's RenderCallback method.
+    pub fn render_callback(&self) -> RenderCallback {
+        self.devices.render_callback()
+    }
+
+    /// Return the system's AudioCallback method.
+    pub fn audio_callback(&self) -> AudioCallback {
+        self.devices.pp().audio_callback()
+    }
+
+    /// Save the state of the system (both cores, all devices, and any pending
+    /// signals).
+    ///
+    /// The contents of the HDD are _not_ included in the snapshot, and must be
+    /// preserved separately.
+    pub fn save_state(&mut self, mut w: impl Write) -> Result<(), SnapshotError> {
+        let w: &mut dyn Write = &mut w;
+        snapshot::write_header(w, B::NAME)?;
+        self.save(w)?;
+        Ok(())
+    }
+
+    /// Start recording the history required to deterministically re-execute
+    /// the system from a checkpoint (i.e: input events, and writes to the
+    /// HDD), discarding any previously recorded history.
+    fn start_history(&mut self) -> std::io::Result<()> {
+        if self.input_log.is_none() {
+            // route all input through an (unsaved) input log, so that events
+            // are delivered at known cycles
+            let shared = self.binds.get_or_insert_with(Default::default).clone();
+            let mut shared = shared.lock().unwrap();
+            let binds = match self.take_binds() {
+                Some(binds) => binds,
+                None => std::mem::take(&mut *shared),
+            };
+            let (input_log, proxy) = InputLog::record(binds, Box::new(std::io::sink()), B::NAME)?;
+            *shared = proxy;
+            self.input_log = Some(input_log);
+        }
+
+        self.input_history = Some(InputHistory::new(self.cycles));
+        let ide = self.devices.pp_mut().ide();
+        ide.set_journaling(false);
+        ide.set_journaling(true);
+        Ok(())
+    }
+
+    /// Stop recording history. Input continues to be routed through the input
+    /// log, which only delays it until the start of the next cycle.
+    fn stop_history(&mut self) {
+        self.input_history = None;
+        self.devices.pp_mut().ide().set_journaling(false);
+    }
+
+    /// Take an in-memory checkpoint of the system's state.
+    fn checkpoint(&mut self) -> Checkpoint {
+        let mut state = Vec::new();
+        self.save(&mut state)
+            .expect("writing to a Vec should never fail");
+        Checkpoint {
+            cycle: self.cycles,
+            state,
+            hdd_journal: self.devices.pp_mut().ide().journal_pos(),
+            held_from: None,
+        }
+    }
+
+    /// Forget any history recorded prior to the checkpoint (i.e: once it's the
+    /// earliest checkpoint remaining).
+    fn forget_before(&mut self, checkpoint: &Checkpoint) {
+        let ide = self.devices.pp_mut().ide();
+        ide.prune_journal(checkpoint.hdd_journal);
+        if let Some(input_history) = &mut self.input_history {
+            input_history.prune_before(checkpoint.cycle);
+        }
+    }
+
+    /// Discard any history recorded past the current cycle (i.e: when the
+    /// system's state is modified out from under it).
+    fn discard_history_after_now(&mut self) {
+        if let Some(input_history) = &mut self.input_history {
+            input_history.discard_after(self.cycles);
+        }
+    }
+
+    /// Return the system to the state it was in when the checkpoint was taken.
+    fn restore(&mut self, checkpoint: &Checkpoint) -> std::io::Result<()> {
+        let ide = self.devices.pp_mut().ide();
+        ide.rollback(checkpoint.hdd_journal)?;
+        self.load(&mut checkpoint.state.as_slice())?;
+        if let Some(input_history) = &mut self.input_history {
+            input_history.rewind(checkpoint.cycle);
+        }
+        self.exit = None;
+        Ok(())
+    }
+
+    /// Restore a system state created via `save_state`.
+    ///
+    /// The system must have been constructed with the same configuration (e.g:
+    /// attached drives) as the system which saved the state.
+    pub fn load_state(&mut self, mut r: impl Read) -> Result<(), SnapshotError> {
+        let r: &mut dyn Read = &mut r;
+        snapshot::read_header(r, B::NAME)?;
+        self.load(r)?;
+        Ok(())
+    }
+}
+
+// the clock must be restored before any devices, as devices may query the
+// current time while loading.
+impl<B: PpBoard> Snapshot for PpSystem<B> {
+    fn save(&mut self, w: &mut dyn Write) -> std::io::Result<()> {
+        self.clock.save(w)?;
+        self.cpu.save(w)?;
+        self.cop.save(w)?;
+        self.devices.save(w)?;
+        self.irq_pending.save(w)?;
+        self.dma_pending.save(w)?;
+        self.gpio_changed.save(w)?;
+        self.i2c_changed.save(w)?;
+        self.power_off.save(w)?;
+        for abort in self.last_abort.iter() {
+            save_abort(w, abort)?;
+        }
+        self.cycles.save(w)
+    }
+
+    fn load(&mut self, r: &mut dyn Read) -> std::io::Result<()> {
+        self.clock.load(r)?;
+        self.cpu.load(r)?;
+        self.cop.load(r)?;
+        self.devices.load(r)?;
+        self.irq_pending.load(r)?;
+        self.dma_pending.load(r)?;
+        self.gpio_changed.load(r)?;
+        self.i2c_changed.load(r)?;
+        self.power_off.load(r)?;
+        for abort in self.last_abort.iter_mut() {
+            *abort = load_abort(r)?;
+        }
+        self.cycles.load(r)
+    }
+}
+
+/// Look up a system's devices by the name they're given in its memory map
+/// (e.g: `intcon`). Implemented by the `mmap!` and `soc_mmap!` macros.
+pub trait DeviceMap {
+    /// Return the names of all devices in the memory map.
+    fn device_names(&self) -> Vec<&'static str>;
+
+    /// Return a debug view of the specified device's state.
+    fn find_device(&self, name: &str) -> Option<&dyn std::fmt::Debug>;
+}
+
+/// A PortalPlayer based board (i.e: a specific iPod model).
+///
+/// Boards own all of the system's devices, and define the system's memory map
+/// via their [Memory] implementation (typically generated using the `mmap!`
+/// macro).
+pub trait PpBoard: Memory + Device + DeviceMap + Snapshot + std::fmt::Debug + Sized {
+    /// The SoC the board is built around.
+    type Soc: PpSoc;
+
+    /// Name of the system. Also used to tag save-states.
+    const NAME: &'static str;
+    /// Visible area of the LCD (in pixels).
+    const SCREEN_DIMS: (usize, usize);
+    /// Value reported in `sysinfo_t::boardHwSwInterfaceRev` by the HLE
+    /// bootloader.
+    const HW_REV: u32;
+    /// GPIO pin the (active-low) hold switch is wired to, as an index into
+    /// GPIO ports A-D (e.g: `5` == GPIOA:5).
+    const HOLD_GPIO: usize;
+    /// How the board's buttons and scroll wheel are wired up.
+    const INPUT: PpInput = PpInput::ClickWheel;
+
+    /// Construct the board's devices.
+    fn new(ctx: PpBoardCtx) -> Self;
+
+    /// Return a reference to the board's SoC-level devices.
+    fn pp(&self) -> &Self::Soc;
+
+    /// Return a mutable reference to the board's SoC-level devices.
+    fn pp_mut(&mut self) -> &mut Self::Soc;
+
+    /// Return the board's (LCD) RenderCallback method.
+    fn render_callback(&self) -> RenderCallback;
+}
+
+/// The SoC-level devices of a PortalPlayer based system (i.e: [PpDevices] for
+/// the PP502x, and [Pp5002Devices] for the PP5002).
+///
+/// SoCs define the system's _physical_ memory map via their [Memory]
+/// implementation (typically generated using the `soc_mmap!` macro), and
+/// expose the devices [PpSystem] needs to drive directly.
+pub trait PpSoc: Memory + Device + DeviceMap + Snapshot + std::fmt::Debug {
+    /// Name of the SoC.
+    const NAME: &'static str;
+
+    /// Translate a virtual address into a physical address, along with the
+    /// memory region's protection bits.
+    fn virt_to_phys(&self, addr: u32) -> (u32, devices::Protection);
+
+    /// Check if the specified (virtual) address maps to MMIO, as opposed to
+    /// RAM or flash.
+    fn is_mmio(&self, addr: u32) -> bool;
+
+    /// Set which CPU core is performing subsequent memory accesses.
+    fn set_cpuid(&mut self, cpuid: CpuId);
+
+    /// Returns the earliest time at which a time-based device will next need
+    /// to be serviced (e.g: a timer firing its IRQ).
+    fn next_deadline(&self) -> Option<Duration>;
+
+    /// Drive time-based devices when using a virtual clock. Called once per
+    /// step, after the clock has been advanced.
+    fn tick(&mut self);
+
+    /// Service devices which exchange data with the host (e.g: serial ports,
+    /// audio output). Called once per step.
+    fn poll(&mut self);
+
+    /// Check if the specified core is awake.
+    fn is_cpu_running(&mut self, cpu: CpuId) -> bool;
+
+    /// Check if both cores are asleep with no way of ever waking up (e.g: no
+    /// pending countdowns, and all interrupts disabled).
+    fn is_halted(&mut self) -> bool;
+
+    /// Wake up the specified core if it's sleeping until the next interrupt.
+    fn wake_on_interrupt(&mut self, cpu: CpuId);
+
+    /// Check if an IRQ/FIQ is being requested on the (cpu, cop).
+    fn interrupt_status(&mut self) -> (devices::IntStatus, devices::IntStatus);
+
+    /// Return the state of all registered IRQ lines.
+    fn irq_lines(&self) -> Vec<devices::IrqLine>;
+
+    /// GPIO ports A-D (i.e: where the hold switch is wired up).
+    fn gpio_abcd(&self) -> &ArcMutexDevice<devices::GpioBlock>;
+
+    /// Called whenever a GPIO input changes.
+    fn on_gpio_changed(&mut self);
+
+    /// Wire up the click wheel controller to the board's controls. SoCs
+    /// without a click wheel controller ignore the controls.
+    fn register_clickwheel(
+        &mut self,
+        controls: devices::Controls<signal::Slave>,
+        hold: gpio::Reciever,
+    );
+
+    /// Called whenever the click wheel's controls change.
+    fn on_clickwheel_changed(&mut self);
+
+    fn sdram(&mut self) -> &mut devices::AsanRam;
+    fn fastram(&mut self) -> &mut devices::AsanRam;
+    fn flash(&mut self) -> &mut devices::Flash;
+    fn ide(&mut self) -> &mut devices::ide::IdeController;
+
+    /// Return the direction and address of the next 16-bit IDE DMA transfer
+    /// (if any).
+    fn next_ide_dma(&mut self) -> Option<(MemAccessKind, u32)>;
+
+    /// Return the source and destination addresses of the next word to be
+    /// transferred to the audio output via DMA (if any).
+    fn next_audio_dma(&mut self) -> Option<(u32, u32)>;
+
+    /// Abort the in-progress audio DMA transfer.
+    fn abort_audio_dma(&mut self);
+
+    /// Return the SoC's AudioCallback method.
+    fn audio_callback(&self) -> AudioCallback;
+}
+
+/// Snapshot a core's registers (in the current mode).
+fn read_regs(cpu: &Cpu) -> [u32; trace::NUM_REGS] {
+    let mut regs = [0; trace::NUM_REGS];
+    let mode = cpu.mode();
+    for (i, val) in regs.iter_mut().enumerate() {
+        *val = cpu.reg_get(mode, i as u8);
+    }
+    regs
+}
+
+/// Deliver a guest fault to `cpu` as a Data / Prefetch Abort, returning the
+/// original exception if the fault can't be handled by the guest.
+///
+/// `pc` and `regs` are the core's state _before_ executing the faulting
+/// instruction. Note that the faulting instruction has already been executed
+/// (with the faulting access reading back as 0, and writes being dropped), so
+/// the core's registers are rolled back to `regs`, aside from any base register
+/// writeback (i.e: the ARM7TDMI's "base updated" abort model).
+fn deliver_abort(
+    cpu: &mut Cpu,
+    mem: &mut impl Memory,
+    pc: u32,
+    regs: &[u32; trace::NUM_REGS],
+    access: MemAccess,
+    reason: MemException,
+) -> Result<GuestAbort, MemException> {
+    const CPSR_MODE_ABORT: u32 = 0x17;
+
+    let cpsr = regs[reg::CPSR as usize];
+
+    // armv4t_emu doesn't differentiate between instruction-fetch reads, and
+    // regular reads, so assume a read from the PC was an instruction fetch.
+    let kind = match access.kind {
+        MemAccessKind::Read if access.offset == pc => AbortKind::Prefetch,
+        _ => AbortKind::Data,
+    };
+
+    // faulting while handling an abort would just loop forever
+    if cpsr & 0x1f == CPSR_MODE_ABORT {
+        return Err(reason);
+    }
+
+    // uninitialized vectors are typically either zeroed, or a branch-to-self
+    match mem.r32(kind.exception().address()) {
+        Ok(0) | Ok(0xeaff_fffe) | Err(_) => return Err(reason),
+        Ok(_) => {}
+    }
+
+    // the aborted instruction shouldn't have any visible effect on the flags
+    // (e.g: the zeroed-out opcode of a faulting Thumb fetch is `lsls r0, r0, #0`)
+    cpu.reg_set(cpu.mode(), reg::CPSR, cpsr);
+
+    // ...nor on any registers (e.g: the destination of a faulting `ldr`), aside
+    // from the base register writeback
+    let writeback = match kind {
+        AbortKind::Prefetch => None,
+        AbortKind::Data => base_writeback(cpu, mem, pc, regs),
+    };
+    let mode = cpu.mode();
+    for (i, &val) in regs.iter().enumerate().take(reg::PC as usize) {
+        cpu.reg_set(mode, i as u8, val);
+    }
+    if let Some((rn, val)) = writeback {
+        cpu.reg_set(mode, rn, val);
+    }
+
+    cpu.exception(kind.exception());
+    cpu.reg_set(Mode::Abort, reg::LR, pc.wrapping_add(kind.lr_offset()));
+
+    Ok(GuestAbort {
+        kind,
+        pc,
+        access,
+        reason,
+    })
+}
+
+/// Decode the data transfer instruction at `pc`, returning the base register
+/// it writes back to (if any), along with the written back value.
+///
+/// `regs` are the core's registers prior to executing the instruction, whereas
+/// `cpu` reflects the state after executing it.
+fn base_writeback(
+    cpu: &Cpu,
+    mem: &mut impl Memory,
+    pc: u32,
+    regs: &[u32; trace::NUM_REGS],
+) -> Option<(u8, u32)> {
+    // instructions aren't fetched from MMIO, so this is side-effect free
+    let block_transfer = |rn: u8, count: u32, up: bool| {
+        let base = regs[rn as usize];
+        let val = match up {
+            true => base.wrapping_add(count * 4),
+            false => base.wrapping_sub(count * 4),
+        };
+        Some((rn, val))
+    };
+
+    if regs[reg::CPSR as usize] & (1 << 5) != 0 {
+        let insn = mem.r16(pc).ok()?;
+        return match insn >> 12 {
+            // LDMIA / STMIA
+            0b1100 => block_transfer(((insn >> 8) & 0x7) as u8, (insn & 0xff).count_ones(), true),
+            // PUSH / POP
+            0b1011 if insn & 0x0600 == 0x0400 => {
+                block_transfer(reg::SP, (insn & 0x1ff).count_ones(), insn & 0x0800 != 0)
+            }
+            _ => None,
+        };
+    }
+
+    let insn = mem.r32(pc).ok()?;
+    let rn = ((insn >> 16) & 0xf) as u8;
+    let rd = ((insn >> 12) & 0xf) as u8;
+    let (pre, up, writeback, load) = (
+        insn & (1 << 24) != 0,
+        insn & (1 << 23) != 0,
+        insn & (1 << 21) != 0,
+        insn & (1 << 20) != 0,
+    );
+
+    let single = insn & 0x0c00_0000 == 0x0400_0000;
+    // (excluding SWP and multiplies, which share the same encoding space)
+    let halfword = insn & 0x0e00_0090 == 0x0000_0090 && insn & 0x60 != 0;
+    let block = insn & 0x0e00_0000 == 0x0800_0000;
+
+    if (single || halfword) && (!pre || writeback) && !(load && rd == rn) && rn != reg::PC {
+        // the emulator has already calculated the updated base
+        Some((rn, cpu.reg_get(cpu.mode(), rn)))
+    } else if block && writeback {
+        block_transfer(rn, (insn & 0xffff).count_ones(), up)
+    } else {
+        None
+    }
+}
+
+/// How a board's buttons and scroll wheel are wired up to the SoC.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PpInput {
+    /// Buttons and wheel are read through the clickwheel controller (i.e: the
+    /// "opto" device).
+    ClickWheel,
+    /// Buttons and wheel are wired directly to GPIO lines (i.e: the iPod mini
+    /// 1g and iPod 3g), with the wheel's quadrature outputs on the specified
+    /// lines (as indexes into GPIO ports A-D).
+    GpioKeypad { wheel: [usize; 2] },
+}
+
+/// System-level resources required to construct a board's devices.
+pub struct PpBoardCtx {
+    pub task_spawner: Spawner,
+    pub clock: Clock,
+    pub irq_pending: irq::Pending,
+    pub dma_pending: irq::Pending,
+    /// Set when the guest powers off the device (via the PMU).
+    pub power_off: signal::Trigger,
+    pub serial0: Box<dyn SerialBackend>,
+    pub serial1: Box<dyn SerialBackend>,
+}
+
+/// Board-specific configuration of the SoC-level devices.
+#[derive(Debug, Clone)]
+pub struct PpDevicesCfg {
+    /// Size of the SDRAM (in bytes).
+    pub sdram_size: usize,
+    /// Chip ID reported by the PP controller (e.g: `b"PP5020D "`).
+    pub chip_id: [u8; 8],
+    /// Audio codec hooked up to the I2C and I2S controllers.
+    pub codec: devices::i2c::WmCodecKind,
+}
+
+/// Devices shared by all PP502x based systems.
+///
+/// This includes the SoC's internal peripherals, along with off-chip devices
+/// which are wired up identically across all boards (i.e: SDRAM, Flash ROM,
+/// power management unit, and audio codec).
+#[derive(Debug)]
+pub struct PpDevices {
+    pub sdram: devices::AsanRam,
+    pub fastram: devices::AsanRam,
+    pub cpuid: devices::CpuIdReg,
+    pub flash: devices::Flash,
+    pub cpucon: devices::CpuCon,
+    pub timer1: devices::CfgTimer,
+    pub timer2: devices::CfgTimer,
+    pub usec_timer: devices::UsecTimer,
+    pub gpio_abcd: ArcMutexDevice<devices::GpioBlock>,
+    pub gpio_efgh: ArcMutexDevice<devices::GpioBlock>,
+    pub gpio_ijkl: ArcMutexDevice<devices::GpioBlock>,
+    pub gpio_mirror_abcd: devices::GpioBlockAtomicMirror,
+    pub gpio_mirror_efgh: devices::GpioBlockAtomicMirror,
+    pub gpio_mirror_ijkl: devices::GpioBlockAtomicMirror,
+    pub i2ccon: devices::I2CCon,
+    pub opto: devices::OptoWheel,
+    pub ppcon: devices::PPCon,
+    pub devcon: devices::DevCon,
+    pub intcon: devices::IntCon,
+    pub eidecon: devices::EIDECon,
+    pub memcon: devices::MemCon,
+    pub piezo: devices::Piezo,
+    pub cachecon: devices::CacheCon,
+    pub i2s: devices::I2SCon,
+    pub mailbox: devices::Mailbox,
+    pub dmacon: devices::DmaCon,
+    pub serial0: devices::Serial,
+    pub serial1: devices::Serial,
+
+    pub mystery_irq_con: devices::Stub,
+    pub mystery_lcd_con: devices::Stub,
+    pub mystery_flash_stub: devices::Stub,
+    pub firewire: devices::Stub,
+    pub total_mystery: devices::Stub,
+}
+
+impl PpDevices {
+    #[allow(clippy::redundant_clone)] // Makes the code cleaner in this case
+    pub fn new(ctx: PpBoardCtx, cfg: PpDevicesCfg) -> PpDevices {
+        let PpBoardCtx {
+            task_spawner,
+            clock,
+            irq_pending,
+            dma_pending,
+            power_off,
+            serial0,
+            serial1,
+        } = ctx;
+
+        let (ide_irq_tx, ide_irq_rx) = irq::new(irq_pending.clone(), "IDE");
+        let (timer1_irq_tx, timer1_irq_rx) = irq::new(irq_pending.clone(), "Timer1");
+        let (timer2_irq_tx, timer2_irq_rx) = irq::new(irq_pending.clone(), "Timer2");
+        let (gpio0_irq_tx, gpio0_irq_rx) = irq::new(irq_pending.clone(), "GPIO0");
+        let (gpio1_irq_tx, gpio1_irq_rx) = irq::new(irq_pending.clone(), "GPIO1");
+        let (gpio2_irq_tx, gpio2_irq_rx) = irq::new(irq_pending.clone(), "GPIO2");
+        let (i2c_irq_tx, i2c_irq_rx) = irq::new(irq_pending.clone(), "I2C");
+        let (i2s_irq_tx, i2s_irq_rx) = irq::new(irq_pending.clone(), "I2S");
+        let (dma_irq_tx, dma_irq_rx) = irq::new(irq_pending.clone(), "DMA");
+        let (ser0_irq_tx, ser0_irq_rx) = irq::new(irq_pending.clone(), "Serial0");
+        let (ser1_irq_tx, ser1_irq_rx) = irq::new(irq_pending.clone(), "Serial1");
+
+        let (ide_dmarq_tx, ide_dmarq_rx) = irq::new(dma_pending.clone(), "IDE DMA");
+
+        // mailbox is the only core-specific IRQ in the system, which is kinda neat
+        let (mbx_cpu_irq_tx, mbx_cpu_irq_rx) = irq::new(irq_pending.clone(), "Mailbox (CPU)");
+        let (mbx_cop_irq_tx, mbx_cop_irq_rx) = irq::new(irq_pending.clone(), "Mailbox (COP)");
+
+        let gpio_abcd = ArcMutexDevice::new(GpioBlock::new(gpio0_irq_tx, ["A", "B", "C", "D"]));
+        let gpio_efgh = ArcMutexDevice::new(GpioBlock::new(gpio1_irq_tx, ["E", "F", "G", "H"]));
+        let gpio_ijkl = ArcMutexDevice::new(GpioBlock::new(gpio2_irq_tx, ["I", "J", "K", "L"]));
+
+        let gpio_mirror_abcd = gpio_abcd.clone();
+        let gpio_mirror_efgh = gpio_efgh.clone();
+        let gpio_mirror_ijkl = gpio_ijkl.clone();
+
+        let mut intcon = IntCon::new();
+        intcon
+            .register(0, timer1_irq_rx)
+            .register(1, timer2_irq_rx)
+            .register_core_specific(4, mbx_cpu_irq_rx, mbx_cop_irq_rx)
+            .register(10, i2s_irq_rx)
+            // .register(20, usb_irq_rx)
+            .register(23, ide_irq_rx)
+            // .register(25, firewire_irq_rx)
+            .register(26, dma_irq_rx)
+            .register(32, gpio0_irq_rx)
+            .register(33, gpio1_irq_rx)
+            .register(34, gpio2_irq_rx)
+            .register(36, ser0_irq_rx)
+            .register(37, ser1_irq_rx)
+            .register(40, i2c_irq_rx);
+
+        let dmacon = DmaCon::new(dma_irq_tx, ide_dmarq_rx);
+
+        let mut i2ccon = I2CCon::new(i2c_irq_tx.clone());
+        i2ccon.register_device(0x08, Box::new(i2c::Pcf5060x::new(clock.clone(), power_off)));
+
+        let codec = i2c::WmCodec::new(cfg.codec);
+        let i2s = I2SCon::new(i2s_irq_tx, clock.clone(), codec.sample_rate());
+        i2ccon.register_device(0x1a, Box::new(codec));
+
+        use devices::*;
+        PpDevices {
+            sdram: AsanRam::new(cfg.sdram_size, true),
+            fastram: AsanRam::new(96 * 1024, true), // 96 KB
+            cpuid: CpuIdReg::new(),
+            flash: Flash::new(),
+            cpucon: CpuCon::new(clock.clone(), task_spawner.clone()),
+            timer1: CfgTimer::new("1", timer1_irq_tx, clock.clone(), task_spawner.clone()),
+            timer2: CfgTimer::new("2", timer2_irq_tx, clock.clone(), task_spawner),
+            usec_timer: UsecTimer::new(clock),
+            gpio_abcd,
+            gpio_efgh,
+            gpio_ijkl,
+            gpio_mirror_abcd: GpioBlockAtomicMirror::new(gpio_mirror_abcd),
+            gpio_mirror_efgh: GpioBlockAtomicMirror::new(gpio_mirror_efgh),
+            gpio_mirror_ijkl: GpioBlockAtomicMirror::new(gpio_mirror_ijkl),
+            i2ccon,
+            opto: OptoWheel::new(i2c_irq_tx),
+            ppcon: PPCon::new(cfg.chip_id),
+            devcon: DevCon::new(),
+            intcon,
+            eidecon: EIDECon::new(ide_irq_tx, ide_dmarq_tx),
+            memcon: MemCon::new(),
+            piezo: Piezo::new(),
+            cachecon: CacheCon::new(),
+            i2s,
+            mailbox: Mailbox::new(mbx_cpu_irq_tx, mbx_cop_irq_tx),
+            dmacon,
+            serial0: Serial::new("0", ser0_irq_tx, serial0),
+            serial1: Serial::new("1", ser1_irq_tx, serial1),
+
+            mystery_irq_con: Stub::new("Mystery IRQ Con?"),
+            mystery_lcd_con: Stub::new("Mystery LCD Con?"),
+            mystery_flash_stub: Stub::new("Mystery FlashROM Con?"),
+            firewire: Stub::new("Firewire Con?"),
+            total_mystery: Stub::new("<total mystery>"),
+        }
+    }
+}
+
+impl PpSoc for PpDevices {
+    const NAME: &'static str = "PP502x";
+
+    fn virt_to_phys(&self, addr: u32) -> (u32, devices::Protection) {
+        self.memcon.virt_to_phys(addr)
+    }
+
+    fn is_mmio(&self, addr: u32) -> bool {
+        let (addr, _) = self.memcon.virt_to_phys(addr);
+        !matches!(
+            addr,
+            0x0000_0000..=0x000f_ffff | 0x1000_0000..=0x11ff_ffff | 0x4000_0000..=0x4001_7fff
+        )
+    }
+
+    fn set_cpuid(&mut self, cpuid: CpuId) {
+        self.cpuid.set_cpuid(cpuid);
+        self.memcon.set_cpuid(cpuid);
+        self.mailbox.set_cpuid(cpuid);
+    }
+
+    fn next_deadline(&self) -> Option<Duration> {
+        [
+            self.timer1.next_deadline(),
+            self.timer2.next_deadline(),
+            self.cpucon.next_deadline(),
+            self.i2s.next_deadline(),
+        ]
+        .iter()
+        .flatten()
+        .copied()
+        .min()
+    }
+
+    fn tick(&mut self) {
+        self.timer1.tick();
+        self.timer2.tick();
+        self.cpucon.tick();
+    }
+
+    fn poll(&mut self) {
+        self.serial0.tick();
+        self.serial1.tick();
+        self.i2s.tick();
+    }
+
+    fn is_cpu_running(&mut self, cpu: CpuId) -> bool {
+        self.cpucon.is_cpu_running(cpu)
+    }
+
+    fn is_halted(&mut self) -> bool {
+        self.cpucon.is_cpu_halted(CpuId::Cpu)
+            && self.cpucon.is_cpu_halted(CpuId::Cop)
+            && !self.intcon.any_enabled()
+    }
+
+    fn wake_on_interrupt(&mut self, cpu: CpuId) {
+        self.cpucon.wake_on_interrupt(cpu)
+    }
+
+    fn interrupt_status(&mut self) -> (devices::IntStatus, devices::IntStatus) {
+        self.intcon.interrupt_status()
+    }
+
+    fn irq_lines(&self) -> Vec<devices::IrqLine> {
+        self.intcon.lines()
+    }
+
+    fn gpio_abcd(&self) -> &ArcMutexDevice<devices::GpioBlock> {
+        &self.gpio_abcd
+    }
+
+    fn on_gpio_changed(&mut self) {
+        self.gpio_abcd.lock().unwrap().update();
+        self.gpio_efgh.lock().unwrap().update();
+        self.gpio_ijkl.lock().unwrap().update();
+    }
+
+    fn register_clickwheel(
+        &mut self,
+        controls: devices::Controls<signal::Slave>,
+        hold: gpio::Reciever,
+    ) {
+        self.opto.register_controls(controls, hold)
+    }
+
+    fn on_clickwheel_changed(&mut self) {
+        self.opto.on_change();
+    }
+
+    fn sdram(&mut self) -> &mut devices::AsanRam {
+        &mut self.sdram
+    }
+
+    fn fastram(&mut self) -> &mut devices::AsanRam {
+        &mut self.fastram
+    }
+
+    fn flash(&mut self) -> &mut devices::Flash {
+        &mut self.flash
+    }
+
+    fn ide(&mut self) -> &mut devices::ide::IdeController {
+        self.eidecon.as_ide()
+    }
+
+    fn next_ide_dma(&mut self) -> Option<(MemAccessKind, u32)> {
+        if !self.dmacon.do_ide_dma() {
+            return None;
+        }
+
+        match self.eidecon.do_dma() {
+            Ok(tup) => Some(tup),
+            Err(_) => panic!("asd"),
+        }
+    }
+
+    fn next_audio_dma(&mut self) -> Option<(u32, u32)> {
+        if self.i2s.tx_free() < 2 {
+            return None;
+        }
+        self.dmacon.next_word(devices::DmaReq::I2S)
+    }
+
+    fn abort_audio_dma(&mut self) {
+        self.dmacon.abort(devices::DmaReq::I2S);
+    }
+
+    fn audio_callback(&self) -> AudioCallback {
+        self.i2s.audio_callback()
+    }
+}
+
+// RAM contents are saved first, followed by devices in the order they appear in
+// the struct. The flash ROM, the gpio mirrors, and the stubs have no state.
+impl_snapshot!(PpDevices {
+    sdram,
+    fastram,
+    cpuid,
+    cpucon,
+    timer1,
+    timer2,
+    usec_timer,
+    gpio_abcd,
+    gpio_efgh,
+    gpio_ijkl,
+    i2ccon,
+    opto,
+    ppcon,
+    devcon,
+    intcon,
+    eidecon,
+    memcon,
+    piezo,
+    cachecon,
+    i2s,
+    mailbox,
+    dmacon,
+    serial0,
+    serial1,
+});
+
+soc_mmap! {
+    PpDevices;
+
+    RAM {
+        0x1000_0000..=0x11ff_ffff => sdram,
+        0x4000_0000..=0x4001_7fff => fastram,
+    }
+
+    DEVICES {
+        0x0000_0000..=0x000f_ffff => flash,
+        0x6000_0000..=0x6000_0fff => cpuid,
+        0x6000_1000..=0x6000_102f => mailbox,
+        0x6000_4000..=0x6000_41ff => intcon,
+        0x6000_5000..=0x6000_5007 => timer1,
+        0x6000_5008..=0x6000_500f => timer2,
+        0x6000_5010..=0x6000_5013 => usec_timer,
+        0x6000_6000..=0x6000_6fff => devcon,
+        0x6000_7000..=0x6000_7fff => cpucon,
+        0x6000_a000..=0x6000_bfff => dmacon,
+        0x6000_c000..=0x6000_cfff => cachecon,
+        0x6000_d000..=0x6000_d07f => gpio_abcd,
+        0x6000_d080..=0x6000_d0ff => gpio_efgh,
+        0x6000_d100..=0x6000_d17f => gpio_ijkl,
+        0x6000_d800..=0x6000_d87f => gpio_mirror_abcd,
+        0x6000_d880..=0x6000_d8ff => gpio_mirror_efgh,
+        0x6000_d900..=0x6000_d97f => gpio_mirror_ijkl,
+
+        0x6400_4000..=0x6400_41ff => intcon, // i guess there's a mirror?
+
+        0x7000_0000..=0x7000_1fff => ppcon,
+        0x7000_6000..=0x7000_6020 => serial0,
+        0x7000_6040..=0x7000_6060 => serial1,
+        0x7000_a000..=0x7000_a003 => piezo,
+        0x7000_c000..=0x7000_c0ff => i2ccon,
+        0x7000_c100..=0x7000_c1ff => opto,
+        0x7000_2800..=0x7000_28ff => i2s,
+        0xc300_0000..=0xc300_0fff => eidecon,
+        0xf000_0000..=0xf000_ffff => memcon,
+
+        // all the stubs
+
+        0x6000_1038 => mystery_irq_con,
+        0x6000_111c => mystery_irq_con,
+        0x6000_1128 => mystery_irq_con,
+        0x6000_1138 => mystery_irq_con,
+        0x6000_3000..=0x6000_30ff => total_mystery,
+        0x6000_9000..=0x6000_90ff => total_mystery,
+        // Diagnostics program reads from address, and write back 0x10000000
+        0x6000_f100..=0x6000_f11f => total_mystery,
+        0x7000_a010 => mystery_lcd_con,
+        0x7000_3800 => total_mystery,
+        0xc031_b1d8 => mystery_flash_stub,
+        0xc031_b1e8 => mystery_flash_stub,
+        // Diagnostics program writes 0xffffffff
+        0xc600_008c => firewire,
+        0xffff_fe00..=0xffff_ffff => mystery_flash_stub,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::block::backend::Null;
+    use crate::sys::ipod4g::Ipod4gBus;
+
+    pub(super) fn new_system() -> PpSystem<Ipod4gBus> {
+        new_system_with_hdd(Box::new(Null::new(0)))
+    }
+
+    pub(super) fn new_system_with_hdd(hdd: Box<dyn BlockDev>) -> PpSystem<Ipod4gBus> {
+        let cfg = PpCfg {
+            clock: ClockMode::Virtual {
+                nanos_per_step: 1000,
+            },
+            ..PpCfg::default()
+        };
+        let boot = BootKind::<std::io::Cursor<Vec<u8>>>::ColdBoot;
+        PpSystem::new(hdd, None, boot, cfg).unwrap()
+    }
+
+    fn save(sys: &mut PpSystem<Ipod4gBus>) -> Vec<u8> {
+        let mut state = Vec::new();
+        sys.save_state(&mut state).unwrap();
+        state
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut sys = new_system();
+
+        // banked registers
+        sys.cpu.reg_set(Mode::User, 4, 0x0404_0404);
+        sys.cpu.reg_set(Mode::Fiq, 8, 0x0808_0808);
+        sys.cpu.reg_set(Mode::Irq, reg::SP, 0x4000_1000);
+        sys.cop.reg_set(Mode::Abort, reg::LR, 0x1000_0040);
+        sys.cop.reg_set(Mode::Undefined, reg::SPSR, 0x6000_00db);
+
+        // devices
+        sys.devices.w32(0x1000_0000, 0xdead_beef).unwrap(); // SDRAM
+        sys.devices.w32(0x4000_0000, 0xcafe_f00d).unwrap(); // fast RAM
+        sys.devices.w32(0x6000_4024, 0x0000_0010).unwrap(); // CPU_INT_EN
+        sys.devices.w32(0x6000_402c, 0x0000_0010).unwrap(); // CPU_INT_PRIORITY
+
+        // signals, and other system state
+        sys.power_off.set();
+        sys.i2c_changed.set();
+        sys.cycles = 1234;
+        sys.last_abort[CpuId::Cop as usize] = Some(GuestAbort {
+            kind: AbortKind::Data,
+            pc: 0x1000_0010,
+            access: MemAccess {
+                kind: MemAccessKind::Write,
+                offset: 0x1,
+                val: MemAccessVal::U16(0xabcd),
+            },
+            reason: MemException::Misaligned,
+        });
+
+        let state = save(&mut sys);
+
+        let mut restored = new_system();
+        restored.load_state(state.as_slice()).unwrap();
+
+        assert_eq!(restored.cpu.reg_get(Mode::User, 4), 0x0404_0404);
+        assert_eq!(restored.cpu.reg_get(Mode::Fiq, 8), 0x0808_0808);
+        assert_eq!(restored.cpu.reg_get(Mode::User, 8), 0);
+        assert_eq!(restored.cpu.reg_get(Mode::Irq, reg::SP), 0x4000_1000);
+        assert_eq!(restored.cop.reg_get(Mode::Abort, reg::LR), 0x1000_0040);
+        assert_eq!(
+            restored.cop.reg_get(Mode::Undefined, reg::SPSR),
+            0x6000_00db
+        );
+
+        assert_eq!(restored.devices.r32(0x1000_0000).unwrap(), 0xdead_beef);
+        assert_eq!(restored.devices.r32(0x4000_0000).unwrap(), 0xcafe_f00d);
+        assert_eq!(restored.devices.r32(0x6000_4020).unwrap(), 0x0000_0010);
+        assert_eq!(restored.devices.r32(0x6000_402c).unwrap(), 0x0000_0010);
+
+        assert!(restored.power_off.check());
+        assert!(restored.i2c_changed.check());
+        assert_eq!(restored.cycles(), 1234);
+        assert!(restored.last_abort(CpuId::Cpu).is_none());
+        let abort = restored.last_abort(CpuId::Cop).unwrap();
+        assert_eq!(abort.kind, AbortKind::Data);
+        assert_eq!(abort.pc, 0x1000_0010);
+        assert_eq!(abort.access.kind, MemAccessKind::Write);
+        assert_eq!(abort.access.offset, 0x1);
+        assert_eq!(abort.access.val, MemAccessVal::U16(0xabcd));
+        assert!(matches!(abort.reason, MemException::Misaligned));
+
+        // nothing else was lost along the way
+        assert_eq!(save(&mut restored), state);
+    }
+
+    #[test]
+    fn load_state_rejects_other_systems() {
+        let mut sys = new_system();
+        let mut state = Vec::new();
+        snapshot::write_header(&mut state, "Ipod5g").unwrap();
+        sys.save(&mut state).unwrap();
+
+        assert!(matches!(
+            sys.load_state(state.as_slice()),
+            Err(SnapshotError::WrongSystem(kind)) if kind == "Ipod5g"
+        ));
+    }
+
+    /// Run `base_writeback` on a single instruction, with r0 = 0x2000,
+    /// r1 = 0x1000 and sp = 0x3000. The emulator is assumed to have updated r1
+    /// to 0x1004.
+    fn writeback(insn: u32, thumb: bool) -> Option<(u8, u32)> {
+        let mut mem = crate::devices::generic::Ram::new(4);
+        match thumb {
+            true => mem.w16(0, insn as u16).unwrap(),
+            false => mem.w32(0, insn).unwrap(),
+        }
+
+        let mut regs = [0; trace::NUM_REGS];
+        regs[0] = 0x2000;
+        regs[1] = 0x1000;
+        regs[reg::SP as usize] = 0x3000;
+        regs[reg::CPSR as usize] = 0xd3 | (thumb as u32) << 5;
+
+        let mut cpu = Cpu::new();
+        let mode = cpu.mode();
+        for (i, &val) in regs.iter().enumerate().take(reg::PC as usize) {
+            cpu.reg_set(mode, i as u8, val);
+        }
+        cpu.reg_set(mode, 1, 0x1004);
+
+        base_writeback(&cpu, &mut mem, 0, &regs)
+    }
+
+    #[test]
+    fn base_writeback_arm() {
+        #[rustfmt::skip]
+        let cases: &[(u32, Option<(u8, u32)>)] = &[
+            // ldr r2, [r1, #0x4]
+            (0xe591_2004, None),
+            // ldr r2, [r1, #0x4]!
+            (0xe5b1_2004, Some((1, 0x1004))),
+            // ldr r2, [r1], #0x4
+            (0xe491_2004, Some((1, 0x1004))),
+            // str r2, [r1, #0x4]!
+            (0xe5a1_2004, Some((1, 0x1004))),
+            // strb r2, [r1], #0x4
+            (0xe4c1_2004, Some((1, 0x1004))),
+            // ldr r1, [r1], #0x4 (the loaded value takes precedence)
+            (0xe491_1004, None),
+            // ldr r0, [pc], #0x4
+            (0xe49f_0004, None),
+            // ldrh r0, [r1, #0x2]
+            (0xe1d1_00b2, None),
+            // ldrh r0, [r1, #0x4]!
+            (0xe1f1_00b4, Some((1, 0x1004))),
+            // strh r0, [r1], #0x4
+            (0xe0c1_00b4, Some((1, 0x1004))),
+            // ldrsb r0, [r1], r2
+            (0xe091_00d2, Some((1, 0x1004))),
+            // swp r0, r2, [r1]
+            (0xe101_0092, None),
+            // mul r1, r2, r3
+            (0xe001_0392, None),
+            // ldmia r0!, {r1, r2, r3}
+            (0xe8b0_000e, Some((0, 0x200c))),
+            // ldmia r0, {r1, r2, r3}
+            (0xe890_000e, None),
+            // stmdb sp!, {r4, lr}
+            (0xe92d_4010, Some((reg::SP, 0x2ff8))),
+            // ldmib r0!, {r4}
+            (0xe9b0_0010, Some((0, 0x2004))),
+            // stmda r0!, {r1, r2}
+            (0xe820_0006, Some((0, 0x1ff8))),
+        ];
+
+        for &(insn, expected) in cases {
+            assert_eq!(writeback(insn, false), expected, "{:#010x}", insn);
+        }
+    }
+
+    #[test]
+    fn base_writeback_thumb() {
+        #[rustfmt::skip]
+        let cases: &[(u16, Option<(u8, u32)>)] = &[
+            // ldmia r0!, {r1, r2}
+            (0xc806, Some((0, 0x2008))),
+            // stmia r0!, {r1, r2, r3}
+            (0xc00e, Some((0, 0x200c))),
+            // push {r4, lr}
+            (0xb510, Some((reg::SP, 0x2ff8))),
+            // pop {r4, pc}
+            (0xbd10, Some((reg::SP, 0x3008))),
+            // ldr r0, [r1, #0x4]
+            (0x6848, None),
+            // sub sp, #0x8
+            (0xb082, None),
+        ];
+
+        for &(insn, expected) in cases {
+            assert_eq!(writeback(insn as u32, true), expected, "{:#06x}", insn);
+        }
+    }
+}
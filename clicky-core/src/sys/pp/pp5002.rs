@@ -0,0 +1,293 @@
+//! The PP5002 (i.e: the SoC used in the iPod 3g).
+//!
+//! The PP5002 is the PP502x's predecessor. Most of its peripherals have the
+//! same registers as their PP502x counterparts, but live at completely
+//! different addresses. Notable differences include:
+//!
+//! - no memory controller (i.e: no address remapping / protection)
+//! - no click wheel controller (i.e: the keypad is wired directly to GPIO)
+//! - a single bank of 32 IRQs
+//! - a much simpler CPU controller (no countdowns)
+//! - no inter-core mailbox, and no DMA controller
+//!
+//! Audio output isn't emulated (the PP5002's I2S controller is stubbed out).
+//! The frontend's audio callback only ever receives silence, albeit at the
+//! sample rate the guest programmed into the codec.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::devices::util::ArcMutexDevice;
+use crate::gui::AudioCallback;
+use crate::memory::MemAccessKind;
+use crate::signal::{self, gpio, irq};
+
+use super::devices::{self, i2c, pp5002};
+use super::{CpuId, PpBoardCtx, PpSoc};
+
+/// Board-specific configuration of the PP5002's devices.
+#[derive(Debug, Clone)]
+pub struct Pp5002DevicesCfg {
+    /// Size of the SDRAM (in bytes).
+    pub sdram_size: usize,
+    /// Audio codec hooked up to the I2C controller.
+    pub codec: i2c::WmCodecKind,
+}
+
+/// Devices shared by all PP5002 based systems.
+///
+/// Like [PpDevices](super::PpDevices), this includes the SDRAM, Flash ROM,
+/// power management unit, and audio codec.
+#[derive(Debug)]
+pub struct Pp5002Devices {
+    pub sdram: devices::AsanRam,
+    pub fastram: devices::AsanRam,
+    pub cpuid: devices::CpuIdReg,
+    pub flash: devices::Flash,
+    pub cpucon: pp5002::CpuCon,
+    pub intcon: devices::IntCon32,
+    pub timer1: devices::CfgTimer,
+    pub timer2: devices::CfgTimer,
+    pub usec_timer: devices::UsecTimer,
+    pub gpio_abcd: ArcMutexDevice<devices::GpioBlock>,
+    pub i2ccon: devices::I2CCon,
+    pub eidecon: devices::EIDECon,
+    pub serial0: devices::Serial,
+    pub serial1: devices::Serial,
+
+    pub i2s: devices::Stub,
+    /// Sample rate configured on the attached codec
+    pub sample_rate: Arc<AtomicU32>,
+    pub syscon: devices::Stub,
+    pub devcon: devices::Stub,
+}
+
+impl Pp5002Devices {
+    pub fn new(ctx: PpBoardCtx, cfg: Pp5002DevicesCfg) -> Pp5002Devices {
+        let PpBoardCtx {
+            task_spawner,
+            clock,
+            irq_pending,
+            dma_pending,
+            power_off,
+            serial0,
+            serial1,
+        } = ctx;
+
+        let (ide_irq_tx, ide_irq_rx) = irq::new(irq_pending.clone(), "IDE");
+        let (timer1_irq_tx, timer1_irq_rx) = irq::new(irq_pending.clone(), "Timer1");
+        let (timer2_irq_tx, timer2_irq_rx) = irq::new(irq_pending.clone(), "Timer2");
+        let (gpio_irq_tx, gpio_irq_rx) = irq::new(irq_pending.clone(), "GPIO");
+        let (ser0_irq_tx, ser0_irq_rx) = irq::new(irq_pending.clone(), "Serial0");
+        let (ser1_irq_tx, ser1_irq_rx) = irq::new(irq_pending.clone(), "Serial1");
+        // the I2C controller is polled
+        let (i2c_irq_tx, _) = irq::new(irq_pending, "I2C");
+
+        // there's no DMA controller, so IDE DMA requests go nowhere
+        let (ide_dmarq_tx, _) = irq::new(dma_pending, "IDE DMA");
+
+        let mut intcon = devices::IntCon32::new("PP5002");
+        intcon
+            .register(1, ide_irq_rx)
+            .register(4, ser0_irq_rx)
+            .register(7, ser1_irq_rx)
+            .register(11, timer1_irq_rx)
+            .register(12, timer2_irq_rx)
+            .register(14, gpio_irq_rx);
+
+        let mut i2ccon = devices::I2CCon::new(i2c_irq_tx);
+        i2ccon.register_device(0x08, Box::new(i2c::Pcf5060x::new(clock.clone(), power_off)));
+        let codec = i2c::WmCodec::new(cfg.codec);
+        let sample_rate = codec.sample_rate();
+        i2ccon.register_device(0x1a, Box::new(codec));
+
+        use devices::*;
+        Pp5002Devices {
+            sdram: AsanRam::new(cfg.sdram_size, true),
+            fastram: AsanRam::new(96 * 1024, true), // 96 KB
+            cpuid: CpuIdReg::new(),
+            flash: Flash::new(),
+            cpucon: pp5002::CpuCon::new(),
+            intcon,
+            timer1: CfgTimer::new("1", timer1_irq_tx, clock.clone(), task_spawner.clone()),
+            timer2: CfgTimer::new("2", timer2_irq_tx, clock.clone(), task_spawner),
+            usec_timer: UsecTimer::new(clock),
+            gpio_abcd: ArcMutexDevice::new(GpioBlock::new(gpio_irq_tx, ["A", "B", "C", "D"])),
+            i2ccon,
+            eidecon: EIDECon::new(ide_irq_tx, ide_dmarq_tx),
+            serial0: Serial::new("0", ser0_irq_tx, serial0),
+            serial1: Serial::new("1", ser1_irq_tx, serial1),
+
+            i2s: Stub::new("I2S Con (PP5002)"),
+            sample_rate,
+            syscon: Stub::new("System Controller Block?"),
+            devcon: Stub::new("Device Controller?"),
+        }
+    }
+}
+
+impl PpSoc for Pp5002Devices {
+    const NAME: &'static str = "PP5002";
+
+    fn virt_to_phys(&self, addr: u32) -> (u32, devices::Protection) {
+        let prot = devices::Protection {
+            r: true,
+            w: true,
+            d: false,
+            x: true,
+        };
+        (addr, prot)
+    }
+
+    fn is_mmio(&self, addr: u32) -> bool {
+        !matches!(
+            addr,
+            0x0000_0000..=0x000f_ffff | 0x2800_0000..=0x29ff_ffff | 0x4000_0000..=0x4001_7fff
+        )
+    }
+
+    fn set_cpuid(&mut self, cpuid: CpuId) {
+        self.cpuid.set_cpuid(cpuid);
+    }
+
+    fn next_deadline(&self) -> Option<Duration> {
+        [self.timer1.next_deadline(), self.timer2.next_deadline()]
+            .iter()
+            .flatten()
+            .copied()
+            .min()
+    }
+
+    fn tick(&mut self) {
+        self.timer1.tick();
+        self.timer2.tick();
+    }
+
+    fn poll(&mut self) {
+        self.serial0.tick();
+        self.serial1.tick();
+    }
+
+    fn is_cpu_running(&mut self, cpu: CpuId) -> bool {
+        self.cpucon.is_cpu_running(cpu)
+    }
+
+    fn is_halted(&mut self) -> bool {
+        !self.cpucon.is_cpu_running(CpuId::Cpu)
+            && !self.cpucon.is_cpu_running(CpuId::Cop)
+            && !self.intcon.any_enabled()
+    }
+
+    fn wake_on_interrupt(&mut self, cpu: CpuId) {
+        self.cpucon.wake_on_interrupt(cpu)
+    }
+
+    fn interrupt_status(&mut self) -> (devices::IntStatus, devices::IntStatus) {
+        self.intcon.interrupt_status()
+    }
+
+    fn irq_lines(&self) -> Vec<devices::IrqLine> {
+        self.intcon.lines()
+    }
+
+    fn gpio_abcd(&self) -> &ArcMutexDevice<devices::GpioBlock> {
+        &self.gpio_abcd
+    }
+
+    fn on_gpio_changed(&mut self) {
+        self.gpio_abcd.lock().unwrap().update();
+    }
+
+    fn register_clickwheel(
+        &mut self,
+        _controls: devices::Controls<signal::Slave>,
+        _hold: gpio::Reciever,
+    ) {
+        warn!("the PP5002 doesn't have a click wheel controller");
+    }
+
+    fn on_clickwheel_changed(&mut self) {}
+
+    fn sdram(&mut self) -> &mut devices::AsanRam {
+        &mut self.sdram
+    }
+
+    fn fastram(&mut self) -> &mut devices::AsanRam {
+        &mut self.fastram
+    }
+
+    fn flash(&mut self) -> &mut devices::Flash {
+        &mut self.flash
+    }
+
+    fn ide(&mut self) -> &mut devices::ide::IdeController {
+        self.eidecon.as_ide()
+    }
+
+    fn next_ide_dma(&mut self) -> Option<(MemAccessKind, u32)> {
+        None
+    }
+
+    fn next_audio_dma(&mut self) -> Option<(u32, u32)> {
+        None
+    }
+
+    fn abort_audio_dma(&mut self) {}
+
+    fn audio_callback(&self) -> AudioCallback {
+        warn!("audio output isn't emulated on the PP5002, the frontend will only receive silence");
+
+        let sample_rate = Arc::clone(&self.sample_rate);
+        Box::new(move |buf: &mut Vec<i16>| -> u32 {
+            buf.clear();
+            sample_rate.load(Ordering::Relaxed)
+        })
+    }
+}
+
+// RAM contents are saved first, followed by devices in the order they appear in
+// the struct. The flash ROM and the stubs have no state, and the sample rate is
+// restored along with the codec.
+impl_snapshot!(Pp5002Devices {
+    sdram,
+    fastram,
+    cpuid,
+    cpucon,
+    intcon,
+    timer1,
+    timer2,
+    usec_timer,
+    gpio_abcd,
+    i2ccon,
+    eidecon,
+    serial0,
+    serial1,
+});
+
+soc_mmap! {
+    Pp5002Devices;
+
+    RAM {
+        0x2800_0000..=0x29ff_ffff => sdram,
+        0x4000_0000..=0x4001_7fff => fastram,
+    }
+
+    DEVICES {
+        0x0000_0000..=0x000f_ffff => flash,
+        0xc000_2500..=0xc000_25ff => i2s,
+        0xc000_3000..=0xc000_3fff => eidecon,
+        0xc000_6000..=0xc000_6020 => serial0,
+        0xc000_6040..=0xc000_6060 => serial1,
+        0xc000_8000..=0xc000_80ff => i2ccon,
+        0xc400_0000..=0xc400_0fff => cpuid,
+        0xcf00_0000..=0xcf00_007f => gpio_abcd,
+        0xcf00_1000..=0xcf00_10ff => intcon,
+        0xcf00_1100..=0xcf00_1107 => timer1,
+        0xcf00_1108..=0xcf00_110f => timer2,
+        0xcf00_1110..=0xcf00_1113 => usec_timer,
+        0xcf00_4054..=0xcf00_405b => cpucon,
+        0xcf00_4000..=0xcf00_4fff => syscon,
+        0xcf00_5000..=0xcf00_5fff => devcon,
+    }
+}
@@ -0,0 +1,562 @@
+//! ARM semihosting.
+//!
+//! Guest code can request services from the host by executing `swi 0x123456`
+//! (ARM) / `swi 0xab` or `bkpt 0xab` (Thumb), with an operation number in `r0`,
+//! and a pointer to the operation's parameter block in `r1`. The operation's
+//! result is returned in `r0`.
+//!
+//! Calls are intercepted once the core has taken the resulting SWI / Undefined
+//! Instruction exception (the ARMv4T has no `bkpt` instruction), at which point
+//! the exception is rolled back, and the call is serviced in its place.
+//!
+//! Only the subset of operations required for basic console / file IO (and for
+//! exiting) is implemented. Unsupported operations return `-1`.
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use armv4t_emu::{reg, Cpu, Exception, Mode};
+
+use crate::error::MemException;
+use crate::memory::Memory;
+
+const SYS_OPEN: u32 = 0x01;
+const SYS_CLOSE: u32 = 0x02;
+const SYS_WRITEC: u32 = 0x03;
+const SYS_WRITE0: u32 = 0x04;
+const SYS_WRITE: u32 = 0x05;
+const SYS_READ: u32 = 0x06;
+const SYS_READC: u32 = 0x07;
+const SYS_ISTTY: u32 = 0x09;
+const SYS_SEEK: u32 = 0x0a;
+const SYS_FLEN: u32 = 0x0c;
+const SYS_ERRNO: u32 = 0x13;
+const SYS_EXIT: u32 = 0x18;
+const SYS_EXIT_EXTENDED: u32 = 0x20;
+
+/// `SYS_EXIT` reason code signaling that the application exited normally.
+const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x20026;
+
+/// Upper bound on the length of a `SYS_WRITE0` string.
+const MAX_STRING_LEN: u32 = 0x10000;
+/// Upper bound on the number of bytes transferred by a single `SYS_READ`.
+const MAX_READ_LEN: u32 = 0x10_0000;
+
+const EBADF: i32 = 9;
+const EIO: i32 = 5;
+
+/// Check if an instruction is a semihosting call.
+///
+/// Conditional ARM `swi` instructions are not treated as semihosting calls.
+fn is_semihosting_call(insn: u32, thumb: bool) -> bool {
+    match thumb {
+        true => matches!(insn & 0xffff, 0xdfab | 0xbeab),
+        false => insn == 0xef12_3456,
+    }
+}
+
+/// The banked registers clobbered by a core taking a SWI / Undefined
+/// Instruction exception, saved prior to stepping the core.
+#[derive(Debug, Clone, Copy)]
+pub struct Banked {
+    svc: (u32, u32),
+    und: (u32, u32),
+}
+
+impl Banked {
+    pub fn save(cpu: &Cpu) -> Banked {
+        let regs = |mode| (cpu.reg_get(mode, reg::LR), cpu.reg_get(mode, reg::SPSR));
+        Banked {
+            svc: regs(Mode::Supervisor),
+            und: regs(Mode::Undefined),
+        }
+    }
+
+    /// Undo the exception taken by the instruction at `pc`, restoring the
+    /// core's prior `cpsr`.
+    fn roll_back(&self, cpu: &mut Cpu, pc: u32, cpsr: u32) {
+        for (mode, (lr, spsr)) in [(Mode::Supervisor, self.svc), (Mode::Undefined, self.und)] {
+            cpu.reg_set(mode, reg::LR, lr);
+            cpu.reg_set(mode, reg::SPSR, spsr);
+        }
+        cpu.reg_set(cpu.mode(), reg::CPSR, cpsr);
+        cpu.reg_set(cpu.mode(), reg::PC, pc);
+    }
+}
+
+#[derive(Debug)]
+enum Handle {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(fs::File),
+}
+
+enum Error {
+    Mem(MemException),
+    Io(io::Error),
+    BadHandle,
+}
+
+impl From<MemException> for Error {
+    fn from(e: MemException) -> Error {
+        Error::Mem(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+enum Outcome {
+    Return(u32),
+    Exit(i32),
+}
+
+fn read_bytes(mem: &mut impl Memory, addr: u32, len: u32) -> Result<Vec<u8>, MemException> {
+    (0..len).map(|i| mem.r8(addr.wrapping_add(i))).collect()
+}
+
+fn write_bytes(mem: &mut impl Memory, addr: u32, data: &[u8]) -> Result<(), MemException> {
+    for (i, b) in data.iter().enumerate() {
+        mem.w8(addr.wrapping_add(i as u32), *b)?;
+    }
+    Ok(())
+}
+
+/// Host-side semihosting state (i.e: open file handles).
+#[derive(Debug, Default)]
+pub struct Semihosting {
+    /// Indexed by `handle - 1`
+    handles: Vec<Option<Handle>>,
+    errno: i32,
+}
+
+impl Semihosting {
+    pub fn new() -> Semihosting {
+        Semihosting::default()
+    }
+
+    /// Check if the core just took an exception by executing a semihosting call
+    /// at `pc` (with the core's state prior to executing it being `cpsr` and
+    /// `banked`). If so, the exception is rolled back, and the call is serviced.
+    ///
+    /// Returns `None` if the instruction wasn't a semihosting call, or
+    /// `Some(exit)` if it was (where `exit` is the guest's exit code if it
+    /// requested to exit).
+    pub fn on_exception(
+        &mut self,
+        cpu: &mut Cpu,
+        mem: &mut impl Memory,
+        pc: u32,
+        cpsr: u32,
+        banked: &Banked,
+    ) -> Option<Option<i32>> {
+        let vector = match cpu.mode() {
+            Mode::Supervisor => Exception::Software.address(),
+            Mode::Undefined => Exception::Undefined.address(),
+            _ => return None,
+        };
+        if cpu.reg_get(cpu.mode(), reg::PC) != vector {
+            return None;
+        }
+
+        // instructions aren't fetched from MMIO, so this is side-effect free
+        let thumb = cpsr & (1 << 5) != 0;
+        let insn = match thumb {
+            true => mem.r16(pc).map(|v| v as u32),
+            false => mem.r32(pc),
+        };
+        if !matches!(insn, Ok(insn) if is_semihosting_call(insn, thumb)) {
+            return None;
+        }
+
+        banked.roll_back(cpu, pc, cpsr);
+        Some(self.call(cpu, mem))
+    }
+
+    /// Service the semihosting call the core is currently stopped at, and
+    /// advance the core's PC past the call instruction. Returns the guest's
+    /// exit code if it requested to exit.
+    pub fn call(&mut self, cpu: &mut Cpu, mem: &mut impl Memory) -> Option<i32> {
+        let mode = cpu.mode();
+        let op = cpu.reg_get(mode, 0);
+        let param = cpu.reg_get(mode, 1);
+
+        let pc = cpu.reg_get(mode, reg::PC);
+        cpu.reg_set(
+            mode,
+            reg::PC,
+            pc.wrapping_add(if cpu.thumb_mode() { 2 } else { 4 }),
+        );
+
+        let ret = match self.dispatch(op, param, mem) {
+            Ok(Outcome::Return(ret)) => ret,
+            Ok(Outcome::Exit(code)) => return Some(code),
+            Err(e) => {
+                self.errno = match e {
+                    Error::Mem(e) => {
+                        warn!(
+                            target: "MMIO",
+                            "[pc {:#010x?}] semihosting call {:#x?} accessed invalid memory: {:?}",
+                            pc,
+                            op,
+                            e
+                        );
+                        EIO
+                    }
+                    Error::Io(e) => e.raw_os_error().unwrap_or(EIO),
+                    Error::BadHandle => EBADF,
+                };
+                -1i32 as u32
+            }
+        };
+
+        cpu.reg_set(mode, 0, ret);
+        None
+    }
+
+    fn handle(&mut self, handle: u32) -> Result<&mut Handle, Error> {
+        (self.handles.get_mut((handle as usize).wrapping_sub(1)))
+            .and_then(|h| h.as_mut())
+            .ok_or(Error::BadHandle)
+    }
+
+    fn dispatch<M: Memory>(&mut self, op: u32, param: u32, mem: &mut M) -> Result<Outcome, Error> {
+        let arg = |mem: &mut M, n: u32| mem.r32(param.wrapping_add(n * 4));
+
+        let ret = match op {
+            SYS_OPEN => {
+                let (name, mode, len) = (arg(mem, 0)?, arg(mem, 1)?, arg(mem, 2)?);
+                let name = read_bytes(mem, name, len)?;
+                let name = String::from_utf8_lossy(&name);
+
+                // see the `fopen` mode strings
+                let handle = match (name.as_ref(), mode / 4) {
+                    (":tt", 0) => Handle::Stdin,
+                    (":tt", 1) => Handle::Stdout,
+                    (":tt", _) => Handle::Stderr,
+                    (path, kind) => {
+                        let update = mode & 2 != 0;
+                        let file = match kind {
+                            0 => fs::OpenOptions::new().read(true).write(update).open(path),
+                            1 => fs::OpenOptions::new()
+                                .read(update)
+                                .write(true)
+                                .create(true)
+                                .truncate(true)
+                                .open(path),
+                            _ => fs::OpenOptions::new()
+                                .read(update)
+                                .append(true)
+                                .create(true)
+                                .open(path),
+                        }?;
+                        Handle::File(file)
+                    }
+                };
+
+                let idx = match self.handles.iter().position(|h| h.is_none()) {
+                    Some(idx) => idx,
+                    None => {
+                        self.handles.push(None);
+                        self.handles.len() - 1
+                    }
+                };
+                self.handles[idx] = Some(handle);
+                idx as u32 + 1
+            }
+            SYS_CLOSE => {
+                let handle = arg(mem, 0)?;
+                self.handle(handle)?;
+                self.handles[handle as usize - 1] = None;
+                0
+            }
+            SYS_WRITEC => {
+                let c = mem.r8(param)?;
+                let mut stdout = io::stdout();
+                stdout.write_all(&[c])?;
+                stdout.flush()?;
+                0
+            }
+            SYS_WRITE0 => {
+                let mut s = Vec::new();
+                for i in 0..MAX_STRING_LEN {
+                    match mem.r8(param.wrapping_add(i))? {
+                        0 => break,
+                        c => s.push(c),
+                    }
+                }
+                let mut stdout = io::stdout();
+                stdout.write_all(&s)?;
+                stdout.flush()?;
+                0
+            }
+            SYS_WRITE => {
+                let (handle, buf, len) = (arg(mem, 0)?, arg(mem, 1)?, arg(mem, 2)?);
+                let data = read_bytes(mem, buf, len)?;
+                match self.handle(handle)? {
+                    Handle::Stdin => return Err(Error::BadHandle),
+                    Handle::Stdout => {
+                        let mut stdout = io::stdout();
+                        stdout.write_all(&data)?;
+                        stdout.flush()?;
+                    }
+                    Handle::Stderr => io::stderr().write_all(&data)?,
+                    Handle::File(file) => file.write_all(&data)?,
+                }
+                // number of bytes which were _not_ written
+                0
+            }
+            SYS_READ => {
+                let (handle, buf, len) = (arg(mem, 0)?, arg(mem, 1)?, arg(mem, 2)?);
+                let mut data = vec![0; len.min(MAX_READ_LEN) as usize];
+                let n = match self.handle(handle)? {
+                    Handle::Stdin => io::stdin().read(&mut data)?,
+                    Handle::File(file) => file.read(&mut data)?,
+                    _ => return Err(Error::BadHandle),
+                };
+                write_bytes(mem, buf, &data[..n])?;
+                // number of bytes which were _not_ read
+                len - n as u32
+            }
+            SYS_READC => {
+                let mut c = [0];
+                io::stdin().read_exact(&mut c)?;
+                c[0] as u32
+            }
+            SYS_ISTTY => match self.handle(arg(mem, 0)?)? {
+                Handle::File(_) => 0,
+                _ => 1,
+            },
+            SYS_SEEK => {
+                let (handle, pos) = (arg(mem, 0)?, arg(mem, 1)?);
+                match self.handle(handle)? {
+                    Handle::File(file) => file.seek(SeekFrom::Start(pos as u64))?,
+                    _ => return Err(Error::BadHandle),
+                };
+                0
+            }
+            SYS_FLEN => match self.handle(arg(mem, 0)?)? {
+                Handle::File(file) => file.metadata()?.len() as u32,
+                _ => return Err(Error::BadHandle),
+            },
+            SYS_ERRNO => self.errno as u32,
+            SYS_EXIT => {
+                // on 32-bit ARM, the reason code is passed directly in r1
+                let code = match param {
+                    ADP_STOPPED_APPLICATION_EXIT => 0,
+                    _ => 1,
+                };
+                info!("guest exited via semihosting (reason {:#x?})", param);
+                return Ok(Outcome::Exit(code));
+            }
+            SYS_EXIT_EXTENDED => {
+                let (reason, subcode) = (arg(mem, 0)?, arg(mem, 1)?);
+                let code = match reason {
+                    ADP_STOPPED_APPLICATION_EXIT => subcode as i32,
+                    _ => 1,
+                };
+                info!(
+                    "guest exited via semihosting (reason {:#x?}, code {})",
+                    reason, subcode as i32
+                );
+                return Ok(Outcome::Exit(code));
+            }
+            _ => {
+                warn!("unsupported semihosting call {:#x?}", op);
+                -1i32 as u32
+            }
+        };
+
+        Ok(Outcome::Return(ret))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use crate::devices::generic::Ram;
+    use crate::devices::util::MemSniffer;
+    use crate::memory::{MemAccess, MemAccessKind};
+    use crate::sys::ipod4g::Ipod4gBus;
+    use crate::sys::pp::tests::new_system;
+    use crate::sys::pp::{ExitReason, PpSystem};
+
+    struct Scratch {
+        path: PathBuf,
+    }
+
+    impl Scratch {
+        fn new(name: &str, data: &[u8]) -> Scratch {
+            let path = std::env::temp_dir().join(format!(
+                "clicky-semihosting-{}-{}",
+                std::process::id(),
+                name
+            ));
+            fs::write(&path, data).unwrap();
+            Scratch { path }
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn ret(outcome: Result<Outcome, Error>) -> u32 {
+        match outcome {
+            Ok(Outcome::Return(ret)) => ret,
+            _ => panic!("expected the call to return"),
+        }
+    }
+
+    fn exit(outcome: Result<Outcome, Error>) -> i32 {
+        match outcome {
+            Ok(Outcome::Exit(code)) => code,
+            _ => panic!("expected the call to exit"),
+        }
+    }
+
+    #[test]
+    fn write0() {
+        let mut ram = Ram::new(0x100);
+        ram.bulk_write(0x10, b"ok\n\0");
+
+        let mut reads = Vec::new();
+        let mut mem = MemSniffer::new_all(&mut ram, |access: MemAccess| reads.push(access));
+        let mut sh = Semihosting::new();
+        assert_eq!(ret(sh.dispatch(SYS_WRITE0, 0x10, &mut mem)), 0);
+
+        // the string is read up to (and including) the NUL terminator
+        let offsets = reads.iter().map(|a| a.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, [0x10, 0x11, 0x12, 0x13]);
+        assert!(reads.iter().all(|a| a.kind == MemAccessKind::Read));
+    }
+
+    #[test]
+    fn read() {
+        let file = Scratch::new("read", b"hello world");
+        let name = file.path.to_str().unwrap().as_bytes();
+
+        let mut ram = Ram::new(0x1000);
+        ram.bulk_write(0x800, name);
+        // SYS_OPEN [name, mode ("rb"), len]
+        for (i, arg) in [0x800, 1, name.len() as u32].iter().enumerate() {
+            ram.w32(0x10 + i as u32 * 4, *arg).unwrap();
+        }
+        let mut sh = Semihosting::new();
+        let handle = ret(sh.dispatch(SYS_OPEN, 0x10, &mut ram));
+        assert_eq!(handle, 1);
+
+        // SYS_READ [handle, buf, len] returns the number of bytes _not_ read
+        for (i, arg) in [handle, 0x100, 5].iter().enumerate() {
+            ram.w32(0x20 + i as u32 * 4, *arg).unwrap();
+        }
+        assert_eq!(ret(sh.dispatch(SYS_READ, 0x20, &mut ram)), 0);
+
+        for (i, arg) in [handle, 0x200, 16].iter().enumerate() {
+            ram.w32(0x20 + i as u32 * 4, *arg).unwrap();
+        }
+        assert_eq!(ret(sh.dispatch(SYS_READ, 0x20, &mut ram)), 10);
+
+        let mut buf = [0; 8];
+        ram.bulk_read(0x100, &mut buf);
+        assert_eq!(&buf, b"hello---");
+        ram.bulk_read(0x200, &mut buf);
+        assert_eq!(&buf, b" world--");
+
+        // reading from a closed handle fails
+        ram.w32(0x20, handle + 1).unwrap();
+        assert!(matches!(
+            sh.dispatch(SYS_READ, 0x20, &mut ram),
+            Err(Error::BadHandle)
+        ));
+    }
+
+    #[test]
+    fn exit_codes() {
+        let mut ram = Ram::new(0x100);
+        let mut sh = Semihosting::new();
+
+        // SYS_EXIT passes the reason code directly in r1
+        let code = exit(sh.dispatch(SYS_EXIT, ADP_STOPPED_APPLICATION_EXIT, &mut ram));
+        assert_eq!(code, 0);
+        // ADP_Stopped_RunTimeErrorUnknown
+        assert_eq!(exit(sh.dispatch(SYS_EXIT, 0x20023, &mut ram)), 1);
+
+        // SYS_EXIT_EXTENDED [reason, subcode]
+        ram.w32(0x10, ADP_STOPPED_APPLICATION_EXIT).unwrap();
+        ram.w32(0x14, -3i32 as u32).unwrap();
+        assert_eq!(exit(sh.dispatch(SYS_EXIT_EXTENDED, 0x10, &mut ram)), -3);
+        ram.w32(0x10, 0x20023).unwrap();
+        assert_eq!(exit(sh.dispatch(SYS_EXIT_EXTENDED, 0x10, &mut ram)), 1);
+    }
+
+    /// A semihosting-enabled system, with the CPU running `program` (in user
+    /// mode) from 0x1000_0000.
+    fn semihosting_system(program: &[u16], thumb: bool) -> PpSystem<Ipod4gBus> {
+        let mut sys = new_system();
+        sys.semihosting = Some(Semihosting::new());
+        for (i, half) in program.iter().enumerate() {
+            sys.devices.w16(0x1000_0000 + i as u32 * 2, *half).unwrap();
+        }
+        sys.cpu
+            .reg_set(Mode::User, reg::CPSR, 0x10 | (thumb as u32) << 5);
+        sys.cpu.reg_set(Mode::User, reg::PC, 0x1000_0000);
+        sys.cpu.reg_set(Mode::Supervisor, reg::LR, 0xdead_0000);
+        sys.cpu.reg_set(Mode::Undefined, reg::LR, 0xdead_0001);
+        sys.cop.reg_set(sys.cop.mode(), reg::PC, 0x1000_0000);
+        sys
+    }
+
+    fn check_calls(mut sys: PpSystem<Ipod4gBus>, errno_call: u32) {
+        // the first call returns to the next instruction, as if it had never
+        // raised an exception
+        assert!(sys.run_cycles(2).unwrap().is_none());
+        let cpu = &sys.cpu;
+        assert_eq!(cpu.mode(), Mode::User);
+        assert_eq!(cpu.reg_get(Mode::User, 0), 0);
+        assert_eq!(cpu.reg_get(Mode::User, reg::PC), errno_call);
+        assert_eq!(cpu.reg_get(Mode::Supervisor, reg::LR), 0xdead_0000);
+        assert_eq!(cpu.reg_get(Mode::Undefined, reg::LR), 0xdead_0001);
+
+        let exit = sys.run_cycles(8).unwrap();
+        assert!(matches!(exit, Some(ExitReason::Semihosting(0))));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn arm_calls() {
+        let sys = semihosting_system(&[
+            0x0013, 0xe3a0, // mov r0, #0x13 (SYS_ERRNO)
+            0x3456, 0xef12, // swi 0x123456
+            0x0018, 0xe3a0, // mov r0, #0x18 (SYS_EXIT)
+            0x1000, 0xe59f, // ldr r1, [pc, #0]
+            0x3456, 0xef12, // swi 0x123456
+            0x0026, 0x0002, // .word 0x20026 (ADP_Stopped_ApplicationExit)
+        ], false);
+        check_calls(sys, 0x1000_0008);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn thumb_calls() {
+        let sys = semihosting_system(&[
+            0x2013, // movs r0, #0x13 (SYS_ERRNO)
+            0xbeab, // bkpt 0xab
+            0x2018, // movs r0, #0x18 (SYS_EXIT)
+            0x4901, // ldr r1, [pc, #4]
+            0xdfab, // swi 0xab
+            0x46c0, // nop
+            0x0026, 0x0002, // .word 0x20026 (ADP_Stopped_ApplicationExit)
+        ], true);
+        check_calls(sys, 0x1000_0004);
+    }
+}
@@ -0,0 +1,268 @@
+//! Watchpoint bookkeeping for the GDB stub.
+//!
+//! Watchpoints cover arbitrary address ranges, and may optionally only trigger
+//! when a specific value is accessed. They're kept in a map keyed by their
+//! start address, so checking an access against the set of watchpoints only
+//! costs a (bounded) range query, regardless of how many are set.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use gdbstub::target::ext::breakpoints::WatchKind;
+
+use crate::memory::{MemAccess, MemAccessKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Watchpoint {
+    pub addr: u32,
+    pub len: u32,
+    pub kind: WatchKind,
+    /// Only trigger on accesses of this (zero-extended) value.
+    pub value: Option<u32>,
+    /// Set via `monitor watch` (as opposed to by GDB).
+    pub user: bool,
+}
+
+impl Watchpoint {
+    /// One past the last watched address.
+    fn end(&self) -> u64 {
+        self.addr as u64 + self.len as u64
+    }
+
+    fn overlaps(&self, addr: u32, len: u32) -> bool {
+        (addr as u64) < self.end() && (self.addr as u64) < addr as u64 + len as u64
+    }
+
+    fn matches(&self, access: &MemAccess) -> bool {
+        let kind = matches!(
+            (self.kind, access.kind),
+            (WatchKind::ReadWrite, _)
+                | (WatchKind::Read, MemAccessKind::Read)
+                | (WatchKind::Write, MemAccessKind::Write)
+        );
+        kind && self.value.map_or(true, |v| v == access.val.as_u32())
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#010x}-{:#010x} ({})",
+            self.addr,
+            self.end() - 1,
+            match self.kind {
+                WatchKind::Write => "w",
+                WatchKind::Read => "r",
+                WatchKind::ReadWrite => "rw",
+            }
+        )?;
+        if let Some(value) = self.value {
+            write!(f, " if value == {:#x}", value)?;
+        }
+        if !self.user {
+            write!(f, " [gdb]")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Watchpoints {
+    by_addr: BTreeMap<u32, Vec<Watchpoint>>,
+    /// Length of the longest watchpoint, which bounds how far back from an
+    /// access the map needs to be searched.
+    max_len: u32,
+}
+
+impl Watchpoints {
+    pub fn len(&self) -> usize {
+        self.by_addr.values().map(Vec::len).sum()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.by_addr.values().flatten()
+    }
+
+    pub fn insert(&mut self, wp: Watchpoint) {
+        self.max_len = self.max_len.max(wp.len);
+        self.by_addr.entry(wp.addr).or_default().push(wp);
+    }
+
+    /// Remove the first watchpoint starting at `addr` for which `f` returns
+    /// true.
+    pub fn remove(&mut self, addr: u32, f: impl Fn(&Watchpoint) -> bool) -> bool {
+        let wps = match self.by_addr.get_mut(&addr) {
+            Some(wps) => wps,
+            None => return false,
+        };
+        let pos = match wps.iter().position(f) {
+            Some(pos) => pos,
+            None => return false,
+        };
+        wps.remove(pos);
+        if wps.is_empty() {
+            self.by_addr.remove(&addr);
+        }
+        self.max_len = self.iter().map(|wp| wp.len).max().unwrap_or(0);
+        true
+    }
+
+    /// Remove all watchpoints set via `monitor watch`.
+    pub fn clear_user(&mut self) {
+        self.by_addr.retain(|_, wps| {
+            wps.retain(|wp| !wp.user);
+            !wps.is_empty()
+        });
+        self.max_len = self.iter().map(|wp| wp.len).max().unwrap_or(0);
+    }
+
+    /// Iterate over all watchpoints which overlap the `len` bytes at `addr`.
+    fn overlapping(&self, addr: u32, len: u32) -> impl Iterator<Item = &Watchpoint> {
+        let start = addr.saturating_sub(self.max_len.saturating_sub(1));
+        let end = addr.saturating_add(len.saturating_sub(1));
+        (self.by_addr.range(start..=end))
+            .flat_map(|(_, wps)| wps)
+            .filter(move |wp| wp.overlaps(addr, len))
+    }
+
+    /// Cheap check to see if an access to the `len` bytes at `addr` could
+    /// trigger a watchpoint.
+    pub fn wants(&self, addr: u32, len: u32) -> bool {
+        !self.by_addr.is_empty() && self.overlapping(addr, len).next().is_some()
+    }
+
+    /// Return the watchpoint triggered by the access (if any), along with the
+    /// watched address which was accessed.
+    pub fn hit(&self, access: &MemAccess) -> Option<(&Watchpoint, u32)> {
+        let wp =
+            (self.overlapping(access.offset, access.val.size())).find(|wp| wp.matches(access))?;
+        Some((wp, access.offset.max(wp.addr)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::memory::MemAccessVal;
+
+    fn wp(addr: u32, len: u32, kind: WatchKind, value: Option<u32>) -> Watchpoint {
+        Watchpoint {
+            addr,
+            len,
+            kind,
+            value,
+            user: true,
+        }
+    }
+
+    fn access(kind: MemAccessKind, offset: u32, val: MemAccessVal) -> MemAccess {
+        MemAccess { kind, offset, val }
+    }
+
+    fn read32(offset: u32) -> MemAccess {
+        access(MemAccessKind::Read, offset, MemAccessVal::U32(0))
+    }
+
+    #[test]
+    fn access_kinds() {
+        let read = access(MemAccessKind::Read, 0x100, MemAccessVal::U8(0));
+        let write = access(MemAccessKind::Write, 0x100, MemAccessVal::U8(0));
+
+        let cases = [
+            (WatchKind::Read, true, false),
+            (WatchKind::Write, false, true),
+            (WatchKind::ReadWrite, true, true),
+        ];
+        for (kind, on_read, on_write) in cases {
+            let wp = wp(0x100, 1, kind, None);
+            assert_eq!(wp.matches(&read), on_read, "{:?} read", kind);
+            assert_eq!(wp.matches(&write), on_write, "{:?} write", kind);
+        }
+    }
+
+    #[test]
+    fn value_conditions() {
+        let wp = wp(0x100, 4, WatchKind::Write, Some(0xff));
+        let write = |val| access(MemAccessKind::Write, 0x100, val);
+
+        assert!(wp.matches(&write(MemAccessVal::U8(0xff))));
+        assert!(wp.matches(&write(MemAccessVal::U16(0xff))));
+        assert!(wp.matches(&write(MemAccessVal::U32(0xff))));
+        assert!(!wp.matches(&write(MemAccessVal::U32(0xffff_ffff))));
+        assert!(!wp.matches(&write(MemAccessVal::U16(0xfe))));
+    }
+
+    #[test]
+    fn range_overlap() {
+        let wp = wp(0x100, 8, WatchKind::ReadWrite, None);
+        assert!(!wp.overlaps(0xfc, 4));
+        assert!(wp.overlaps(0xfd, 4));
+        assert!(wp.overlaps(0x100, 1));
+        assert!(wp.overlaps(0x104, 4));
+        assert!(wp.overlaps(0x107, 1));
+        assert!(!wp.overlaps(0x108, 4));
+
+        // ranges touching the end of the address space don't overflow
+        let wp = Watchpoint {
+            addr: 0xffff_fffc,
+            len: 4,
+            ..wp
+        };
+        assert!(wp.overlaps(0xffff_ffff, 1));
+        assert!(!wp.overlaps(0xffff_fff8, 4));
+        assert_eq!(wp.end(), 0x1_0000_0000);
+    }
+
+    #[test]
+    fn hits() {
+        let mut wps = Watchpoints::default();
+        assert!(!wps.wants(0x100, 4));
+
+        wps.insert(wp(0x100, 0x10, WatchKind::Write, None));
+        wps.insert(wp(0x200, 1, WatchKind::Read, Some(0x42)));
+        assert_eq!(wps.len(), 2);
+
+        assert!(wps.wants(0xfe, 4));
+        assert!(wps.wants(0x10c, 4));
+        assert!(!wps.wants(0x110, 4));
+        assert!(wps.wants(0x1fd, 4));
+
+        // the reported address is the first watched byte which was accessed
+        let write = access(MemAccessKind::Write, 0xfe, MemAccessVal::U32(0));
+        assert_eq!(
+            wps.hit(&write).map(|(wp, addr)| (wp.addr, addr)),
+            Some((0x100, 0x100))
+        );
+        let write = access(MemAccessKind::Write, 0x108, MemAccessVal::U16(0));
+        assert_eq!(wps.hit(&write).map(|(_, addr)| addr), Some(0x108));
+        assert!(wps.hit(&read32(0x108)).is_none());
+
+        let read = access(MemAccessKind::Read, 0x200, MemAccessVal::U8(0x42));
+        assert_eq!(wps.hit(&read).map(|(wp, _)| wp.addr), Some(0x200));
+        assert!(wps.hit(&read32(0x200)).is_none());
+    }
+
+    #[test]
+    fn removal() {
+        let mut wps = Watchpoints::default();
+        wps.insert(wp(0x100, 0x100, WatchKind::Write, None));
+        wps.insert(Watchpoint {
+            user: false,
+            ..wp(0x180, 4, WatchKind::Read, None)
+        });
+        assert!(wps.wants(0x1fc, 4));
+
+        assert!(!wps.remove(0x100, |wp| wp.kind == WatchKind::Read));
+        assert!(wps.remove(0x100, |wp| wp.kind == WatchKind::Write));
+        // the search window shrinks along with the longest watchpoint
+        assert_eq!(wps.max_len, 4);
+        assert!(!wps.wants(0x1fc, 4));
+        assert!(wps.wants(0x180, 4));
+
+        wps.insert(wp(0x300, 4, WatchKind::Write, None));
+        wps.clear_user();
+        assert_eq!(wps.iter().map(|wp| wp.addr).collect::<Vec<_>>(), [0x180]);
+    }
+}
@@ -0,0 +1,604 @@
+//! A minimal ARMv4T (ARM + Thumb) disassembler.
+//!
+//! Output loosely follows GNU `objdump` syntax. PC-relative targets (e.g:
+//! branches, literal loads) are resolved to absolute addresses, which is why
+//! the instruction's address must be provided.
+
+use bit_field::BitField;
+
+const COND: [&str; 16] = [
+    "eq", "ne", "cs", "cc", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le", "", "nv",
+];
+
+const DP_OPS: [&str; 16] = [
+    "and", "eor", "sub", "rsb", "add", "adc", "sbc", "rsc", "tst", "teq", "cmp", "cmn", "orr",
+    "mov", "bic", "mvn",
+];
+
+const SHIFTS: [&str; 4] = ["lsl", "lsr", "asr", "ror"];
+
+fn reg(r: u32) -> &'static str {
+    const REGS: [&str; 16] = [
+        "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "r12", "sp",
+        "lr", "pc",
+    ];
+    REGS[(r & 0xf) as usize]
+}
+
+fn reg_list(list: u32) -> String {
+    let regs = (0..16)
+        .filter(|i| list.get_bit(*i))
+        .map(|i| reg(i as u32))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", regs.join(", "))
+}
+
+fn signed_imm(up: bool, imm: u32) -> String {
+    format!("#{}{:#x}", if up { "" } else { "-" }, imm)
+}
+
+/// Sign-extend the low `bits` bits of `val`.
+fn sext(val: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((val << shift) as i32) >> shift
+}
+
+/// Disassemble a 32-bit ARM instruction located at `pc`.
+pub fn disasm_arm(insn: u32, pc: u32) -> String {
+    let cond = COND[insn.get_bits(28..=31) as usize];
+    let rn = insn.get_bits(16..=19);
+    let rd = insn.get_bits(12..=15);
+    let rs = insn.get_bits(8..=11);
+    let rm = insn.get_bits(0..=3);
+
+    // Branch and exchange
+    if insn & 0x0fff_fff0 == 0x012f_ff10 {
+        return format!("bx{} {}", cond, reg(rm));
+    }
+
+    // Multiply (accumulate)
+    if insn & 0x0fc0_00f0 == 0x0000_0090 {
+        let s = if insn.get_bit(20) { "s" } else { "" };
+        return match insn.get_bit(21) {
+            false => format!("mul{}{} {}, {}, {}", cond, s, reg(rn), reg(rm), reg(rs)),
+            true => format!(
+                "mla{}{} {}, {}, {}, {}",
+                cond,
+                s,
+                reg(rn),
+                reg(rm),
+                reg(rs),
+                reg(rd)
+            ),
+        };
+    }
+
+    // Multiply (accumulate) long
+    if insn & 0x0f80_00f0 == 0x0080_0090 {
+        let op = match (insn.get_bit(22), insn.get_bit(21)) {
+            (false, false) => "umull",
+            (false, true) => "umlal",
+            (true, false) => "smull",
+            (true, true) => "smlal",
+        };
+        let s = if insn.get_bit(20) { "s" } else { "" };
+        return format!(
+            "{}{}{} {}, {}, {}, {}",
+            op,
+            cond,
+            s,
+            reg(rd),
+            reg(rn),
+            reg(rm),
+            reg(rs)
+        );
+    }
+
+    // Single data swap
+    if insn & 0x0fb0_0ff0 == 0x0100_0090 {
+        let b = if insn.get_bit(22) { "b" } else { "" };
+        return format!("swp{}{} {}, {}, [{}]", cond, b, reg(rd), reg(rm), reg(rn));
+    }
+
+    // Halfword / signed data transfer
+    if insn & 0x0e00_0090 == 0x0000_0090 && insn.get_bits(5..=6) != 0 {
+        let load = insn.get_bit(20);
+        let op = match (load, insn.get_bits(5..=6)) {
+            (false, 0b01) => "strh",
+            (true, 0b01) => "ldrh",
+            (true, 0b10) => "ldrsb",
+            (true, 0b11) => "ldrsh",
+            _ => return format!("<undefined {:#010x}>", insn),
+        };
+        let up = insn.get_bit(23);
+        let offset = match insn.get_bit(22) {
+            true => signed_imm(up, rs << 4 | rm),
+            false => format!("{}{}", if up { "" } else { "-" }, reg(rm)),
+        };
+        let addr = transfer_addr(insn, rn, offset);
+        return format!("{}{} {}, {}", op, cond, reg(rd), addr);
+    }
+
+    // PSR transfer
+    if insn & 0x0fbf_0fff == 0x010f_0000 {
+        let psr = if insn.get_bit(22) { "spsr" } else { "cpsr" };
+        return format!("mrs{} {}, {}", cond, reg(rd), psr);
+    }
+    if insn & 0x0db0_f000 == 0x0120_f000 {
+        let psr = if insn.get_bit(22) { "spsr" } else { "cpsr" };
+        let fields = ["c", "x", "s", "f"]
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| insn.get_bit(16 + i))
+            .map(|(_, f)| *f)
+            .collect::<String>();
+        let src = match insn.get_bit(25) {
+            true => format!("#{:#x}", (insn & 0xff).rotate_right(rs * 2)),
+            false => reg(rm).to_string(),
+        };
+        return format!("msr{} {}_{}, {}", cond, psr, fields, src);
+    }
+
+    match insn.get_bits(25..=27) {
+        // Data processing
+        0b000 | 0b001 => {
+            let opcode = insn.get_bits(21..=24);
+            let op = DP_OPS[opcode as usize];
+            let op2 = match insn.get_bit(25) {
+                true => format!("#{:#x}", (insn & 0xff).rotate_right(rs * 2)),
+                false => shifted_reg(insn),
+            };
+            match opcode {
+                // tst, teq, cmp, cmn
+                0x8..=0xb => format!("{}{} {}, {}", op, cond, reg(rn), op2),
+                // mov, mvn
+                0xd | 0xf => {
+                    let s = if insn.get_bit(20) { "s" } else { "" };
+                    format!("{}{}{} {}, {}", op, cond, s, reg(rd), op2)
+                }
+                _ => {
+                    let s = if insn.get_bit(20) { "s" } else { "" };
+                    format!("{}{}{} {}, {}, {}", op, cond, s, reg(rd), reg(rn), op2)
+                }
+            }
+        }
+        // Single data transfer
+        0b010 | 0b011 => {
+            if insn.get_bit(25) && insn.get_bit(4) {
+                return format!("<undefined {:#010x}>", insn);
+            }
+
+            let op = if insn.get_bit(20) { "ldr" } else { "str" };
+            let b = if insn.get_bit(22) { "b" } else { "" };
+            // post-indexed with writeback == user-mode access
+            let t = if !insn.get_bit(24) && insn.get_bit(21) {
+                "t"
+            } else {
+                ""
+            };
+            let up = insn.get_bit(23);
+            let offset = match insn.get_bit(25) {
+                false => signed_imm(up, insn & 0xfff),
+                true => format!("{}{}", if up { "" } else { "-" }, shifted_reg(insn)),
+            };
+            let mut s = format!(
+                "{}{}{}{} {}, {}",
+                op,
+                cond,
+                b,
+                t,
+                reg(rd),
+                transfer_addr(insn, rn, offset)
+            );
+            // resolve literal loads
+            if rn == 15 && !insn.get_bit(25) && insn.get_bit(24) {
+                let off = insn & 0xfff;
+                let base = pc.wrapping_add(8);
+                let target = match up {
+                    true => base.wrapping_add(off),
+                    false => base.wrapping_sub(off),
+                };
+                s += &format!(" ; {:#010x}", target);
+            }
+            s
+        }
+        // Block data transfer
+        0b100 => {
+            let op = if insn.get_bit(20) { "ldm" } else { "stm" };
+            let mode = match (insn.get_bit(24), insn.get_bit(23)) {
+                (false, true) => "ia",
+                (true, true) => "ib",
+                (false, false) => "da",
+                (true, false) => "db",
+            };
+            let wb = if insn.get_bit(21) { "!" } else { "" };
+            let user = if insn.get_bit(22) { "^" } else { "" };
+            format!(
+                "{}{}{} {}{}, {}{}",
+                op,
+                cond,
+                mode,
+                reg(rn),
+                wb,
+                reg_list(insn & 0xffff),
+                user
+            )
+        }
+        // Branch (with link)
+        0b101 => {
+            let l = if insn.get_bit(24) { "l" } else { "" };
+            let offset = sext(insn & 0xff_ffff, 24) << 2;
+            let target = pc.wrapping_add(8).wrapping_add(offset as u32);
+            format!("b{}{} {:#010x}", l, cond, target)
+        }
+        // Coprocessor data transfer
+        0b110 => {
+            let op = if insn.get_bit(20) { "ldc" } else { "stc" };
+            let offset = signed_imm(insn.get_bit(23), (insn & 0xff) << 2);
+            format!(
+                "{}{} p{}, c{}, {}",
+                op,
+                cond,
+                rs,
+                rd,
+                transfer_addr(insn, rn, offset)
+            )
+        }
+        0b111 => match (insn.get_bit(24), insn.get_bit(4)) {
+            (true, _) => format!("swi{} {:#x}", cond, insn & 0xff_ffff),
+            // Coprocessor data operation
+            (false, false) => format!(
+                "cdp{} p{}, {}, c{}, c{}, c{}, {}",
+                cond,
+                rs,
+                insn.get_bits(20..=23),
+                rd,
+                rn,
+                rm,
+                insn.get_bits(5..=7)
+            ),
+            // Coprocessor register transfer
+            (false, true) => format!(
+                "{}{} p{}, {}, {}, c{}, c{}, {}",
+                if insn.get_bit(20) { "mrc" } else { "mcr" },
+                cond,
+                rs,
+                insn.get_bits(21..=23),
+                reg(rd),
+                rn,
+                rm,
+                insn.get_bits(5..=7)
+            ),
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// Format the `Rm{, <shift>}` operand of a data processing / data transfer
+/// instruction.
+fn shifted_reg(insn: u32) -> String {
+    let rm = reg(insn.get_bits(0..=3));
+    let shift = insn.get_bits(5..=6);
+
+    if insn.get_bit(4) {
+        return format!(
+            "{}, {} {}",
+            rm,
+            SHIFTS[shift as usize],
+            reg(insn.get_bits(8..=11))
+        );
+    }
+
+    match (shift, insn.get_bits(7..=11)) {
+        (0, 0) => rm.to_string(),
+        (3, 0) => format!("{}, rrx", rm),
+        (1, 0) | (2, 0) => format!("{}, {} #32", rm, SHIFTS[shift as usize]),
+        (_, amount) => format!("{}, {} #{}", rm, SHIFTS[shift as usize], amount),
+    }
+}
+
+/// Format the addressing mode of a (pre/post-indexed) data transfer.
+fn transfer_addr(insn: u32, rn: u32, offset: String) -> String {
+    let pre = insn.get_bit(24);
+    let wb = insn.get_bit(21);
+    match (pre, wb) {
+        (true, false) => format!("[{}, {}]", reg(rn), offset),
+        (true, true) => format!("[{}, {}]!", reg(rn), offset),
+        (false, _) => format!("[{}], {}", reg(rn), offset),
+    }
+}
+
+/// Disassemble a 16-bit Thumb instruction located at `pc`.
+///
+/// The two halves of a `bl` instruction are disassembled separately.
+pub fn disasm_thumb(insn: u16, pc: u32) -> String {
+    let insn = insn as u32;
+    let rd = insn.get_bits(0..=2);
+    let rs = insn.get_bits(3..=5);
+    let rd8 = insn.get_bits(8..=10);
+    let imm8 = insn & 0xff;
+
+    match insn >> 11 {
+        // move shifted register
+        0b00000..=0b00010 => {
+            let op = SHIFTS[(insn >> 11) as usize];
+            let amount = match insn.get_bits(6..=10) {
+                0 if op != "lsl" => 32,
+                amount => amount,
+            };
+            format!("{}s {}, {}, #{}", op, reg(rd), reg(rs), amount)
+        }
+        // add / subtract
+        0b00011 => {
+            let op = if insn.get_bit(9) { "subs" } else { "adds" };
+            let rn = insn.get_bits(6..=8);
+            match insn.get_bit(10) {
+                true => format!("{} {}, {}, #{}", op, reg(rd), reg(rs), rn),
+                false => format!("{} {}, {}, {}", op, reg(rd), reg(rs), reg(rn)),
+            }
+        }
+        // move / compare / add / subtract immediate
+        0b00100..=0b00111 => {
+            let op = ["movs", "cmp", "adds", "subs"][insn.get_bits(11..=12) as usize];
+            format!("{} {}, #{:#x}", op, reg(rd8), imm8)
+        }
+        0b01000 => match insn.get_bit(10) {
+            // ALU operations
+            false => {
+                const ALU_OPS: [&str; 16] = [
+                    "ands", "eors", "lsls", "lsrs", "asrs", "adcs", "sbcs", "rors", "tst", "negs",
+                    "cmp", "cmn", "orrs", "muls", "bics", "mvns",
+                ];
+                let op = ALU_OPS[insn.get_bits(6..=9) as usize];
+                format!("{} {}, {}", op, reg(rd), reg(rs))
+            }
+            // hi register operations / branch exchange
+            true => {
+                let rd = rd | (insn.get_bit(7) as u32) << 3;
+                let rs = insn.get_bits(3..=6);
+                match insn.get_bits(8..=9) {
+                    0b00 => format!("add {}, {}", reg(rd), reg(rs)),
+                    0b01 => format!("cmp {}, {}", reg(rd), reg(rs)),
+                    0b10 => format!("mov {}, {}", reg(rd), reg(rs)),
+                    _ => format!("bx {}", reg(rs)),
+                }
+            }
+        },
+        // PC-relative load
+        0b01001 => {
+            let target = (pc.wrapping_add(4) & !2).wrapping_add(imm8 << 2);
+            format!(
+                "ldr {}, [pc, #{:#x}] ; {:#010x}",
+                reg(rd8),
+                imm8 << 2,
+                target
+            )
+        }
+        // load / store with register offset, sign-extended byte / halfword
+        0b01010 | 0b01011 => {
+            let ro = insn.get_bits(6..=8);
+            let op = match (insn.get_bit(9), insn.get_bits(10..=11)) {
+                (false, 0b00) => "str",
+                (false, 0b01) => "strb",
+                (false, 0b10) => "ldr",
+                (false, _) => "ldrb",
+                (true, 0b00) => "strh",
+                (true, 0b01) => "ldrsb",
+                (true, 0b10) => "ldrh",
+                (true, _) => "ldrsh",
+            };
+            format!("{} {}, [{}, {}]", op, reg(rd), reg(rs), reg(ro))
+        }
+        // load / store with immediate offset
+        0b01100..=0b01111 => {
+            let byte = insn.get_bit(12);
+            let op = match (insn.get_bit(11), byte) {
+                (false, false) => "str",
+                (true, false) => "ldr",
+                (false, true) => "strb",
+                (true, true) => "ldrb",
+            };
+            let offset = insn.get_bits(6..=10) << if byte { 0 } else { 2 };
+            format!("{} {}, [{}, #{:#x}]", op, reg(rd), reg(rs), offset)
+        }
+        // load / store halfword
+        0b10000 | 0b10001 => {
+            let op = if insn.get_bit(11) { "ldrh" } else { "strh" };
+            let offset = insn.get_bits(6..=10) << 1;
+            format!("{} {}, [{}, #{:#x}]", op, reg(rd), reg(rs), offset)
+        }
+        // SP-relative load / store
+        0b10010 | 0b10011 => {
+            let op = if insn.get_bit(11) { "ldr" } else { "str" };
+            format!("{} {}, [sp, #{:#x}]", op, reg(rd8), imm8 << 2)
+        }
+        // load address
+        0b10100 | 0b10101 => {
+            let base = if insn.get_bit(11) { "sp" } else { "pc" };
+            format!("add {}, {}, #{:#x}", reg(rd8), base, imm8 << 2)
+        }
+        0b10110 | 0b10111 => match insn.get_bits(9..=10) {
+            // add offset to stack pointer
+            0b00 if insn.get_bits(8..=11) == 0 => {
+                let op = if insn.get_bit(7) { "sub" } else { "add" };
+                format!("{} sp, #{:#x}", op, (insn & 0x7f) << 2)
+            }
+            // push / pop registers
+            0b10 => {
+                let pop = insn.get_bit(11);
+                let mut list = imm8;
+                if insn.get_bit(8) {
+                    list |= 1 << if pop { 15 } else { 14 };
+                }
+                format!("{} {}", if pop { "pop" } else { "push" }, reg_list(list))
+            }
+            _ => format!("<undefined {:#06x}>", insn),
+        },
+        // multiple load / store
+        0b11000 | 0b11001 => {
+            let op = if insn.get_bit(11) { "ldmia" } else { "stmia" };
+            format!("{} {}!, {}", op, reg(rd8), reg_list(imm8))
+        }
+        // conditional branch / software interrupt
+        0b11010 | 0b11011 => match insn.get_bits(8..=11) {
+            0b1111 => format!("swi {:#x}", imm8),
+            0b1110 => format!("<undefined {:#06x}>", insn),
+            cond => {
+                let target = pc.wrapping_add(4).wrapping_add((sext(imm8, 8) << 1) as u32);
+                format!("b{} {:#010x}", COND[cond as usize], target)
+            }
+        },
+        // unconditional branch
+        0b11100 => {
+            let target = pc
+                .wrapping_add(4)
+                .wrapping_add((sext(insn & 0x7ff, 11) << 1) as u32);
+            format!("b {:#010x}", target)
+        }
+        // long branch with link (first half)
+        0b11110 => {
+            let target = pc
+                .wrapping_add(4)
+                .wrapping_add((sext(insn & 0x7ff, 11) << 12) as u32);
+            format!("bl (1/2) lr = {:#010x}", target)
+        }
+        // long branch with link (second half)
+        0b11111 => format!("bl (2/2) pc = lr + {:#x}", (insn & 0x7ff) << 1),
+        _ => format!("<undefined {:#06x}>", insn),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arm() {
+        const PC: u32 = 0x1000;
+        #[rustfmt::skip]
+        let cases: &[(u32, &str)] = &[
+            // branch and exchange
+            (0xe12f_ff1e, "bx lr"),
+            // multiply (accumulate)
+            (0xe001_0392, "mul r1, r2, r3"),
+            (0xe031_0392, "mlas r1, r2, r3, r0"),
+            // multiply (accumulate) long
+            (0xe082_1493, "umull r1, r2, r3, r4"),
+            (0xe0e2_1493, "smlal r1, r2, r3, r4"),
+            // single data swap
+            (0xe102_0091, "swp r0, r1, [r2]"),
+            (0xe142_0091, "swpb r0, r1, [r2]"),
+            // halfword / signed data transfer
+            (0xe1d1_00b2, "ldrh r0, [r1, #0x2]"),
+            (0xe0c1_00b2, "strh r0, [r1], #0x2"),
+            (0xe191_00d2, "ldrsb r0, [r1, r2]"),
+            (0xe131_00f2, "ldrsh r0, [r1, -r2]!"),
+            // PSR transfer
+            (0xe10f_0000, "mrs r0, cpsr"),
+            (0xe14f_1000, "mrs r1, spsr"),
+            (0xe129_f000, "msr cpsr_cf, r0"),
+            (0xe328_f202, "msr cpsr_f, #0x20000000"),
+            // data processing
+            (0xe1a0_0000, "mov r0, r0"),
+            (0xe3a0_1c01, "mov r1, #0x100"),
+            (0x1091_2003, "addnes r2, r1, r3"),
+            (0xe153_0004, "cmp r3, r4"),
+            (0xe1a0_1102, "mov r1, r2, lsl #2"),
+            (0xe1a0_1022, "mov r1, r2, lsr #32"),
+            (0xe1a0_1062, "mov r1, r2, rrx"),
+            (0xe1a0_1332, "mov r1, r2, lsr r3"),
+            (0xe3c0_00ff, "bic r0, r0, #0xff"),
+            // single data transfer
+            (0xe591_2004, "ldr r2, [r1, #0x4]"),
+            (0xe5b1_2004, "ldr r2, [r1, #0x4]!"),
+            (0xe491_2004, "ldr r2, [r1], #0x4"),
+            (0xe4b1_2004, "ldrt r2, [r1], #0x4"),
+            (0xe501_2004, "str r2, [r1, #-0x4]"),
+            (0xe5c1_2000, "strb r2, [r1, #0x0]"),
+            (0xe791_2103, "ldr r2, [r1, r3, lsl #2]"),
+            (0xe59f_0008, "ldr r0, [pc, #0x8] ; 0x00001010"),
+            (0xe51f_0008, "ldr r0, [pc, #-0x8] ; 0x00001000"),
+            (0xe600_0010, "<undefined 0xe6000010>"),
+            // block data transfer
+            (0xe8bd_8010, "ldmia sp!, {r4, pc}"),
+            (0xe92d_4010, "stmdb sp!, {r4, lr}"),
+            (0xe9d0_000f, "ldmib r0, {r0, r1, r2, r3}^"),
+            // branch (with link)
+            (0xeaff_fffe, "b 0x00001000"),
+            (0xebff_fffe, "bl 0x00001000"),
+            (0x0a00_0000, "beq 0x00001008"),
+            (0xea00_0010, "b 0x00001048"),
+            // coprocessor data transfer
+            (0xed91_1201, "ldc p2, c1, [r1, #0x4]"),
+            // coprocessor data operation / register transfer
+            (0xee01_2343, "cdp p3, 0, c2, c1, c3, 2"),
+            (0xee07_0f9a, "mcr p15, 0, r0, c7, c10, 4"),
+            (0xee11_0f10, "mrc p15, 0, r0, c1, c0, 0"),
+            // software interrupt
+            (0xef12_3456, "swi 0x123456"),
+        ];
+
+        for &(insn, expected) in cases {
+            assert_eq!(disasm_arm(insn, PC), expected, "{:#010x}", insn);
+        }
+    }
+
+    #[test]
+    fn thumb() {
+        const PC: u32 = 0x2000;
+        #[rustfmt::skip]
+        let cases: &[(u16, &str)] = &[
+            // move shifted register
+            (0x0088, "lsls r0, r1, #2"),
+            (0x0808, "lsrs r0, r1, #32"),
+            // add / subtract
+            (0x1888, "adds r0, r1, r2"),
+            (0x1e48, "subs r0, r1, #1"),
+            // move / compare / add / subtract immediate
+            (0x2005, "movs r0, #0x5"),
+            (0x2aff, "cmp r2, #0xff"),
+            // ALU operations
+            (0x4008, "ands r0, r1"),
+            (0x4351, "muls r1, r2"),
+            // hi register operations / branch exchange
+            (0x46c0, "mov r8, r8"),
+            (0x4485, "add sp, r0"),
+            (0x4770, "bx lr"),
+            // PC-relative load
+            (0x4801, "ldr r0, [pc, #0x4] ; 0x00002008"),
+            // load / store with register offset
+            (0x5888, "ldr r0, [r1, r2]"),
+            (0x5e88, "ldrsh r0, [r1, r2]"),
+            // load / store with immediate offset
+            (0x6848, "ldr r0, [r1, #0x4]"),
+            (0x7048, "strb r0, [r1, #0x1]"),
+            // load / store halfword
+            (0x8848, "ldrh r0, [r1, #0x2]"),
+            // SP-relative load / store
+            (0x9001, "str r0, [sp, #0x4]"),
+            // load address
+            (0xa801, "add r0, sp, #0x4"),
+            (0xa001, "add r0, pc, #0x4"),
+            // add offset to stack pointer
+            (0xb082, "sub sp, #0x8"),
+            // push / pop registers
+            (0xb510, "push {r4, lr}"),
+            (0xbd10, "pop {r4, pc}"),
+            // multiple load / store
+            (0xc806, "ldmia r0!, {r1, r2}"),
+            // conditional branch / software interrupt
+            (0xd0fe, "beq 0x00002000"),
+            (0xdf12, "swi 0x12"),
+            (0xde00, "<undefined 0xde00>"),
+            // unconditional branch
+            (0xe7fe, "b 0x00002000"),
+            // long branch with link
+            (0xf7ff, "bl (1/2) lr = 0x00001004"),
+            (0xfffe, "bl (2/2) pc = lr + 0xffc"),
+        ];
+
+        for &(insn, expected) in cases {
+            assert_eq!(disasm_thumb(insn, PC), expected, "{:#06x}", insn);
+        }
+    }
+}
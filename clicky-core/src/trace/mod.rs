@@ -0,0 +1,475 @@
+//! Instruction execution traces.
+//!
+//! A [Tracer] records every instruction executed by the system (optionally
+//! filtered by PC range) to a compact binary trace file, which can later be
+//! read back using a [TraceReader].
+//!
+//! # Format
+//!
+//! All values are little-endian. The file starts with an 8 byte magic
+//! (`CLKYTRC\0`) followed by a `u16` format version. What follows is a stream
+//! of records, one per executed instruction:
+//!
+//! ```text
+//! u8      flags        bit 0: core (0 = CPU, 1 = COP), bit 1: thumb
+//! u32     pc
+//! u16/u32 instruction  (u16 in thumb mode, u32 otherwise)
+//! u8      num_regs
+//! [u8 reg, u32 val]    registers changed by the instruction (PC excluded)
+//! u16     num_mmio
+//! [u8 kind, u32 addr, u8/u16/u32 val]
+//!                      MMIO accesses. kind bit 0: write, bits 1..=2: log2 size
+//! ```
+
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+
+use crate::devices::platform::pp::common::CpuId;
+use crate::memory::{MemAccess, MemAccessKind, MemAccessVal};
+
+pub mod disasm;
+
+const MAGIC: &[u8; 8] = b"CLKYTRC\0";
+const VERSION: u16 = 2;
+
+/// Number of general-purpose registers + CPSR tracked in each record
+pub const NUM_REGS: usize = 17;
+
+/// A register index, as used by [armv4t_emu::reg]
+const REG_CPSR: u8 = 16;
+const REG_PC: u8 = 15;
+
+/// A single executed instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub core: CpuId,
+    pub thumb: bool,
+    pub pc: u32,
+    pub insn: u32,
+    /// Registers modified by the instruction (PC excluded), with their new
+    /// values. Index 16 corresponds to the CPSR.
+    pub regs: Vec<(u8, u32)>,
+    /// MMIO accesses performed by the instruction.
+    pub mmio: Vec<MemAccess>,
+}
+
+impl TraceRecord {
+    /// Disassemble the record's instruction.
+    pub fn disasm(&self) -> String {
+        match self.thumb {
+            true => disasm::disasm_thumb(self.insn as u16, self.pc),
+            false => disasm::disasm_arm(self.insn, self.pc),
+        }
+    }
+
+    fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        let mut flags = 0u8;
+        if self.core == CpuId::Cop {
+            flags |= 1 << 0;
+        }
+        if self.thumb {
+            flags |= 1 << 1;
+        }
+
+        w.write_u8(flags)?;
+        w.write_u32::<LE>(self.pc)?;
+        match self.thumb {
+            true => w.write_u16::<LE>(self.insn as u16)?,
+            false => w.write_u32::<LE>(self.insn)?,
+        }
+
+        // at most NUM_REGS registers can change
+        w.write_u8(self.regs.len() as u8)?;
+        for &(reg, val) in &self.regs {
+            w.write_u8(reg)?;
+            w.write_u32::<LE>(val)?;
+        }
+
+        if self.mmio.len() > u16::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("too many MMIO accesses in one record ({})", self.mmio.len()),
+            ));
+        }
+        w.write_u16::<LE>(self.mmio.len() as u16)?;
+        for access in &self.mmio {
+            let (size, val) = match access.val {
+                MemAccessVal::U8(v) => (0u8, v as u32),
+                MemAccessVal::U16(v) => (1, v as u32),
+                MemAccessVal::U32(v) => (2, v),
+            };
+            w.write_u8((access.kind == MemAccessKind::Write) as u8 | size << 1)?;
+            w.write_u32::<LE>(access.offset)?;
+            match size {
+                0 => w.write_u8(val as u8)?,
+                1 => w.write_u16::<LE>(val as u16)?,
+                _ => w.write_u32::<LE>(val)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read(r: &mut impl Read) -> io::Result<Option<TraceRecord>> {
+        let flags = match r.read_u8() {
+            Ok(flags) => flags,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let core = match flags & 1 {
+            0 => CpuId::Cpu,
+            _ => CpuId::Cop,
+        };
+        let thumb = flags & (1 << 1) != 0;
+        let pc = r.read_u32::<LE>()?;
+        let insn = match thumb {
+            true => r.read_u16::<LE>()? as u32,
+            false => r.read_u32::<LE>()?,
+        };
+
+        let num_regs = r.read_u8()?;
+        let mut regs = Vec::with_capacity(num_regs as usize);
+        for _ in 0..num_regs {
+            let reg = r.read_u8()?;
+            let val = r.read_u32::<LE>()?;
+            regs.push((reg, val));
+        }
+
+        let num_mmio = r.read_u16::<LE>()?;
+        let mut mmio = Vec::with_capacity(num_mmio as usize);
+        for _ in 0..num_mmio {
+            let kind = r.read_u8()?;
+            let offset = r.read_u32::<LE>()?;
+            let val = match kind >> 1 {
+                0 => MemAccessVal::U8(r.read_u8()?),
+                1 => MemAccessVal::U16(r.read_u16::<LE>()?),
+                2 => MemAccessVal::U32(r.read_u32::<LE>()?),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "invalid MMIO access size",
+                    ))
+                }
+            };
+            mmio.push(MemAccess {
+                kind: match kind & 1 {
+                    0 => MemAccessKind::Read,
+                    _ => MemAccessKind::Write,
+                },
+                offset,
+                val,
+            });
+        }
+
+        Ok(Some(TraceRecord {
+            core,
+            thumb,
+            pc,
+            insn,
+            regs,
+            mmio,
+        }))
+    }
+}
+
+impl std::fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let core = match self.core {
+            CpuId::Cpu => "cpu",
+            CpuId::Cop => "cop",
+        };
+        let insn = match self.thumb {
+            true => format!("    {:04x}", self.insn),
+            false => format!("{:08x}", self.insn),
+        };
+        write!(
+            f,
+            "[{}] {:08x}: {}  {:<40}",
+            core,
+            self.pc,
+            insn,
+            self.disasm()
+        )?;
+
+        for &(reg, val) in &self.regs {
+            match reg {
+                REG_CPSR => write!(f, " cpsr={:08x}", val)?,
+                13 => write!(f, " sp={:08x}", val)?,
+                14 => write!(f, " lr={:08x}", val)?,
+                reg => write!(f, " r{}={:08x}", reg, val)?,
+            }
+        }
+
+        for access in &self.mmio {
+            write!(f, " | {}", access)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Records executed instructions to a binary trace file.
+pub struct Tracer {
+    w: Box<dyn Write + Send>,
+    enabled: bool,
+    /// Only instructions within these ranges are recorded. Empty == record
+    /// everything.
+    filter: Vec<RangeInclusive<u32>>,
+}
+
+impl std::fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tracer")
+            .field("w", &"<dyn Write>")
+            .field("enabled", &self.enabled)
+            .field("filter", &self.filter)
+            .finish()
+    }
+}
+
+impl Tracer {
+    /// Create a new tracer which writes to `w`. Tracing starts off enabled.
+    pub fn new(mut w: Box<dyn Write + Send>) -> io::Result<Tracer> {
+        w.write_all(MAGIC)?;
+        w.write_u16::<LE>(VERSION)?;
+        Ok(Tracer {
+            w,
+            enabled: true,
+            filter: Vec::new(),
+        })
+    }
+
+    /// Check if tracing is currently enabled.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Pause / resume tracing.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Return the current PC filter.
+    pub fn filter(&self) -> &[RangeInclusive<u32>] {
+        &self.filter
+    }
+
+    /// Only record instructions whose PC falls within one of the specified
+    /// ranges. An empty filter records all instructions.
+    pub fn set_filter(&mut self, filter: Vec<RangeInclusive<u32>>) {
+        self.filter = filter;
+    }
+
+    /// Check if an instruction at the specified PC should be recorded.
+    pub fn wants(&self, pc: u32) -> bool {
+        self.enabled && (self.filter.is_empty() || self.filter.iter().any(|r| r.contains(&pc)))
+    }
+
+    /// Append a record to the trace.
+    pub fn record(&mut self, record: &TraceRecord) -> io::Result<()> {
+        record.write(&mut self.w)
+    }
+
+    /// Flush any buffered records.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        let _ = self.w.flush();
+    }
+}
+
+/// Reads records from a binary trace file.
+pub struct TraceReader<R: Read> {
+    r: R,
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Validate the trace's header, and return a new reader.
+    pub fn new(mut r: R) -> io::Result<TraceReader<R>> {
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a clicky trace file",
+            ));
+        }
+
+        let version = r.read_u16::<LE>()?;
+        if version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "unsupported trace version {} (expected {})",
+                    version, VERSION
+                ),
+            ));
+        }
+
+        Ok(TraceReader { r })
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<io::Result<TraceRecord>> {
+        TraceRecord::read(&mut self.r).transpose()
+    }
+}
+
+/// Parse a `<start>-<end>` PC range (inclusive). Addresses may be specified in
+/// decimal, or in hex with a `0x` prefix.
+pub fn parse_pc_range(s: &str) -> Result<RangeInclusive<u32>, String> {
+    fn parse_addr(s: &str) -> Result<u32, String> {
+        let res = match s.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => s.parse(),
+        };
+        res.map_err(|e| format!("couldn't parse '{}': {}", s, e))
+    }
+
+    let mut s = s.splitn(2, '-');
+    let start = parse_addr(s.next().unwrap())?;
+    let end = parse_addr(s.next().ok_or("expected <start>-<end>")?)?;
+    if end < start {
+        return Err("range end is before range start".into());
+    }
+    Ok(start..=end)
+}
+
+/// Compute the registers which differ between two register snapshots (PC
+/// excluded).
+pub fn reg_deltas(before: &[u32; NUM_REGS], after: &[u32; NUM_REGS]) -> Vec<(u8, u32)> {
+    (0..NUM_REGS as u8)
+        .filter(|&i| i != REG_PC && before[i as usize] != after[i as usize])
+        .map(|i| (i, after[i as usize]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    /// Buffer which outlives the tracer writing to it.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn records() -> Vec<TraceRecord> {
+        vec![
+            TraceRecord {
+                core: CpuId::Cpu,
+                thumb: false,
+                pc: 0x1000_0000,
+                insn: 0xe591_2004,
+                regs: vec![(2, 0xdead_beef)],
+                mmio: vec![MemAccess {
+                    kind: MemAccessKind::Read,
+                    offset: 0x6000_5010,
+                    val: MemAccessVal::U32(0xdead_beef),
+                }],
+            },
+            TraceRecord {
+                core: CpuId::Cop,
+                thumb: true,
+                pc: 0x4000_0102,
+                insn: 0x8048,
+                regs: vec![(0, 1), (REG_CPSR, 0x6000_003f)],
+                mmio: vec![
+                    MemAccess {
+                        kind: MemAccessKind::Write,
+                        offset: 0x7000_6000,
+                        val: MemAccessVal::U8(0x41),
+                    },
+                    MemAccess {
+                        kind: MemAccessKind::Read,
+                        offset: 0x7000_6002,
+                        val: MemAccessVal::U16(0x1234),
+                    },
+                ],
+            },
+            TraceRecord {
+                core: CpuId::Cpu,
+                thumb: false,
+                pc: 0,
+                insn: 0xe1a0_0000,
+                regs: Vec::new(),
+                mmio: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn record_round_trip() {
+        let buf = SharedBuf::default();
+        let mut tracer = Tracer::new(Box::new(buf.clone())).unwrap();
+        for record in records() {
+            tracer.record(&record).unwrap();
+        }
+        drop(tracer);
+
+        let buf = buf.0.lock().unwrap();
+        let read = TraceReader::new(buf.as_slice())
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read, records());
+    }
+
+    #[test]
+    fn many_mmio_accesses() {
+        let mut record = records().remove(0);
+        record.mmio = (0..300)
+            .map(|i| MemAccess {
+                kind: MemAccessKind::Write,
+                offset: 0x7000_0000 + i * 4,
+                val: MemAccessVal::U32(i),
+            })
+            .collect();
+
+        let mut buf = Vec::new();
+        record.write(&mut buf).unwrap();
+        let read = TraceRecord::read(&mut buf.as_slice()).unwrap().unwrap();
+        assert_eq!(read, record);
+
+        record.mmio = vec![record.mmio[0]; u16::MAX as usize + 1];
+        let err = record.write(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let err = TraceReader::new(&b"CLKYSNP\0\x02\x00"[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = TraceReader::new(&b"CLKYTRC\0\x01\x00"[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_record() {
+        let mut buf = Vec::new();
+        records()[1].write(&mut buf).unwrap();
+        buf.pop();
+        let err = TraceRecord::read(&mut buf.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
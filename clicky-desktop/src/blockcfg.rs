@@ -8,6 +8,23 @@ pub enum BlockCfg {
     Raw { path: String },
     /// `mem:file=/path/[,truncate=<len>]`
     Mem { path: String, truncate: Option<u64> },
+    /// `qcow2:file=/path/`
+    Qcow2 { path: String },
+    /// `overlay:base=/path/,delta=/path/[,on-exit=<commit|discard>]`
+    Overlay {
+        base: String,
+        delta: String,
+        on_exit: Option<OverlayExitAction>,
+    },
+}
+
+/// What to do with an overlay's delta file once the emulator exits.
+#[derive(Debug, Copy, Clone)]
+pub enum OverlayExitAction {
+    /// Write the changes back into the base image.
+    Commit,
+    /// Throw away the changes.
+    Discard,
 }
 
 fn parse_capacity(desc: &str) -> Option<u64> {
@@ -100,6 +117,61 @@ impl FromStr for BlockCfg {
                     truncate,
                 }
             }
+            "qcow2" => {
+                let s = s.next().ok_or("missing required options")?.split(',');
+
+                let mut file = None;
+
+                for arg in s {
+                    let mut s = arg.split('=');
+                    let kind = s.next().unwrap();
+                    match kind {
+                        "file" => {
+                            file = Some(s.next().ok_or("missing argument for `file`")?.into())
+                        }
+                        _ => return Err("unknown `qcow2` option"),
+                    }
+                }
+
+                BlockCfg::Qcow2 {
+                    path: file.ok_or("missing `file` parameter")?,
+                }
+            }
+            "overlay" => {
+                let s = s.next().ok_or("missing required options")?.split(',');
+
+                let mut base = None;
+                let mut delta = None;
+                let mut on_exit = None;
+
+                for arg in s {
+                    let mut s = arg.split('=');
+                    let kind = s.next().unwrap();
+                    match kind {
+                        "base" => {
+                            base = Some(s.next().ok_or("missing argument for `base`")?.into())
+                        }
+                        "delta" => {
+                            delta = Some(s.next().ok_or("missing argument for `delta`")?.into())
+                        }
+                        "on-exit" => {
+                            on_exit =
+                                Some(match s.next().ok_or("missing argument for `on-exit`")? {
+                                    "commit" => OverlayExitAction::Commit,
+                                    "discard" => OverlayExitAction::Discard,
+                                    _ => return Err("could not parse `on-exit`"),
+                                })
+                        }
+                        _ => return Err("unknown `overlay` option"),
+                    }
+                }
+
+                BlockCfg::Overlay {
+                    base: base.ok_or("missing `base` parameter")?,
+                    delta: delta.ok_or("missing `delta` parameter")?,
+                    on_exit,
+                }
+            }
             _ => return Err("invalid block kind"),
         })
     }
@@ -1 +1 @@
-pub mod ipod4g;
+pub mod pp;
@@ -0,0 +1,29 @@
+use clicky_core::sys::pp::{PpBinds, PpKey};
+use minifb::Key;
+
+use crate::backends::minifb::MinifbControls;
+
+fn pp_key_to_minifb(key: PpKey) -> Key {
+    match key {
+        PpKey::Up => Key::Up,
+        PpKey::Down => Key::Down,
+        PpKey::Left => Key::Left,
+        PpKey::Right => Key::Right,
+        PpKey::Action => Key::Enter,
+        PpKey::Hold => Key::H,
+    }
+}
+
+impl From<PpBinds> for MinifbControls {
+    fn from(binds: PpBinds) -> MinifbControls {
+        let PpBinds { keys, wheel } = binds;
+
+        MinifbControls {
+            keymap: keys
+                .into_iter()
+                .map(|(k, v)| (pp_key_to_minifb(k), v))
+                .collect(),
+            on_scroll: wheel,
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clicky_core::coverage::CoverageOpts;
+
+/// Helper struct to parse code coverage configurations.
+///
+/// `/path/to/coverage[,format=<drcov|lcov>][,core=<cpu|cop>][,module=<name>][,base=<addr>]`
+#[derive(Debug, Clone)]
+pub struct CoverageCfg {
+    pub path: PathBuf,
+    pub opts: CoverageOpts,
+}
+
+impl FromStr for CoverageCfg {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<CoverageCfg, &'static str> {
+        let mut s = s.split(',');
+        let path = s.next().unwrap();
+        if path.is_empty() {
+            return Err("missing path");
+        }
+
+        let mut opts = CoverageOpts::default();
+        for opt in s {
+            opts.parse_opt(opt)?;
+        }
+
+        Ok(CoverageCfg {
+            path: path.into(),
+            opts,
+        })
+    }
+}
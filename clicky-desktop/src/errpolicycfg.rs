@@ -0,0 +1,63 @@
+use clicky_core::error::{ErrorAction, ErrorClass, ErrorPolicy, ErrorScope};
+use clicky_core::trace::parse_pc_range;
+
+/// Helper to parse error policy configurations.
+///
+/// `[<preset>][,<rule>...]`, where `<preset>` is one of `default`, `strict`, or
+/// `permissive`, and `<rule>` is `<class>[@<scope>]=<action>`:
+///
+/// - `<class>`: `stub`, `unimplemented`, `contract`, or `*`
+/// - `<scope>`: a device kind (e.g: `IDE`), or an address range (e.g:
+///   `0x70000000-0x70000fff`)
+/// - `<action>`: `default`, `ignore`, `log`, `count`, or `fatal`
+pub fn parse_error_policy(s: &str) -> Result<ErrorPolicy, String> {
+    let mut s = s.split(',').peekable();
+
+    let mut policy = match s.peek() {
+        Some(preset) if !preset.contains('=') => {
+            let policy = match *preset {
+                "" | "default" => ErrorPolicy::new(),
+                "strict" => ErrorPolicy::strict(),
+                "permissive" => ErrorPolicy::permissive(),
+                other => return Err(format!("unknown error policy preset `{}`", other)),
+            };
+            s.next();
+            policy
+        }
+        _ => ErrorPolicy::new(),
+    };
+
+    for rule in s {
+        let mut rule_s = rule.splitn(2, '=');
+        let target = rule_s.next().unwrap();
+        let action = match rule_s.next() {
+            Some("default") => ErrorAction::Default,
+            Some("ignore") => ErrorAction::Ignore,
+            Some("log") => ErrorAction::Log,
+            Some("count") => ErrorAction::Count,
+            Some("fatal") => ErrorAction::Fatal,
+            Some(other) => return Err(format!("unknown error policy action `{}`", other)),
+            None => return Err(format!("missing action for rule `{}`", rule)),
+        };
+
+        let mut target = target.splitn(2, '@');
+        let class = match target.next().unwrap() {
+            "*" => None,
+            "stub" => Some(ErrorClass::Stub),
+            "unimplemented" => Some(ErrorClass::Unimplemented),
+            "contract" => Some(ErrorClass::ContractViolation),
+            other => return Err(format!("unknown error class `{}`", other)),
+        };
+        let scope = match target.next() {
+            None => ErrorScope::Any,
+            Some(scope) if scope.starts_with(|c: char| c.is_ascii_digit()) => {
+                ErrorScope::Range(parse_pc_range(scope)?)
+            }
+            Some(device) => ErrorScope::Device(device.to_string()),
+        };
+
+        policy.add_rule(class, scope, action);
+    }
+
+    Ok(policy)
+}
@@ -6,7 +6,13 @@ use std::os::unix::net::{UnixListener, UnixStream};
 
 use crate::DynResult;
 
-use gdbstub::{Connection, GdbStub};
+use clicky_core::error::FatalMemException;
+use clicky_core::sys::pp::{PpBoard, PpGdb, PpGdbEventLoop};
+use gdbstub::conn::ConnectionExt;
+use gdbstub::stub::{DisconnectReason, GdbStub};
+
+/// A connection to a GDB client.
+pub type GdbConn = Box<dyn ConnectionExt<Error = std::io::Error>>;
 
 /// GDB server configuration. Typically instantiated via StructOpt.
 #[derive(Debug, Clone)]
@@ -89,14 +95,9 @@ fn wait_for_uds(path: PathBuf) -> std::io::Result<UnixStream> {
     Ok(stream)
 }
 
-pub fn make_gdbstub<'a, T>(
-    cfg: GdbCfg,
-) -> DynResult<GdbStub<'a, T, Box<dyn Connection<Error = std::io::Error>>>>
-where
-    T: gdbstub::target::Target,
-    T::Error: 'a,
-{
-    let connection: Box<dyn Connection<Error = std::io::Error>> = match cfg.kind {
+/// Wait for a GDB client to connect.
+pub fn make_gdb_conn(cfg: GdbCfg) -> DynResult<GdbConn> {
+    let connection: GdbConn = match cfg.kind {
         ConnKind::Tcp(port) => Box::new(wait_for_tcp(port)?),
         ConnKind::Uds(path) => {
             #[cfg(not(unix))]
@@ -111,5 +112,22 @@ where
         }
     };
 
-    Ok(GdbStub::new(connection))
+    Ok(connection)
+}
+
+/// Run a GDB session over an existing connection, until the client
+/// disconnects (or the system exits). Fatal errors encountered by the system
+/// are returned separately from errors in the GDB session itself.
+pub fn run_gdbstub<B: PpBoard>(
+    conn: &mut GdbConn,
+    system_gdb: &mut PpGdb<B>,
+) -> DynResult<Result<DisconnectReason, FatalMemException>> {
+    let conn: &mut dyn ConnectionExt<Error = std::io::Error> = conn.as_mut();
+    match GdbStub::new(conn).run_blocking::<PpGdbEventLoop<B, _>>(system_gdb) {
+        Ok(reason) => Ok(Ok(reason)),
+        Err(e) => match e.is_target_error() {
+            true => Ok(Err(e.into_target_error().unwrap())),
+            false => Err(format!("GDB session error: {:?}", e).into()),
+        },
+    }
 }
@@ -0,0 +1,337 @@
+//! Headless, scriptable test runner.
+//!
+//! Scripts are plain-text files with one command per line. Blank lines and
+//! lines starting with `#` are ignored.
+//!
+//! ```text
+//! run <cycles>                             run the system for some cycles
+//! press <key>                              press and hold a key
+//! release <key>                            release a held key
+//! tap <key> [<cycles>]                     press, run (default: 100000), release
+//! scroll <dy>                              scroll the clickwheel
+//! screenshot <path.png>                    dump the screen to a PNG
+//! expect-screen <path.png>                 compare the screen to a golden PNG
+//! run-until-screen <path.png> <max-cycles> run until the screen matches a PNG
+//! ```
+//!
+//! Valid keys are `up`, `down`, `left`, `right`, `action`, and `hold`.
+//!
+//! Key and scroll commands are ignored while replaying recorded input (see
+//! `--replay-input`).
+//!
+//! Any audio output is written to the WAV sink (if provided) after every
+//! command.
+//!
+//! When a screen comparison fails, the actual screen contents are dumped
+//! alongside the golden image (as `<path>.actual.png`).
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clicky_core::gui::{ButtonCallback, RenderCallback};
+use clicky_core::sys::pp::{ExitReason, PpBinds, PpBoard, PpKey, PpSystem};
+
+use crate::wav::WavSink;
+use crate::{sysdump, DynResult, ExitReports, SYSDUMP_FILENAME};
+
+/// Number of cycles to run between screen checks in `run-until-screen`.
+const SCREEN_POLL_CYCLES: usize = 100_000;
+
+/// Default number of cycles to hold a key for in `tap`.
+const DEFAULT_TAP_CYCLES: usize = 100_000;
+
+#[derive(Debug)]
+enum Cmd {
+    Run(usize),
+    Press(PpKey),
+    Release(PpKey),
+    Tap(PpKey, usize),
+    Scroll(f32),
+    Screenshot(PathBuf),
+    ExpectScreen(PathBuf),
+    RunUntilScreen(PathBuf, usize),
+}
+
+fn parse_key(s: &str) -> Result<PpKey, String> {
+    s.parse().map_err(|_| format!("unknown key `{}`", s))
+}
+
+fn parse_cmd(line: &str) -> Result<Cmd, String> {
+    let mut args = line.split_whitespace();
+    let cmd = args.next().unwrap();
+    let mut arg = |name: &str| {
+        args.next()
+            .ok_or_else(|| format!("missing argument <{}> for `{}`", name, cmd))
+    };
+
+    fn num(s: &str) -> Result<usize, String> {
+        s.parse().map_err(|_| format!("could not parse `{}`", s))
+    }
+
+    let cmd = match cmd {
+        "run" => Cmd::Run(num(arg("cycles")?)?),
+        "press" => Cmd::Press(parse_key(arg("key")?)?),
+        "release" => Cmd::Release(parse_key(arg("key")?)?),
+        "tap" => {
+            let key = parse_key(arg("key")?)?;
+            let cycles = match arg("cycles") {
+                Ok(s) => num(s)?,
+                Err(_) => DEFAULT_TAP_CYCLES,
+            };
+            Cmd::Tap(key, cycles)
+        }
+        "scroll" => {
+            let dy = arg("dy")?;
+            Cmd::Scroll(
+                dy.parse()
+                    .map_err(|_| format!("could not parse `{}`", dy))?,
+            )
+        }
+        "screenshot" => Cmd::Screenshot(arg("path")?.into()),
+        "expect-screen" => Cmd::ExpectScreen(arg("path")?.into()),
+        "run-until-screen" => {
+            let path = arg("path")?.into();
+            Cmd::RunUntilScreen(path, num(arg("max-cycles")?)?)
+        }
+        _ => return Err(format!("unknown command `{}`", cmd)),
+    };
+
+    if let Some(extra) = args.next() {
+        return Err(format!("unexpected argument `{}`", extra));
+    }
+
+    Ok(cmd)
+}
+
+fn parse_script(script: &str) -> Result<Vec<(usize, Cmd)>, String> {
+    script
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(lineno, line)| {
+            parse_cmd(line)
+                .map(|cmd| (lineno, cmd))
+                .map_err(|e| format!("line {}: {}", lineno, e))
+        })
+        .collect()
+}
+
+/// An RGB8 screen capture.
+#[derive(PartialEq, Eq)]
+struct Screen {
+    width: usize,
+    height: usize,
+    rgb: Vec<u8>,
+}
+
+impl Screen {
+    fn capture(
+        (width, height): (usize, usize),
+        update_fb: &mut RenderCallback,
+        buf: &mut Vec<u32>,
+    ) -> Screen {
+        let (w, _h) = update_fb(buf);
+
+        // crop the emulated buffer
+        let rgb = buf
+            .chunks_exact(w)
+            .take(height)
+            .flat_map(|row| row.iter().take(width))
+            .flat_map(|argb| {
+                let [_a, r, g, b] = argb.to_be_bytes();
+                vec![r, g, b]
+            })
+            .collect();
+
+        Screen { width, height, rgb }
+    }
+
+    fn load_png(path: &Path) -> DynResult<Screen> {
+        let decoder = png::Decoder::new(fs::File::open(path)?);
+        let (info, mut reader) = decoder.read_info()?;
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data)?;
+
+        let rgb = match reader.output_color_type() {
+            (png::ColorType::RGB, png::BitDepth::Eight) => data,
+            (png::ColorType::Grayscale, png::BitDepth::Eight) => {
+                data.iter().flat_map(|&l| vec![l, l, l]).collect()
+            }
+            other => return Err(format!("unsupported PNG format: {:?}", other).into()),
+        };
+
+        Ok(Screen {
+            width: info.width as usize,
+            height: info.height as usize,
+            rgb,
+        })
+    }
+
+    fn save_png(&self, path: &Path) -> DynResult<()> {
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.rgb)?;
+        Ok(())
+    }
+
+    /// Returns the number of mismatched pixels.
+    fn diff(&self, other: &Screen) -> usize {
+        if (self.width, self.height) != (other.width, other.height) {
+            return self.width * self.height;
+        }
+
+        (self.rgb.chunks_exact(3))
+            .zip(other.rgb.chunks_exact(3))
+            .filter(|(a, b)| a != b)
+            .count()
+    }
+}
+
+fn actual_path(golden: &Path) -> PathBuf {
+    let mut path = golden.as_os_str().to_owned();
+    path.push(".actual.png");
+    path.into()
+}
+
+/// Press / release a key. Keys without binds (e.g: while replaying recorded
+/// input) are ignored.
+fn press(keys: &mut HashMap<PpKey, ButtonCallback>, key: PpKey, pressed: bool) {
+    match keys.get_mut(&key) {
+        Some(cb) => cb(pressed),
+        None => debug!("ignoring input to {:?}", key),
+    }
+}
+
+/// Run the system for some cycles, dumping the system state on fatal errors.
+/// Returns early if the system exits.
+fn run_cycles<B: PpBoard>(
+    system: &mut PpSystem<B>,
+    cycles: usize,
+    exit_reports: &ExitReports,
+) -> DynResult<Option<ExitReason>> {
+    match system.run_cycles(cycles) {
+        Ok(exit) => Ok(exit),
+        Err(fatal_error) => {
+            exit_reports.write(system);
+            error!("Fatal Error! Caused by: {:#010x?}", fatal_error);
+            error!("Dumping system state to {}", SYSDUMP_FILENAME);
+            fs::write(SYSDUMP_FILENAME, sysdump(system))?;
+            Err("system encountered a fatal error".into())
+        }
+    }
+}
+
+/// Run a headless test script, returning the exit code the emulator should
+/// exit with (i.e: `1` if any screen comparisons failed, or the guest's exit
+/// code if it exited before the end of the script).
+pub fn run_script<B: PpBoard>(
+    mut system: PpSystem<B>,
+    controls: PpBinds,
+    script: &Path,
+    mut audio_sink: Option<WavSink>,
+    exit_reports: &ExitReports,
+) -> DynResult<i32> {
+    let script = parse_script(&fs::read_to_string(script)?)?;
+
+    let mut update_fb = system.render_callback();
+    let PpBinds {
+        mut keys,
+        mut wheel,
+    } = controls;
+    let mut fb = Vec::new();
+
+    let mut passed = true;
+    let mut exit = None;
+    for (lineno, cmd) in script {
+        debug!("line {}: {:?}", lineno, cmd);
+
+        match cmd {
+            Cmd::Run(cycles) => exit = run_cycles(&mut system, cycles, exit_reports)?,
+            Cmd::Press(key) => press(&mut keys, key, true),
+            Cmd::Release(key) => press(&mut keys, key, false),
+            Cmd::Tap(key, cycles) => {
+                press(&mut keys, key, true);
+                exit = run_cycles(&mut system, cycles, exit_reports)?;
+                press(&mut keys, key, false);
+            }
+            Cmd::Scroll(dy) => {
+                if let Some(wheel) = &mut wheel {
+                    wheel((0.0, dy))
+                }
+            }
+            Cmd::Screenshot(path) => {
+                Screen::capture(B::SCREEN_DIMS, &mut update_fb, &mut fb).save_png(&path)?;
+                info!("Saved screenshot to {}", path.display());
+            }
+            Cmd::ExpectScreen(golden_path) => {
+                let golden = Screen::load_png(&golden_path)?;
+                let actual = Screen::capture(B::SCREEN_DIMS, &mut update_fb, &mut fb);
+                let mismatched = actual.diff(&golden);
+                if mismatched != 0 {
+                    let path = actual_path(&golden_path);
+                    actual.save_png(&path)?;
+                    error!(
+                        "line {}: screen doesn't match {} ({} pixels differ, see {})",
+                        lineno,
+                        golden_path.display(),
+                        mismatched,
+                        path.display()
+                    );
+                    passed = false;
+                }
+            }
+            Cmd::RunUntilScreen(golden_path, max_cycles) => {
+                let golden = Screen::load_png(&golden_path)?;
+                let mut remaining = max_cycles;
+                let mut actual = Screen::capture(B::SCREEN_DIMS, &mut update_fb, &mut fb);
+                while actual != golden && remaining != 0 && exit.is_none() {
+                    let cycles = remaining.min(SCREEN_POLL_CYCLES);
+                    exit = run_cycles(&mut system, cycles, exit_reports)?;
+                    remaining -= cycles;
+                    actual = Screen::capture(B::SCREEN_DIMS, &mut update_fb, &mut fb);
+                }
+
+                if actual != golden {
+                    let path = actual_path(&golden_path);
+                    actual.save_png(&path)?;
+                    error!(
+                        "line {}: screen didn't match {} within {} cycles (see {})",
+                        lineno,
+                        golden_path.display(),
+                        max_cycles,
+                        path.display()
+                    );
+                    passed = false;
+                } else {
+                    info!(
+                        "line {}: screen matched {} after {} cycles",
+                        lineno,
+                        golden_path.display(),
+                        max_cycles - remaining
+                    );
+                }
+            }
+        }
+
+        if let Some(audio_sink) = &mut audio_sink {
+            audio_sink.poll()?;
+        }
+
+        if let Some(exit) = exit {
+            info!("line {}: system exited ({:?})", lineno, exit);
+            break;
+        }
+    }
+
+    exit_reports.write(&system);
+    Ok(match (passed, exit) {
+        (false, _) => 1,
+        (true, Some(exit)) => exit.exit_code(),
+        (true, None) => 0,
+    })
+}
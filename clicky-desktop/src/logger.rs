@@ -0,0 +1,88 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Log filters used when `RUST_LOG` doesn't say otherwise.
+const DEFAULT_FILTERS: &str = "error,clicky=trace,MMIO=info,I2C=info,armv4t_emu=debug";
+
+struct Inner {
+    logger: RwLock<Box<dyn log::Log>>,
+    filters: Mutex<String>,
+}
+
+/// A logger whose filters can be updated at runtime (e.g: via the GDB stub's
+/// `log` command).
+struct ReloadableLogger(Arc<Inner>);
+
+impl log::Log for ReloadableLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.0.logger.read().unwrap().enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.0.logger.read().unwrap().log(record)
+    }
+
+    fn flush(&self) {
+        self.0.logger.read().unwrap().flush()
+    }
+}
+
+/// Handle used to update the global logger's filters.
+#[derive(Clone)]
+pub struct LogFilters(Arc<Inner>);
+
+fn build_logger(filters: &str) -> Box<dyn log::Log> {
+    let logger = pretty_env_logger::formatted_builder()
+        .parse_filters(filters)
+        .build();
+    log::set_max_level(logger.filter());
+    Box::new(logger)
+}
+
+/// Install the global logger, returning a handle which can be used to update
+/// its filters later on.
+pub fn init() -> LogFilters {
+    let mut filters = DEFAULT_FILTERS.to_string();
+    if let Ok(rust_log) = std::env::var("RUST_LOG") {
+        if !rust_log.is_empty() {
+            filters = format!("{},{}", filters, rust_log);
+        }
+    }
+
+    let inner = Arc::new(Inner {
+        logger: RwLock::new(build_logger(&filters)),
+        filters: Mutex::new(filters),
+    });
+
+    log::set_boxed_logger(Box::new(ReloadableLogger(inner.clone())))
+        .expect("logger was already initialized");
+
+    LogFilters(inner)
+}
+
+impl LogFilters {
+    /// Apply additional `RUST_LOG` style directives on top of the current
+    /// filters (later directives take precedence), returning the resulting
+    /// filters. Passing `None` simply returns the current filters.
+    pub fn update(&self, directives: Option<&str>) -> Result<String, String> {
+        let mut filters = self.0.filters.lock().unwrap();
+
+        let directives = match directives {
+            None => return Ok(filters.clone()),
+            Some(directives) => directives,
+        };
+
+        // env_logger silently ignores invalid directives, so validate them
+        // up-front to provide some feedback
+        for directive in directives.split(',') {
+            if let Some(level) = directive.split('=').nth(1) {
+                if level.parse::<log::LevelFilter>().is_err() {
+                    return Err(format!("invalid log level '{}'", level));
+                }
+            }
+        }
+
+        *filters = format!("{},{}", filters, directives);
+        *self.0.logger.write().unwrap() = build_logger(&filters);
+        Ok(filters.clone())
+    }
+}
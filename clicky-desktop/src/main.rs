@@ -2,7 +2,8 @@
 extern crate log;
 
 use std::fs;
-use std::io::Read;
+use std::io::{self, Read};
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 
 pub type DynResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
@@ -10,25 +11,60 @@ pub type DynResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
 use structopt::StructOpt;
 
 use clicky_core::block::{self, BlockDev};
+use clicky_core::clock::ClockMode;
+use clicky_core::coverage::Coverage;
+use clicky_core::error::ErrorPolicy;
 use clicky_core::gui::TakeControls;
-use clicky_core::sys::ipod4g::{BootKind, Ipod4g, Ipod4gGdb};
+use clicky_core::profiler::Profiler;
+use clicky_core::serial::{self, SerialBackend};
+use clicky_core::symbols::parse_elf_spec;
+use clicky_core::sys::ipod3g::Ipod3gBus;
+use clicky_core::sys::ipod4g::Ipod4gBus;
+use clicky_core::sys::ipod5g::Ipod5gBus;
+use clicky_core::sys::ipodmini1g::IpodMini1gBus;
+use clicky_core::sys::ipodmini2g::IpodMini2gBus;
+use clicky_core::sys::ipodphoto::IpodPhotoBus;
+use clicky_core::sys::pp::{AbortMode, BootKind, PpBinds, PpBoard, PpCfg, PpGdb, PpSystem};
+use clicky_core::trace::{parse_pc_range, Tracer};
 
 mod backends;
 mod blockcfg;
 mod controls;
+mod coveragecfg;
+mod errpolicycfg;
 mod gdb;
-
-use crate::blockcfg::BlockCfg;
-use crate::gdb::{make_gdbstub, GdbCfg};
+mod headless;
+mod logger;
+mod modelcfg;
+mod profilecfg;
+mod serialcfg;
+mod statecfg;
+mod wav;
+
+use crate::blockcfg::{BlockCfg, OverlayExitAction};
+use crate::coveragecfg::CoverageCfg;
+use crate::errpolicycfg::parse_error_policy;
+use crate::gdb::{make_gdb_conn, run_gdbstub, GdbCfg};
+use crate::logger::LogFilters;
+use crate::modelcfg::Model;
+use crate::profilecfg::ProfileCfg;
+use crate::serialcfg::SerialCfg;
+use crate::statecfg::SaveStateCfg;
+use crate::wav::WavSink;
 
 const SYSDUMP_FILENAME: &str = "sysdump.log";
 
 #[derive(StructOpt)]
 #[structopt(name = "clicky")]
 #[structopt(about = r#"
-An emulator for the classic clickwheel iPod 4g.
+An emulator for classic clickwheel iPods.
 "#)]
 struct Args {
+    /// Which iPod model to emulate. One of `ipod3g`, `ipod4g`, `ipod5g`,
+    /// `ipodmini1g`, `ipodmini2g`, or `ipodphoto`.
+    #[structopt(long, default_value = "ipod4g")]
+    model: Model,
+
     /// Load a firmware file using the HLE bootloader.
     #[structopt(long, parse(from_os_str))]
     hle: Option<PathBuf>,
@@ -40,8 +76,13 @@ struct Args {
     /// HDD image to use.
     ///
     /// At the moment, this should most likely be set to either
-    /// `raw:file=/path/to/ipodhd.img` (for persistence) or
-    /// `mem:file=/path/to/ipodhd.img` (for testing).
+    /// `raw:file=/path/to/ipodhd.img` (for persistence),
+    /// `mem:file=/path/to/ipodhd.img` (for testing),
+    /// `qcow2:file=/path/to/ipodhd.qcow2` (for sparse images), or
+    /// `overlay:base=/path/to/ipodhd.img,delta=/path/to/delta.img` (to keep
+    /// the base image pristine). Overlays accept an optional
+    /// `on-exit=<commit|discard>` parameter, which writes back / throws away
+    /// any changes when the emulator exits.
     #[structopt(long)]
     hdd: BlockCfg,
 
@@ -59,17 +100,206 @@ struct Args {
     /// connection before starting execution.
     #[structopt(short, long)]
     gdb: Option<GdbCfg>,
+
+    /// Restore a previously saved system state at startup.
+    ///
+    /// The system must be configured identically to the one which saved the
+    /// state (e.g: same HDD image, in the same state).
+    #[structopt(long, parse(from_os_str))]
+    load_state: Option<PathBuf>,
+
+    /// Save the system state after running for a certain number of cycles.
+    ///
+    /// Format: `--save-state <path>[,after=<cycles>]`
+    ///
+    /// Execution continues as normal after the state is saved.
+    #[structopt(long)]
+    save_state: Option<SaveStateCfg>,
+
+    /// Use deterministic virtual time, advancing the system clock by the
+    /// specified number of nanoseconds per executed instruction. Periods where
+    /// both cores are asleep are skipped over entirely.
+    ///
+    /// By default, the system clock tracks wall-clock time.
+    #[structopt(long, value_name = "nanos-per-instr")]
+    virtual_time: Option<u32>,
+
+    /// Run a test script without a GUI, exiting with a non-zero exit code if
+    /// any screen comparisons fail.
+    ///
+    /// Pair with `--virtual-time` for reproducible results. See
+    /// `src/headless.rs` for the script format.
+    #[structopt(long, parse(from_os_str), conflicts_with_all(&["gdb", "save-state"]))]
+    headless: Option<PathBuf>,
+
+    /// Deliver guest memory faults (e.g: misaligned accesses, MMU violations,
+    /// accesses to unmapped memory) to the CPU as Data / Prefetch Aborts.
+    ///
+    /// By default, such faults immediately halt the emulator. Faults are still
+    /// treated as fatal if the guest hasn't installed an abort handler.
+    #[structopt(long)]
+    guest_aborts: bool,
+
+    /// Service ARM semihosting calls (`swi 0x123456` / Thumb `swi 0xab` or
+    /// `bkpt 0xab`).
+    ///
+    /// Enables console / host file IO for bare-metal test programs. When the
+    /// guest exits via `SYS_EXIT` / `SYS_EXIT_EXTENDED`, the emulator exits
+    /// with the guest's exit code.
+    #[structopt(long)]
+    semihosting: bool,
+
+    /// How to handle accesses to stubbed / unimplemented registers, and device
+    /// contract violations.
+    ///
+    /// Format: `--error-policy [<preset>][,<class>[@<scope>]=<action>...]`
+    ///
+    /// `<preset>` is one of `default`, `strict` (unimplemented accesses and
+    /// contract violations are always fatal), or `permissive` (keep going at
+    /// all costs). Rules are applied on top of the preset, with later rules
+    /// taking precedence. `<class>` is one of `stub`, `unimplemented`,
+    /// `contract`, or `*`. `<scope>` is either a device kind, or an address
+    /// range. `<action>` is one of `default`, `ignore`, `log`, `count`, or
+    /// `fatal`.
+    ///
+    /// e.g: `--error-policy strict,stub@IDE=count,*@0x70000000-0x70000fff=log`
+    #[structopt(long, parse(try_from_str = parse_error_policy))]
+    error_policy: Option<ErrorPolicy>,
+
+    /// Enable MMIO discovery mode, writing a report to the specified file on
+    /// exit.
+    ///
+    /// Accesses to unmapped memory (or unimplemented registers) are
+    /// automatically backed by a stub which returns the last value written to
+    /// it (or zero). The report lists every unmapped / stubbed address which
+    /// was accessed, along with access counts, widths, values, and the PCs
+    /// which accessed them.
+    #[structopt(long, parse(from_os_str))]
+    mmio_report: Option<PathBuf>,
+
+    /// Run a sampling profiler, writing its output to the specified file on
+    /// exit.
+    ///
+    /// Format: `--profile <path>[,interval=<instrs>][,stacks][,format=<fmt>]`
+    ///
+    /// Each core's PC is sampled every `interval` instructions (default: 1000).
+    /// `stacks` additionally records call stacks. `format` is either
+    /// `collapsed` (default, for use with flamegraph tools), or `callgrind`
+    /// (for use with KCachegrind). Samples are aggregated by function when
+    /// symbols are loaded (see `--elf`). The profiler can also be controlled at
+    /// runtime using the `monitor profile` GDB command.
+    #[structopt(long)]
+    profile: Option<ProfileCfg>,
+
+    /// Collect basic-block code coverage, writing it to the specified file on
+    /// exit.
+    ///
+    /// Format: `--coverage <path>[,format=<fmt>][,core=<cpu|cop>][,module=<name>][,base=<addr>]`
+    ///
+    /// `format` is either `drcov` (default, for use with Lighthouse and
+    /// friends), or `lcov` (requires an ELF with DWARF line info to be loaded
+    /// via `--elf`). By default, coverage from both cores is merged, unless a
+    /// specific `core` is specified. `module` and `base` set the name and base
+    /// address of the drcov module (default: `firmware` at 0). Coverage can
+    /// also be controlled at runtime using the `monitor coverage` GDB command.
+    #[structopt(long)]
+    coverage: Option<CoverageCfg>,
+
+    /// Dump the system's audio output to a WAV file.
+    #[structopt(long, parse(from_os_str))]
+    audio_out: Option<PathBuf>,
+
+    /// Load symbols from an ELF file (e.g: `rockbox.elf`), which are used to
+    /// symbolize addresses in logs, crash dumps, and GDB monitor commands. Can
+    /// be specified multiple times.
+    ///
+    /// Format: `--elf <path>[@<offset>]`, where `offset` is added to the
+    /// address of each symbol (e.g: for relocated code).
+    #[structopt(long, parse(try_from_str = parse_elf_spec))]
+    elf: Vec<(String, u32)>,
+
+    /// Record an instruction trace to the specified file.
+    ///
+    /// Traces can be pretty-printed using `clicky-trace`. Tracing can also be
+    /// started / stopped at runtime using the `monitor trace` GDB command.
+    #[structopt(long, parse(from_os_str))]
+    trace: Option<PathBuf>,
+
+    /// Only trace instructions within the specified PC range. Can be specified
+    /// multiple times.
+    ///
+    /// Format: `--trace-filter <start>-<end>` (e.g: `0x40000000-0x40017fff`)
+    #[structopt(long, requires("trace"), parse(try_from_str = parse_pc_range))]
+    trace_filter: Vec<RangeInclusive<u32>>,
+
+    /// Record every input event (key presses / releases, and scroll wheel
+    /// movements) to the specified file, stamped with the cycle at which it was
+    /// delivered to the system.
+    ///
+    /// Pair with `--virtual-time` to capture reproducible bug reports, which
+    /// can be replayed using `--replay-input`.
+    #[structopt(long, parse(from_os_str))]
+    record_input: Option<PathBuf>,
+
+    /// Replay input events recorded via `--record-input`, delivering them at
+    /// the same cycles they were recorded at. Any other input (i.e: from the
+    /// GUI, or from a headless script) is ignored.
+    ///
+    /// The system must be configured identically to the one which recorded
+    /// the events (e.g: same `--virtual-time`, same HDD image, same
+    /// `--load-state`).
+    #[structopt(long, parse(from_os_str), conflicts_with("record-input"))]
+    replay_input: Option<PathBuf>,
+
+    /// Host backend to connect to Serial0.
+    ///
+    /// One of `null`, `stdio`, `pty`, `tcp:port=<port>`, or
+    /// `file:path=/path/` (output only).
+    #[structopt(long, default_value = "stdio")]
+    serial0: SerialCfg,
+
+    /// Host backend to connect to Serial1. Same format as `--serial0`.
+    #[structopt(long, default_value = "null")]
+    serial1: SerialCfg,
 }
 
-enum System {
-    Bare(Ipod4g),
-    Debug { system_gdb: Ipod4gGdb, cfg: GdbCfg },
+fn make_serial_backend(cfg: SerialCfg) -> DynResult<Box<dyn SerialBackend>> {
+    let backend: Box<dyn SerialBackend> = match cfg {
+        SerialCfg::Null => Box::new(serial::backend::Null::new()),
+        SerialCfg::Stdio => Box::new(serial::backend::Stdio::new()),
+        #[cfg(unix)]
+        SerialCfg::Pty => Box::new(serial::backend::Pty::new()?),
+        #[cfg(not(unix))]
+        SerialCfg::Pty => return Err("PTY serial backends are only supported on unix".into()),
+        SerialCfg::Tcp { port } => Box::new(serial::backend::Tcp::new(("127.0.0.1", port))?),
+        SerialCfg::File { path } => Box::new(serial::backend::File::new(fs::File::create(path)?)),
+    };
+    Ok(backend)
 }
 
-impl core::ops::Deref for System {
-    type Target = Ipod4g;
+fn finish_overlay(ctl: block::backend::OverlayCtl, action: OverlayExitAction) -> DynResult<()> {
+    match action {
+        OverlayExitAction::Commit => {
+            let n = ctl.commit()?;
+            info!("Committed {} modified HDD blocks to the base image", n);
+        }
+        OverlayExitAction::Discard => {
+            let n = ctl.discard()?;
+            info!("Discarded {} modified HDD blocks", n);
+        }
+    }
+    Ok(())
+}
+
+enum System<B: PpBoard> {
+    Bare(PpSystem<B>),
+    Debug { system_gdb: PpGdb<B>, cfg: GdbCfg },
+}
+
+impl<B: PpBoard> core::ops::Deref for System<B> {
+    type Target = PpSystem<B>;
 
-    fn deref(&self) -> &Ipod4g {
+    fn deref(&self) -> &PpSystem<B> {
         use self::System::*;
         match self {
             Bare(sys) => sys,
@@ -78,8 +308,8 @@ impl core::ops::Deref for System {
     }
 }
 
-impl core::ops::DerefMut for System {
-    fn deref_mut(&mut self) -> &mut Ipod4g {
+impl<B: PpBoard> core::ops::DerefMut for System<B> {
+    fn deref_mut(&mut self) -> &mut PpSystem<B> {
         use self::System::*;
         match self {
             Bare(sys) => sys,
@@ -88,18 +318,95 @@ impl core::ops::DerefMut for System {
     }
 }
 
+/// Generate a crash dump of the system.
+fn sysdump<B: PpBoard>(system: &PpSystem<B>) -> String {
+    format!("{}\n{:#x?}", system.core_summary(), system)
+}
+
+/// Reports which are generated when the emulator exits.
+pub struct ExitReports {
+    /// Where to write the MMIO discovery report
+    pub mmio_report: Option<PathBuf>,
+    /// Where to write the profiler's output
+    pub profile: Option<ProfileCfg>,
+    /// Where to write code coverage
+    pub coverage: Option<CoverageCfg>,
+}
+
+impl ExitReports {
+    /// Log a summary of any exceptions tallied by the system's error policy,
+    /// and write out any requested reports.
+    pub fn write<B: PpBoard>(&self, system: &PpSystem<B>) {
+        for (class, device, count) in system.error_policy().counts() {
+            info!("{:?}: {} access(es) to {}", class, count, device);
+        }
+
+        if let (Some(path), Some(discovery)) = (&self.mmio_report, system.mmio_discovery()) {
+            match fs::write(path, discovery.to_string()) {
+                Ok(()) => info!("Wrote MMIO discovery report to {}", path.display()),
+                Err(e) => error!("Failed to write MMIO discovery report: {}", e),
+            }
+        }
+
+        if let (Some(cfg), Some(profiler)) = (&self.profile, system.profiler()) {
+            let res = fs::File::create(&cfg.path).and_then(|file| {
+                profiler.write(io::BufWriter::new(file), cfg.format, system.symbols())
+            });
+            match res {
+                Ok(()) => info!(
+                    "Wrote profile ({} samples) to {}",
+                    profiler.num_samples(),
+                    cfg.path.display()
+                ),
+                Err(e) => error!("Failed to write profile: {}", e),
+            }
+        }
+
+        if let (Some(cfg), Some(coverage)) = (&self.coverage, system.coverage()) {
+            let res = fs::File::create(&cfg.path).and_then(|file| {
+                let lines = system.symbols().lines();
+                coverage.write(io::BufWriter::new(file), &cfg.opts, lines)
+            });
+            match res {
+                Ok(()) => info!(
+                    "Wrote coverage ({} blocks) to {}",
+                    coverage.num_blocks(cfg.opts.core),
+                    cfg.path.display()
+                ),
+                Err(e) => error!("Failed to write coverage: {}", e),
+            }
+        }
+    }
+}
+
 fn main() -> DynResult<()> {
-    pretty_env_logger::formatted_builder()
-        .filter(None, log::LevelFilter::Error)
-        .filter(Some("clicky"), log::LevelFilter::Trace)
-        .filter(Some("MMIO"), log::LevelFilter::Info)
-        .filter(Some("I2C"), log::LevelFilter::Info)
-        .filter(Some("armv4t_emu"), log::LevelFilter::Debug)
-        .parse_filters(&std::env::var("RUST_LOG").unwrap_or_default())
-        .init();
+    let log_filters = logger::init();
 
     let args = Args::from_args();
 
+    let exit_code = match args.model {
+        Model::Ipod3g => run_system::<Ipod3gBus>(args, log_filters)?,
+        Model::Ipod4g => run_system::<Ipod4gBus>(args, log_filters)?,
+        Model::Ipod5g => run_system::<Ipod5gBus>(args, log_filters)?,
+        Model::IpodMini1g => run_system::<IpodMini1gBus>(args, log_filters)?,
+        Model::IpodMini2g => run_system::<IpodMini2gBus>(args, log_filters)?,
+        Model::IpodPhoto => run_system::<IpodPhotoBus>(args, log_filters)?,
+    };
+
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+
+    Ok(())
+}
+
+/// Build and run the system, returning the exit code the emulator should exit
+/// with (e.g: non-zero if a headless test script failed, or the guest's
+/// semihosting exit code).
+fn run_system<B: PpBoard + Send + 'static>(args: Args, log_filters: LogFilters) -> DynResult<i32> {
+    // changes to overlay HDD images are committed / discarded on exit
+    let mut overlay_exit = None;
+
     let hdd: Box<dyn BlockDev> = match args.hdd {
         BlockCfg::Null { len } => Box::new(block::backend::Null::new(len)),
         BlockCfg::Raw { path } => {
@@ -120,6 +427,19 @@ fn main() -> DynResult<()> {
             }
             Box::new(block::backend::Mem::new(data.into_boxed_slice()))
         }
+        BlockCfg::Qcow2 { path } => {
+            let file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+            Box::new(block::backend::Qcow2::new(file)?)
+        }
+        BlockCfg::Overlay {
+            base,
+            delta,
+            on_exit,
+        } => {
+            let overlay = block::backend::Overlay::new(base, delta)?;
+            overlay_exit = on_exit.map(|action| (overlay.ctl(), action));
+            Box::new(overlay)
+        }
     };
 
     let boot_kind = match args.hle {
@@ -134,18 +454,103 @@ fn main() -> DynResult<()> {
         None => None,
     };
 
-    let mut system = Ipod4g::new(hdd, flash_rom, boot_kind)?;
+    let cfg = PpCfg {
+        clock: match args.virtual_time {
+            Some(nanos_per_step) => ClockMode::Virtual { nanos_per_step },
+            None => ClockMode::Wall,
+        },
+        serial0: Some(make_serial_backend(args.serial0)?),
+        serial1: Some(make_serial_backend(args.serial1)?),
+        aborts: match args.guest_aborts {
+            true => AbortMode::Guest,
+            false => AbortMode::Fatal,
+        },
+        error_policy: args.error_policy.unwrap_or_default(),
+        mmio_discovery: args.mmio_report.is_some(),
+        semihosting: args.semihosting,
+    };
+
+    let mut system = PpSystem::<B>::new(hdd, flash_rom, boot_kind, cfg)?;
+
+    for (path, offset) in args.elf {
+        let n = system
+            .symbols_mut()
+            .load_elf(&fs::read(&path)?, offset)
+            .map_err(|e| format!("couldn't load symbols from {}: {}", path, e))?;
+        info!("Loaded {} symbols from {}", n, path);
+    }
+
+    if let Some(cfg) = &args.profile {
+        system.set_profiler(Some(Profiler::new(cfg.interval, cfg.stacks)));
+    }
+
+    if args.coverage.is_some() {
+        system.set_coverage(Some(Coverage::new()));
+    }
+
+    let exit_reports = ExitReports {
+        mmio_report: args.mmio_report,
+        profile: args.profile,
+        coverage: args.coverage,
+    };
+
+    if let Some(path) = args.trace {
+        let mut tracer = Tracer::new(Box::new(io::BufWriter::new(fs::File::create(path)?)))?;
+        tracer.set_filter(args.trace_filter);
+        system.set_tracer(Some(tracer));
+    }
+
+    if let Some(path) = args.load_state {
+        system.load_state(io::BufReader::new(fs::File::open(&path)?))?;
+        info!("Loaded system state from {}", path.display());
+    }
+
+    let controls = match (args.record_input, args.replay_input) {
+        (Some(path), _) => {
+            let file = fs::File::create(&path)?;
+            let controls = system.record_input(Box::new(io::BufWriter::new(file)))?;
+            info!("Recording input to {}", path.display());
+            controls
+        }
+        (None, Some(path)) => {
+            system.replay_input(io::BufReader::new(fs::File::open(&path)?))?;
+            info!("Replaying input from {}", path.display());
+            PpBinds::default()
+        }
+        (None, None) => system.take_controls().unwrap(),
+    };
+
+    let audio_sink = match args.audio_out {
+        Some(path) => Some(WavSink::new(&path, system.audio_callback())?),
+        None => None,
+    };
+
+    if let Some(script) = args.headless {
+        let exit_code = headless::run_script(system, controls, &script, audio_sink, &exit_reports)?;
+        if let Some((ctl, action)) = overlay_exit {
+            finish_overlay(ctl, action)?;
+        }
+        return Ok(exit_code);
+    }
+
+    if let Some(audio_sink) = audio_sink {
+        audio_sink.spawn();
+    }
 
     // grab a bunch of UI wiring stuff
     let update_fb = system.render_callback();
-    let controls = system.take_controls().unwrap();
     let (kill_ui_tx, kill_ui_rx) = std::sync::mpsc::channel();
+    let (exit_code_tx, exit_code_rx) = std::sync::mpsc::channel();
+    let save_state = args.save_state;
 
     let mut system = match args.gdb {
-        Some(cfg) => System::Debug {
-            system_gdb: Ipod4gGdb::new(system),
-            cfg,
-        },
+        Some(cfg) => {
+            let mut system_gdb = PpGdb::new(system);
+            system_gdb.set_log_filter_callback(Box::new(move |directives| {
+                log_filters.update(directives)
+            }));
+            System::Debug { system_gdb, cfg }
+        }
         None => System::Bare(system),
     };
 
@@ -154,48 +559,68 @@ fn main() -> DynResult<()> {
     std::thread::spawn(move || -> DynResult<()> {
         let mut debugger = None;
 
-        let system_result = match &mut system {
-            System::Bare(system) => system.run(),
-            System::Debug { system_gdb, cfg } => {
-                // check if a debugger should be connected at boot
-                if cfg.on_start {
-                    debugger = Some(make_gdbstub(cfg.clone())?)
-                }
+        // run the system up until the save-state point (if requested)
+        let mut system_result = Ok(None);
+        if let Some(cfg) = save_state {
+            system_result = system.run_cycles(cfg.after);
+            if let Ok(None) = system_result {
+                system.save_state(io::BufWriter::new(fs::File::create(&cfg.path)?))?;
+                info!("Saved system state to {}", cfg.path.display());
+            }
+        }
 
-                match debugger {
-                    None => system.run(),
-                    // hand off control to the debugger
-                    Some(ref mut debugger) => match debugger.run(system_gdb) {
-                        Ok(dc_reason) => {
-                            info!("Disconnected from GDB: {:?}", dc_reason);
-
-                            use gdbstub::DisconnectReason;
-                            match dc_reason {
-                                DisconnectReason::Disconnect => {
-                                    info!("Target is still running. Resuming execution...");
-                                    system.run()
-                                }
-                                DisconnectReason::TargetHalted => {
-                                    info!("Target halted!");
-                                    Ok(())
-                                }
-                                DisconnectReason::Kill => {
-                                    info!("GDB sent a kill command!");
-                                    return Ok(());
+        let system_result = match system_result {
+            Ok(None) => match &mut system {
+                System::Bare(system) => system.run().map(Some),
+                System::Debug { system_gdb, cfg } => {
+                    // check if a debugger should be connected at boot
+                    if cfg.on_start {
+                        debugger = Some(make_gdb_conn(cfg.clone())?)
+                    }
+
+                    match debugger {
+                        None => system.run().map(Some),
+                        // hand off control to the debugger
+                        Some(ref mut debugger) => match run_gdbstub(debugger, system_gdb)? {
+                            Ok(dc_reason) => {
+                                info!("Disconnected from GDB: {:?}", dc_reason);
+
+                                use gdbstub::stub::DisconnectReason;
+                                match dc_reason {
+                                    DisconnectReason::Disconnect => {
+                                        info!("Target is still running. Resuming execution...");
+                                        system.run().map(Some)
+                                    }
+                                    DisconnectReason::TargetExited(_)
+                                    | DisconnectReason::TargetTerminated(_) => {
+                                        info!("Target halted!");
+                                        Ok(system.exit_reason())
+                                    }
+                                    DisconnectReason::Kill => {
+                                        info!("GDB sent a kill command!");
+                                        return Ok(());
+                                    }
                                 }
                             }
-                        }
-                        Err(gdbstub::GdbStubError::TargetError(e)) => Err(e),
-                        Err(e) => return Err(e.into()),
-                    },
+                            Err(e) => Err(e),
+                        },
+                    }
                 }
-            }
+            },
+            res => res,
         };
 
+        exit_reports.write(&system);
+
+        if let Ok(Some(exit)) = system_result {
+            info!("System exited ({:?})", exit);
+            let _ = exit_code_tx.send(exit.exit_code());
+        }
+
         if let Err(fatal_error) = system_result {
             error!("Fatal Error! Caused by: {:#010x?}", fatal_error);
             error!("Dumping system state to {}", SYSDUMP_FILENAME);
-            std::fs::write(SYSDUMP_FILENAME, format!("{:#x?}", *system))?;
+            std::fs::write(SYSDUMP_FILENAME, sysdump(&system))?;
 
             match &mut system {
                 System::Bare(_system) => {}
@@ -204,12 +629,12 @@ fn main() -> DynResult<()> {
                         system_gdb.sys_mut().freeze();
 
                         if debugger.is_none() {
-                            debugger = Some(make_gdbstub(cfg.clone())?)
+                            debugger = Some(make_gdb_conn(cfg.clone())?)
                         }
 
-                        match debugger.unwrap().run(system_gdb) {
+                        match run_gdbstub(debugger.as_mut().unwrap(), system_gdb)? {
                             Ok(_) => info!("Disconnected from post-mortem GDB session."),
-                            Err(e) => return Err(e.into()),
+                            Err(e) => return Err(format!("{:#010x?}", e).into()),
                         }
                     }
                 }
@@ -226,16 +651,21 @@ fn main() -> DynResult<()> {
         if #[cfg(feature = "minifb")] {
             use crate::backends::minifb::MinifbRenderer;
             MinifbRenderer::run(
-                "iPod 4g",
-                (160, 128),
+                args.model.title(),
+                B::SCREEN_DIMS,
                 update_fb,
                 controls,
                 kill_ui_rx,
             );
         } else {
             info!("No GUI selected, running in headless mode!");
+            let _ = kill_ui_rx.recv();
         }
     };
 
-    Ok(())
+    if let Some((ctl, action)) = overlay_exit {
+        finish_overlay(ctl, action)?;
+    }
+
+    Ok(exit_code_rx.try_recv().unwrap_or(0))
 }
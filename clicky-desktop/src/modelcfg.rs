@@ -0,0 +1,54 @@
+use std::str::FromStr;
+
+/// Helper enum to parse the emulated iPod model.
+#[derive(Debug, Copy, Clone)]
+pub enum Model {
+    /// `ipod3g`
+    Ipod3g,
+    /// `ipod4g`
+    Ipod4g,
+    /// `ipod5g`
+    Ipod5g,
+    /// `ipodmini1g`
+    IpodMini1g,
+    /// `ipodmini2g`
+    IpodMini2g,
+    /// `ipodphoto`
+    IpodPhoto,
+}
+
+impl Model {
+    /// Title used for the emulator window.
+    #[cfg_attr(not(feature = "minifb"), allow(dead_code))]
+    pub fn title(self) -> &'static str {
+        match self {
+            Model::Ipod3g => "iPod 3g",
+            Model::Ipod4g => "iPod 4g",
+            Model::Ipod5g => "iPod 5g",
+            Model::IpodMini1g => "iPod mini 1g",
+            Model::IpodMini2g => "iPod mini 2g",
+            Model::IpodPhoto => "iPod photo",
+        }
+    }
+}
+
+impl FromStr for Model {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Model, &'static str> {
+        Ok(match s {
+            "ipod3g" => Model::Ipod3g,
+            "ipod4g" => Model::Ipod4g,
+            "ipod5g" => Model::Ipod5g,
+            "ipodmini1g" => Model::IpodMini1g,
+            "ipodmini2g" => Model::IpodMini2g,
+            "ipodphoto" => Model::IpodPhoto,
+            _ => {
+                return Err(
+                    "invalid model (expected one of `ipod3g`, `ipod4g`, `ipod5g`, \
+                     `ipodmini1g`, `ipodmini2g`, or `ipodphoto`)",
+                )
+            }
+        })
+    }
+}
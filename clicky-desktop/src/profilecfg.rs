@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clicky_core::profiler::ProfileFormat;
+
+/// Helper struct to parse profiler configurations.
+///
+/// `/path/to/profile[,interval=<instrs>][,stacks][,format=<collapsed|callgrind>]`
+#[derive(Debug, Clone)]
+pub struct ProfileCfg {
+    pub path: PathBuf,
+    pub interval: u64,
+    pub stacks: bool,
+    pub format: ProfileFormat,
+}
+
+impl FromStr for ProfileCfg {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<ProfileCfg, &'static str> {
+        let mut s = s.split(',');
+        let path = s.next().unwrap();
+        if path.is_empty() {
+            return Err("missing path");
+        }
+
+        let mut interval = 1000;
+        let mut stacks = false;
+        let mut format = ProfileFormat::Collapsed;
+
+        for arg in s {
+            let mut s = arg.split('=');
+            let kind = s.next().unwrap();
+            match kind {
+                "interval" => {
+                    interval = s
+                        .next()
+                        .ok_or("missing argument for `interval`")?
+                        .parse()
+                        .map_err(|_| "could not parse `interval`")?
+                }
+                "stacks" => stacks = true,
+                "format" => format = s.next().ok_or("missing argument for `format`")?.parse()?,
+                _ => return Err("unknown profile option"),
+            }
+        }
+
+        Ok(ProfileCfg {
+            path: path.into(),
+            interval,
+            stacks,
+            format,
+        })
+    }
+}
@@ -0,0 +1,74 @@
+use std::str::FromStr;
+
+/// Helper struct to parse Serial backend configurations.
+pub enum SerialCfg {
+    /// `null`
+    Null,
+    /// `stdio`
+    Stdio,
+    /// `pty`
+    Pty,
+    /// `tcp:port=<port>`
+    Tcp { port: u16 },
+    /// `file:path=/path/`
+    File { path: String },
+}
+
+impl FromStr for SerialCfg {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<SerialCfg, &'static str> {
+        let mut s = s.splitn(2, ':');
+        let kind = s.next().unwrap();
+        Ok(match kind {
+            "null" => SerialCfg::Null,
+            "stdio" => SerialCfg::Stdio,
+            "pty" => SerialCfg::Pty,
+            "tcp" => {
+                let s = s.next().ok_or("missing required options")?.split(',');
+
+                let mut port = None;
+
+                for arg in s {
+                    let mut s = arg.split('=');
+                    let kind = s.next().unwrap();
+                    match kind {
+                        "port" => {
+                            port = Some(
+                                (s.next().ok_or("missing argument for `port`")?)
+                                    .parse()
+                                    .map_err(|_| "could not parse `port`")?,
+                            )
+                        }
+                        _ => return Err("unknown `tcp` option"),
+                    }
+                }
+
+                SerialCfg::Tcp {
+                    port: port.ok_or("missing `port` parameter")?,
+                }
+            }
+            "file" => {
+                let s = s.next().ok_or("missing required options")?.split(',');
+
+                let mut path = None;
+
+                for arg in s {
+                    let mut s = arg.split('=');
+                    let kind = s.next().unwrap();
+                    match kind {
+                        "path" => {
+                            path = Some(s.next().ok_or("missing argument for `path`")?.into())
+                        }
+                        _ => return Err("unknown `file` option"),
+                    }
+                }
+
+                SerialCfg::File {
+                    path: path.ok_or("missing `path` parameter")?,
+                }
+            }
+            _ => return Err("invalid serial backend kind"),
+        })
+    }
+}
@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Helper struct to parse save-state configurations.
+///
+/// `/path/to/state[,after=<cycles>]`
+#[derive(Debug, Clone)]
+pub struct SaveStateCfg {
+    pub path: PathBuf,
+    pub after: usize,
+}
+
+impl FromStr for SaveStateCfg {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<SaveStateCfg, &'static str> {
+        let mut s = s.split(',');
+        let path = s.next().unwrap();
+        if path.is_empty() {
+            return Err("missing path");
+        }
+
+        let mut after = 0;
+
+        for arg in s {
+            let mut s = arg.split('=');
+            let kind = s.next().unwrap();
+            match kind {
+                "after" => {
+                    after = s
+                        .next()
+                        .ok_or("missing argument for `after`")?
+                        .parse()
+                        .map_err(|_| "could not parse `after`")?
+                }
+                _ => return Err("unknown save-state option"),
+            }
+        }
+
+        Ok(SaveStateCfg {
+            path: path.into(),
+            after,
+        })
+    }
+}
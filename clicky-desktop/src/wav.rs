@@ -0,0 +1,104 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use clicky_core::gui::AudioCallback;
+
+/// How often the background sink thread polls for new samples.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Dumps the system's audio output to a 16-bit stereo WAV file.
+///
+/// The WAV header is rewritten after every poll, so the file remains valid
+/// even if the emulator is killed mid-way through.
+pub struct WavSink {
+    file: File,
+    audio_cb: AudioCallback,
+    buf: Vec<i16>,
+    sample_rate: Option<u32>,
+    data_len: u32,
+}
+
+impl WavSink {
+    pub fn new(path: &Path, audio_cb: AudioCallback) -> io::Result<WavSink> {
+        let mut sink = WavSink {
+            file: File::create(path)?,
+            audio_cb,
+            buf: Vec::new(),
+            sample_rate: None,
+            data_len: 0,
+        };
+        sink.write_header()?;
+        Ok(sink)
+    }
+
+    /// Append any new samples to the WAV file.
+    pub fn poll(&mut self) -> io::Result<()> {
+        let sample_rate = (self.audio_cb)(&mut self.buf);
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+
+        match self.sample_rate {
+            None => self.sample_rate = Some(sample_rate),
+            Some(rate) if rate != sample_rate => {
+                warn!(
+                    "sample rate changed from {} to {}, WAV output will be distorted",
+                    rate, sample_rate
+                );
+                self.sample_rate = Some(sample_rate);
+            }
+            Some(_) => {}
+        }
+
+        let data = self
+            .buf
+            .iter()
+            .flat_map(|s| s.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>();
+        self.file.write_all(&data)?;
+        self.data_len += data.len() as u32;
+
+        self.write_header()
+    }
+
+    /// Poll for new samples every [POLL_INTERVAL] on a background thread.
+    pub fn spawn(mut self) {
+        std::thread::spawn(move || loop {
+            if let Err(e) = self.poll() {
+                error!("failed to write audio output: {}", e);
+                return;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        });
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        const CHANNELS: u16 = 2;
+        const BITS_PER_SAMPLE: u16 = 16;
+        const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+
+        let sample_rate = self.sample_rate.unwrap_or(0);
+
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(36 + self.data_len).to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        header.extend_from_slice(&CHANNELS.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * BLOCK_ALIGN as u32).to_le_bytes());
+        header.extend_from_slice(&BLOCK_ALIGN.to_le_bytes());
+        header.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&self.data_len.to_le_bytes());
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+//! FAT32 data partition generation.
+
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+
+use fatfs::{FatType, FileSystem, FormatVolumeOptions, FsOptions};
+
+/// FAT32 volumes must contain at least this many clusters
+const MIN_FAT32_CLUSTERS: u64 = 65525;
+
+/// Pick a cluster size using the same heuristics as Windows' formatter,
+/// shrinking it for small volumes.
+fn cluster_size(len: u64) -> Option<u32> {
+    const GIB: u64 = 1024 * 1024 * 1024;
+    let mut size: u32 = match len {
+        _ if len < 8 * GIB => 4096,
+        _ if len < 16 * GIB => 8192,
+        _ if len < 32 * GIB => 16384,
+        _ => 32768,
+    };
+
+    // leave some headroom for the reserved sectors + FATs
+    while len / size as u64 <= MIN_FAT32_CLUSTERS + MIN_FAT32_CLUSTERS / 16 {
+        if size == 512 {
+            return None;
+        }
+        size /= 2;
+    }
+
+    Some(size)
+}
+
+/// Format the disk as FAT32, and copy the contents of `dir` into the root
+/// directory.
+pub fn make_fat32<T: Read + Write + Seek>(
+    mut disk: T,
+    len: u64,
+    label: &str,
+    dir: Option<&Path>,
+) -> io::Result<()> {
+    let bytes_per_cluster = cluster_size(len).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "data partition is too small to be formatted as FAT32",
+        )
+    })?;
+
+    let mut volume_label = [b' '; 11];
+    for (dst, src) in volume_label.iter_mut().zip(label.bytes()) {
+        *dst = src.to_ascii_uppercase();
+    }
+
+    fatfs::format_volume(
+        &mut disk,
+        FormatVolumeOptions::new()
+            .fat_type(FatType::Fat32)
+            .bytes_per_cluster(bytes_per_cluster)
+            .volume_label(volume_label),
+    )?;
+
+    disk.seek(io::SeekFrom::Start(0))?;
+    let fs = FileSystem::new(disk, FsOptions::new())?;
+    if let Some(dir) = dir {
+        copy_dir(dir, &fs.root_dir())?;
+    }
+    fs.unmount()
+}
+
+fn copy_dir<T: fatfs::ReadWriteSeek>(src: &Path, dst: &fatfs::Dir<T>) -> io::Result<()> {
+    let mut entries = fs::read_dir(src)?.collect::<Result<Vec<_>, _>>()?;
+    // keep the output reproducible
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name.to_str().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("non UTF-8 file name: {:?}", entry.path()),
+            )
+        })?;
+
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            debug!("creating dir {}", entry.path().display());
+            copy_dir(&entry.path(), &dst.create_dir(name)?)?;
+        } else if file_type.is_file() {
+            debug!("copying {}", entry.path().display());
+            let mut file = dst.create_file(name)?;
+            file.truncate()?;
+            io::copy(&mut fs::File::open(entry.path())?, &mut file)?;
+        } else {
+            warn!(
+                "skipping {} (not a file or directory)",
+                entry.path().display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    use byteorder::{ByteOrder, LE};
+
+    const MIB: u64 = 1024 * 1024;
+
+    /// Scratch host directory, removed when dropped.
+    struct Scratch {
+        dir: PathBuf,
+    }
+
+    impl Scratch {
+        fn new(name: &str) -> Scratch {
+            let dir =
+                std::env::temp_dir().join(format!("clicky-mkimg-{}-{}", std::process::id(), name));
+            fs::create_dir_all(dir.join("Music")).unwrap();
+            fs::write(dir.join("hello.txt"), b"hello world\n").unwrap();
+            fs::write(dir.join("Music/track.mp3"), vec![0x5a; 10000]).unwrap();
+            Scratch { dir }
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn cluster_sizes() {
+        assert_eq!(cluster_size(16 * MIB), None);
+        assert_eq!(cluster_size(40 * MIB), Some(512));
+        assert_eq!(cluster_size(80 * MIB), Some(1024));
+        assert_eq!(cluster_size(1024 * MIB), Some(4096));
+        assert_eq!(cluster_size(10 * 1024 * MIB), Some(8192));
+        assert_eq!(cluster_size(64 * 1024 * MIB), Some(32768));
+    }
+
+    #[test]
+    fn rejects_small_volumes() {
+        let disk = Cursor::new(vec![0; 16 * MIB as usize]);
+        let err = make_fat32(disk, 16 * MIB, "IPOD", None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn format_and_copy() {
+        let scratch = Scratch::new("format");
+        let len = 40 * MIB;
+        let mut disk = Cursor::new(vec![0; len as usize]);
+        make_fat32(&mut disk, len, "ipod", Some(&scratch.dir)).unwrap();
+
+        let boot = &disk.get_ref()[..512];
+        assert_eq!(LE::read_u16(&boot[0x0b..]), 512); // bytes per sector
+        assert_eq!(boot[0x0d], 1); // sectors per cluster
+        assert_eq!(LE::read_u16(&boot[0x11..]), 0); // no fixed root dir on FAT32
+        assert_eq!(LE::read_u32(&boot[0x20..]) as u64, len / 512);
+        assert_eq!(&boot[0x47..0x52], b"IPOD       ");
+        assert_eq!(&boot[0x52..0x5a], b"FAT32   ");
+        assert_eq!(&boot[0x1fe..], &[0x55, 0xaa]);
+
+        disk.set_position(0);
+        let fs = FileSystem::new(disk, FsOptions::new()).unwrap();
+        assert_eq!(fs.fat_type(), FatType::Fat32);
+
+        let root = fs.root_dir();
+        let entries = root
+            .iter()
+            .map(|e| e.unwrap())
+            .map(|e| (e.file_name(), e.is_dir(), e.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                ("Music".to_string(), true, 0),
+                ("hello.txt".to_string(), false, 12),
+            ]
+        );
+
+        let mut contents = Vec::new();
+        root.open_file("hello.txt")
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, b"hello world\n");
+
+        contents.clear();
+        root.open_file("Music/track.mp3")
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, vec![0x5a; 10000]);
+    }
+}
@@ -0,0 +1,84 @@
+//! Firmware partition image generation.
+//!
+//! Generates version 3 (iPod 4g / mini / photo) firmware images, using the same
+//! layout as the `make_fw` utility bundled with `ipodloader`.
+//!
+//! See http://www.ipodlinux.org/Firmware.html
+
+use byteorder::{ByteOrder, LE};
+
+/// Offset of the volume header
+const VOLUME_HEADER: usize = 0x100;
+/// Offset of the image directory (relative to the start of the firmware, minus
+/// 0x200)
+const DIR_OFFSET: u32 = 0x4000;
+/// Offset of the first directory entry
+const DIR_START: usize = DIR_OFFSET as usize + 0x200;
+/// Images in version 3 firmware have 0x200 bytes of padding before their data
+const IMAGE_PADDING: u32 = 0x200;
+/// Offset of the first image's data
+const FIRST_IMAGE: usize = DIR_START + 0x200 + IMAGE_PADDING as usize;
+
+/// The same copyright blurb Apple uses (and which `make_fw` reproduces).
+const APPLE_COPYRIGHT: &[u8] = b"{{~~  /-----\\   {{~~ /       \\  {{~~|         | {{~~| S T O P | {{~~|         | {{~~ \\       /  {{~~  \\-----/   Copyright(C) 2001 Apple Computer, Inc.---------------------------------------------------------------------------------------------------------";
+
+/// Firmware image generation options.
+#[derive(Debug, Clone)]
+pub struct OsImage<'a> {
+    /// Raw image binary
+    pub data: &'a [u8],
+    /// Address the image should be loaded at
+    pub addr: u32,
+    /// Entry point offset (relative to `addr`)
+    pub entry_offset: u32,
+}
+
+/// Wrap a raw binary into a firmware image containing a single `osos` image.
+pub fn make_firmware(os: &OsImage) -> Vec<u8> {
+    let mut fw = vec![0; FIRST_IMAGE + os.data.len()];
+
+    // NUL-terminated, filling the first 0x100 bytes
+    fw[..APPLE_COPYRIGHT.len()].copy_from_slice(APPLE_COPYRIGHT);
+
+    // volume header
+    let hdr = &mut fw[VOLUME_HEADER..];
+    hdr[0..4].copy_from_slice(b"]ih["); // "[hi]", stored as a LE u32
+    LE::write_u32(&mut hdr[4..8], DIR_OFFSET);
+    LE::write_u16(&mut hdr[8..10], 0x010c); // ext_header_loc
+    LE::write_u16(&mut hdr[10..12], 3); // format_version
+
+    // directory entry
+    let checksum = os
+        .data
+        .iter()
+        .fold(0u32, |sum, b| sum.wrapping_add(*b as u32));
+
+    let entry = &mut fw[DIR_START..];
+    entry[0..4].copy_from_slice(b"!ATA"); // dev
+    entry[4..8].copy_from_slice(b"soso"); // "osos", stored as a LE u32
+    LE::write_u32(&mut entry[8..12], 0); // id
+    LE::write_u32(&mut entry[12..16], (FIRST_IMAGE as u32) - IMAGE_PADDING); // dev_offset
+    LE::write_u32(&mut entry[16..20], os.data.len() as u32); // len
+    LE::write_u32(&mut entry[20..24], os.addr); // addr
+    LE::write_u32(&mut entry[24..28], os.entry_offset); // entry_offset
+    LE::write_u32(&mut entry[28..32], checksum); // checksum
+    LE::write_u32(&mut entry[32..36], 0); // vers
+    LE::write_u32(&mut entry[36..40], 0xffff_ffff); // load_addr
+
+    fw[FIRST_IMAGE..].copy_from_slice(os.data);
+    fw
+}
+
+/// Perform some basic sanity checks on an existing firmware image.
+pub fn validate_firmware(fw: &[u8]) -> Result<(), &'static str> {
+    if fw.len() < DIR_START + 40 {
+        return Err("firmware image is too small");
+    }
+    if &fw[VOLUME_HEADER..VOLUME_HEADER + 4] != b"]ih[" {
+        return Err("missing \"[hi]\" magic in volume header");
+    }
+    if LE::read_u16(&fw[VOLUME_HEADER + 10..VOLUME_HEADER + 12]) != 3 {
+        return Err("only version 3 firmware images are supported");
+    }
+    Ok(())
+}
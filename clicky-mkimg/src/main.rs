@@ -0,0 +1,303 @@
+#[macro_use]
+extern crate log;
+
+use std::fs;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use clicky_core::block::backend::Qcow2;
+
+mod fat;
+mod firmware;
+mod mbr;
+mod slice;
+
+use crate::firmware::OsImage;
+use crate::mbr::Partition;
+use crate::slice::Slice;
+
+pub type DynResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+const SECTOR_SIZE: u64 = 512;
+
+/// Disk signature used by `scripts/rawhd/make_rawhd.sh`
+const DISK_ID: u32 = 0x0420_6969;
+/// Start of the firmware partition (in sectors)
+const FW_PART_START: u64 = 2048;
+/// Minimum size of the firmware partition (in sectors)
+const FW_PART_MIN_LEN: u64 = 10240;
+
+fn parse_capacity(desc: &str) -> Result<u64, String> {
+    use human_size::{Byte, ParsingError, Size, SpecificSize};
+    match desc.parse::<Size>() {
+        Ok(s) => {
+            let bytes: SpecificSize<Byte> = s.into();
+            Ok(bytes.value() as u64)
+        }
+        Err(ParsingError::MissingMultiple) => desc.parse::<u64>().map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_u32(s: &str) -> Result<u32, String> {
+    let res = match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    res.map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ImageFormat {
+    Raw,
+    Qcow2,
+}
+
+impl std::str::FromStr for ImageFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<ImageFormat, &'static str> {
+        match s {
+            "raw" => Ok(ImageFormat::Raw),
+            "qcow2" => Ok(ImageFormat::Qcow2),
+            _ => Err("invalid image format (expected `raw` or `qcow2`)"),
+        }
+    }
+}
+
+#[derive(StructOpt)]
+#[structopt(name = "clicky-mkimg")]
+#[structopt(about = r#"
+Generate a bootable (DOS partitioned) iPod HDD image.
+
+The image contains a firmware partition, followed by a FAT32 data partition.
+"#)]
+struct Args {
+    /// Output image path.
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+
+    /// Total size of the disk image (e.g: `64MiB`, `1GiB`).
+    #[structopt(long, default_value = "64MiB", parse(try_from_str = parse_capacity))]
+    size: u64,
+
+    /// Output image format (`raw` or `qcow2`).
+    #[structopt(long, default_value = "raw")]
+    format: ImageFormat,
+
+    /// Firmware image to copy into the firmware partition verbatim (e.g: one
+    /// generated using `make_fw`).
+    #[structopt(long, parse(from_os_str), required_unless("osos"))]
+    fw: Option<PathBuf>,
+
+    /// Raw binary to wrap into a firmware image as the `osos` image (e.g:
+    /// Rockbox's `bootloader-ipod4g.bin`, or `ipodloader`'s `loader.bin`).
+    #[structopt(long, parse(from_os_str), conflicts_with("fw"))]
+    osos: Option<PathBuf>,
+
+    /// Load address of the `osos` image.
+    #[structopt(long, default_value = "0x10000000", parse(try_from_str = parse_u32))]
+    load_addr: u32,
+
+    /// Entry point of the `osos` image (as an offset from the load address).
+    #[structopt(long, default_value = "0", parse(try_from_str = parse_u32))]
+    entry: u32,
+
+    /// Host directory to copy into the FAT32 data partition.
+    #[structopt(long, parse(from_os_str))]
+    fat_dir: Option<PathBuf>,
+
+    /// FAT32 volume label.
+    #[structopt(long, default_value = "IPOD")]
+    label: String,
+}
+
+fn main() -> DynResult<()> {
+    pretty_env_logger::formatted_builder()
+        .filter(None, log::LevelFilter::Info)
+        .parse_filters(&std::env::var("RUST_LOG").unwrap_or_default())
+        .init();
+
+    let args = Args::from_args();
+
+    let fw = match (&args.fw, &args.osos) {
+        (Some(path), _) => {
+            let fw = fs::read(path)?;
+            if let Err(e) = firmware::validate_firmware(&fw) {
+                warn!(
+                    "{} may not be a valid firmware image: {}",
+                    path.display(),
+                    e
+                );
+            }
+            fw
+        }
+        (None, Some(path)) => firmware::make_firmware(&OsImage {
+            data: &fs::read(path)?,
+            addr: args.load_addr,
+            entry_offset: args.entry,
+        }),
+        (None, None) => unreachable!("enforced by structopt"),
+    };
+
+    if args.size % SECTOR_SIZE != 0 {
+        return Err("image size must be a multiple of 512 bytes".into());
+    }
+
+    let fw_part = Partition {
+        bootable: true,
+        kind: mbr::KIND_IPOD_FIRMWARE,
+        start: FW_PART_START as u32,
+        len: (fw.len() as u64).div_ceil(SECTOR_SIZE).max(FW_PART_MIN_LEN) as u32,
+    };
+
+    let data_start = (fw_part.start + fw_part.len) as u64;
+    let total_sectors = args.size / SECTOR_SIZE;
+    if total_sectors <= data_start {
+        return Err("image size is too small to fit the firmware partition".into());
+    }
+    let data_part = Partition {
+        bootable: false,
+        kind: mbr::KIND_FAT32,
+        start: data_start as u32,
+        len: (total_sectors - data_start) as u32,
+    };
+
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&args.output)?;
+
+    match args.format {
+        ImageFormat::Raw => {
+            // keep the image sparse
+            file.set_len(args.size)?;
+            write_image(file, &fw, fw_part, data_part, &args)?;
+        }
+        ImageFormat::Qcow2 => {
+            let disk = Qcow2::create(file, args.size)?;
+            write_image(disk, &fw, fw_part, data_part, &args)?;
+        }
+    }
+
+    info!(
+        "Wrote {} ({} firmware partition, {} FAT32 partition)",
+        args.output.display(),
+        fw_part.len as u64 * SECTOR_SIZE,
+        data_part.len as u64 * SECTOR_SIZE
+    );
+
+    Ok(())
+}
+
+fn write_image<T: io::Read + Write + Seek>(
+    mut disk: T,
+    fw: &[u8],
+    fw_part: Partition,
+    data_part: Partition,
+    args: &Args,
+) -> DynResult<()> {
+    disk.seek(SeekFrom::Start(0))?;
+    disk.write_all(&mbr::make_mbr(DISK_ID, &[fw_part, data_part]))?;
+
+    disk.seek(SeekFrom::Start(fw_part.start as u64 * SECTOR_SIZE))?;
+    disk.write_all(fw)?;
+
+    info!("Formatting data partition...");
+    let data = Slice::new(
+        &mut disk,
+        data_part.start as u64 * SECTOR_SIZE,
+        data_part.len as u64 * SECTOR_SIZE,
+    )?;
+    fat::make_fat32(
+        data,
+        data_part.len as u64 * SECTOR_SIZE,
+        &args.label,
+        args.fat_dir.as_deref(),
+    )?;
+
+    disk.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use byteorder::{ByteOrder, LE};
+
+    #[test]
+    fn partitioned_image() {
+        let size = 48 * 1024 * 1024;
+        let fw = firmware::make_firmware(&OsImage {
+            data: &[0xe1, 0xa0, 0x00, 0x00],
+            addr: 0x1000_0000,
+            entry_offset: 0,
+        });
+        let args = Args {
+            output: PathBuf::new(),
+            size,
+            format: ImageFormat::Raw,
+            fw: None,
+            osos: None,
+            load_addr: 0x1000_0000,
+            entry: 0,
+            fat_dir: None,
+            label: "IPOD".into(),
+        };
+        let fw_part = Partition {
+            bootable: true,
+            kind: mbr::KIND_IPOD_FIRMWARE,
+            start: FW_PART_START as u32,
+            len: FW_PART_MIN_LEN as u32,
+        };
+        let data_part = Partition {
+            bootable: false,
+            kind: mbr::KIND_FAT32,
+            start: (FW_PART_START + FW_PART_MIN_LEN) as u32,
+            len: (size / SECTOR_SIZE - FW_PART_START - FW_PART_MIN_LEN) as u32,
+        };
+
+        let mut disk = Cursor::new(vec![0; size as usize]);
+        write_image(&mut disk, &fw, fw_part, data_part, &args).unwrap();
+        let img = disk.get_ref();
+
+        // parse the partition table back
+        assert_eq!(LE::read_u32(&img[0x1b8..]), DISK_ID);
+        assert_eq!(&img[0x1fe..0x200], &[0x55, 0xaa]);
+        let entry = |i: usize| &img[0x1be + i * 16..][..16];
+        let (fw_entry, data_entry) = (entry(0), entry(1));
+        assert_eq!(fw_entry[0], 0x80);
+        assert_eq!(fw_entry[4], mbr::KIND_IPOD_FIRMWARE);
+        assert_eq!(LE::read_u32(&fw_entry[8..]), fw_part.start);
+        assert_eq!(LE::read_u32(&fw_entry[12..]), fw_part.len);
+        assert_eq!(data_entry[0], 0x00);
+        assert_eq!(data_entry[4], mbr::KIND_FAT32);
+        assert_eq!(LE::read_u32(&data_entry[8..]), data_part.start);
+        assert_eq!(LE::read_u32(&data_entry[12..]), data_part.len);
+        assert!(entry(2).iter().chain(entry(3)).all(|&b| b == 0));
+
+        // the firmware partition starts with the firmware image
+        let fw_start = (fw_part.start as u64 * SECTOR_SIZE) as usize;
+        assert_eq!(&img[fw_start..][..fw.len()], &fw[..]);
+        assert!(firmware::validate_firmware(&img[fw_start..][..fw.len()]).is_ok());
+
+        // the data partition starts with a FAT32 boot sector
+        let data_start = data_part.start as u64 * SECTOR_SIZE;
+        let boot = &img[data_start as usize..][..512];
+        assert_eq!(&boot[0x52..0x5a], b"FAT32   ");
+        assert_eq!(LE::read_u32(&boot[0x20..]), data_part.len);
+        assert_eq!(&boot[0x1fe..], &[0x55, 0xaa]);
+
+        // ...which is mountable, and contains an empty root directory
+        let data = Slice::new(&mut disk, data_start, data_part.len as u64 * SECTOR_SIZE).unwrap();
+        let fs = fatfs::FileSystem::new(data, fatfs::FsOptions::new()).unwrap();
+        assert_eq!(fs.root_dir().iter().count(), 0);
+    }
+}
@@ -0,0 +1,79 @@
+//! DOS (MBR) partition tables.
+
+use byteorder::{ByteOrder, LE};
+
+/// A single MBR partition table entry.
+#[derive(Debug, Clone, Copy)]
+pub struct Partition {
+    pub bootable: bool,
+    pub kind: u8,
+    /// Start of the partition (in sectors)
+    pub start: u32,
+    /// Length of the partition (in sectors)
+    pub len: u32,
+}
+
+/// iPod firmware partition (as used by "WinPod" formatted iPods)
+pub const KIND_IPOD_FIRMWARE: u8 = 0x00;
+/// FAT32 (CHS)
+pub const KIND_FAT32: u8 = 0x0b;
+
+/// Returns a MBR sector with the given partitions.
+pub fn make_mbr(disk_id: u32, partitions: &[Partition]) -> [u8; 512] {
+    assert!(partitions.len() <= 4);
+
+    let mut mbr = [0; 512];
+    LE::write_u32(&mut mbr[0x1b8..0x1bc], disk_id);
+
+    for (i, part) in partitions.iter().enumerate() {
+        let entry = &mut mbr[0x1be + i * 16..][..16];
+        entry[0] = if part.bootable { 0x80 } else { 0x00 };
+        // LBA-only partition entries
+        entry[1..4].copy_from_slice(&[0xfe, 0xff, 0xff]);
+        entry[4] = part.kind;
+        entry[5..8].copy_from_slice(&[0xfe, 0xff, 0xff]);
+        LE::write_u32(&mut entry[8..12], part.start);
+        LE::write_u32(&mut entry[12..16], part.len);
+    }
+
+    mbr[0x1fe] = 0x55;
+    mbr[0x1ff] = 0xaa;
+    mbr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partition_entries() {
+        let parts = [
+            Partition {
+                bootable: true,
+                kind: KIND_IPOD_FIRMWARE,
+                start: 2048,
+                len: 10240,
+            },
+            Partition {
+                bootable: false,
+                kind: KIND_FAT32,
+                start: 12288,
+                len: 0x0012_3456,
+            },
+        ];
+        let mbr = make_mbr(0xdead_beef, &parts);
+
+        assert_eq!(LE::read_u32(&mbr[0x1b8..]), 0xdead_beef);
+        assert_eq!(&mbr[0x1fe..], &[0x55, 0xaa]);
+        for (i, part) in parts.iter().enumerate() {
+            let entry = &mbr[0x1be + i * 16..][..16];
+            assert_eq!(entry[0] == 0x80, part.bootable);
+            assert_eq!(entry[4], part.kind);
+            assert_eq!(LE::read_u32(&entry[8..]), part.start);
+            assert_eq!(LE::read_u32(&entry[12..]), part.len);
+        }
+        // unused entries are left zeroed
+        assert!(mbr[0x1be + 2 * 16..0x1fe].iter().all(|&b| b == 0));
+        assert!(mbr[..0x1b8].iter().all(|&b| b == 0));
+    }
+}
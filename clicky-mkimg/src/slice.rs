@@ -0,0 +1,102 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Restricts access to a byte range of an underlying stream.
+pub struct Slice<T> {
+    inner: T,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl<T: Seek> Slice<T> {
+    pub fn new(mut inner: T, start: u64, len: u64) -> io::Result<Slice<T>> {
+        inner.seek(SeekFrom::Start(start))?;
+        Ok(Slice {
+            inner,
+            start,
+            len,
+            pos: 0,
+        })
+    }
+
+    fn remaining(&self, n: usize) -> usize {
+        (self.len.saturating_sub(self.pos)).min(n as u64) as usize
+    }
+}
+
+impl<T: Read + Seek> Read for Slice<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.remaining(buf.len());
+        let n = self.inner.read(&mut buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<T: Write + Seek> Write for Slice<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.remaining(buf.len());
+        let n = self.inner.write(&buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Seek> Seek for Slice<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(off) => Some(off as i64),
+            SeekFrom::End(off) => (self.len as i64).checked_add(off),
+            SeekFrom::Current(off) => (self.pos as i64).checked_add(off),
+        };
+
+        match new_pos {
+            Some(p) if p >= 0 => {
+                self.pos = p as u64;
+                self.inner.seek(SeekFrom::Start(self.start + self.pos))?;
+                Ok(self.pos)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn io_is_clamped_to_the_slice() {
+        let mut backing = Cursor::new(vec![0u8; 64]);
+        {
+            let mut slice = Slice::new(&mut backing, 16, 32).unwrap();
+            assert_eq!(slice.write(&[0xaa; 48]).unwrap(), 32);
+            assert_eq!(slice.write(&[0xbb; 4]).unwrap(), 0);
+
+            assert_eq!(slice.seek(SeekFrom::End(-4)).unwrap(), 28);
+            slice.write_all(&[1, 2, 3, 4]).unwrap();
+
+            slice.seek(SeekFrom::Start(24)).unwrap();
+            let mut buf = [0; 16];
+            assert_eq!(slice.read(&mut buf).unwrap(), 8);
+            assert_eq!(&buf[..8], &[0xaa, 0xaa, 0xaa, 0xaa, 1, 2, 3, 4]);
+
+            assert!(slice.seek(SeekFrom::Current(-64)).is_err());
+        }
+
+        let data = backing.into_inner();
+        assert!(data[..16].iter().all(|&b| b == 0));
+        assert!(data[16..44].iter().all(|&b| b == 0xaa));
+        assert_eq!(&data[44..48], &[1, 2, 3, 4]);
+        assert!(data[48..].iter().all(|&b| b == 0));
+    }
+}
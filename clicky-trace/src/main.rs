@@ -0,0 +1,122 @@
+use std::fs;
+use std::io::{self, BufReader, Write};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use clicky_core::devices::platform::pp::common::CpuId;
+use clicky_core::symbols::{parse_elf_spec, SymbolTable};
+use clicky_core::trace::{parse_pc_range, TraceReader, TraceRecord};
+
+pub type DynResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+fn parse_core(s: &str) -> Result<CpuId, &'static str> {
+    Ok(match s {
+        "cpu" => CpuId::Cpu,
+        "cop" => CpuId::Cop,
+        _ => return Err("expected `cpu` or `cop`"),
+    })
+}
+
+#[derive(StructOpt)]
+#[structopt(name = "clicky-trace")]
+#[structopt(about = r#"
+Pretty-print (and disassemble) an instruction trace recorded by clicky.
+"#)]
+struct Args {
+    /// Trace file (as recorded by `clicky-desktop --trace`).
+    #[structopt(parse(from_os_str))]
+    trace: PathBuf,
+
+    /// Only show instructions executed by the specified core (`cpu` or `cop`).
+    #[structopt(long, parse(try_from_str = parse_core))]
+    core: Option<CpuId>,
+
+    /// Only show instructions within the specified PC range. Can be specified
+    /// multiple times.
+    ///
+    /// Format: `--filter <start>-<end>` (e.g: `0x40000000-0x40017fff`)
+    #[structopt(long, parse(try_from_str = parse_pc_range))]
+    filter: Vec<RangeInclusive<u32>>,
+
+    /// Stop after printing the specified number of instructions.
+    #[structopt(short = "n", long)]
+    count: Option<usize>,
+
+    /// Load symbols from an ELF file, and annotate the trace with the function
+    /// being executed. Can be specified multiple times.
+    ///
+    /// Format: `--elf <path>[@<offset>]`
+    #[structopt(long, parse(try_from_str = parse_elf_spec))]
+    elf: Vec<(String, u32)>,
+}
+
+/// Pretty-print a record, preceded by a `<symbol>:` header whenever the core
+/// moves to a different symbol.
+fn print_record(
+    w: &mut impl Write,
+    symbols: &SymbolTable,
+    last_sym: &mut [Option<String>; 2],
+    record: &TraceRecord,
+) -> io::Result<()> {
+    if !symbols.is_empty() {
+        let sym = symbols.lookup(record.pc).map(|(sym, _)| &sym.name);
+        let last_sym = &mut last_sym[record.core as usize];
+        if sym != last_sym.as_ref() {
+            let core = match record.core {
+                CpuId::Cpu => "cpu",
+                CpuId::Cop => "cop",
+            };
+            match symbols.symbolize(record.pc) {
+                Some(sym) => writeln!(w, "[{}] <{}>:", core, sym)?,
+                None => writeln!(w, "[{}] <unknown>:", core)?,
+            }
+            *last_sym = sym.cloned();
+        }
+    }
+
+    writeln!(w, "{}", record)
+}
+
+fn main() -> DynResult<()> {
+    let args = Args::from_args();
+
+    let mut symbols = SymbolTable::new();
+    for (path, offset) in &args.elf {
+        symbols
+            .load_elf(&fs::read(path)?, *offset)
+            .map_err(|e| format!("couldn't load symbols from {}: {}", path, e))?;
+    }
+
+    let reader = TraceReader::new(BufReader::new(fs::File::open(&args.trace)?))?;
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+
+    let records = reader
+        .filter(|record| match record {
+            Err(_) => true,
+            Ok(record) => {
+                let core_ok = args.core.map_or(true, |core| core == record.core);
+                let pc_ok = args.filter.is_empty()
+                    || args.filter.iter().any(|range| range.contains(&record.pc));
+                core_ok && pc_ok
+            }
+        })
+        .take(args.count.unwrap_or(usize::MAX));
+
+    let mut last_sym = [None, None];
+    for record in records {
+        let res = print_record(&mut stdout, &symbols, &mut last_sym, &record?);
+        // don't complain when piped into `head`
+        if let Err(e) = res {
+            if e.kind() == io::ErrorKind::BrokenPipe {
+                break;
+            }
+            return Err(e.into());
+        }
+    }
+
+    Ok(())
+}
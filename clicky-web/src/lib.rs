@@ -8,7 +8,7 @@ use wasm_bindgen::prelude::*;
 
 use clicky_core::block::{self, BlockDev};
 use clicky_core::gui::{RenderCallback, TakeControls};
-use clicky_core::sys::ipod4g::{BootKind, Ipod4g, Ipod4gBinds, Ipod4gKey};
+use clicky_core::sys::ipod4g::{BootKind, Ipod4g, Ipod4gBinds, Ipod4gCfg, Ipod4gKey};
 
 #[wasm_bindgen(start)]
 pub fn init() {
@@ -62,6 +62,7 @@ impl Ipod4gContainer {
             BootKind::HLEBoot {
                 fw_file: io::Cursor::new(fw),
             },
+            Ipod4gCfg::default(),
         )
         .map_err(|e| e.to_string())?;
         debug!("built system");
@@ -105,6 +106,7 @@ impl Ipod4gContainer {
     pub fn run(&mut self, cycles: usize) -> Result<(), JsValue> {
         self.system
             .run_cycles(cycles)
+            .map(|_| ())
             .map_err(|e| format!("fatal error: {:?}", e).into())
     }
 }